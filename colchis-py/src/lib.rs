@@ -0,0 +1,9 @@
+//! The `colchis` Python extension module. The bindings themselves are in
+//! `colchis::python`; build this crate with maturin.
+
+use pyo3::prelude::*;
+
+#[pymodule(name = "colchis")]
+fn colchis_module(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    colchis::python::register(module)
+}
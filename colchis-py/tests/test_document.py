@@ -0,0 +1,37 @@
+import pytest
+
+import colchis
+
+
+def test_navigation():
+    doc = colchis.Document.parse('{"items": [{"id": 1, "tags": ["a"]}, {"id": 2.5}], "ok": true}')
+    root = doc.root
+    assert root.type == "object"
+    assert root.keys() == ["items", "ok"]
+    items = root["items"]
+    assert len(items) == 2
+    assert items[-1]["id"].value() == 2.5
+    assert items[0].path == "/items/0"
+    assert items[0].parent.path == "/items"
+    assert [node.type for node in root.children()] == ["array", "boolean"]
+    assert doc.pointer("/items/0/tags/0").value() == "a"
+    assert doc.pointer("/missing") is None
+
+
+def test_values():
+    doc = colchis.Document.parse(b'{"a": [1, null, "x", false], "b": {"c": -2}}')
+    assert doc.root.value() == {"a": [1, None, "x", False], "b": {"c": -2}}
+    assert doc.root["b"].json() == '{"c":-2}'
+    assert [node.path for node in doc.strings_containing("x")] == ["/a/2"]
+
+
+def test_errors():
+    with pytest.raises(ValueError):
+        colchis.Document.parse("[1,")
+    doc = colchis.Document.parse('{"a": [1]}')
+    with pytest.raises(KeyError):
+        doc.root["b"]
+    with pytest.raises(IndexError):
+        doc.root["a"][1]
+    with pytest.raises(TypeError):
+        len(doc.root["a"][0])
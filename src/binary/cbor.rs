@@ -0,0 +1,141 @@
+use std::io::Read;
+
+use ciborium_ll::{Decoder, Header, simple};
+
+use crate::sink::EventSink;
+
+use super::{BinaryError, Nesting, Scalar};
+
+impl<E> From<ciborium_ll::Error<std::io::Error>> for BinaryError<E> {
+    fn from(err: ciborium_ll::Error<std::io::Error>) -> Self {
+        match err {
+            ciborium_ll::Error::Io(err) => BinaryError::Io(err),
+            ciborium_ll::Error::Syntax(offset) => BinaryError::Syntax(offset),
+        }
+    }
+}
+
+// the size of the chunks text and bytes are read in
+const CHUNK_SIZE: usize = 4096;
+
+/// Read a CBOR value into the sink.
+///
+/// Maps become objects and need text or integer keys; integer keys become
+/// their decimal text. Byte strings become arrays of byte values, and
+/// `undefined` becomes null. Tags are skipped, so a tagged value is read as
+/// the value itself. Other simple values are not supported. Integers
+/// beyond the range of `i64` become floats. Only the first value of the
+/// input is read.
+pub fn read_cbor<R: Read, S: EventSink>(
+    reader: R,
+    sink: &mut S,
+) -> Result<(), BinaryError<S::Error>> {
+    let mut decoder = Decoder::from(reader);
+    let mut nesting = Nesting::new();
+    let mut chunk = [0; CHUNK_SIZE];
+    let mut text = String::new();
+    let mut bytes = Vec::new();
+    while !nesting.is_done() {
+        let offset = decoder.offset();
+        let scalar = match decoder.pull()? {
+            Header::Positive(u) => {
+                i64::try_from(u).map_or(Scalar::Number(u as f64), Scalar::Integer)
+            }
+            // the value is -1 - n
+            Header::Negative(n) => i64::try_from(n)
+                .map_or(Scalar::Number(-1.0 - n as f64), |n| Scalar::Integer(-1 - n)),
+            Header::Float(n) => Scalar::Number(n),
+            Header::Simple(simple::FALSE) => Scalar::Boolean(false),
+            Header::Simple(simple::TRUE) => Scalar::Boolean(true),
+            Header::Simple(simple::NULL | simple::UNDEFINED) => Scalar::Null,
+            Header::Simple(_) => return Err(BinaryError::Unsupported(offset)),
+            Header::Tag(_) => continue,
+            Header::Break => {
+                nesting.end_break(sink, offset)?;
+                continue;
+            }
+            Header::Text(len) => {
+                text.clear();
+                let mut segments = decoder.text(len);
+                while let Some(mut segment) = segments.pull()? {
+                    while let Some(part) = segment.pull(&mut chunk)? {
+                        text.push_str(part);
+                    }
+                }
+                Scalar::String(&text)
+            }
+            Header::Bytes(len) => {
+                bytes.clear();
+                let mut segments = decoder.bytes(len);
+                while let Some(mut segment) = segments.pull()? {
+                    while let Some(part) = segment.pull(&mut chunk)? {
+                        bytes.extend_from_slice(part);
+                    }
+                }
+                nesting.start(sink, false, Some(bytes.len()), offset)?;
+                for &byte in &bytes {
+                    nesting.scalar(sink, Scalar::Integer(byte.into()), offset)?;
+                }
+                continue;
+            }
+            Header::Array(len) => {
+                nesting.start(sink, false, len, offset)?;
+                continue;
+            }
+            Header::Map(len) => {
+                nesting.start(sink, true, len, offset)?;
+                continue;
+            }
+        };
+        nesting.scalar(sink, scalar, offset)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Document, usage::BitpackingUsageBuilder};
+
+    use super::*;
+
+    fn to_json(cbor: &[u8]) -> String {
+        let doc = Document::parse_cbor::<BitpackingUsageBuilder, _>(cbor).unwrap();
+        let mut out = Vec::new();
+        doc.serialize(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_parse_cbor() {
+        // {"a": [1, -2, 1.5, true, null], "b": h'01ff', 3: "x"}
+        let mut input = vec![0xa3, 0x61, b'a', 0x85, 0x01, 0x21, 0xfb];
+        input.extend(1.5f64.to_be_bytes());
+        input.extend([0xf5, 0xf6, 0x61, b'b', 0x42, 0x01, 0xff, 0x03, 0x61, b'x']);
+        assert_eq!(
+            to_json(&input),
+            r#"{"a":[1,-2,1.5,true,null],"b":[1,255],"3":"x"}"#
+        );
+
+        // indefinite lengths, chunked text, a half float, a tag and
+        // undefined
+        let input = [
+            0xbf, 0x61, b't', 0x7f, 0x62, b'a', b'b', 0x61, b'c', 0xff, 0x61, b'l', 0x9f, 0xf9,
+            0x3e, 0x00, 0xc1, 0x1a, 0x00, 0x01, 0x00, 0x00, 0xf7, 0x80, 0xff, 0xff,
+        ];
+        assert_eq!(to_json(&input), r#"{"t":"abc","l":[1.5,65536,null,[]]}"#);
+    }
+
+    #[test]
+    fn test_parse_cbor_errors() {
+        let parse = |input: &[u8]| Document::parse_cbor::<BitpackingUsageBuilder, _>(input);
+        assert!(matches!(parse(&[0x82, 0x01]), Err(BinaryError::Io(_))));
+        // a break outside of a container of unknown length
+        assert!(matches!(parse(&[0x81, 0xff]), Err(BinaryError::Syntax(1))));
+        // a key that is not text or an integer, and an unknown simple value
+        assert!(matches!(
+            parse(&[0xa1, 0xf6, 0x01]),
+            Err(BinaryError::Unsupported(1))
+        ));
+        assert!(matches!(parse(&[0xe0]), Err(BinaryError::Unsupported(0))));
+    }
+}
@@ -0,0 +1,230 @@
+//! Tokenizers for binary formats that hold the same kind of values as
+//! JSON, feeding an [`EventSink`] so their input can be built into a
+//! document like JSON text is.
+
+#[cfg(feature = "cbor")]
+mod cbor;
+#[cfg(feature = "msgpack")]
+mod msgpack;
+
+use std::{
+    fmt,
+    io::{self, Read},
+};
+
+#[cfg(feature = "cbor")]
+pub use cbor::read_cbor;
+#[cfg(feature = "msgpack")]
+pub use msgpack::read_msgpack;
+
+use crate::{
+    document::Document,
+    document_builder::BuildError,
+    sink::{DocumentSink, EventSink},
+    usage::{UsageBuilder, UsageIndex},
+};
+
+/// An error reading a value in a binary format into an [`EventSink`].
+#[derive(Debug)]
+pub enum BinaryError<E> {
+    Io(io::Error),
+    /// The input is not valid in the format, at this byte offset.
+    Syntax(usize),
+    /// The input holds something JSON has no counterpart of, such as a map
+    /// key that is not a string or integer, at this byte offset.
+    Unsupported(usize),
+    /// The sink rejected an event.
+    Sink(E),
+}
+
+impl<E: fmt::Display> fmt::Display for BinaryError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BinaryError::Io(err) => write!(f, "I/O error: {err}"),
+            BinaryError::Syntax(offset) => write!(f, "invalid input at byte {offset}"),
+            BinaryError::Unsupported(offset) => {
+                write!(f, "value without a JSON counterpart at byte {offset}")
+            }
+            BinaryError::Sink(err) => write!(f, "rejected by the sink: {err}"),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for BinaryError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BinaryError::Io(err) => Some(err),
+            BinaryError::Sink(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl<E> From<io::Error> for BinaryError<E> {
+    fn from(err: io::Error) -> Self {
+        BinaryError::Io(err)
+    }
+}
+
+/// A key or scalar value read from the input.
+pub(crate) enum Scalar<'a> {
+    String(&'a str),
+    Integer(i64),
+    Number(f64),
+    Boolean(bool),
+    Null,
+}
+
+// a map or array that is still being read
+struct Open {
+    map: bool,
+    // the entries still to come, or `None` if a break ends the container
+    remaining: Option<usize>,
+    // whether a map expects a key next
+    key_next: bool,
+}
+
+/// The maps and arrays being read, to turn the items of a binary format
+/// into events. Lengths are given up front or the container is ended by a
+/// break; map keys can be strings or integers.
+pub(crate) struct Nesting {
+    open: Vec<Open>,
+    // whether the root value is complete
+    done: bool,
+}
+
+impl Nesting {
+    pub(crate) fn new() -> Self {
+        Self {
+            open: Vec::new(),
+            done: false,
+        }
+    }
+
+    pub(crate) fn is_done(&self) -> bool {
+        self.done
+    }
+
+    pub(crate) fn scalar<S: EventSink>(
+        &mut self,
+        sink: &mut S,
+        scalar: Scalar,
+        offset: usize,
+    ) -> Result<(), BinaryError<S::Error>> {
+        if let Some(open) = self.open.last_mut()
+            && open.key_next
+        {
+            open.key_next = false;
+            return match scalar {
+                Scalar::String(key) => sink.key(key),
+                Scalar::Integer(key) => sink.key(&key.to_string()),
+                _ => return Err(BinaryError::Unsupported(offset)),
+            }
+            .map_err(BinaryError::Sink);
+        }
+        match scalar {
+            Scalar::String(s) => sink.string(s),
+            Scalar::Integer(i) => sink.integer(i),
+            Scalar::Number(n) => sink.number(n),
+            Scalar::Boolean(b) => sink.boolean(b),
+            Scalar::Null => sink.null(),
+        }
+        .map_err(BinaryError::Sink)?;
+        self.value_done(sink)
+    }
+
+    pub(crate) fn start<S: EventSink>(
+        &mut self,
+        sink: &mut S,
+        map: bool,
+        len: Option<usize>,
+        offset: usize,
+    ) -> Result<(), BinaryError<S::Error>> {
+        if self.open.last().is_some_and(|open| open.key_next) {
+            return Err(BinaryError::Unsupported(offset));
+        }
+        if map {
+            sink.start_object()
+        } else {
+            sink.start_array()
+        }
+        .map_err(BinaryError::Sink)?;
+        self.open.push(Open {
+            map,
+            remaining: len,
+            key_next: map,
+        });
+        if len == Some(0) {
+            self.end(sink)?;
+        }
+        Ok(())
+    }
+
+    /// A break, which ends a container of unknown length; only CBOR has
+    /// these.
+    #[cfg(feature = "cbor")]
+    pub(crate) fn end_break<S: EventSink>(
+        &mut self,
+        sink: &mut S,
+        offset: usize,
+    ) -> Result<(), BinaryError<S::Error>> {
+        match self.open.last() {
+            Some(open) if open.remaining.is_none() && (!open.map || open.key_next) => {
+                self.end(sink)
+            }
+            _ => Err(BinaryError::Syntax(offset)),
+        }
+    }
+
+    fn end<S: EventSink>(&mut self, sink: &mut S) -> Result<(), BinaryError<S::Error>> {
+        let open = self.open.pop().expect("A container should be open");
+        if open.map {
+            sink.end_object()
+        } else {
+            sink.end_array()
+        }
+        .map_err(BinaryError::Sink)?;
+        self.value_done(sink)
+    }
+
+    // count a complete value against its container, ending the containers
+    // that are complete with it
+    fn value_done<S: EventSink>(&mut self, sink: &mut S) -> Result<(), BinaryError<S::Error>> {
+        let Some(open) = self.open.last_mut() else {
+            self.done = true;
+            return Ok(());
+        };
+        open.key_next = open.map;
+        if let Some(remaining) = &mut open.remaining {
+            *remaining -= 1;
+            if *remaining == 0 {
+                return self.end(sink);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// Build a document from a MessagePack value. Binary data becomes an
+    /// array of byte values; see [`read_msgpack`].
+    #[cfg(feature = "msgpack")]
+    pub fn parse_msgpack<B: UsageBuilder<Index = U>, R: Read>(
+        reader: R,
+    ) -> Result<Document<U>, BinaryError<BuildError>> {
+        let mut sink = DocumentSink::<B>::new();
+        read_msgpack(reader, &mut sink)?;
+        sink.finish().map_err(BinaryError::Sink)
+    }
+
+    /// Build a document from a CBOR value. Byte strings become arrays of
+    /// byte values; see [`read_cbor`].
+    #[cfg(feature = "cbor")]
+    pub fn parse_cbor<B: UsageBuilder<Index = U>, R: Read>(
+        reader: R,
+    ) -> Result<Document<U>, BinaryError<BuildError>> {
+        let mut sink = DocumentSink::<B>::new();
+        read_cbor(reader, &mut sink)?;
+        sink.finish().map_err(BinaryError::Sink)
+    }
+}
@@ -0,0 +1,204 @@
+use std::io::Read;
+
+use rmp::Marker;
+
+use crate::sink::EventSink;
+
+use super::{BinaryError, Nesting, Scalar};
+
+// the input, counting the bytes read for errors
+struct Input<R: Read> {
+    reader: R,
+    offset: usize,
+}
+
+impl<R: Read> Input<R> {
+    fn bytes<const N: usize>(&mut self) -> std::io::Result<[u8; N]> {
+        let mut bytes = [0; N];
+        self.reader.read_exact(&mut bytes)?;
+        self.offset += N;
+        Ok(bytes)
+    }
+
+    fn len(&mut self, size: usize) -> std::io::Result<usize> {
+        Ok(match size {
+            1 => u8::from_be_bytes(self.bytes()?) as usize,
+            2 => u16::from_be_bytes(self.bytes()?) as usize,
+            _ => u32::from_be_bytes(self.bytes()?) as usize,
+        })
+    }
+
+    fn read_into(&mut self, buffer: &mut Vec<u8>, len: usize) -> std::io::Result<()> {
+        buffer.clear();
+        // read in steps, so a corrupt length can't make us allocate more
+        // than the input holds
+        let read = (&mut self.reader).take(len as u64).read_to_end(buffer)?;
+        self.offset += read;
+        if read < len {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+}
+
+/// Read a MessagePack value into the sink.
+///
+/// Maps become objects and need string or integer keys; integer keys
+/// become their decimal text. Binary data becomes an array of byte values.
+/// Extension types are not supported. Unsigned integers beyond `i64::MAX`
+/// become floats. Only the first value of the input is read.
+pub fn read_msgpack<R: Read, S: EventSink>(
+    reader: R,
+    sink: &mut S,
+) -> Result<(), BinaryError<S::Error>> {
+    let mut input = Input { reader, offset: 0 };
+    let mut nesting = Nesting::new();
+    let mut text = Vec::new();
+    while !nesting.is_done() {
+        let offset = input.offset;
+        let [byte] = input.bytes()?;
+        let scalar = match Marker::from_u8(byte) {
+            Marker::FixPos(i) => Scalar::Integer(i.into()),
+            Marker::FixNeg(i) => Scalar::Integer(i.into()),
+            Marker::U8 => Scalar::Integer(u8::from_be_bytes(input.bytes()?).into()),
+            Marker::U16 => Scalar::Integer(u16::from_be_bytes(input.bytes()?).into()),
+            Marker::U32 => Scalar::Integer(u32::from_be_bytes(input.bytes()?).into()),
+            Marker::U64 => {
+                let u = u64::from_be_bytes(input.bytes()?);
+                i64::try_from(u).map_or(Scalar::Number(u as f64), Scalar::Integer)
+            }
+            Marker::I8 => Scalar::Integer(i8::from_be_bytes(input.bytes()?).into()),
+            Marker::I16 => Scalar::Integer(i16::from_be_bytes(input.bytes()?).into()),
+            Marker::I32 => Scalar::Integer(i32::from_be_bytes(input.bytes()?).into()),
+            Marker::I64 => Scalar::Integer(i64::from_be_bytes(input.bytes()?)),
+            Marker::F32 => Scalar::Number(f32::from_be_bytes(input.bytes()?).into()),
+            Marker::F64 => Scalar::Number(f64::from_be_bytes(input.bytes()?)),
+            Marker::Null => Scalar::Null,
+            Marker::True => Scalar::Boolean(true),
+            Marker::False => Scalar::Boolean(false),
+            Marker::FixStr(len) => {
+                input.read_into(&mut text, len.into())?;
+                Scalar::String(std::str::from_utf8(&text).map_err(|_| BinaryError::Syntax(offset))?)
+            }
+            marker @ (Marker::Str8 | Marker::Str16 | Marker::Str32) => {
+                let len = input.len(size(marker))?;
+                input.read_into(&mut text, len)?;
+                Scalar::String(std::str::from_utf8(&text).map_err(|_| BinaryError::Syntax(offset))?)
+            }
+            marker @ (Marker::Bin8 | Marker::Bin16 | Marker::Bin32) => {
+                let len = input.len(size(marker))?;
+                input.read_into(&mut text, len)?;
+                nesting.start(sink, false, Some(len), offset)?;
+                for &byte in &text {
+                    nesting.scalar(sink, Scalar::Integer(byte.into()), offset)?;
+                }
+                continue;
+            }
+            Marker::FixArray(len) => {
+                nesting.start(sink, false, Some(len.into()), offset)?;
+                continue;
+            }
+            marker @ (Marker::Array16 | Marker::Array32) => {
+                let len = input.len(size(marker))?;
+                nesting.start(sink, false, Some(len), offset)?;
+                continue;
+            }
+            Marker::FixMap(len) => {
+                nesting.start(sink, true, Some(len.into()), offset)?;
+                continue;
+            }
+            marker @ (Marker::Map16 | Marker::Map32) => {
+                let len = input.len(size(marker))?;
+                nesting.start(sink, true, Some(len), offset)?;
+                continue;
+            }
+            Marker::FixExt1
+            | Marker::FixExt2
+            | Marker::FixExt4
+            | Marker::FixExt8
+            | Marker::FixExt16
+            | Marker::Ext8
+            | Marker::Ext16
+            | Marker::Ext32 => return Err(BinaryError::Unsupported(offset)),
+            Marker::Reserved => return Err(BinaryError::Syntax(offset)),
+        };
+        nesting.scalar(sink, scalar, offset)?;
+    }
+    Ok(())
+}
+
+// the size in bytes of the length after a marker
+fn size(marker: Marker) -> usize {
+    match marker {
+        Marker::Str8 | Marker::Bin8 => 1,
+        Marker::Str16 | Marker::Bin16 | Marker::Array16 | Marker::Map16 => 2,
+        _ => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Document, DocumentSink,
+        usage::{BitpackingUsageBuilder, UsageBuilder},
+    };
+
+    use super::*;
+
+    fn to_json(msgpack: &[u8]) -> String {
+        let doc = Document::parse_msgpack::<BitpackingUsageBuilder, _>(msgpack).unwrap();
+        let mut out = Vec::new();
+        doc.serialize(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_parse_msgpack() {
+        let json = r#"{"a":[1,-2,1.5,300,-40000,true,null,"x"],"b":{},"c":[],"d":"ünï"}"#;
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+        let mut msgpack = Vec::new();
+        doc.to_msgpack(doc.root(), &mut msgpack).unwrap();
+        assert_eq!(to_json(&msgpack), json);
+
+        // integer keys, binary data, a 32-bit float and a large unsigned
+        // integer
+        let mut input = vec![0x84, 0x07, 0xc4, 0x02, 0x01, 0xff, 0xa1, b'f', 0xca];
+        input.extend(0.5f32.to_be_bytes());
+        input.extend([0xd0, 0xfb, 0xc3, 0xa1, b'u', 0xcf]);
+        input.extend(u64::MAX.to_be_bytes());
+        assert_eq!(
+            to_json(&input),
+            r#"{"7":[1,255],"f":0.5,"-5":true,"u":1.8446744073709552e19}"#
+        );
+    }
+
+    #[test]
+    fn test_parse_msgpack_errors() {
+        let parse = |input: &[u8]| Document::parse_msgpack::<BitpackingUsageBuilder, _>(input);
+        // truncated
+        assert!(matches!(parse(&[0x92, 0x01]), Err(BinaryError::Io(_))));
+        assert!(matches!(parse(&[0xa3, b'a']), Err(BinaryError::Io(_))));
+        // invalid UTF-8
+        assert!(matches!(parse(&[0xa1, 0xff]), Err(BinaryError::Syntax(0))));
+        assert!(matches!(parse(&[0xc1]), Err(BinaryError::Syntax(0))));
+        // a key that is not a string or integer, and an extension
+        assert!(matches!(
+            parse(&[0x81, 0xc0, 0x01]),
+            Err(BinaryError::Unsupported(1))
+        ));
+        assert!(matches!(
+            parse(&[0x91, 0xd4, 0x01, 0x00]),
+            Err(BinaryError::Unsupported(1))
+        ));
+    }
+
+    #[test]
+    fn test_read_msgpack_first_value() {
+        let mut sink = DocumentSink::<BitpackingUsageBuilder>::new();
+        read_msgpack(&[0x91, 0x90, 0xc0][..], &mut sink).unwrap();
+        let doc = sink.finish().unwrap();
+        let mut out = Vec::new();
+        doc.serialize(&mut out).unwrap();
+        assert_eq!(out, b"[[]]");
+    }
+}
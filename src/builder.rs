@@ -0,0 +1,567 @@
+use std::io;
+
+use vers_vecs::BitVec;
+
+use crate::{
+    document::{Document, OwnedValue, Value},
+    info::{NodeInfoId, NodeType},
+    lookup::NodeLookup,
+    memory_report::MemoryReport,
+    numbers::{Number, NumbersBuilder},
+    progress::Progress,
+    source_spans::SourceSpans,
+    spill::SpillFile,
+    structure::Structure,
+    text::TextUsageBuilder,
+    tree_builder::TreeBuilder,
+    usage::{UsageBuilder, UsageIndex},
+};
+
+const TEXT_USAGE_BLOCK_SIZE: usize = 1024 * 1024; // 1 MiB
+const TEXT_USAGE_CACHE_BLOCKS: usize = 10;
+// the parameters used for deterministic builds; changing these changes the
+// output of deterministic builds
+const DETERMINISTIC_TEXT_BLOCK_SIZE: usize = 1024 * 1024; // 1 MiB
+const DETERMINISTIC_COMPRESSION_LEVEL: u32 = 6;
+
+/// Low-level construction of a document.
+///
+/// This is what the JSON parser uses to build documents, and it can be used
+/// to build documents from other sources of events. Every `open` needs to
+/// be followed by a matching `close` later on, and an opened field needs to
+/// contain exactly one value before it is closed. Scalars are added in one
+/// go with [`Builder::string`], [`Builder::number`], [`Builder::integer`],
+/// [`Builder::boolean`] and [`Builder::null`]. This is not checked; use [`Document::verify`] on
+/// the result if you are unsure.
+pub struct Builder<B: UsageBuilder> {
+    pub(crate) tree_builder: TreeBuilder<B>,
+    pub(crate) text_builder: TextUsageBuilder,
+    pub(crate) numbers: NumbersBuilder,
+    // the text of every number, if it is kept
+    pub(crate) number_lexemes: Option<TextUsageBuilder>,
+    pub(crate) booleans: BitVec,
+    // the offset in the input of every parenthesis, if source spans are
+    // recorded
+    pub(crate) source_offsets: Option<Vec<u64>>,
+}
+
+impl<B: UsageBuilder> Default for Builder<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: UsageBuilder> Builder<B> {
+    pub fn new() -> Self {
+        #[cfg(feature = "memory-tracking")]
+        crate::memory::start_phase();
+        Self {
+            tree_builder: TreeBuilder::new(),
+            text_builder: TextUsageBuilder::new(TEXT_USAGE_BLOCK_SIZE, TEXT_USAGE_CACHE_BLOCKS),
+            numbers: NumbersBuilder::default(),
+            number_lexemes: None,
+            booleans: BitVec::new(),
+            source_offsets: None,
+        }
+    }
+
+    /// Use fixed text block boundaries and compression parameters, so that
+    /// building the same input always results in the same bytes. This
+    /// should be called before anything is added.
+    pub fn deterministic(mut self) -> Self {
+        self.text_builder = self
+            .text_builder
+            .with_block_size(DETERMINISTIC_TEXT_BLOCK_SIZE)
+            .with_compression_level(DETERMINISTIC_COMPRESSION_LEVEL);
+        self.number_lexemes = self.number_lexemes.map(|lexemes| {
+            lexemes
+                .with_block_size(DETERMINISTIC_TEXT_BLOCK_SIZE)
+                .with_compression_level(DETERMINISTIC_COMPRESSION_LEVEL)
+        });
+        self
+    }
+
+    /// Compress text in blocks of about `block_size` bytes. Smaller blocks
+    /// make reading a single string faster, larger blocks compress better.
+    /// This should be called before anything is added.
+    pub fn with_text_block_size(mut self, block_size: usize) -> Self {
+        self.text_builder = self.text_builder.with_block_size(block_size);
+        self.number_lexemes = self
+            .number_lexemes
+            .map(|lexemes| lexemes.with_block_size(block_size));
+        self
+    }
+
+    /// Keep up to `blocks` decompressed text blocks cached in the document.
+    pub fn with_text_cache_capacity(mut self, blocks: usize) -> Self {
+        self.text_builder = self.text_builder.with_cache_capacity(blocks);
+        self.number_lexemes = self
+            .number_lexemes
+            .map(|lexemes| lexemes.with_cache_capacity(blocks));
+        self
+    }
+
+    /// Compress text with the deflate level, from 0 (none) to 9 (best).
+    pub fn with_compression_level(mut self, level: u32) -> Self {
+        self.text_builder = self.text_builder.with_compression_level(level);
+        self.number_lexemes = self
+            .number_lexemes
+            .map(|lexemes| lexemes.with_compression_level(level));
+        self
+    }
+
+    /// Compress text blocks on a worker thread while more is added; see
+    /// [`TextUsageBuilder::with_background_compression`].
+    pub fn with_background_compression(mut self) -> Self {
+        self.text_builder = self.text_builder.with_background_compression();
+        self.number_lexemes = self
+            .number_lexemes
+            .map(|lexemes| lexemes.with_background_compression());
+        self
+    }
+
+    /// Keep the text of numbers as it was given to
+    /// [`Builder::number_with_lexeme`], so that serializing writes it back
+    /// verbatim. This should be called before anything is added.
+    pub fn with_number_lexemes(mut self) -> Self {
+        self.number_lexemes = Some(TextUsageBuilder::new(
+            TEXT_USAGE_BLOCK_SIZE,
+            TEXT_USAGE_CACHE_BLOCKS,
+        ));
+        self
+    }
+
+    /// Compress strings with a dictionary trained on the first
+    /// `training_blocks` text blocks; see
+    /// [`TextUsageBuilder::with_dictionary`]. This should be called before
+    /// anything is added.
+    pub fn with_text_dictionary(mut self, training_blocks: usize) -> Self {
+        self.text_builder = self.text_builder.with_dictionary(training_blocks);
+        self
+    }
+
+    /// Store a hash of every string, which makes
+    /// [`Document::strings_equal`] avoid decompressing strings that differ.
+    pub fn with_string_hashes(mut self) -> Self {
+        self.text_builder = self.text_builder.with_hashes();
+        self
+    }
+
+    /// Write finished text blocks and usage blocks to a temporary file
+    /// instead of keeping them in memory, which bounds peak memory while
+    /// building. The document reads its text back from that file. This
+    /// should be called before anything is added.
+    pub fn spill_to_disk(mut self) -> io::Result<Self> {
+        let spill = SpillFile::new()?;
+        self.tree_builder.usage_builder.set_spill(spill.clone());
+        self.number_lexemes = self
+            .number_lexemes
+            .map(|lexemes| lexemes.with_spill(spill.clone()));
+        self.text_builder = self.text_builder.with_spill(spill);
+        Ok(self)
+    }
+
+    /// Start from a node lookup, such as that of an earlier document, so
+    /// the node infos in it keep their ids.
+    pub(crate) fn with_node_lookup(mut self, node_lookup: NodeLookup) -> Self {
+        *self.tree_builder.usage_builder.node_lookup_mut() = node_lookup;
+        self
+    }
+
+    /// Record where every node is in the input, given by the parser with
+    /// [`Builder::source_offset`]. This should be called before anything
+    /// is added.
+    pub(crate) fn with_source_spans(mut self) -> Self {
+        self.source_offsets = Some(Vec::new());
+        self
+    }
+
+    /// The offset in the input of the parenthesis added last: where a node
+    /// starts after it is opened, and where it ends after it is closed.
+    pub(crate) fn source_offset(&mut self, offset: u64) {
+        if let Some(offsets) = &mut self.source_offsets {
+            offsets.push(offset);
+        }
+    }
+
+    fn source_offsets_size(&self) -> usize {
+        self.source_offsets
+            .as_ref()
+            .map_or(0, |offsets| offsets.len() * std::mem::size_of::<u64>())
+    }
+
+    pub(crate) fn progress(&self, nodes: usize, bytes_read: u64) -> Progress {
+        Progress {
+            nodes,
+            bytes_read,
+            tree_heap_size: self.tree_builder.heap_size(),
+            text_heap_size: self.text_builder.heap_size(),
+            uncompressed_text_size: self.text_builder.uncompressed_size(),
+            numbers_heap_size: self.numbers.heap_size()
+                + self
+                    .number_lexemes
+                    .as_ref()
+                    .map_or(0, TextUsageBuilder::heap_size),
+            booleans_heap_size: self.booleans.heap_size(),
+        }
+    }
+
+    /// The heap used so far by each part of the document being built.
+    pub fn memory_report(&self) -> MemoryReport {
+        let lexemes = self.number_lexemes.as_ref();
+        let pending_lexemes = lexemes.map_or(0, TextUsageBuilder::pending_heap_size);
+        MemoryReport {
+            text_blocks: self.text_builder.heap_size() - self.text_builder.pending_heap_size(),
+            pending_text: self.text_builder.pending_heap_size() + pending_lexemes,
+            numbers: self.numbers.heap_size(),
+            number_lexemes: lexemes.map_or(0, TextUsageBuilder::heap_size) - pending_lexemes,
+            booleans: self.booleans.heap_size(),
+            source_spans: self.source_offsets_size(),
+            ..self.tree_builder.memory_report()
+        }
+    }
+
+    /// Compress the pending text now, so it takes less memory, or none
+    /// when spilling to disk.
+    pub(crate) fn flush_text(&mut self) {
+        self.text_builder.flush();
+        if let Some(lexemes) = &mut self.number_lexemes {
+            lexemes.flush();
+        }
+    }
+
+    /// Open a node of the given type. Use this for objects and arrays;
+    /// scalars are added with their dedicated methods, and fields with
+    /// [`Builder::open_field`].
+    pub fn open(&mut self, node_type: NodeType) {
+        self.tree_builder.open(node_type);
+    }
+
+    /// Close a node of the given type.
+    pub fn close(&mut self, node_type: NodeType) {
+        self.tree_builder.close(node_type);
+    }
+
+    /// Open a field in an object. The returned id is needed to close it.
+    pub fn open_field(&mut self, name: &str) -> NodeInfoId {
+        self.tree_builder.open_field(name)
+    }
+
+    /// Close a field opened with [`Builder::open_field`].
+    pub fn close_field(&mut self, close_field_id: NodeInfoId) {
+        self.tree_builder.close_field(close_field_id);
+    }
+
+    pub fn string(&mut self, s: &str) {
+        self.tree_builder.open(NodeType::String);
+        let _text_id = self.text_builder.add_string(s);
+        self.tree_builder.close(NodeType::String);
+    }
+
+    pub fn number(&mut self, n: f64) {
+        self.push_number(Number::Float(n), None);
+    }
+
+    /// Add an integer, which is stored exactly rather than as a float.
+    pub fn integer(&mut self, i: i64) {
+        self.push_number(Number::Integer(i), None);
+    }
+
+    /// Add a number along with the text it was written as. If the builder
+    /// keeps number lexemes, serializing writes this text instead of the
+    /// value; `lexeme` has to be a valid JSON number for that value.
+    pub fn number_with_lexeme(&mut self, n: f64, lexeme: &str) {
+        self.push_number(Number::Float(n), Some(lexeme));
+    }
+
+    pub(crate) fn push_number(&mut self, number: Number, lexeme: Option<&str>) {
+        self.tree_builder.open(NodeType::Number);
+        if let Some(lexemes) = &mut self.number_lexemes {
+            // an integer written plainly serializes the same without it
+            let lexeme = match (number, lexeme) {
+                (Number::Integer(i), Some(lexeme)) if lexeme == i.to_string() => "",
+                (Number::Unsigned(u), Some(lexeme)) if lexeme == u.to_string() => "",
+                (_, lexeme) => lexeme.unwrap_or(""),
+            };
+            lexemes.add_string(lexeme);
+        }
+        self.numbers.push(number);
+        self.tree_builder.close(NodeType::Number);
+    }
+
+    pub fn boolean(&mut self, b: bool) {
+        self.tree_builder.open(NodeType::Boolean);
+        self.booleans.append(b);
+        self.tree_builder.close(NodeType::Boolean);
+    }
+
+    pub fn null(&mut self) {
+        self.tree_builder.open(NodeType::Null);
+        self.tree_builder.close(NodeType::Null);
+    }
+
+    /// Copy a value from a document, including everything under it.
+    pub fn value<U: UsageIndex>(&mut self, value: &Value<'_, U>) {
+        match value {
+            Value::Object(object) => {
+                self.open(NodeType::Object);
+                for (key, value) in object.iter() {
+                    let close_field_id = self.open_field(key);
+                    self.value(&value);
+                    self.close_field(close_field_id);
+                }
+                self.close(NodeType::Object);
+            }
+            Value::Array(array) => {
+                self.open(NodeType::Array);
+                for item in array.iter() {
+                    self.value(&item);
+                }
+                self.close(NodeType::Array);
+            }
+            Value::String(s) => self.string(s),
+            Value::Number(n) => self.number(*n),
+            Value::Integer(i) => self.integer(*i),
+            Value::Boolean(b) => self.boolean(*b),
+            Value::Null => self.null(),
+        }
+    }
+
+    /// Add a materialized value, including everything under it.
+    pub fn owned_value(&mut self, value: &OwnedValue) {
+        match value {
+            OwnedValue::Object(map) => {
+                self.open(NodeType::Object);
+                for (key, value) in map {
+                    let close_field_id = self.open_field(key);
+                    self.owned_value(value);
+                    self.close_field(close_field_id);
+                }
+                self.close(NodeType::Object);
+            }
+            OwnedValue::Array(items) => {
+                self.open(NodeType::Array);
+                for item in items {
+                    self.owned_value(item);
+                }
+                self.close(NodeType::Array);
+            }
+            OwnedValue::String(s) => self.string(s),
+            OwnedValue::Number(n) => self.number(*n),
+            OwnedValue::Integer(i) => self.integer(*i),
+            OwnedValue::Boolean(b) => self.boolean(*b),
+            OwnedValue::Null => self.null(),
+        }
+    }
+
+    /// Build a segment of the children of a node, to be appended to the
+    /// builder of that node with [`Builder::append_segment`]. This is how
+    /// parts of a document are built in parallel.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn segment(mut self) -> Self {
+        self.tree_builder = self.tree_builder.segment();
+        self
+    }
+
+    /// Append a segment built with [`Builder::segment`]; its top-level
+    /// values become children of the node that is open now.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn append_segment(&mut self, segment: Builder<B>) {
+        self.tree_builder.append_segment(segment.tree_builder);
+        self.text_builder.append_segment(segment.text_builder);
+        self.numbers.append(segment.numbers);
+        if let (Some(lexemes), Some(segment_lexemes)) =
+            (&mut self.number_lexemes, segment.number_lexemes)
+        {
+            lexemes.append_segment(segment_lexemes);
+        }
+        for i in 0..segment.booleans.len() {
+            self.booleans
+                .append(segment.booleans.is_bit_set_unchecked(i));
+        }
+        if let (Some(offsets), Some(segment_offsets)) =
+            (&mut self.source_offsets, segment.source_offsets)
+        {
+            offsets.extend(segment_offsets);
+        }
+    }
+
+    /// Turn everything added into a document.
+    pub fn build(self) -> Document<B::Index> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("build").entered();
+        // both the positions and the text is compressed at this point.
+
+        // now uncompress the position data and turn it into a succinct structure
+        // This will use some memory per node type, which is then compacted down
+        // into a succinct structure
+        #[cfg(feature = "memory-tracking")]
+        let tree_building = crate::memory::phase_peak();
+        #[cfg(feature = "memory-tracking")]
+        crate::memory::start_phase();
+        let source_spans = self.source_offsets.map(|offsets| {
+            debug_assert_eq!(offsets.len(), self.tree_builder.parentheses.len());
+            SourceSpans::new(&offsets)
+        });
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!("build_structure").entered();
+        let structure = Structure::<B::Index>::new(self.tree_builder);
+        #[cfg(feature = "tracing")]
+        span.exit();
+        #[cfg(feature = "memory-tracking")]
+        let index_construction = crate::memory::phase_peak();
+        #[cfg(feature = "memory-tracking")]
+        crate::memory::start_phase();
+        // finally complete the text usage
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!("build_text").entered();
+        let text_usage = self.text_builder.build();
+        let mut document =
+            Document::new(structure, text_usage, self.numbers.build(), self.booleans);
+        document.number_lexemes = self.number_lexemes.map(TextUsageBuilder::build);
+        document.source_spans = source_spans;
+        #[cfg(feature = "tracing")]
+        span.exit();
+        #[cfg(feature = "memory-tracking")]
+        if let (Some(tree_building), Some(index_construction), Some(text_compression)) = (
+            tree_building,
+            index_construction,
+            crate::memory::phase_peak(),
+        ) {
+            document.build_memory = Some(crate::memory::BuildMemory {
+                tree_building,
+                index_construction,
+                text_compression,
+            });
+        }
+        document
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Value, usage::BitpackingUsageBuilder};
+
+    use super::*;
+
+    #[test]
+    fn test_build_object() {
+        let mut builder = Builder::<BitpackingUsageBuilder>::new();
+        builder.open(NodeType::Object);
+        let close_id = builder.open_field("a");
+        builder.open(NodeType::Array);
+        builder.string("x");
+        builder.number(1.5);
+        builder.boolean(true);
+        builder.null();
+        builder.close(NodeType::Array);
+        builder.close_field(close_id);
+        builder.close(NodeType::Object);
+        let doc = builder.build();
+
+        assert_eq!(doc.verify(), Ok(()));
+        let mut out = Vec::new();
+        doc.serialize(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"{"a":["x",1.5,true,null]}"#
+        );
+    }
+
+    #[test]
+    fn test_build_scalar() {
+        let mut builder = Builder::<BitpackingUsageBuilder>::new();
+        builder.number(42.0);
+        let doc = builder.build();
+        assert_eq!(doc.root_value(), Value::Number(42.0));
+    }
+
+    #[test]
+    fn test_memory_report() {
+        let mut builder = Builder::<BitpackingUsageBuilder>::new().with_number_lexemes();
+        builder.open(NodeType::Array);
+        builder.string("some text");
+        builder.number_with_lexeme(1.5, "1.50");
+        builder.boolean(true);
+        builder.close(NodeType::Array);
+        let report = builder.memory_report();
+        assert!(report.tree > 0);
+        // nothing is compressed before the first block is full
+        assert_eq!(report.text_blocks, 0);
+        assert!(report.pending_text > 0);
+        assert_eq!(report.caches, 0);
+
+        let doc = builder.build();
+        let report = doc.memory_report();
+        assert_eq!(report.pending_text, 0);
+        assert!(report.text_blocks > 0 && report.number_lexemes > 0);
+        assert_eq!(report.total(), doc.heap_size() + report.caches);
+        doc.as_str(doc.first_child(doc.root()).unwrap()).unwrap();
+        let read = doc.memory_report();
+        assert!(read.caches > report.caches);
+        assert_eq!(read.total() - read.caches, doc.heap_size());
+    }
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_tracing_spans() {
+        use std::sync::{Arc, Mutex};
+
+        use tracing::{Event, Metadata, span};
+
+        // records the names of the spans and the messages of the events
+        #[derive(Clone, Default)]
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+
+        impl tracing::Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+                let mut names = self.0.lock().unwrap();
+                names.push(span.metadata().name().to_string());
+                span::Id::from_u64(names.len() as u64)
+            }
+
+            fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+            fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(event.metadata().name().to_string());
+            }
+
+            fn enter(&self, _: &span::Id) {}
+
+            fn exit(&self, _: &span::Id) {}
+        }
+
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let doc = BitpackingUsageBuilder::parse(r#"["a", "b"]"#.as_bytes())
+                .unwrap()
+                .with_substring_index();
+            doc.as_str(doc.first_child(doc.root()).unwrap()).unwrap();
+        });
+        let names = recorder.0.lock().unwrap().clone();
+        let spans = [
+            "parse",
+            "build",
+            "build_structure",
+            "build_text",
+            "build_index",
+        ];
+        for span in spans {
+            assert!(names.iter().any(|name| name == span), "{span} in {names:?}");
+        }
+        // events are named after where they are emitted
+        assert!(
+            names
+                .iter()
+                .any(|name| name.contains("compressed_storage.rs"))
+        );
+    }
+}
@@ -0,0 +1,268 @@
+//! The on-disk layout of saved documents.
+//!
+//! A container starts with a header:
+//!
+//! - the 8 bytes of [`MAGIC`],
+//! - the format version as a little-endian `u32`,
+//! - the number of sections as a little-endian `u32`,
+//! - a table entry for every section: its id (`u32`), its offset from the
+//!   start of the container (`u64`), its length (`u64`) and the CRC32 of its
+//!   content (`u32`),
+//! - the CRC32 of everything in the header before it.
+//!
+//! The sections follow the header in the order of the table. Readers skip
+//! sections with ids they don't know, so later versions can add sections
+//! without breaking older readers; anything else that changes the layout
+//! bumps [`FORMAT_VERSION`], and readers reject versions they don't know
+//! with [`LoadError::UnsupportedVersion`].
+
+use std::io::{self, Read, Write};
+
+use flate2::Crc;
+
+use crate::persist::{self, LoadError};
+
+/// The bytes every container starts with.
+pub const MAGIC: &[u8; 8] = b"COLCHIS\0";
+
+/// The version of the layout written by this build.
+pub const FORMAT_VERSION: u32 = 7;
+
+// magic, version and section count
+const HEADER_SIZE: u64 = 16;
+// id, offset, length and checksum
+const ENTRY_SIZE: u64 = 24;
+// more sections than any version writes means the header is corrupt
+const MAX_SECTIONS: u32 = 1024;
+
+/// The sections of a saved document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub(crate) enum Section {
+    Structure = 1,
+    Text = 2,
+    Numbers = 3,
+    Booleans = 4,
+    /// Only there if the document keeps number lexemes.
+    NumberLexemes = 5,
+    /// Only there if the document records source spans.
+    SourceSpans = 6,
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(data);
+    crc.sum()
+}
+
+/// Read the format version of a container, checking its magic.
+///
+/// This lets callers tell saved documents apart from other data, and tell
+/// which version wrote them, without loading them.
+pub fn read_version<R: Read>(mut r: R) -> Result<u32, LoadError> {
+    let mut magic = [0; MAGIC.len()];
+    r.read_exact(&mut magic)
+        .map_err(|_| LoadError::NotADocument)?;
+    if &magic != MAGIC {
+        return Err(LoadError::NotADocument);
+    }
+    persist::read_u32(&mut r)
+}
+
+/// Sections collected in memory to be written as a container.
+#[derive(Default)]
+pub(crate) struct ContainerWriter {
+    sections: Vec<(Section, Vec<u8>)>,
+}
+
+impl ContainerWriter {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn section(
+        &mut self,
+        section: Section,
+        write: impl FnOnce(&mut Vec<u8>) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let mut data = Vec::new();
+        write(&mut data)?;
+        self.sections.push((section, data));
+        Ok(())
+    }
+
+    pub(crate) fn write<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let mut header = Vec::new();
+        header.extend_from_slice(MAGIC);
+        persist::write_u32(&mut header, FORMAT_VERSION)?;
+        persist::write_u32(&mut header, self.sections.len() as u32)?;
+        // the header ends with its own checksum
+        let mut offset = HEADER_SIZE + ENTRY_SIZE * self.sections.len() as u64 + 4;
+        for (section, data) in &self.sections {
+            persist::write_u32(&mut header, *section as u32)?;
+            persist::write_u64(&mut header, offset)?;
+            persist::write_u64(&mut header, data.len() as u64)?;
+            persist::write_u32(&mut header, checksum(data))?;
+            offset += data.len() as u64;
+        }
+        let header_checksum = checksum(&header);
+        persist::write_u32(&mut header, header_checksum)?;
+        w.write_all(&header)?;
+        for (_, data) in &self.sections {
+            w.write_all(data)?;
+        }
+        w.flush()
+    }
+}
+
+/// The sections of a container, with their checksums verified.
+pub(crate) struct Container {
+    sections: Vec<(u32, Vec<u8>)>,
+}
+
+struct Entry {
+    id: u32,
+    offset: u64,
+    len: u64,
+    checksum: u32,
+}
+
+impl Container {
+    pub(crate) fn read<R: Read>(mut r: R) -> Result<Self, LoadError> {
+        let version = read_version(&mut r)?;
+        if version != FORMAT_VERSION {
+            return Err(LoadError::UnsupportedVersion(version));
+        }
+        let count = persist::read_u32(&mut r)?;
+        if count > MAX_SECTIONS {
+            return Err(LoadError::Corrupt("too many sections"));
+        }
+        let mut header = Vec::new();
+        header.extend_from_slice(MAGIC);
+        persist::write_u32(&mut header, version)?;
+        persist::write_u32(&mut header, count)?;
+        let mut entries = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let entry = Entry {
+                id: persist::read_u32(&mut r)?,
+                offset: persist::read_u64(&mut r)?,
+                len: persist::read_u64(&mut r)?,
+                checksum: persist::read_u32(&mut r)?,
+            };
+            persist::write_u32(&mut header, entry.id)?;
+            persist::write_u64(&mut header, entry.offset)?;
+            persist::write_u64(&mut header, entry.len)?;
+            persist::write_u32(&mut header, entry.checksum)?;
+            entries.push(entry);
+        }
+        if persist::read_u32(&mut r)? != checksum(&header) {
+            return Err(LoadError::Corrupt("header checksum mismatch"));
+        }
+
+        // the sections are read in order, so this works on any reader
+        let mut position = header.len() as u64 + 4;
+        let mut sections = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let gap = entry
+                .offset
+                .checked_sub(position)
+                .ok_or(LoadError::Corrupt("overlapping sections"))?;
+            io::copy(&mut (&mut r).take(gap), &mut io::sink())?;
+            let mut data = persist::vec_for(entry.len as usize);
+            (&mut r).take(entry.len).read_to_end(&mut data)?;
+            if data.len() as u64 != entry.len {
+                return Err(LoadError::Corrupt("unexpected end of data"));
+            }
+            position = entry.offset + entry.len;
+            if checksum(&data) != entry.checksum {
+                return Err(LoadError::ChecksumMismatch { section: entry.id });
+            }
+            sections.push((entry.id, data));
+        }
+        Ok(Self { sections })
+    }
+
+    /// The content of a section.
+    pub(crate) fn section(&self, section: Section) -> Result<&[u8], LoadError> {
+        self.sections
+            .iter()
+            .find(|(id, _)| *id == section as u32)
+            .map(|(_, data)| data.as_slice())
+            .ok_or(LoadError::MissingSection(section as u32))
+    }
+
+    pub(crate) fn has_section(&self, section: Section) -> bool {
+        self.sections.iter().any(|(id, _)| *id == section as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn container() -> Vec<u8> {
+        let mut writer = ContainerWriter::new();
+        writer
+            .section(Section::Structure, |w| w.write_all(b"structure"))
+            .unwrap();
+        writer
+            .section(Section::Text, |w| w.write_all(b"text"))
+            .unwrap();
+        let mut out = Vec::new();
+        writer.write(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_write_read() {
+        let out = container();
+        assert_eq!(read_version(out.as_slice()).unwrap(), FORMAT_VERSION);
+        let container = Container::read(out.as_slice()).unwrap();
+        assert_eq!(container.section(Section::Structure).unwrap(), b"structure");
+        assert_eq!(container.section(Section::Text).unwrap(), b"text");
+        assert!(matches!(
+            container.section(Section::Numbers),
+            Err(LoadError::MissingSection(3))
+        ));
+    }
+
+    #[test]
+    fn test_corrupt_section() {
+        let mut out = container();
+        let last = out.len() - 1;
+        out[last] ^= 1;
+        assert!(matches!(
+            Container::read(out.as_slice()),
+            Err(LoadError::ChecksumMismatch { section: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_corrupt_header() {
+        let mut out = container();
+        // the length of the first section
+        out[HEADER_SIZE as usize + 12] ^= 1;
+        assert!(matches!(
+            Container::read(out.as_slice()),
+            Err(LoadError::Corrupt("header checksum mismatch"))
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let mut out = container();
+        out[MAGIC.len()] = 99;
+        assert!(matches!(
+            Container::read(out.as_slice()),
+            Err(LoadError::UnsupportedVersion(99))
+        ));
+    }
+
+    #[test]
+    fn test_not_a_container() {
+        assert!(matches!(
+            read_version(b"{}".as_slice()),
+            Err(LoadError::NotADocument)
+        ));
+    }
+}
@@ -0,0 +1,206 @@
+use std::{io::Read, sync::Arc};
+
+use crate::{
+    document::{CompiledPointer, Document},
+    lookup::NodeLookup,
+    options::ParseOptions,
+    parse_error::JsonParseError,
+    usage::{UsageBuilder, UsageIndex},
+};
+
+/// The id of a document in a [`Corpus`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DocumentId(usize);
+
+impl DocumentId {
+    /// The index of the document, in the order the documents were added.
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+/// Many documents that share a single dictionary of node types and field
+/// names, such as thousands of responses of the same API.
+///
+/// Every document parsed into the corpus starts from the field names of
+/// the documents before it, so a field name has the same id in all of
+/// them and its name is stored once rather than once per document. The
+/// documents are otherwise independent; their text is not shared.
+///
+/// ```
+/// use colchis::{BitpackingUsageBuilder, Corpus, EliasFanoUsageIndex};
+///
+/// struct Responses {
+///     corpus: Corpus<EliasFanoUsageIndex>,
+/// }
+///
+/// let mut responses = Responses {
+///     corpus: Corpus::new(),
+/// };
+/// let id = responses
+///     .corpus
+///     .parse::<BitpackingUsageBuilder, _>(r#"{"id": 1}"#.as_bytes())
+///     .unwrap();
+/// assert!(responses.corpus.get(id).is_some());
+/// ```
+pub struct Corpus<U: UsageIndex> {
+    documents: Vec<Document<U>>,
+    node_lookup: Arc<NodeLookup>,
+    options: ParseOptions,
+}
+
+impl<U: UsageIndex> Default for Corpus<U> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<U: UsageIndex> Corpus<U> {
+    pub fn new() -> Self {
+        Self::with_options(ParseOptions::default())
+    }
+
+    /// A corpus whose documents are parsed with these options.
+    pub fn with_options(options: ParseOptions) -> Self {
+        Self {
+            documents: Vec::new(),
+            node_lookup: Arc::new(NodeLookup::new()),
+            options,
+        }
+    }
+
+    /// Parse a document into the corpus.
+    pub fn parse<B: UsageBuilder<Index = U>, R: Read>(
+        &mut self,
+        json: R,
+    ) -> Result<DocumentId, JsonParseError> {
+        let options = self.options.clone().node_lookup(self.node_lookup.clone());
+        let document = Document::parse_with::<B, R>(json, &options)?;
+        Ok(self.add(document))
+    }
+
+    /// Parse newline-delimited JSON into a document of the corpus, with an
+    /// array of the records as its root.
+    pub fn parse_ndjson<B: UsageBuilder<Index = U>, R: Read>(
+        &mut self,
+        json: R,
+    ) -> Result<DocumentId, JsonParseError> {
+        let options = self.options.clone().node_lookup(self.node_lookup.clone());
+        let document = Document::parse_ndjson_with::<B, R>(json, &options)?;
+        Ok(self.add(document))
+    }
+
+    // the document starts from the shared lookup, so either it has the same
+    // node infos, or more that the other documents can share too
+    fn add(&mut self, mut document: Document<U>) -> DocumentId {
+        let usage_index = document.structure.usage_index_mut();
+        if usage_index.node_lookup().len() > self.node_lookup.len() {
+            self.node_lookup = usage_index.shared_node_lookup().clone();
+            for other in &mut self.documents {
+                other
+                    .structure
+                    .usage_index_mut()
+                    .set_node_lookup(self.node_lookup.clone());
+            }
+        } else {
+            usage_index.set_node_lookup(self.node_lookup.clone());
+        }
+        self.documents.push(document);
+        DocumentId(self.documents.len() - 1)
+    }
+
+    /// The document with an id, if it is in this corpus.
+    pub fn get(&self, id: DocumentId) -> Option<&Document<U>> {
+        self.documents.get(id.0)
+    }
+
+    /// The documents with their ids, in the order they were added.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = (DocumentId, &Document<U>)> {
+        self.documents
+            .iter()
+            .enumerate()
+            .map(|(index, document)| (DocumentId(index), document))
+    }
+
+    /// Compile a JSON Pointer against the dictionary of the corpus, to look
+    /// it up in any of its documents with
+    /// [`Document::get_compiled`]; `None` if the pointer is not valid.
+    pub fn compile_pointer(&self, pointer: &str) -> Option<CompiledPointer> {
+        CompiledPointer::new(pointer, &self.node_lookup)
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// The approximate heap used by the documents and the dictionary they
+    /// share.
+    pub fn heap_size(&self) -> usize {
+        self.node_lookup.heap_size()
+            + self
+                .documents
+                .iter()
+                .map(Document::heap_size)
+                .sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        NodeType,
+        usage::{BitpackingUsageBuilder, EliasFanoUsageIndex},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_corpus() {
+        let mut corpus = Corpus::<EliasFanoUsageIndex>::new();
+        let a = corpus
+            .parse::<BitpackingUsageBuilder, _>(r#"{"id": 1, "name": "a"}"#.as_bytes())
+            .unwrap();
+        let b = corpus
+            .parse::<BitpackingUsageBuilder, _>(r#"{"name": "b", "id": 2}"#.as_bytes())
+            .unwrap();
+        let c = corpus
+            .parse_ndjson::<BitpackingUsageBuilder, _>("{\"id\": 3, \"tags\": []}\n".as_bytes())
+            .unwrap();
+        assert_eq!((a.index(), b.index(), c.index()), (0, 1, 2));
+        assert_eq!(corpus.len(), 3);
+
+        // all documents share the lookup, with the fields of all of them
+        let lookups = corpus
+            .iter()
+            .map(|(_, document)| document.structure.usage_index().shared_node_lookup())
+            .collect::<Vec<_>>();
+        assert!(
+            lookups
+                .iter()
+                .all(|lookup| Arc::ptr_eq(lookup, &corpus.node_lookup))
+        );
+        let ids = |name: &str| corpus.node_lookup.by_field_name(name);
+        assert!(ids("id").is_some() && ids("name").is_some() && ids("tags").is_some());
+
+        // the documents still read as they were parsed
+        let document = corpus.get(b).unwrap();
+        let field = document.first_child(document.root()).unwrap();
+        assert_eq!(
+            document.node_type(field),
+            &NodeType::Field("name".to_string())
+        );
+        let document = corpus.get(a).unwrap();
+        assert_eq!(
+            document
+                .get_path(document.root(), &["name"])
+                .map(|node| document.as_str(node).unwrap().to_string()),
+            Some("a".to_string())
+        );
+        assert!(corpus.get(DocumentId(3)).is_none());
+        assert!(corpus.heap_size() > 0);
+    }
+}
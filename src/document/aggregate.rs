@@ -0,0 +1,104 @@
+use crate::{
+    info::{NUMBER_OPEN_ID, NodeType},
+    usage::UsageIndex,
+};
+
+use super::{Document, Node};
+
+/// An aggregate of numbers, computed by [`Document::aggregate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Agg {
+    Sum,
+    Min,
+    Max,
+    /// The mean.
+    Avg,
+    /// The number of numbers.
+    Count,
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// Aggregate the numbers among `nodes`, such as the matches of
+    /// [`Document::typed_descendants`] or [`Document::numbers_in_range`].
+    ///
+    /// For a field its value is taken; nodes that are not numbers are
+    /// skipped. Numbers are read from the number column without
+    /// materializing values, and are added as floats, so integers beyond
+    /// 2^53 are rounded first. Without numbers the sum and count are 0 and
+    /// the other aggregates `None`.
+    ///
+    /// The total of all `amount` fields is:
+    ///
+    /// ```
+    /// use colchis::{Agg, BitpackingUsageBuilder, Document, NodeType};
+    ///
+    /// let json = r#"[{"amount": 2}, {"amount": 3.5}, {"amount": null}]"#;
+    /// let doc = Document::parse::<BitpackingUsageBuilder, _>(json.as_bytes()).unwrap();
+    /// let amounts = doc.typed_descendants(doc.root(), NodeType::Field("amount".into()));
+    /// assert_eq!(doc.aggregate(amounts, Agg::Sum), Some(5.5));
+    /// ```
+    pub fn aggregate(&self, nodes: impl IntoIterator<Item = Node>, agg: Agg) -> Option<f64> {
+        let numbers = nodes
+            .into_iter()
+            .filter_map(|node| self.aggregated_number(node));
+        match agg {
+            Agg::Sum => Some(numbers.sum()),
+            Agg::Min => numbers.reduce(f64::min),
+            Agg::Max => numbers.reduce(f64::max),
+            Agg::Avg => {
+                let (sum, count) = numbers.fold((0.0, 0), |(sum, count), n| (sum + n, count + 1));
+                (count > 0).then(|| sum / count as f64)
+            }
+            Agg::Count => Some(numbers.count() as f64),
+        }
+    }
+
+    // the number of a number node or of the value of a field
+    fn aggregated_number(&self, node: Node) -> Option<f64> {
+        let node = if matches!(self.node_type(node), NodeType::Field(_)) {
+            self.first_child(node)?
+        } else {
+            node
+        };
+        (self.structure.node_info_id(node.get()) == NUMBER_OPEN_ID)
+            .then(|| self.number_value(node).to_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        NodeType,
+        usage::{BitpackingUsageBuilder, UsageBuilder},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_aggregate() {
+        let json = r#"{"orders": [{"amount": 10}, {"amount": -2.5}, {"amount": "n/a"}, {"amount": 4}], "amount": 100}"#;
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+        let orders = doc
+            .first_child(doc.first_child(doc.root()).unwrap())
+            .unwrap();
+        let amounts = || doc.typed_descendants(orders, NodeType::Field("amount".into()));
+        assert_eq!(doc.aggregate(amounts(), Agg::Sum), Some(11.5));
+        assert_eq!(doc.aggregate(amounts(), Agg::Min), Some(-2.5));
+        assert_eq!(doc.aggregate(amounts(), Agg::Max), Some(10.0));
+        assert_eq!(doc.aggregate(amounts(), Agg::Avg), Some(11.5 / 3.0));
+        assert_eq!(doc.aggregate(amounts(), Agg::Count), Some(3.0));
+
+        // number nodes directly, from anywhere in the document
+        let numbers = doc.typed_descendants(doc.root(), NodeType::Number);
+        assert_eq!(doc.aggregate(numbers, Agg::Max), Some(100.0));
+        assert_eq!(
+            doc.aggregate(doc.numbers_in_range(0.0, 50.0), Agg::Sum),
+            Some(14.0)
+        );
+
+        let none = doc.typed_descendants(doc.root(), NodeType::Field("price".into()));
+        assert_eq!(doc.aggregate(none, Agg::Sum), Some(0.0));
+        assert_eq!(doc.aggregate([doc.root()], Agg::Avg), None);
+        assert_eq!(doc.aggregate([], Agg::Count), Some(0.0));
+    }
+}
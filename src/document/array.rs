@@ -1,17 +1,28 @@
 use std::io::Write;
+use std::iter::FusedIterator;
 
 use struson::writer::{JsonStreamWriter, JsonWriter};
 
 use crate::usage::UsageIndex;
 
-use super::{Document, Node, value::Value};
+use super::{Document, Node, nav::siblings_size_hint, value::Value};
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct ArrayValue<'a, U: UsageIndex> {
     document: &'a Document<U>,
     node: Node,
 }
 
+// implemented manually so we don't require U: Clone
+impl<U: UsageIndex> Clone for ArrayValue<'_, U> {
+    fn clone(&self) -> Self {
+        Self {
+            document: self.document,
+            node: self.node,
+        }
+    }
+}
+
 impl<U: UsageIndex> PartialEq for ArrayValue<'_, U> {
     fn eq(&self, other: &Self) -> bool {
         // document reference equality
@@ -37,17 +48,95 @@ impl<'a, U: UsageIndex> ArrayValue<'a, U> {
         Self { document, node }
     }
 
-    fn iter(&self) -> ArrayIterator<'a, U> {
+    /// The number of items, counted without visiting every one of them;
+    /// see [`Document::child_count`].
+    pub fn len(&self) -> usize {
+        self.document.child_count(self.node)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.document.first_child(self.node).is_none()
+    }
+
+    pub fn iter(&self) -> ArrayIterator<'a, U> {
         ArrayIterator {
             document: self.document,
-            node: self.document.primitive_first_child(self.node),
+            node: self.document.first_child(self.node),
+            end: self.document.primitive_close(self.node),
         }
     }
 
+    /// The item at index `i`, or `None` if the array is shorter.
+    pub fn get(&self, i: usize) -> Option<Value<'a, U>> {
+        self.document
+            .nth_child(self.node, i)
+            .map(|node| self.document.value(node))
+    }
+
+    /// The items as floats, if every item is a number, or `None` otherwise.
+    ///
+    /// This reads the numbers straight from the number column, without
+    /// creating a [`Value`] for every item, so it is a fast way to hand a
+    /// column of numbers to libraries like ndarray or polars. Integers that
+    /// a float can't hold are rounded. An empty array gives an empty vector.
+    pub fn collect_numbers(&self) -> Option<Vec<f64>> {
+        let structure = &self.document.structure;
+        let open = self.node.get();
+        let close = self.document.primitive_close(self.node);
+        let first = structure.number_id(open)?;
+        let count = structure.number_id(close)? - first;
+        // every number item takes an open and a close position, so the
+        // items are all numbers if they fill the array
+        if count * 2 != close - open - 1 {
+            return None;
+        }
+        Some(
+            (first..first + count)
+                .map(|number_id| self.document.numbers.get(number_id).to_f64())
+                .collect(),
+        )
+    }
+
+    /// Iterate over the items in parallel, with rayon. The items are
+    /// split at the children whose position the document records, every
+    /// 64th child of a large container, so splitting visits no items;
+    /// arrays too small to have these are iterated on one thread.
+    /// Collecting keeps the items in order.
+    #[cfg(feature = "parallel")]
+    pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = Value<'a, U>> + use<'a, U>
+    where
+        U: Sync,
+    {
+        use rayon::prelude::*;
+
+        use crate::tree_builder::CHILD_SKIP_INTERVAL;
+
+        let document = self.document;
+        let starts = self
+            .document
+            .first_child(self.node)
+            .into_iter()
+            .chain(
+                self.document
+                    .structure
+                    .child_skips(self.node.get())
+                    .iter()
+                    .map(|&position| Node::new(position)),
+            )
+            .collect::<Vec<_>>();
+        starts.into_par_iter().flat_map_iter(move |start| {
+            std::iter::successors(Some(start), move |node| document.next_sibling(*node))
+                .take(CHILD_SKIP_INTERVAL)
+                .map(move |node| document.value(node))
+        })
+    }
+
     pub fn serialize<W: Write>(&self, writer: &mut JsonStreamWriter<W>) -> std::io::Result<()> {
         writer.begin_array()?;
-        for value in self.iter() {
-            value.serialize(writer)?;
+        let mut item = self.document.first_child(self.node);
+        while let Some(node) = item {
+            self.document.serialize_node(node, writer)?;
+            item = self.document.next_sibling(node);
         }
         writer.end_array()
     }
@@ -56,6 +145,8 @@ impl<'a, U: UsageIndex> ArrayValue<'a, U> {
 pub struct ArrayIterator<'a, U: UsageIndex> {
     document: &'a Document<U>,
     node: Option<Node>,
+    // the closing position of the array
+    end: usize,
 }
 
 impl<'a, U: UsageIndex> Iterator for ArrayIterator<'a, U> {
@@ -63,10 +154,87 @@ impl<'a, U: UsageIndex> Iterator for ArrayIterator<'a, U> {
 
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(node) = self.node {
-            self.node = self.document.primitive_next_sibling(node);
+            self.node = self.document.next_sibling(node);
             Some(self.document.value(node))
         } else {
             None
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // every item takes at least an open and a close position
+        siblings_size_hint(self.node, self.end, 2)
+    }
+}
+
+impl<U: UsageIndex> FusedIterator for ArrayIterator<'_, U> {}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Value,
+        usage::{BitpackingUsageBuilder, UsageBuilder},
+    };
+
+    #[test]
+    fn test_collect_numbers() {
+        let doc = BitpackingUsageBuilder::parse(
+            r#"{"a": [1, 2.5, -3, 1e3], "b": [1, "x"], "c": [[1], 2], "d": []}"#.as_bytes(),
+        )
+        .unwrap();
+        let Value::Object(root) = doc.root_value() else {
+            panic!("root should be an object");
+        };
+        let array = |name: &str| match root.get(name) {
+            Some(Value::Array(array)) => array,
+            _ => panic!("{name} should be an array"),
+        };
+        assert_eq!(
+            array("a").collect_numbers(),
+            Some(vec![1.0, 2.5, -3.0, 1000.0])
+        );
+        assert_eq!(array("b").collect_numbers(), None);
+        assert_eq!(array("c").collect_numbers(), None);
+        assert_eq!(array("d").collect_numbers(), Some(vec![]));
+    }
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_par_iter() {
+        use rayon::prelude::*;
+
+        let items = (0..1000).map(|i| i.to_string()).collect::<Vec<_>>();
+        let doc = BitpackingUsageBuilder::parse(
+            format!(
+                r#"{{"big": [{}], "small": [1, "x"], "empty": []}}"#,
+                items.join(", ")
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        let Value::Object(root) = doc.root_value() else {
+            panic!("root should be an object");
+        };
+        let array = |name: &str| match root.get(name) {
+            Some(Value::Array(array)) => array,
+            _ => panic!("{name} should be an array"),
+        };
+        let big = array("big");
+        assert_eq!(
+            big.par_iter().collect::<Vec<_>>(),
+            big.iter().collect::<Vec<_>>()
+        );
+        let sum = big
+            .par_iter()
+            .map(|value| match value {
+                Value::Integer(i) => i,
+                _ => panic!("item should be an integer"),
+            })
+            .sum::<i64>();
+        assert_eq!(sum, 999 * 1000 / 2);
+        assert_eq!(
+            array("small").par_iter().collect::<Vec<_>>(),
+            array("small").iter().collect::<Vec<_>>()
+        );
+        assert_eq!(array("empty").par_iter().count(), 0);
+    }
 }
@@ -0,0 +1,148 @@
+use std::iter::FusedIterator;
+
+use crate::usage::UsageIndex;
+
+use super::{Document, Node};
+
+/// An iterator over the nodes of an XPath-style axis, in document order.
+///
+/// Created by [`Document::following`] and [`Document::preceding`]. Iterate
+/// it in reverse to get the nodes closest to the context node first.
+pub struct AxisIter<'a, U: UsageIndex> {
+    document: &'a Document<U>,
+    // the range of positions still to visit
+    front: usize,
+    back: usize,
+    // opening positions in the range that are not on the axis, sorted
+    skip: Vec<usize>,
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// Iterate over the nodes after `node` in document order, excluding its
+    /// descendants.
+    pub fn following(&self, node: Node) -> AxisIter<'_, U> {
+        AxisIter {
+            document: self,
+            front: self.primitive_close(node) + 1,
+            back: self.structure.len(),
+            skip: Vec::new(),
+        }
+    }
+
+    /// Iterate over the nodes before `node` in document order, excluding its
+    /// ancestors.
+    pub fn preceding(&self, node: Node) -> AxisIter<'_, U> {
+        let mut skip = std::iter::successors(self.parent(node), |node| self.parent(*node))
+            .map(|node| node.get())
+            .collect::<Vec<_>>();
+        skip.reverse();
+        AxisIter {
+            document: self,
+            front: 0,
+            back: node.get(),
+            skip,
+        }
+    }
+}
+
+impl<U: UsageIndex> AxisIter<'_, U> {
+    fn on_axis(&self, position: usize) -> bool {
+        self.document.structure.is_open(position) && self.skip.binary_search(&position).is_err()
+    }
+}
+
+impl<U: UsageIndex> Iterator for AxisIter<'_, U> {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.front < self.back {
+            let position = self.front;
+            self.front += 1;
+            if self.on_axis(position) {
+                return Some(Node::new(position));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // the range can end in the middle of a subtree, so any of its
+        // positions may open a node
+        (0, Some(self.back - self.front))
+    }
+}
+
+impl<U: UsageIndex> DoubleEndedIterator for AxisIter<'_, U> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while self.front < self.back {
+            self.back -= 1;
+            let position = self.back;
+            if self.on_axis(position) {
+                return Some(Node::new(position));
+            }
+        }
+        None
+    }
+}
+
+impl<U: UsageIndex> FusedIterator for AxisIter<'_, U> {}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        NodeType, Value,
+        usage::{BitpackingUsageBuilder, UsageBuilder},
+    };
+
+    #[test]
+    fn test_following() {
+        let doc = BitpackingUsageBuilder::parse(r#"[[1, 2], 3, [4]]"#.as_bytes()).unwrap();
+        let first = doc.first_child(doc.root()).unwrap();
+        let following = doc
+            .following(first)
+            .map(|node| doc.value(node))
+            .filter(|value| matches!(value, Value::Number(_) | Value::Integer(_)))
+            .collect::<Vec<_>>();
+        assert_eq!(following, vec![Value::Number(3.0), Value::Number(4.0)]);
+        assert_eq!(doc.following(first).count(), 3);
+        assert_eq!(doc.following(doc.root()).count(), 0);
+    }
+
+    #[test]
+    fn test_size_hint_from_both_ends() {
+        let doc = BitpackingUsageBuilder::parse(r#"["x", [[1]]]"#.as_bytes()).unwrap();
+        let x = doc.first_child(doc.root()).unwrap();
+        let mut following = doc.following(x);
+        assert!(following.next_back().is_some());
+        let (_, upper) = following.size_hint();
+        assert!(upper.unwrap() >= 2);
+        assert_eq!(following.count(), 2);
+    }
+
+    #[test]
+    fn test_preceding() {
+        let doc = BitpackingUsageBuilder::parse(r#"[[1, 2], 3, [4]]"#.as_bytes()).unwrap();
+        let last = doc.last_child(doc.root()).unwrap();
+        let four = doc.first_child(last).unwrap();
+        // the root and the last array are ancestors, so they are skipped
+        let preceding = doc
+            .preceding(four)
+            .map(|node| doc.node_type(node).clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            preceding,
+            vec![
+                NodeType::Array,
+                NodeType::Number,
+                NodeType::Number,
+                NodeType::Number
+            ]
+        );
+        // reversed, the closest node comes first
+        assert_eq!(
+            doc.preceding(four).next_back().map(|node| doc.value(node)),
+            Some(Value::Number(3.0))
+        );
+        assert_eq!(doc.preceding(doc.root()).count(), 0);
+    }
+}
@@ -0,0 +1,106 @@
+//! Low-level balanced parentheses operations.
+//!
+//! These expose the raw parentheses sequence underlying the document tree.
+//! Positions are indexes into that sequence: every node is represented by an
+//! opening parenthesis at its position, and a matching closing parenthesis
+//! further on. This is only available with the `unstable` feature, and may
+//! change at any time.
+
+use crate::usage::UsageIndex;
+
+use super::{Document, Node};
+
+impl<U: UsageIndex> Document<U> {
+    /// The position of the opening parenthesis of a node.
+    pub fn node_position(&self, node: Node) -> usize {
+        node.get()
+    }
+
+    /// The node that opens at `position`, if there is an opening parenthesis
+    /// there.
+    pub fn node_at_position(&self, position: usize) -> Option<Node> {
+        if self.is_open_position(position) {
+            Some(Node::new(position))
+        } else {
+            None
+        }
+    }
+
+    /// The length of the parentheses sequence.
+    pub fn parentheses_len(&self) -> usize {
+        self.structure.len()
+    }
+
+    /// Whether there is an opening parenthesis at `position`.
+    pub fn is_open_position(&self, position: usize) -> bool {
+        self.structure.is_open(position)
+    }
+
+    /// The number of opening parentheses minus the number of closing
+    /// parentheses up to and including `position`.
+    pub fn excess(&self, position: usize) -> Option<i64> {
+        if position < self.parentheses_len() {
+            Some(self.structure.tree().excess(position))
+        } else {
+            None
+        }
+    }
+
+    /// The position of the closing parenthesis matching the opening
+    /// parenthesis at `position`.
+    pub fn find_close(&self, position: usize) -> Option<usize> {
+        if !self.is_open_position(position) {
+            return None;
+        }
+        self.structure.tree().close(position)
+    }
+
+    /// The position of the opening parenthesis matching the closing
+    /// parenthesis at `position`.
+    pub fn find_open(&self, position: usize) -> Option<usize> {
+        if position >= self.parentheses_len() || self.is_open_position(position) {
+            return None;
+        }
+        self.structure.tree().open(position)
+    }
+
+    /// The position of the opening parenthesis of the nearest pair that
+    /// encloses `position`.
+    pub fn enclose(&self, position: usize) -> Option<usize> {
+        self.structure.tree().enclose(position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::{BitpackingUsageBuilder, UsageBuilder};
+
+    #[test]
+    fn test_parentheses_queries() {
+        // ( ( ) ( ( ) ) )
+        // 0 1 2 3 4 5 6 7
+        let doc = BitpackingUsageBuilder::parse(r#"[1, [2]]"#.as_bytes()).unwrap();
+        assert_eq!(doc.parentheses_len(), 8);
+        assert_eq!(doc.node_position(doc.root()), 0);
+
+        assert_eq!(doc.excess(0), Some(1));
+        assert_eq!(doc.excess(4), Some(3));
+        assert_eq!(doc.excess(7), Some(0));
+        assert_eq!(doc.excess(8), None);
+
+        assert_eq!(doc.find_close(0), Some(7));
+        assert_eq!(doc.find_close(3), Some(6));
+        assert_eq!(doc.find_close(2), None);
+
+        assert_eq!(doc.find_open(6), Some(3));
+        assert_eq!(doc.find_open(2), Some(1));
+        assert_eq!(doc.find_open(1), None);
+
+        assert_eq!(doc.enclose(4), Some(3));
+        assert_eq!(doc.enclose(3), Some(0));
+        assert_eq!(doc.enclose(0), None);
+
+        assert!(doc.node_at_position(4).is_some());
+        assert!(doc.node_at_position(5).is_none());
+    }
+}
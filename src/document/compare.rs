@@ -0,0 +1,211 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::{
+    info::NodeType,
+    numbers::{Number, float_equals_integer, float_equals_unsigned},
+    usage::UsageIndex,
+};
+
+use super::{Document, Node};
+
+// distinguishes the kinds of values in a hash, so that for instance an
+// empty array and an empty object hash differently
+#[derive(Hash)]
+enum Kind {
+    Object,
+    Array,
+    String,
+    Number,
+    Boolean,
+    Null,
+}
+
+// whether two numbers are equal, as `Value` compares them
+pub(super) fn numbers_equal(a: Number, b: Number) -> bool {
+    match (a, b) {
+        (Number::Float(a), Number::Float(b)) => a == b,
+        (Number::Integer(a), Number::Integer(b)) => a == b,
+        (Number::Unsigned(a), Number::Unsigned(b)) => a == b,
+        (Number::Float(a), Number::Integer(b)) | (Number::Integer(b), Number::Float(a)) => {
+            float_equals_integer(a, b)
+        }
+        (Number::Float(a), Number::Unsigned(b)) | (Number::Unsigned(b), Number::Float(a)) => {
+            float_equals_unsigned(a, b)
+        }
+        // unsigned integers are all above i64::MAX
+        (Number::Integer(_), Number::Unsigned(_)) | (Number::Unsigned(_), Number::Integer(_)) => {
+            false
+        }
+    }
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// Whether the values at two nodes are equal, comparing their whole
+    /// subtrees without making a [`Value`](super::Value) for every node.
+    ///
+    /// Values compare as [`Value`](super::Value) compares scalars, so an
+    /// integer equals the float with the same value and NaN equals
+    /// nothing. Objects are equal if they have the same fields with equal
+    /// values in any order; the values of duplicate fields are compared in
+    /// the order they occur. For a field node the value of the field is
+    /// compared.
+    pub fn subtree_eq(&self, a: Node, b: Node) -> bool {
+        let (a, b) = (self.field_value(a), self.field_value(b));
+        match (self.node_type(a), self.node_type(b)) {
+            (NodeType::Object, NodeType::Object) => {
+                let (a, b) = (self.sorted_fields(a), self.sorted_fields(b));
+                a.len() == b.len()
+                    && a.iter().zip(&b).all(|((a_name, a), (b_name, b))| {
+                        a_name == b_name && self.subtree_eq(*a, *b)
+                    })
+            }
+            (NodeType::Array, NodeType::Array) => {
+                let (mut a, mut b) = (self.children(a), self.children(b));
+                loop {
+                    match (a.next(), b.next()) {
+                        (Some(a), Some(b)) if self.subtree_eq(a, b) => {}
+                        (None, None) => return true,
+                        _ => return false,
+                    }
+                }
+            }
+            (NodeType::String, NodeType::String) => self.strings_equal(a, b) == Some(true),
+            (NodeType::Number, NodeType::Number) => {
+                numbers_equal(self.number_value(a), self.number_value(b))
+            }
+            (NodeType::Boolean, NodeType::Boolean) => {
+                self.boolean_value(a) == self.boolean_value(b)
+            }
+            (NodeType::Null, NodeType::Null) => true,
+            _ => false,
+        }
+    }
+
+    /// A hash of the value at a node and its whole subtree, such that
+    /// values that are equal by [`Document::subtree_eq`] hash the same.
+    ///
+    /// The hash doesn't depend on the document or the position of the
+    /// node, so it can be used to find equal values across documents, but
+    /// it may change between versions of this crate.
+    pub fn subtree_hash(&self, node: Node) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash_subtree(self.field_value(node), &mut hasher);
+        hasher.finish()
+    }
+
+    fn hash_subtree<H: Hasher>(&self, node: Node, hasher: &mut H) {
+        match self.node_type(node) {
+            NodeType::Object => {
+                Kind::Object.hash(hasher);
+                let fields = self.sorted_fields(node);
+                fields.len().hash(hasher);
+                for (name, value) in fields {
+                    name.hash(hasher);
+                    self.hash_subtree(value, hasher);
+                }
+            }
+            NodeType::Array => {
+                Kind::Array.hash(hasher);
+                self.child_count(node).hash(hasher);
+                for item in self.children(node) {
+                    self.hash_subtree(item, hasher);
+                }
+            }
+            NodeType::String => {
+                Kind::String.hash(hasher);
+                let text = self.as_str(node).expect("Node should be a string");
+                text.hash(hasher);
+            }
+            NodeType::Number => {
+                Kind::Number.hash(hasher);
+                // integers hash as the float they equal, if any, and -0.0
+                // as 0.0, so that numbers that compare equal hash the same
+                match self.number_value(node) {
+                    Number::Integer(i) if !float_equals_integer(i as f64, i) => {
+                        i.hash(hasher);
+                    }
+                    Number::Integer(i) => (i as f64).to_bits().hash(hasher),
+                    Number::Unsigned(u) if !float_equals_unsigned(u as f64, u) => {
+                        u.hash(hasher);
+                    }
+                    Number::Unsigned(u) => (u as f64).to_bits().hash(hasher),
+                    Number::Float(n) => (n + 0.0).to_bits().hash(hasher),
+                }
+            }
+            NodeType::Boolean => {
+                Kind::Boolean.hash(hasher);
+                self.boolean_value(node).hash(hasher);
+            }
+            NodeType::Null => Kind::Null.hash(hasher),
+            NodeType::Field(_) => unreachable!("Fields should be resolved to their value"),
+        }
+    }
+
+    // the fields of an object as names and values, sorted by name; fields
+    // with the same name stay in document order
+    fn sorted_fields(&self, object: Node) -> Vec<(&str, Node)> {
+        let mut fields = self
+            .children(object)
+            .map(|field| {
+                let NodeType::Field(name) = self.node_type(field) else {
+                    unreachable!("Object children should be fields");
+                };
+                let value = self.first_child(field).expect("Field should have a value");
+                (name.as_str(), value)
+            })
+            .collect::<Vec<_>>();
+        fields.sort_by_key(|&(name, _)| name);
+        fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::{BitpackingUsageBuilder, UsageBuilder};
+
+    #[test]
+    fn test_subtree_eq() {
+        let doc = BitpackingUsageBuilder::parse(
+            r#"[{"a": [1, "x", null], "b": {"c": true}},
+                {"b": {"c": true}, "a": [1.0, "x", null]},
+                {"a": [1, "x", null], "b": {"c": false}},
+                {"a": [1, "x"], "b": {"c": true}},
+                {"a": [1, "x", null]},
+                [],
+                {},
+                {"d": 1, "d": 2},
+                {"d": 2, "d": 1}]"#
+                .as_bytes(),
+        )
+        .unwrap();
+        let items = doc.children(doc.root()).collect::<Vec<_>>();
+        for (i, &a) in items.iter().enumerate() {
+            for (j, &b) in items.iter().enumerate() {
+                let equal = i == j || (i < 2 && j < 2);
+                assert_eq!(doc.subtree_eq(a, b), equal, "{i} {j}");
+                if equal {
+                    assert_eq!(doc.subtree_hash(a), doc.subtree_hash(b));
+                }
+            }
+        }
+        assert_ne!(doc.subtree_hash(items[0]), doc.subtree_hash(items[2]));
+        assert_ne!(doc.subtree_hash(items[5]), doc.subtree_hash(items[6]));
+        // a field compares as its value
+        let field = doc.first_child(items[0]).unwrap();
+        let value = doc.first_child(field).unwrap();
+        assert!(doc.subtree_eq(field, value));
+        assert_eq!(doc.subtree_hash(field), doc.subtree_hash(value));
+    }
+
+    #[test]
+    fn test_subtree_hash_across_documents() {
+        let a = BitpackingUsageBuilder::parse(r#"{"x": [1, {"y": "z"}], "n": -0.0}"#.as_bytes())
+            .unwrap();
+        let b =
+            BitpackingUsageBuilder::parse(r#"[true, {"n": 0, "x": [1.0, {"y": "z"}]}]"#.as_bytes())
+                .unwrap();
+        let nested = b.nth_child(b.root(), 1).unwrap();
+        assert_eq!(a.subtree_hash(a.root()), b.subtree_hash(nested));
+        assert_ne!(a.subtree_hash(a.root()), b.subtree_hash(b.root()));
+    }
+}
@@ -0,0 +1,408 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use crate::{
+    info::{ARRAY_OPEN_ID, NodeInfoId, NodeType, OBJECT_OPEN_ID},
+    lookup::NodeLookup,
+    pointer,
+    tree_builder::CHILD_SKIP_INTERVAL,
+    usage::UsageIndex,
+};
+
+use super::{Document, Node};
+
+/// A JSON Pointer whose field names are resolved to node info ids once,
+/// to be looked up many times.
+///
+/// Created by [`Document::compile_pointer`] or
+/// [`Corpus::compile_pointer`](crate::Corpus::compile_pointer), and looked
+/// up with [`Document::get_compiled`]. Looking it up compares the ids of
+/// fields rather than their names. It can be used with any document: with
+/// documents that share the node lookup it was compiled against, such as
+/// the documents of a corpus, the ids are used as they are; for others
+/// they are checked against the names first.
+#[derive(Debug, Clone)]
+pub struct CompiledPointer {
+    tokens: Vec<Token>,
+    node_lookup: Arc<NodeLookup>,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    name: String,
+    // the node info of the field name, if the lookup has it
+    field: Option<NodeInfoId>,
+    index: Option<usize>,
+}
+
+impl CompiledPointer {
+    /// Compile a pointer against a node lookup; `None` if the pointer is
+    /// not valid.
+    pub fn new(pointer: &str, node_lookup: &Arc<NodeLookup>) -> Option<Self> {
+        let tokens = pointer::parse(pointer)?
+            .into_iter()
+            .map(|name| Token {
+                field: node_lookup.by_field_name(&name),
+                index: pointer::array_index(&name),
+                name,
+            })
+            .collect();
+        Some(Self {
+            tokens,
+            node_lookup: node_lookup.clone(),
+        })
+    }
+
+    // the node info of the field name of a token in the lookup of another
+    // document; a lookup that extends the one compiled against gives the
+    // same id
+    fn field_in(&self, token: &Token, node_lookup: &NodeLookup) -> Option<NodeInfoId> {
+        if let Some(field) = token.field
+            && matches!(
+                node_lookup.get(field).map(|info| info.node_type()),
+                Some(NodeType::Field(name)) if *name == token.name
+            )
+        {
+            return Some(field);
+        }
+        node_lookup.by_field_name(&token.name)
+    }
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// Compile a JSON Pointer against the node lookup of this document;
+    /// `None` if the pointer is not valid.
+    pub fn compile_pointer(&self, pointer: &str) -> Option<CompiledPointer> {
+        CompiledPointer::new(pointer, self.node_lookup())
+    }
+
+    /// The node at a compiled pointer, relative to `node` like
+    /// [`Document::get_path`].
+    pub fn get_compiled(&self, node: Node, pointer: &CompiledPointer) -> Option<Node> {
+        let node_lookup = self.node_lookup();
+        let shared = Arc::ptr_eq(node_lookup, &pointer.node_lookup);
+        let mut current = self.field_value(node);
+        for token in &pointer.tokens {
+            let node_info_id = self.structure.node_info_id(current.get());
+            current = if node_info_id == OBJECT_OPEN_ID {
+                let field = if shared {
+                    token.field?
+                } else {
+                    pointer.field_in(token, node_lookup)?
+                };
+                self.field_by_id(current, field)
+                    .and_then(|field| self.first_child(field))?
+            } else if node_info_id == ARRAY_OPEN_ID {
+                self.nth_child(current, token.index?)?
+            } else {
+                return None;
+            };
+        }
+        Some(current)
+    }
+}
+
+/// How a step of a compiled pointer finds its node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LookupStrategy {
+    /// The field is looked up in the key index of a large object.
+    KeyIndex,
+    /// The fields of the object are compared one by one.
+    ScanFields,
+    /// The element is reached from the nearest recorded child of the array.
+    ArrayIndex,
+    /// The step cannot match: the node is not a container, the field name
+    /// is not in the document, or the token is not an array index.
+    NoMatch,
+}
+
+impl fmt::Display for LookupStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LookupStrategy::KeyIndex => "key index",
+            LookupStrategy::ScanFields => "scan fields",
+            LookupStrategy::ArrayIndex => "array index",
+            LookupStrategy::NoMatch => "no match",
+        })
+    }
+}
+
+/// One step of an [`Explain`], for one token of the pointer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExplainStep {
+    /// The unescaped reference token.
+    pub token: String,
+    pub strategy: LookupStrategy,
+    /// For a field, the number of fields with its name in the whole
+    /// document, from the rank statistics of the usage index; an upper
+    /// bound on the fields the step could match.
+    pub candidates: Option<usize>,
+    /// The number of nodes the step looked at.
+    pub visits: usize,
+}
+
+/// What looking up a compiled pointer did, from
+/// [`CompiledPointer::explain`].
+///
+/// It displays as one line per step, such as
+/// `/items: key index, 12 candidates, 1 visit`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explain {
+    /// The steps taken, up to the first that found nothing.
+    pub steps: Vec<ExplainStep>,
+    /// The node found, as [`Document::get_compiled`] gives it.
+    pub found: Option<Node>,
+    /// How long the lookup took; `None` on targets without a clock, such
+    /// as `wasm32-unknown-unknown`.
+    pub elapsed: Option<Duration>,
+}
+
+impl Explain {
+    /// The number of nodes looked at in all steps.
+    pub fn visits(&self) -> usize {
+        self.steps.iter().map(|step| step.visits).sum()
+    }
+}
+
+impl fmt::Display for Explain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            write!(f, "/{}: {}", pointer::escape(&step.token), step.strategy)?;
+            if let Some(candidates) = step.candidates {
+                write!(f, ", {candidates} candidate{}", plural(candidates))?;
+            }
+            writeln!(f, ", {} visit{}", step.visits, plural(step.visits))?;
+        }
+        write!(
+            f,
+            "{}",
+            if self.found.is_some() {
+                "found"
+            } else {
+                "not found"
+            }
+        )?;
+        if let Some(elapsed) = self.elapsed {
+            write!(f, " in {elapsed:?}")?;
+        }
+        Ok(())
+    }
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 { "" } else { "s" }
+}
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+fn timed<T>(f: impl FnOnce() -> T) -> (T, Option<Duration>) {
+    let start = std::time::Instant::now();
+    let result = f();
+    (result, Some(start.elapsed()))
+}
+
+// there is no clock to read without JavaScript
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+fn timed<T>(f: impl FnOnce() -> T) -> (T, Option<Duration>) {
+    (f(), None)
+}
+
+impl CompiledPointer {
+    /// Look the pointer up in `document` relative to `node`, like
+    /// [`Document::get_compiled`], and report the strategy of every step,
+    /// the number of candidates the rank statistics give for it and the
+    /// nodes it actually looked at, as well as the time taken.
+    ///
+    /// The lookup is instrumented, so it is slower than
+    /// [`Document::get_compiled`]; the time is that of the instrumented
+    /// lookup.
+    pub fn explain<U: UsageIndex>(&self, document: &Document<U>, node: Node) -> Explain {
+        let mut steps = Vec::with_capacity(self.tokens.len());
+        let (found, elapsed) = timed(|| self.explain_steps(document, node, &mut steps));
+        Explain {
+            steps,
+            found,
+            elapsed,
+        }
+    }
+
+    fn explain_steps<U: UsageIndex>(
+        &self,
+        document: &Document<U>,
+        node: Node,
+        steps: &mut Vec<ExplainStep>,
+    ) -> Option<Node> {
+        let node_lookup = document.node_lookup();
+        let shared = Arc::ptr_eq(node_lookup, &self.node_lookup);
+        let structure = &document.structure;
+        let mut current = document.field_value(node);
+        for token in &self.tokens {
+            let mut step = ExplainStep {
+                token: token.name.clone(),
+                strategy: LookupStrategy::NoMatch,
+                candidates: None,
+                visits: 0,
+            };
+            let node_info_id = structure.node_info_id(current.get());
+            let next = if node_info_id == OBJECT_OPEN_ID {
+                let field = if shared {
+                    token.field
+                } else {
+                    self.field_in(token, node_lookup)
+                };
+                field.and_then(|field| {
+                    step.candidates = structure.usage_index().rank(structure.len(), field);
+                    let found = if document.uses_key_index(current) {
+                        step.strategy = LookupStrategy::KeyIndex;
+                        step.visits = 1;
+                        document.field_by_id(current, field)
+                    } else {
+                        step.strategy = LookupStrategy::ScanFields;
+                        document.children(current).find(|child| {
+                            step.visits += 1;
+                            structure.node_info_id(child.get()) == field
+                        })
+                    };
+                    found.and_then(|field| document.first_child(field))
+                })
+            } else if node_info_id == ARRAY_OPEN_ID {
+                token.index.and_then(|i| {
+                    step.strategy = LookupStrategy::ArrayIndex;
+                    let found = document.nth_child(current, i);
+                    // the children from the nearest one recorded up to the
+                    // element, or as far as there are
+                    let skipped = i / CHILD_SKIP_INTERVAL * CHILD_SKIP_INTERVAL;
+                    step.visits = match found {
+                        Some(_) => i - skipped + 1,
+                        None => document.child_count(current).saturating_sub(skipped),
+                    };
+                    found
+                })
+            } else {
+                None
+            };
+            steps.push(step);
+            current = next?;
+        }
+        Some(current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Corpus, Value,
+        usage::{BitpackingUsageBuilder, EliasFanoUsageIndex, UsageBuilder},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_get_compiled() {
+        let doc =
+            BitpackingUsageBuilder::parse(r#"{"a": [{"b": 1}, {"c/d": 2}], "e": 3}"#.as_bytes())
+                .unwrap();
+        let b = doc.compile_pointer("/a/0/b").unwrap();
+        let node = doc.get_compiled(doc.root(), &b).unwrap();
+        assert_eq!(doc.value(node), Value::Integer(1));
+        let escaped = doc.compile_pointer("/a/1/c~1d").unwrap();
+        let node = doc.get_compiled(doc.root(), &escaped).unwrap();
+        assert_eq!(doc.value(node), Value::Integer(2));
+        let root = doc.compile_pointer("").unwrap();
+        assert_eq!(doc.get_compiled(doc.root(), &root), Some(doc.root()));
+        for missing in ["/a/2", "/x", "/e/0", "/a/b"] {
+            let pointer = doc.compile_pointer(missing).unwrap();
+            assert_eq!(doc.get_compiled(doc.root(), &pointer), None, "{missing}");
+        }
+        assert!(doc.compile_pointer("a").is_none());
+
+        // another document gives the same names other ids
+        let other =
+            BitpackingUsageBuilder::parse(r#"{"x": 0, "b": 5, "e": 6}"#.as_bytes()).unwrap();
+        let e = doc.compile_pointer("/e").unwrap();
+        let node = other.get_compiled(other.root(), &e).unwrap();
+        assert_eq!(other.value(node), Value::Integer(6));
+        let x = doc.compile_pointer("/x").unwrap();
+        assert!(other.get_compiled(other.root(), &x).is_some());
+    }
+
+    #[test]
+    fn test_get_compiled_corpus() {
+        let mut corpus = Corpus::<EliasFanoUsageIndex>::new();
+        let first = corpus
+            .parse::<BitpackingUsageBuilder, _>(r#"{"id": 1}"#.as_bytes())
+            .unwrap();
+        let id = corpus.compile_pointer("/id").unwrap();
+        let second = corpus
+            .parse::<BitpackingUsageBuilder, _>(r#"{"name": "b", "id": 2}"#.as_bytes())
+            .unwrap();
+        let ids = [first, second].map(|document_id| {
+            let doc = corpus.get(document_id).unwrap();
+            doc.value(doc.get_compiled(doc.root(), &id).unwrap())
+                .to_owned_value()
+        });
+        assert_eq!(
+            ids,
+            [crate::OwnedValue::Integer(1), crate::OwnedValue::Integer(2)]
+        );
+    }
+
+    #[test]
+    fn test_explain() {
+        let fields = (0..100)
+            .map(|i| format!(r#""id{i}": [{i}]"#))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let items = (0..200)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let json = format!(r#"{{"big": {{{fields}}}, "items": [{items}], "small": {{"id7": 1}}}}"#);
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes())
+            .unwrap()
+            .with_key_index();
+
+        let pointer = doc.compile_pointer("/big/id7/0").unwrap();
+        let explain = pointer.explain(&doc, doc.root());
+        assert_eq!(explain.found, doc.get_compiled(doc.root(), &pointer));
+        assert_eq!(doc.value(explain.found.unwrap()), Value::Integer(7));
+        let strategies = explain
+            .steps
+            .iter()
+            .map(|step| step.strategy)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            strategies,
+            [
+                LookupStrategy::ScanFields,
+                LookupStrategy::KeyIndex,
+                LookupStrategy::ArrayIndex
+            ]
+        );
+        assert_eq!(explain.steps[0].candidates, Some(1));
+        assert_eq!(explain.steps[0].visits, 1);
+        assert_eq!(explain.steps[1].candidates, Some(2));
+        assert_eq!(explain.visits(), 3);
+        assert!(explain.elapsed.is_some());
+        let text = explain.to_string();
+        assert!(text.starts_with("/big: scan fields, 1 candidate, 1 visit\n/id7: key index"));
+        assert!(text.contains("\nfound in "));
+
+        let items = doc.compile_pointer("/items/130").unwrap();
+        let explain = items.explain(&doc, doc.root());
+        assert_eq!(explain.steps[0].visits, 2);
+        assert_eq!(explain.steps[1].visits, 3);
+        let past = doc.compile_pointer("/items/300").unwrap();
+        assert_eq!(past.explain(&doc, doc.root()).steps[1].visits, 0);
+
+        let missing = doc.compile_pointer("/small/id8/0").unwrap();
+        let explain = missing.explain(&doc, doc.root());
+        assert_eq!(explain.found, None);
+        assert_eq!(explain.steps.len(), 2);
+        assert_eq!(explain.steps[1].strategy, LookupStrategy::ScanFields);
+        assert_eq!(explain.steps[1].visits, 1);
+        let explain = doc
+            .compile_pointer("/small/x")
+            .unwrap()
+            .explain(&doc, doc.root());
+        assert_eq!(explain.steps[1].strategy, LookupStrategy::NoMatch);
+    }
+}
@@ -1,16 +1,30 @@
-use std::io::Read;
+use std::{io::Read, ops::Range, sync::Arc};
 
 use vers_vecs::BitVec;
 
 use crate::{
-    info::NodeType,
-    parser::{JsonParseError, parse},
+    error::Error,
+    info::{NodeInfoId, NodeType},
+    lookup::NodeLookup,
+    memory_report::MemoryReport,
+    numbers::Numbers,
+    options::ParseOptions,
+    parse_error::{JsonParseError, NdjsonReport},
+    parser::{Documents, parse, parse_ndjson_lenient, parse_ndjson_with, parse_with},
+    source_spans::SourceSpans,
     structure::Structure,
-    text::TextUsage,
+    text::{
+        Normalization, TextUsage, TextValidation, equality_index::EqualityIndex, fm_index::FmIndex,
+        text_fst::TextFst,
+    },
     usage::{UsageBuilder, UsageIndex},
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+use super::key_index::KeyIndex;
+
+/// A node in a document. Nodes of the same document are ordered in
+/// document order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Node(usize);
 
 impl Node {
@@ -27,15 +41,35 @@ impl Node {
 pub struct Document<U: UsageIndex> {
     pub(crate) structure: Structure<U>,
     pub(crate) text_usage: TextUsage,
-    pub(crate) numbers: Vec<f64>,
+    pub(crate) numbers: Numbers,
     pub(crate) booleans: BitVec,
+    // the text of every number, empty where the value serializes the same,
+    // if the document keeps number lexemes
+    pub(crate) number_lexemes: Option<TextUsage>,
+    // where every node is in the input, if the document records it
+    pub(crate) source_spans: Option<SourceSpans>,
+    // built on request, to find strings by substring
+    pub(crate) substring_index: Option<FmIndex>,
+    // the distinct strings, forward and with their bytes reversed, built on
+    // request to find strings by prefix, suffix or regex
+    pub(crate) text_fst: Option<TextFst>,
+    pub(crate) reversed_text_fst: Option<TextFst>,
+    // built on request, to find strings by their whole value
+    pub(crate) equality_index: Option<EqualityIndex>,
+    // how strings are normalized for indexes built from now on and for
+    // searches without an index
+    pub(crate) search_normalization: Normalization,
+    // built on request, to look up fields of large objects by name
+    pub(crate) key_index: Option<KeyIndex>,
+    #[cfg(feature = "memory-tracking")]
+    pub(crate) build_memory: Option<crate::memory::BuildMemory>,
 }
 
 impl<U: UsageIndex> Document<U> {
     pub(crate) fn new(
         structure: Structure<U>,
         text_usage: TextUsage,
-        numbers: Vec<f64>,
+        numbers: Numbers,
         booleans: BitVec,
     ) -> Self {
         Self {
@@ -43,14 +77,132 @@ impl<U: UsageIndex> Document<U> {
             text_usage,
             numbers,
             booleans,
+            number_lexemes: None,
+            source_spans: None,
+            substring_index: None,
+            text_fst: None,
+            reversed_text_fst: None,
+            equality_index: None,
+            search_normalization: Normalization::default(),
+            key_index: None,
+            #[cfg(feature = "memory-tracking")]
+            build_memory: None,
+        }
+    }
+
+    /// The peak heap usage during each phase of building this document, if
+    /// it was built with [`TrackingAllocator`](crate::memory::TrackingAllocator)
+    /// installed.
+    #[cfg(feature = "memory-tracking")]
+    pub fn build_memory(&self) -> Option<&crate::memory::BuildMemory> {
+        self.build_memory.as_ref()
+    }
+
+    /// Set how stored text is checked when it is decompressed.
+    ///
+    /// Documents built in this process can skip the checks. The text of
+    /// loaded documents is always checked, whatever is set here.
+    pub fn with_text_validation(mut self, validation: TextValidation) -> Self {
+        self.text_usage.set_validation(validation);
+        if let Some(lexemes) = &mut self.number_lexemes {
+            lexemes.set_validation(validation);
+        }
+        self
+    }
+
+    /// The node types and field names of the document; parse another
+    /// document with it through
+    /// [`ParseOptions::node_lookup`](crate::ParseOptions::node_lookup) to
+    /// give the same field names the same ids.
+    pub fn node_lookup(&self) -> &Arc<NodeLookup> {
+        self.structure.usage_index().shared_node_lookup()
+    }
+
+    /// Find the node type of a node without going through the usage of
+    /// every node type and field name, for documents with many distinct
+    /// field names.
+    ///
+    /// This keeps an id for every position in the structure, two per node,
+    /// in as many bits as the largest id needs: with a thousand field names
+    /// that is 22 bits per node, far more than the usage itself takes. The
+    /// ids are not saved with the document.
+    pub fn with_node_info_tags(mut self) -> Self {
+        self.structure.usage_index_mut().build_node_info_tags();
+        self
+    }
+
+    /// The id of the node type of a node, or of its name for a field.
+    /// Opening ids are given, so a field has the id that
+    /// [`NodeLookup::by_field_name`] gives for its name.
+    pub fn node_info_id(&self, node: Node) -> NodeInfoId {
+        self.structure.node_info_id(node.get())
+    }
+
+    /// The byte range of a node in the input it was parsed from, if the
+    /// document records source spans. A field spans from its name to the
+    /// end of its value.
+    ///
+    /// This is `None` for documents that are edited or built in another way
+    /// than parsing.
+    /// See
+    /// [`ParseOptions::source_spans`](crate::ParseOptions::source_spans).
+    pub fn source_span(&self, node: Node) -> Option<Range<u64>> {
+        let spans = self.source_spans.as_ref()?;
+        let position = node.get();
+        if position >= self.structure.len() || !self.structure.is_open(position) {
+            return None;
         }
+        spans.span(position, self.structure.tree().close(position)?)
     }
 
     pub fn heap_size(&self) -> usize {
         self.structure.heap_size()
             + self.text_usage.heap_size()
-            + self.numbers.len() * std::mem::size_of::<f64>()
+            + self.numbers.heap_size()
             + self.booleans.heap_size()
+            + self.number_lexemes.as_ref().map_or(0, TextUsage::heap_size)
+            + self.source_spans.as_ref().map_or(0, SourceSpans::heap_size)
+            + self.substring_index.as_ref().map_or(0, FmIndex::heap_size)
+            + self.text_fst.as_ref().map_or(0, TextFst::heap_size)
+            + self
+                .reversed_text_fst
+                .as_ref()
+                .map_or(0, TextFst::heap_size)
+            + self
+                .equality_index
+                .as_ref()
+                .map_or(0, EqualityIndex::heap_size)
+            + self.key_index.as_ref().map_or(0, KeyIndex::heap_size)
+    }
+
+    /// The heap used by each part of the document. Unlike
+    /// [`Document::heap_size`] this includes the caches of decompressed
+    /// text, which grow as text is read.
+    pub fn memory_report(&self) -> MemoryReport {
+        MemoryReport {
+            text_blocks: self.text_usage.heap_size(),
+            numbers: self.numbers.heap_size(),
+            number_lexemes: self.number_lexemes.as_ref().map_or(0, TextUsage::heap_size),
+            booleans: self.booleans.heap_size(),
+            source_spans: self.source_spans.as_ref().map_or(0, SourceSpans::heap_size),
+            indexes: self.substring_index.as_ref().map_or(0, FmIndex::heap_size)
+                + self.text_fst.as_ref().map_or(0, TextFst::heap_size)
+                + self
+                    .reversed_text_fst
+                    .as_ref()
+                    .map_or(0, TextFst::heap_size)
+                + self
+                    .equality_index
+                    .as_ref()
+                    .map_or(0, EqualityIndex::heap_size)
+                + self.key_index.as_ref().map_or(0, KeyIndex::heap_size),
+            caches: self.text_usage.cache_heap_size()
+                + self
+                    .number_lexemes
+                    .as_ref()
+                    .map_or(0, TextUsage::cache_heap_size),
+            ..self.structure.memory_report()
+        }
     }
 
     pub fn parse<B: UsageBuilder<Index = U>, R: Read>(
@@ -59,8 +211,113 @@ impl<U: UsageIndex> Document<U> {
         parse::<R, B>(json)
     }
 
-    pub(crate) fn node_type(&self, node: Node) -> &NodeType {
+    /// Parse a document, using the options to control what ends up in it.
+    pub fn parse_with<B: UsageBuilder<Index = U>, R: Read>(
+        json: R,
+        options: &ParseOptions,
+    ) -> Result<Document<B::Index>, JsonParseError> {
+        parse_with::<R, B>(json, options)
+    }
+
+    /// Parse newline-delimited JSON (JSON Lines) into a document whose root
+    /// is an array of the records. Concatenated JSON values are accepted
+    /// too, with any whitespace or none between them. Record filters with
+    /// the path `""` select records.
+    pub fn parse_ndjson_with<B: UsageBuilder<Index = U>, R: Read>(
+        json: R,
+        options: &ParseOptions,
+    ) -> Result<Document<B::Index>, JsonParseError> {
+        parse_ndjson_with::<R, B>(json, options)
+    }
+
+    /// Parse newline-delimited JSON like [`Document::parse_ndjson_with`],
+    /// but skip the lines that are not valid JSON instead of failing, and
+    /// report them. Real log files tend to have a few broken lines.
+    ///
+    /// Each line is parsed on its own, so a value can't span lines.
+    /// Exceeding a limit set in the options, cancelling and failing to
+    /// read the input still fail the whole parse.
+    pub fn parse_ndjson_lenient<B: UsageBuilder<Index = U>, R: Read>(
+        json: R,
+        options: &ParseOptions,
+    ) -> Result<(Document<B::Index>, NdjsonReport), JsonParseError> {
+        parse_ndjson_lenient::<R, B>(json, options)
+    }
+
+    /// Parse concatenated JSON values, such as `{"a": 1} {"a": 2}`, into a
+    /// separate document for each value. The documents are parsed as the
+    /// iterator advances. Use [`Document::parse_ndjson_with`] to collect
+    /// the values into a single document instead.
+    pub fn parse_stream_with<B: UsageBuilder<Index = U>, R: Read>(
+        json: R,
+        options: &ParseOptions,
+    ) -> Documents<'_, R, B> {
+        Documents::new(json, options)
+    }
+
+    /// Parse a document from an asynchronous reader, without blocking the
+    /// runtime.
+    #[cfg(feature = "tokio")]
+    pub async fn parse_async<B, R>(json: R) -> Result<Document<B::Index>, JsonParseError>
+    where
+        B: UsageBuilder<Index = U> + Send + 'static,
+        U: Send + 'static,
+        R: tokio::io::AsyncRead + Unpin,
+    {
+        Self::parse_async_with::<B, R>(json, &ParseOptions::default()).await
+    }
+
+    /// Parse a document from an asynchronous reader like
+    /// [`Document::parse_with`]. The parser runs on the blocking thread pool
+    /// of the tokio runtime, while the input is read on the current task.
+    #[cfg(feature = "tokio")]
+    pub async fn parse_async_with<B, R>(
+        json: R,
+        options: &ParseOptions,
+    ) -> Result<Document<B::Index>, JsonParseError>
+    where
+        B: UsageBuilder<Index = U> + Send + 'static,
+        U: Send + 'static,
+        R: tokio::io::AsyncRead + Unpin,
+    {
+        crate::parser::parse_async_with::<R, B>(json, options).await
+    }
+
+    /// Parse a document like [`Document::parse_with`], using multiple
+    /// threads if the root is an array.
+    ///
+    /// The items of the root array are split into segments of about
+    /// `segment_size` bytes, which are parsed in parallel and then joined.
+    /// Inputs with other roots, and JSONC, are parsed on the current
+    /// thread. With [`ParseOptions::on_progress`] no progress is reported.
+    /// If parsing fails, the input is parsed again on the current thread to
+    /// report the error at its position in the input.
+    #[cfg(feature = "parallel")]
+    pub fn parse_parallel_with<B: UsageBuilder<Index = U> + Send>(
+        json: &[u8],
+        options: &ParseOptions,
+        segment_size: usize,
+    ) -> Result<Document<B::Index>, JsonParseError> {
+        crate::parser::parse_parallel_with::<B>(json, options, segment_size)
+    }
+
+    /// The type of a node. Object fields are nodes of type
+    /// [`NodeType::Field`], holding the field name.
+    ///
+    /// # Panics
+    ///
+    /// If the node is not in the document; see [`Document::try_node_type`].
+    pub fn node_type(&self, node: Node) -> &NodeType {
         let node_info = self.structure.node_info(node.get());
         node_info.node_type()
     }
+
+    /// The type of a node, or [`Error::NodeNotFound`] if the node is not
+    /// in the document, such as a node of another document.
+    pub fn try_node_type(&self, node: Node) -> Result<&NodeType, Error> {
+        match self.structure.try_node_info(node.get()) {
+            Some(node_info) if node_info.is_open_tag => Ok(node_info.node_type()),
+            _ => Err(Error::NodeNotFound(node)),
+        }
+    }
 }
@@ -0,0 +1,351 @@
+use std::fmt;
+
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+
+use crate::{info::NodeType, usage::UsageIndex};
+
+use super::{Children, Document, Node, Value};
+
+/// A node that doesn't hold what the type it is deserialized into expects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeserializeError {
+    message: String,
+}
+
+impl DeserializeError {
+    /// What went wrong, as reported by the type being deserialized.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+// serde requires errors to display and to be errors
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for DeserializeError {}
+
+impl de::Error for DeserializeError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        Self {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// Deserialize the value at a node into a type that implements
+    /// [`serde::Deserialize`], such as a struct of a record, without
+    /// converting the node to JSON or an [`OwnedValue`](crate::OwnedValue)
+    /// first.
+    ///
+    /// Strings are copied out of the document, so the type can't borrow
+    /// from it. An object deserializes into a map or struct, an array into
+    /// a sequence or tuple, and an enum from a string for a unit variant or
+    /// an object with a single field for the other variants.
+    pub fn deserialize<T: DeserializeOwned>(&self, node: Node) -> Result<T, DeserializeError> {
+        T::deserialize(NodeDeserializer {
+            document: self,
+            node,
+        })
+    }
+}
+
+struct NodeDeserializer<'a, U: UsageIndex> {
+    document: &'a Document<U>,
+    node: Node,
+}
+
+impl<'a, U: UsageIndex> NodeDeserializer<'a, U> {
+    fn new(document: &'a Document<U>, node: Node) -> Self {
+        Self { document, node }
+    }
+
+    // the value of a field, or the node itself
+    fn value_node(&self) -> Node {
+        match self.document.node_type(self.node) {
+            NodeType::Field(_) => self
+                .document
+                .first_child(self.node)
+                .expect("Field should have a value"),
+            _ => self.node,
+        }
+    }
+}
+
+impl<'de, U: UsageIndex> de::Deserializer<'de> for NodeDeserializer<'_, U> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let node = self.value_node();
+        let document = self.document;
+        match document.node_type(node) {
+            NodeType::Object => visitor.visit_map(Fields {
+                document,
+                fields: document.children(node),
+                value: None,
+            }),
+            NodeType::Array => visitor.visit_seq(Items {
+                document,
+                items: document.children(node),
+            }),
+            NodeType::String => {
+                let text = document.as_str(node).expect("Node should be a string");
+                visitor.visit_str(&text)
+            }
+            NodeType::Number => match document.value(node) {
+                Value::Integer(integer) => visitor.visit_i64(integer),
+                Value::Number(float) => visitor.visit_f64(float),
+                _ => unreachable!("Number nodes should have a number value"),
+            },
+            NodeType::Boolean => {
+                visitor.visit_bool(document.as_bool(node).expect("Node should be a boolean"))
+            }
+            NodeType::Null => visitor.visit_unit(),
+            NodeType::Field(_) => unreachable!("Fields should be resolved to their value"),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.document.is_null(self.value_node()) {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        let node = self.value_node();
+        let document = self.document;
+        match document.node_type(node) {
+            NodeType::String => {
+                let text = document.as_str(node).expect("Node should be a string");
+                visitor.visit_enum(text.to_string().into_deserializer())
+            }
+            NodeType::Object => {
+                let mut fields = document.children(node);
+                match (fields.next(), fields.next()) {
+                    (Some(field), None) => visitor.visit_enum(Variant { document, field }),
+                    _ => Err(de::Error::invalid_length(
+                        document.children(node).count(),
+                        &"an object with a single field",
+                    )),
+                }
+            }
+            _ => Err(de::Error::invalid_type(
+                de::Unexpected::Other("a value that is not a string or object"),
+                &"an enum",
+            )),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+/// The items of an array.
+struct Items<'a, U: UsageIndex> {
+    document: &'a Document<U>,
+    items: Children<'a, U>,
+}
+
+impl<'de, U: UsageIndex> SeqAccess<'de> for Items<'_, U> {
+    type Error = DeserializeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        self.items
+            .next()
+            .map(|item| seed.deserialize(NodeDeserializer::new(self.document, item)))
+            .transpose()
+    }
+}
+
+/// The fields of an object.
+struct Fields<'a, U: UsageIndex> {
+    document: &'a Document<U>,
+    fields: Children<'a, U>,
+    // the field whose name was deserialized last
+    value: Option<Node>,
+}
+
+impl<'de, U: UsageIndex> MapAccess<'de> for Fields<'_, U> {
+    type Error = DeserializeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        let Some(field) = self.fields.next() else {
+            return Ok(None);
+        };
+        self.value = Some(field);
+        let NodeType::Field(name) = self.document.node_type(field) else {
+            unreachable!("Object children should be fields");
+        };
+        seed.deserialize(name.as_str().into_deserializer())
+            .map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let field = self.value.take().expect("A value should follow its key");
+        seed.deserialize(NodeDeserializer::new(self.document, field))
+    }
+}
+
+/// An enum variant written as an object with a single field.
+struct Variant<'a, U: UsageIndex> {
+    document: &'a Document<U>,
+    field: Node,
+}
+
+impl<'a, 'de, U: UsageIndex> EnumAccess<'de> for Variant<'a, U> {
+    type Error = DeserializeError;
+    type Variant = NodeDeserializer<'a, U>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Self::Error> {
+        let NodeType::Field(name) = self.document.node_type(self.field) else {
+            unreachable!("Object children should be fields");
+        };
+        let variant = seed.deserialize(name.as_str().into_deserializer())?;
+        Ok((variant, NodeDeserializer::new(self.document, self.field)))
+    }
+}
+
+impl<'de, U: UsageIndex> VariantAccess<'de> for NodeDeserializer<'_, U> {
+    type Error = DeserializeError;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        de::Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::Deserialize;
+
+    use crate::usage::{BitpackingUsageBuilder, UsageBuilder};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum Shape {
+        Point,
+        Circle(f64),
+        Rect { w: u32, h: u32 },
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Record {
+        id: u64,
+        name: String,
+        tags: Vec<String>,
+        score: Option<f64>,
+        active: bool,
+        shapes: Vec<Shape>,
+        extra: HashMap<String, i32>,
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let doc = BitpackingUsageBuilder::parse(
+            r#"[{"id": 7, "name": "seven", "tags": ["a", "b"], "score": null,
+                 "active": true, "ignored": [1, {"x": 2}],
+                 "shapes": ["Point", {"Circle": 1.5}, {"Rect": {"w": 2, "h": 3}}],
+                 "extra": {"k": -1}}]"#
+                .as_bytes(),
+        )
+        .unwrap();
+        let records: Vec<Record> = doc.deserialize(doc.root()).unwrap();
+        assert_eq!(
+            records,
+            vec![Record {
+                id: 7,
+                name: "seven".to_string(),
+                tags: vec!["a".to_string(), "b".to_string()],
+                score: None,
+                active: true,
+                shapes: vec![Shape::Point, Shape::Circle(1.5), Shape::Rect { w: 2, h: 3 }],
+                extra: HashMap::from([("k".to_string(), -1)]),
+            }]
+        );
+        // a sub-record
+        let record = doc.first_child(doc.root()).unwrap();
+        let name = doc
+            .first_child(record)
+            .and_then(|id| doc.next_sibling(id))
+            .unwrap();
+        assert_eq!(doc.deserialize::<String>(name).unwrap(), "seven");
+    }
+
+    #[test]
+    fn test_deserialize_errors() {
+        let doc = BitpackingUsageBuilder::parse(r#"{"id": -1, "name": 3}"#.as_bytes()).unwrap();
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Named {
+            name: String,
+        }
+        let error = doc.deserialize::<Named>(doc.root()).unwrap_err();
+        assert!(
+            error.message().contains("invalid type"),
+            "{}",
+            error.message()
+        );
+        assert!(doc.deserialize::<HashMap<String, u8>>(doc.root()).is_err());
+        assert!(doc.deserialize::<Shape>(doc.root()).is_err());
+    }
+}
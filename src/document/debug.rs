@@ -0,0 +1,109 @@
+use std::fmt::Write;
+
+use crate::{info::NodeType, usage::UsageIndex};
+
+use super::{Document, Node};
+
+impl<U: UsageIndex> Document<U> {
+    /// Render the parentheses sequence with its node information.
+    ///
+    /// Every position is rendered on its own line with the position, the
+    /// parenthesis, the node info id and the node type, indented by depth.
+    /// Opening scalar positions also show the id into their value storage.
+    /// If a node is given, only its subtree is rendered.
+    ///
+    /// This is meant for debugging; the output format is not stable.
+    pub fn debug_dump(&self, node: Option<Node>) -> String {
+        if self.structure.len() == 0 {
+            return String::new();
+        }
+        let (start, end) = match node {
+            Some(node) => (
+                node.get(),
+                self.structure
+                    .tree()
+                    .close(node.get())
+                    .expect("Node should be closed"),
+            ),
+            None => (0, self.structure.len().saturating_sub(1)),
+        };
+        let width = end.to_string().len();
+        let mut depth = 0usize;
+        let mut out = String::new();
+        for position in start..=end {
+            let is_open = self.structure.is_open(position);
+            if !is_open {
+                depth = depth.saturating_sub(1);
+            }
+            let paren = if is_open { '(' } else { ')' };
+            let indent = "  ".repeat(depth);
+            match self.structure.usage_index().node_info_id(position) {
+                Some(node_info_id) => {
+                    let node_info = self.structure.lookup_node_info(node_info_id);
+                    let _ = write!(
+                        out,
+                        "{position:>width$} {paren} {indent}{:?} #{}",
+                        node_info.node_type,
+                        node_info_id.id()
+                    );
+                    if node_info.is_open_tag != is_open {
+                        out.push_str(" (tag mismatch)");
+                    }
+                    if is_open {
+                        let id = match node_info.node_type {
+                            NodeType::String => self.structure.text_id(position),
+                            NodeType::Number => self.structure.number_id(position),
+                            NodeType::Boolean => self.structure.boolean_id(position),
+                            _ => None,
+                        };
+                        if let Some(id) = id {
+                            let _ = write!(out, " id={id}");
+                        }
+                    }
+                }
+                None => {
+                    let _ = write!(out, "{position:>width$} {paren} {indent}<missing>");
+                }
+            }
+            out.push('\n');
+            if is_open {
+                depth += 1;
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::{BitpackingUsageBuilder, UsageBuilder};
+
+    #[test]
+    fn test_debug_dump() {
+        let doc = BitpackingUsageBuilder::parse(r#"{"a": [1, "x"]}"#.as_bytes()).unwrap();
+        assert_eq!(
+            doc.debug_dump(None),
+            r#"0 ( Object #0
+1 (   Field("a") #12
+2 (     Array #2
+3 (       Number #6 id=0
+4 )       Number #7
+5 (       String #4 id=0
+6 )       String #5
+7 )     Array #3
+8 )   Field("a") #13
+9 ) Object #1
+"#
+        );
+    }
+
+    #[test]
+    fn test_debug_dump_subtree() {
+        let doc = BitpackingUsageBuilder::parse(r#"[true, [null]]"#.as_bytes()).unwrap();
+        let array = doc.next_sibling(doc.first_child(doc.root()).unwrap());
+        assert_eq!(
+            doc.debug_dump(array),
+            "3 ( Array #2\n4 (   Null #10\n5 )   Null #11\n6 ) Array #3\n"
+        );
+    }
+}
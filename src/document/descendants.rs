@@ -0,0 +1,165 @@
+use std::iter::FusedIterator;
+
+use crate::{
+    info::{NodeInfo, NodeInfoId, NodeType},
+    usage::UsageIndex,
+};
+
+use super::{Document, Node};
+
+/// An iterator over the descendants of a node that have a particular node
+/// type, in document order.
+///
+/// Created by [`Document::typed_descendants`].
+pub struct TypedDescendants<'a, U: UsageIndex> {
+    document: &'a Document<U>,
+    node_info_id: NodeInfoId,
+    // the rank of the next match
+    rank: usize,
+    // the rank past the last match in the subtree
+    end_rank: usize,
+    // the positions of the next matches, from `rank` on, in reverse
+    batch: Vec<usize>,
+}
+
+// the matches selected at once
+const BATCH_SIZE: usize = 64;
+
+impl<U: UsageIndex> Document<U> {
+    /// Iterate over the descendants of `node` with the given node type.
+    ///
+    /// This jumps directly from one match to the next using the type index,
+    /// without visiting the nodes in between, so it is fast even if matches
+    /// are rare. `node` itself is not included.
+    pub fn typed_descendants(&self, node: Node, node_type: NodeType) -> TypedDescendants<'_, U> {
+        let usage_index = self.structure.usage_index();
+        // a field name that does not occur has no node info
+        let Some(node_info_id) = usage_index
+            .node_lookup()
+            .by_node_info(&NodeInfo::open(node_type))
+        else {
+            return TypedDescendants {
+                document: self,
+                node_info_id: NodeInfoId::new(0),
+                rank: 0,
+                end_rank: 0,
+                batch: Vec::new(),
+            };
+        };
+        let close = self.primitive_close(node);
+        let ranks = usage_index
+            .rank_many(&[node.get() + 1, close], node_info_id)
+            .expect("Node should be in the document");
+        TypedDescendants {
+            document: self,
+            node_info_id,
+            rank: ranks[0],
+            end_rank: ranks[1],
+            batch: Vec::new(),
+        }
+    }
+}
+
+impl<U: UsageIndex> Iterator for TypedDescendants<'_, U> {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rank >= self.end_rank {
+            return None;
+        }
+        if self.batch.is_empty() {
+            let count = BATCH_SIZE.min(self.end_rank - self.rank);
+            self.batch = self.document.structure.usage_index().select_range(
+                self.rank,
+                count,
+                self.node_info_id,
+            );
+            self.batch.reverse();
+        }
+        let position = self.batch.pop()?;
+        self.rank += 1;
+        Some(Node::new(position))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.end_rank.saturating_sub(self.rank);
+        (len, Some(len))
+    }
+}
+
+impl<U: UsageIndex> ExactSizeIterator for TypedDescendants<'_, U> {}
+
+impl<U: UsageIndex> FusedIterator for TypedDescendants<'_, U> {}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Value,
+        usage::{BitpackingUsageBuilder, UsageBuilder},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_typed_descendants() {
+        let doc = BitpackingUsageBuilder::parse(
+            r#"{"a": [1, "x", {"b": 2}], "c": 3, "d": {"b": "y"}}"#.as_bytes(),
+        )
+        .unwrap();
+        let numbers = doc
+            .typed_descendants(doc.root(), NodeType::Number)
+            .map(|node| doc.value(node))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            numbers,
+            vec![Value::Number(1.0), Value::Number(2.0), Value::Number(3.0)]
+        );
+        assert_eq!(
+            doc.typed_descendants(doc.root(), NodeType::Field("b".to_string()))
+                .len(),
+            2
+        );
+        assert_eq!(
+            doc.typed_descendants(doc.root(), NodeType::Field("z".to_string()))
+                .count(),
+            0
+        );
+        assert_eq!(doc.typed_descendants(doc.root(), NodeType::Null).count(), 0);
+    }
+
+    #[test]
+    fn test_typed_descendants_subtree() {
+        let doc = BitpackingUsageBuilder::parse(r#"[[1, [2]], 3, [4]]"#.as_bytes()).unwrap();
+        let first = doc.first_child(doc.root()).unwrap();
+        let mut numbers = doc.typed_descendants(first, NodeType::Number);
+        assert_eq!(numbers.len(), 2);
+        assert_eq!(
+            numbers.next().map(|node| doc.value(node)),
+            Some(Value::Number(1.0))
+        );
+        assert_eq!(
+            numbers.next().map(|node| doc.value(node)),
+            Some(Value::Number(2.0))
+        );
+        assert_eq!(numbers.next(), None);
+        // the node itself is not included
+        assert_eq!(doc.typed_descendants(first, NodeType::Array).count(), 1);
+    }
+
+    #[test]
+    fn test_typed_descendants_batches() {
+        let json = format!(
+            "[{}]",
+            (0..200)
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+        let numbers = doc
+            .typed_descendants(doc.root(), NodeType::Number)
+            .map(|node| doc.value(node))
+            .collect::<Vec<_>>();
+        assert_eq!(numbers, (0..200).map(Value::Integer).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,357 @@
+use std::iter::FusedIterator;
+
+use crate::{info::NodeType, pointer, usage::UsageIndex};
+
+use super::{Children, Document, Node, compare::numbers_equal};
+
+/// A difference between two documents, at a JSON Pointer that is valid in
+/// the document where the value is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// A field or item only in the new document, at a node of it.
+    Added { path: String, node: Node },
+    /// A field or item only in the old document, at a node of it.
+    Removed { path: String, node: Node },
+    /// A value that differs, at a node of the old document and of the new
+    /// document. Containers of different kinds or scalars that differ are
+    /// changed as a whole.
+    Changed { path: String, old: Node, new: Node },
+}
+
+impl Change {
+    /// The JSON Pointer to the value that changed.
+    pub fn path(&self) -> &str {
+        match self {
+            Change::Added { path, .. } | Change::Removed { path, .. } => path,
+            Change::Changed { path, .. } => path,
+        }
+    }
+}
+
+/// An iterator over the changes from one document to another, in the
+/// document order of the old document; fields only in the new document
+/// come after the other fields of their object.
+///
+/// Created by [`Document::diff`] and [`Document::diff_nodes`].
+pub struct Diff<'a, U: UsageIndex, V: UsageIndex> {
+    old: &'a Document<U>,
+    new: &'a Document<V>,
+    // a pair of values still to compare
+    pending: Option<(String, Node, Node)>,
+    // the containers being compared, innermost last
+    frames: Vec<Frame<'a, U, V>>,
+}
+
+enum Frame<'a, U: UsageIndex, V: UsageIndex> {
+    Object {
+        path: String,
+        old: Node,
+        new: Node,
+        old_fields: Children<'a, U>,
+        new_fields: Children<'a, V>,
+    },
+    Array {
+        path: String,
+        old_items: Children<'a, U>,
+        new_items: Children<'a, V>,
+        index: usize,
+    },
+}
+
+// what comparing the next items of a container leads to
+enum Step {
+    Change(Change),
+    // a pair of values, at a path, to compare in turn
+    Compare(String, Node, Node),
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// Iterate over the changes from this document to another one, such as
+    /// the next dump of the same data.
+    ///
+    /// Fields of objects are matched by name, whatever their order; of
+    /// duplicate fields only the first counts. Items of arrays are matched
+    /// by index, so an item inserted at the start of an array changes every
+    /// item after it. Changes are found as the iterator advances, so they
+    /// are never all in memory at once.
+    pub fn diff<'a, V: UsageIndex>(&'a self, other: &'a Document<V>) -> Diff<'a, U, V> {
+        self.diff_nodes(self.root(), other, other.root())
+    }
+
+    /// Iterate over the changes from the value at a node of this document
+    /// to the value at a node of another one, or of this one. Paths are
+    /// relative to the nodes.
+    pub fn diff_nodes<'a, V: UsageIndex>(
+        &'a self,
+        node: Node,
+        other: &'a Document<V>,
+        other_node: Node,
+    ) -> Diff<'a, U, V> {
+        Diff {
+            old: self,
+            new: other,
+            pending: Some((
+                String::new(),
+                self.field_value(node),
+                other.field_value(other_node),
+            )),
+            frames: Vec::new(),
+        }
+    }
+}
+
+impl<'a, U: UsageIndex, V: UsageIndex> Diff<'a, U, V> {
+    // the old and new object whose fields are being compared, if any; for
+    // a field that was just reported, these are the objects it is in
+    pub(super) fn objects(&self) -> Option<(Node, Node)> {
+        match self.frames.last()? {
+            Frame::Object { old, new, .. } => Some((*old, *new)),
+            Frame::Array { .. } => None,
+        }
+    }
+
+    // compare a pair of values, returning the change if they are scalars
+    // that differ and starting on their items if they are containers
+    fn compare(&mut self, path: String, old: Node, new: Node) -> Option<Change> {
+        match (self.old.node_type(old), self.new.node_type(new)) {
+            (NodeType::Object, NodeType::Object) => {
+                self.frames.push(Frame::Object {
+                    path,
+                    old,
+                    new,
+                    old_fields: self.old.children(old),
+                    new_fields: self.new.children(new),
+                });
+                None
+            }
+            (NodeType::Array, NodeType::Array) => {
+                self.frames.push(Frame::Array {
+                    path,
+                    old_items: self.old.children(old),
+                    new_items: self.new.children(new),
+                    index: 0,
+                });
+                None
+            }
+            _ if scalars_equal(self.old, old, self.new, new) => None,
+            _ => Some(Change::Changed { path, old, new }),
+        }
+    }
+
+    // the next change or pair of values to compare of the innermost
+    // container that has any left, or `None` once all are done
+    fn advance(&mut self) -> Option<Step> {
+        let (old_document, new_document) = (self.old, self.new);
+        loop {
+            match self.frames.last_mut()? {
+                Frame::Object {
+                    path,
+                    old,
+                    new,
+                    old_fields,
+                    new_fields,
+                } => {
+                    for field in old_fields.by_ref() {
+                        let name = field_name(old_document, field);
+                        // later duplicates are shadowed by the first
+                        if old_document.field_by_name(*old, name) != Some(field) {
+                            continue;
+                        }
+                        let path = pointer::push(path, name);
+                        let value = old_document.field_value(field);
+                        return Some(match new_document.field_by_name(*new, name) {
+                            Some(other) => {
+                                Step::Compare(path, value, new_document.field_value(other))
+                            }
+                            None => Step::Change(Change::Removed { path, node: value }),
+                        });
+                    }
+                    for field in new_fields.by_ref() {
+                        let name = field_name(new_document, field);
+                        if new_document.field_by_name(*new, name) == Some(field)
+                            && old_document.field_by_name(*old, name).is_none()
+                        {
+                            return Some(Step::Change(Change::Added {
+                                path: pointer::push(path, name),
+                                node: new_document.field_value(field),
+                            }));
+                        }
+                    }
+                }
+                Frame::Array {
+                    path,
+                    old_items,
+                    new_items,
+                    index,
+                } => {
+                    let item_path = format!("{path}/{index}");
+                    *index += 1;
+                    match (old_items.next(), new_items.next()) {
+                        (Some(old), Some(new)) => return Some(Step::Compare(item_path, old, new)),
+                        (Some(old), None) => {
+                            return Some(Step::Change(Change::Removed {
+                                path: item_path,
+                                node: old,
+                            }));
+                        }
+                        (None, Some(new)) => {
+                            return Some(Step::Change(Change::Added {
+                                path: item_path,
+                                node: new,
+                            }));
+                        }
+                        (None, None) => {}
+                    }
+                }
+            }
+            self.frames.pop();
+        }
+    }
+}
+
+impl<U: UsageIndex, V: UsageIndex> Iterator for Diff<'_, U, V> {
+    type Item = Change;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((path, old, new)) = self.pending.take()
+                && let Some(change) = self.compare(path, old, new)
+            {
+                return Some(change);
+            }
+            match self.advance()? {
+                Step::Change(change) => return Some(change),
+                Step::Compare(path, old, new) => self.pending = Some((path, old, new)),
+            }
+        }
+    }
+}
+
+impl<U: UsageIndex, V: UsageIndex> FusedIterator for Diff<'_, U, V> {}
+
+fn field_name<U: UsageIndex>(document: &Document<U>, field: Node) -> &str {
+    let NodeType::Field(name) = document.node_type(field) else {
+        unreachable!("Object children should be fields");
+    };
+    name
+}
+
+// whether two scalars of different documents are equal, comparing numbers
+// like `Value` does
+fn scalars_equal<U: UsageIndex, V: UsageIndex>(
+    a_document: &Document<U>,
+    a: Node,
+    b_document: &Document<V>,
+    b: Node,
+) -> bool {
+    match (a_document.node_type(a), b_document.node_type(b)) {
+        (NodeType::String, NodeType::String) => {
+            *a_document.as_str(a).expect("Node should be a string")
+                == *b_document.as_str(b).expect("Node should be a string")
+        }
+        (NodeType::Number, NodeType::Number) => {
+            numbers_equal(a_document.number_value(a), b_document.number_value(b))
+        }
+        (NodeType::Boolean, NodeType::Boolean) => {
+            a_document.boolean_value(a) == b_document.boolean_value(b)
+        }
+        (NodeType::Null, NodeType::Null) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use struson::writer::{JsonStreamWriter, JsonWriter};
+
+    use crate::{
+        Value,
+        usage::{BitpackingUsageBuilder, RoaringUsageBuilder, UsageBuilder},
+    };
+
+    use super::*;
+
+    fn json<U: UsageIndex>(value: Value<'_, U>) -> String {
+        let mut out = Vec::new();
+        let mut writer = JsonStreamWriter::new(&mut out);
+        value.serialize(&mut writer).unwrap();
+        writer.finish_document().unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = BitpackingUsageBuilder::parse(
+            r#"{"id": 1, "name": "a", "tags": ["x", "y", "z"], "meta": {"v": 1, "old": true},
+                "kind": [1], "same": {"deep": [1, {"n": null}]}}"#
+                .as_bytes(),
+        )
+        .unwrap();
+        let new = RoaringUsageBuilder::parse(
+            r#"{"same": {"deep": [1.0, {"n": null}]}, "name": "b", "id": 1, "tags": ["x", "q"],
+                "meta": {"v": 1, "new/key": false}, "kind": {"a": 1}}"#
+                .as_bytes(),
+        )
+        .unwrap();
+        let changes = old
+            .diff(&new)
+            .map(|change| {
+                let (kind, value) = match change {
+                    Change::Added { node, .. } => ("added", json(new.value(node))),
+                    Change::Removed { node, .. } => ("removed", json(old.value(node))),
+                    Change::Changed {
+                        old: node,
+                        new: other,
+                        ..
+                    } => (
+                        "changed",
+                        format!("{} -> {}", json(old.value(node)), json(new.value(other))),
+                    ),
+                };
+                (change.path().to_string(), kind, value)
+            })
+            .collect::<Vec<_>>();
+        let expected = [
+            ("/name", "changed", r#""a" -> "b""#),
+            ("/tags/1", "changed", r#""y" -> "q""#),
+            ("/tags/2", "removed", r#""z""#),
+            ("/meta/old", "removed", "true"),
+            ("/meta/new~1key", "added", "false"),
+            ("/kind", "changed", r#"[1] -> {"a":1}"#),
+        ];
+        assert_eq!(
+            changes,
+            expected
+                .iter()
+                .map(|&(path, kind, value)| (path.to_string(), kind, value.to_string()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(old.diff(&old).next(), None);
+    }
+
+    #[test]
+    fn test_diff_nodes() {
+        let doc = BitpackingUsageBuilder::parse(
+            r#"[[1, 2], [1, 2, 3], "s", {"a": 1, "a": 2}, {"a": 1}]"#.as_bytes(),
+        )
+        .unwrap();
+        let items = doc.children(doc.root()).collect::<Vec<_>>();
+        assert_eq!(
+            doc.diff_nodes(items[0], &doc, items[1]).collect::<Vec<_>>(),
+            vec![Change::Added {
+                path: "/2".to_string(),
+                node: doc.nth_child(items[1], 2).unwrap()
+            }]
+        );
+        assert_eq!(
+            doc.diff_nodes(items[0], &doc, items[2]).collect::<Vec<_>>(),
+            vec![Change::Changed {
+                path: String::new(),
+                old: items[0],
+                new: items[2]
+            }]
+        );
+        // only the first of duplicate fields counts
+        assert_eq!(doc.diff_nodes(items[3], &doc, items[4]).next(), None);
+    }
+}
@@ -0,0 +1,142 @@
+use std::fmt::Write;
+
+use crate::{info::NodeType, usage::UsageIndex};
+
+use super::{Document, Node, Value};
+
+// the maximum amount of characters of a string value shown in a label
+const MAX_LABEL_CHARS: usize = 24;
+
+/// Truncate a string to at most `max` characters, adding an ellipsis if
+/// anything was cut off.
+pub(crate) fn truncate(s: &str, max: usize) -> String {
+    match s.char_indices().nth(max) {
+        Some((i, _)) => format!("{}…", &s[..i]),
+        None => s.to_string(),
+    }
+}
+
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// Render the subtree under `node` as a Graphviz DOT graph.
+    ///
+    /// Values become graph nodes labeled with their type or (truncated)
+    /// value, and object fields become edge labels. If `depth_limit` is
+    /// given, containers deeper than that are not expanded.
+    pub fn to_dot(&self, node: Node, depth_limit: Option<usize>) -> String {
+        let mut out = String::from("digraph {\n    node [shape=box];\n");
+        self.dot_node(&mut out, node, 0, depth_limit);
+        out.push_str("}\n");
+        out
+    }
+
+    fn dot_node(&self, out: &mut String, node: Node, depth: usize, depth_limit: Option<usize>) {
+        let expand = depth_limit.is_none_or(|limit| depth < limit);
+        let label = match self.value(node) {
+            Value::Object(_) if expand => "object".to_string(),
+            Value::Object(_) => "{…}".to_string(),
+            Value::Array(_) if expand => "array".to_string(),
+            Value::Array(_) => "[…]".to_string(),
+            Value::String(s) => format!("\"{}\"", truncate(&s, MAX_LABEL_CHARS)),
+            Value::Number(n) => n.to_string(),
+            Value::Integer(i) => i.to_string(),
+            Value::Boolean(b) => b.to_string(),
+            Value::Null => "null".to_string(),
+        };
+        let _ = writeln!(
+            out,
+            "    n{} [label=\"{}\"];",
+            node.get(),
+            escape_dot(&label)
+        );
+        if !expand {
+            return;
+        }
+        let mut child = self.first_child(node);
+        while let Some(current) = child {
+            let (value_node, edge_label) = match self.node_type(current) {
+                NodeType::Field(name) => (
+                    self.first_child(current)
+                        .expect("Field should have a value"),
+                    Some(name.as_str()),
+                ),
+                _ => (current, None),
+            };
+            match edge_label {
+                Some(name) => {
+                    let _ = writeln!(
+                        out,
+                        "    n{} -> n{} [label=\"{}\"];",
+                        node.get(),
+                        value_node.get(),
+                        escape_dot(&truncate(name, MAX_LABEL_CHARS))
+                    );
+                }
+                None => {
+                    let _ = writeln!(out, "    n{} -> n{};", node.get(), value_node.get());
+                }
+            }
+            self.dot_node(out, value_node, depth + 1, depth_limit);
+            child = self.next_sibling(current);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::{BitpackingUsageBuilder, UsageBuilder};
+
+    use super::*;
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("hello", 10), "hello");
+        assert_eq!(truncate("hello", 5), "hello");
+        assert_eq!(truncate("hello", 3), "hel…");
+        assert_eq!(truncate("héllo", 2), "hé…");
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let doc =
+            BitpackingUsageBuilder::parse(r#"{"a": [1, "x\"y"], "b": null}"#.as_bytes()).unwrap();
+        assert_eq!(
+            doc.to_dot(doc.root(), None),
+            r#"digraph {
+    node [shape=box];
+    n0 [label="object"];
+    n0 -> n2 [label="a"];
+    n2 [label="array"];
+    n2 -> n3;
+    n3 [label="1"];
+    n2 -> n5;
+    n5 [label="\"x\"y\""];
+    n0 -> n10 [label="b"];
+    n10 [label="null"];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_to_dot_depth_limit() {
+        let doc = BitpackingUsageBuilder::parse(r#"[[1], {"a": 2}]"#.as_bytes()).unwrap();
+        assert_eq!(
+            doc.to_dot(doc.root(), Some(1)),
+            r#"digraph {
+    node [shape=box];
+    n0 [label="array"];
+    n0 -> n1;
+    n1 [label="[…]"];
+    n0 -> n5;
+    n5 [label="{…}"];
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use std::fmt;
+
+use crate::{
+    builder::Builder,
+    info::NodeType,
+    pointer,
+    usage::{UsageBuilder, UsageIndex},
+};
+
+use super::{Document, OwnedValue, Value};
+
+/// An error editing a document with [`Document::with`] or
+/// [`Document::without`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditError {
+    /// The pointer is not a valid JSON Pointer.
+    InvalidPointer(String),
+    /// The pointer does not point to a value, or for `with`, to a place
+    /// where a value can be added.
+    NotFound(String),
+    /// The whole document cannot be removed.
+    RemoveRoot,
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditError::InvalidPointer(pointer) => write!(f, "invalid JSON Pointer '{pointer}'"),
+            EditError::NotFound(pointer) => write!(f, "nothing to edit at '{pointer}'"),
+            EditError::RemoveRoot => write!(f, "the root cannot be removed"),
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+enum Edit<'e> {
+    Set(&'e OwnedValue),
+    Remove,
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// A copy of this document with the value at `pointer` set.
+    ///
+    /// An existing value is replaced. A missing object field is added at
+    /// the end of the object; an array item can be added at the end by
+    /// using the array length or `-` as index. The empty pointer replaces
+    /// the whole document.
+    pub fn with<B: UsageBuilder<Index = U>>(
+        &self,
+        pointer: &str,
+        value: &OwnedValue,
+    ) -> Result<Document<U>, EditError> {
+        self.edit::<B>(pointer, Edit::Set(value))
+    }
+
+    /// A copy of this document without the value at `pointer`.
+    pub fn without<B: UsageBuilder<Index = U>>(
+        &self,
+        pointer: &str,
+    ) -> Result<Document<U>, EditError> {
+        self.edit::<B>(pointer, Edit::Remove)
+    }
+
+    fn edit<B: UsageBuilder<Index = U>>(
+        &self,
+        pointer: &str,
+        edit: Edit,
+    ) -> Result<Document<U>, EditError> {
+        let tokens = pointer::parse(pointer)
+            .ok_or_else(|| EditError::InvalidPointer(pointer.to_string()))?;
+        let mut builder = Builder::<B>::new();
+        match (tokens.is_empty(), &edit) {
+            (true, Edit::Set(value)) => builder.owned_value(value),
+            (true, Edit::Remove) => return Err(EditError::RemoveRoot),
+            (false, _) => {
+                edit_value(&mut builder, self.root_value(), &tokens, &edit)
+                    .map_err(|_| EditError::NotFound(pointer.to_string()))?;
+            }
+        }
+        Ok(builder.build())
+    }
+}
+
+// copy the value into the builder, applying the edit at the place the
+// (non-empty) tokens point to
+fn edit_value<B: UsageBuilder, U: UsageIndex>(
+    builder: &mut Builder<B>,
+    value: Value<'_, U>,
+    tokens: &[String],
+    edit: &Edit,
+) -> Result<(), ()> {
+    let (token, rest) = tokens.split_first().ok_or(())?;
+    match value {
+        Value::Object(object) => {
+            let mut found = false;
+            builder.open(NodeType::Object);
+            for (key, value) in object.iter() {
+                if key != token {
+                    let close_field_id = builder.open_field(key);
+                    builder.value(&value);
+                    builder.close_field(close_field_id);
+                    continue;
+                }
+                found = true;
+                match (rest.is_empty(), edit) {
+                    (true, Edit::Remove) => {}
+                    (true, Edit::Set(new_value)) => {
+                        let close_field_id = builder.open_field(key);
+                        builder.owned_value(new_value);
+                        builder.close_field(close_field_id);
+                    }
+                    (false, _) => {
+                        let close_field_id = builder.open_field(key);
+                        edit_value(builder, value, rest, edit)?;
+                        builder.close_field(close_field_id);
+                    }
+                }
+            }
+            if !found {
+                match (rest.is_empty(), edit) {
+                    (true, Edit::Set(new_value)) => {
+                        let close_field_id = builder.open_field(token);
+                        builder.owned_value(new_value);
+                        builder.close_field(close_field_id);
+                    }
+                    _ => return Err(()),
+                }
+            }
+            builder.close(NodeType::Object);
+            Ok(())
+        }
+        Value::Array(array) => {
+            let index = pointer::array_index(token);
+            let mut len = 0;
+            builder.open(NodeType::Array);
+            for (i, item) in array.iter().enumerate() {
+                len = i + 1;
+                if Some(i) != index {
+                    builder.value(&item);
+                    continue;
+                }
+                match (rest.is_empty(), edit) {
+                    (true, Edit::Remove) => {}
+                    (true, Edit::Set(new_value)) => builder.owned_value(new_value),
+                    (false, _) => edit_value(builder, item, rest, edit)?,
+                }
+            }
+            let appends = token == "-" || index == Some(len);
+            match (
+                index.is_some_and(|i| i < len),
+                appends,
+                rest.is_empty(),
+                edit,
+            ) {
+                (true, _, _, _) => {}
+                (false, true, true, Edit::Set(new_value)) => builder.owned_value(new_value),
+                _ => return Err(()),
+            }
+            builder.close(NodeType::Array);
+            Ok(())
+        }
+        _ => Err(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::BitpackingUsageBuilder;
+
+    use super::*;
+
+    fn serialized<U: UsageIndex>(doc: &Document<U>) -> String {
+        let mut out = Vec::new();
+        doc.serialize(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn doc() -> Document<<BitpackingUsageBuilder as UsageBuilder>::Index> {
+        BitpackingUsageBuilder::parse(r#"{"a": {"b": 1}, "c": [1, 2]}"#.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_with_replace() {
+        let edited = doc()
+            .with::<BitpackingUsageBuilder>("/a/b", &OwnedValue::Boolean(true))
+            .unwrap();
+        assert_eq!(serialized(&edited), r#"{"a":{"b":true},"c":[1,2]}"#);
+    }
+
+    #[test]
+    fn test_with_add() {
+        let doc = doc();
+        let edited = doc
+            .with::<BitpackingUsageBuilder>("/a/x", &OwnedValue::Null)
+            .unwrap();
+        assert_eq!(serialized(&edited), r#"{"a":{"b":1,"x":null},"c":[1,2]}"#);
+        let edited = doc
+            .with::<BitpackingUsageBuilder>("/c/-", &OwnedValue::Number(3.0))
+            .unwrap();
+        assert_eq!(serialized(&edited), r#"{"a":{"b":1},"c":[1,2,3]}"#);
+        let edited = doc
+            .with::<BitpackingUsageBuilder>("/c/0", &OwnedValue::Number(0.0))
+            .unwrap();
+        assert_eq!(serialized(&edited), r#"{"a":{"b":1},"c":[0,2]}"#);
+    }
+
+    #[test]
+    fn test_with_root() {
+        let edited = doc()
+            .with::<BitpackingUsageBuilder>("", &OwnedValue::Number(3.0))
+            .unwrap();
+        assert_eq!(serialized(&edited), "3");
+    }
+
+    #[test]
+    fn test_without() {
+        let doc = doc();
+        let edited = doc.without::<BitpackingUsageBuilder>("/a/b").unwrap();
+        assert_eq!(serialized(&edited), r#"{"a":{},"c":[1,2]}"#);
+        let edited = doc.without::<BitpackingUsageBuilder>("/c/0").unwrap();
+        assert_eq!(serialized(&edited), r#"{"a":{"b":1},"c":[2]}"#);
+    }
+
+    #[test]
+    fn test_edit_errors() {
+        let doc = doc();
+        assert_eq!(
+            doc.without::<BitpackingUsageBuilder>("/x").err(),
+            Some(EditError::NotFound("/x".to_string()))
+        );
+        assert_eq!(
+            doc.without::<BitpackingUsageBuilder>("/c/2").err(),
+            Some(EditError::NotFound("/c/2".to_string()))
+        );
+        assert_eq!(
+            doc.with::<BitpackingUsageBuilder>("/x/y", &OwnedValue::Null)
+                .err(),
+            Some(EditError::NotFound("/x/y".to_string()))
+        );
+        assert_eq!(
+            doc.with::<BitpackingUsageBuilder>("/a/b/c", &OwnedValue::Null)
+                .err(),
+            Some(EditError::NotFound("/a/b/c".to_string()))
+        );
+        assert_eq!(
+            doc.without::<BitpackingUsageBuilder>("").err(),
+            Some(EditError::RemoveRoot)
+        );
+        assert_eq!(
+            doc.without::<BitpackingUsageBuilder>("x").err(),
+            Some(EditError::InvalidPointer("x".to_string()))
+        );
+    }
+}
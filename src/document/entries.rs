@@ -0,0 +1,250 @@
+use std::{fmt, sync::Arc};
+
+use indexmap::IndexMap;
+
+use crate::{
+    builder::Builder,
+    pointer,
+    usage::{UsageBuilder, UsageIndex},
+};
+
+use super::{Document, OwnedValue};
+
+/// An error assembling a document from pointer/value pairs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FromEntriesError {
+    /// There were no entries, so there is no document.
+    Empty,
+    /// The pointer is not a valid JSON Pointer.
+    InvalidPointer(String),
+    /// The pointer was already set, or it goes through a value that was set
+    /// before, or it uses a key where an array was created before.
+    Conflict(String),
+    /// The pointer has an array index more than 1024 items past the end of
+    /// the array as assembled so far.
+    IndexOutOfRange(String),
+}
+
+// the most array items an entry may skip past the end of an array, so that
+// entries can come in any order without an index making room for an
+// arbitrary number of items
+const MAX_ARRAY_GAP: usize = 1024;
+
+impl fmt::Display for FromEntriesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FromEntriesError::Empty => write!(f, "there are no entries"),
+            FromEntriesError::InvalidPointer(pointer) => {
+                write!(f, "invalid JSON Pointer '{pointer}'")
+            }
+            FromEntriesError::Conflict(pointer) => {
+                write!(f, "'{pointer}' conflicts with an earlier entry")
+            }
+            FromEntriesError::IndexOutOfRange(pointer) => {
+                write!(f, "the array index of '{pointer}' is out of range")
+            }
+        }
+    }
+}
+
+impl std::error::Error for FromEntriesError {}
+
+// a tree that is still being assembled
+enum Partial {
+    Unset,
+    Object(IndexMap<Arc<str>, Partial>),
+    Array(Vec<Partial>),
+    Leaf(OwnedValue),
+}
+
+// why an entry could not be inserted
+enum Rejected {
+    Conflict,
+    IndexOutOfRange,
+}
+
+impl Partial {
+    fn insert(&mut self, tokens: &[String], value: OwnedValue) -> Result<(), Rejected> {
+        let Some((token, rest)) = tokens.split_first() else {
+            return match self {
+                Partial::Unset => {
+                    *self = Partial::Leaf(value);
+                    Ok(())
+                }
+                _ => Err(Rejected::Conflict),
+            };
+        };
+        if let Partial::Unset = self {
+            // a token that looks like an index creates an array
+            *self = if pointer::array_index(token).is_some() {
+                Partial::Array(Vec::new())
+            } else {
+                Partial::Object(IndexMap::new())
+            };
+        }
+        match self {
+            Partial::Object(map) => map
+                .entry(Arc::from(token.as_str()))
+                .or_insert(Partial::Unset)
+                .insert(rest, value),
+            Partial::Array(items) => {
+                let index = pointer::array_index(token).ok_or(Rejected::Conflict)?;
+                if index > items.len() + MAX_ARRAY_GAP {
+                    return Err(Rejected::IndexOutOfRange);
+                }
+                if index >= items.len() {
+                    items.resize_with(index + 1, || Partial::Unset);
+                }
+                items[index].insert(rest, value)
+            }
+            Partial::Leaf(_) | Partial::Unset => Err(Rejected::Conflict),
+        }
+    }
+
+    fn into_owned_value(self) -> OwnedValue {
+        match self {
+            // gaps in arrays become null
+            Partial::Unset => OwnedValue::Null,
+            Partial::Object(map) => OwnedValue::Object(
+                map.into_iter()
+                    .map(|(key, value)| (key, value.into_owned_value()))
+                    .collect(),
+            ),
+            Partial::Array(items) => {
+                OwnedValue::Array(items.into_iter().map(Partial::into_owned_value).collect())
+            }
+            Partial::Leaf(value) => value,
+        }
+    }
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// Assemble a document from pairs of JSON Pointer and value.
+    ///
+    /// Objects and arrays are created as needed along each pointer: a
+    /// reference token that is an array index creates an array, any other
+    /// token an object. Array items that are never set become null; an
+    /// entry may skip at most 1024 items past the end of an
+    /// array as assembled so far, as the entries may come from outside.
+    pub fn from_entries<B, I, P>(entries: I) -> Result<Document<U>, FromEntriesError>
+    where
+        B: UsageBuilder<Index = U>,
+        I: IntoIterator<Item = (P, OwnedValue)>,
+        P: AsRef<str>,
+    {
+        let mut root = Partial::Unset;
+        let mut empty = true;
+        for (path, value) in entries {
+            let path = path.as_ref();
+            let tokens = pointer::parse(path)
+                .ok_or_else(|| FromEntriesError::InvalidPointer(path.to_string()))?;
+            root.insert(&tokens, value)
+                .map_err(|rejected| match rejected {
+                    Rejected::Conflict => FromEntriesError::Conflict(path.to_string()),
+                    Rejected::IndexOutOfRange => {
+                        FromEntriesError::IndexOutOfRange(path.to_string())
+                    }
+                })?;
+            empty = false;
+        }
+        if empty {
+            return Err(FromEntriesError::Empty);
+        }
+        let mut builder = Builder::<B>::new();
+        builder.owned_value(&root.into_owned_value());
+        Ok(builder.build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::BitpackingUsageBuilder;
+
+    use super::*;
+
+    fn serialized<U: UsageIndex>(doc: &Document<U>) -> String {
+        let mut out = Vec::new();
+        doc.serialize(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_from_entries() {
+        let doc = Document::from_entries::<BitpackingUsageBuilder, _, _>([
+            ("/a/b", OwnedValue::Number(1.0)),
+            ("/a/c/0", OwnedValue::String("x".into())),
+            ("/a/c/2", OwnedValue::Boolean(true)),
+            ("/d~1e", OwnedValue::Null),
+        ])
+        .unwrap();
+        assert_eq!(
+            serialized(&doc),
+            r#"{"a":{"b":1,"c":["x",null,true]},"d/e":null}"#
+        );
+    }
+
+    #[test]
+    fn test_from_entries_root_scalar() {
+        let doc =
+            Document::from_entries::<BitpackingUsageBuilder, _, _>([("", OwnedValue::Number(3.0))])
+                .unwrap();
+        assert_eq!(serialized(&doc), "3");
+    }
+
+    #[test]
+    fn test_from_entries_errors() {
+        let empty: [(&str, OwnedValue); 0] = [];
+        assert!(matches!(
+            Document::from_entries::<BitpackingUsageBuilder, _, _>(empty),
+            Err(FromEntriesError::Empty)
+        ));
+        assert!(matches!(
+            Document::from_entries::<BitpackingUsageBuilder, _, _>([("a", OwnedValue::Null)]),
+            Err(FromEntriesError::InvalidPointer(p)) if p == "a"
+        ));
+        assert!(matches!(
+            Document::from_entries::<BitpackingUsageBuilder, _, _>([
+                ("/a", OwnedValue::Null),
+                ("/a/b", OwnedValue::Null)
+            ]),
+            Err(FromEntriesError::Conflict(p)) if p == "/a/b"
+        ));
+        assert!(matches!(
+            Document::from_entries::<BitpackingUsageBuilder, _, _>([
+                ("/0", OwnedValue::Null),
+                ("/a", OwnedValue::Null)
+            ]),
+            Err(FromEntriesError::Conflict(p)) if p == "/a"
+        ));
+    }
+
+    #[test]
+    fn test_from_entries_huge_index() {
+        assert!(matches!(
+            Document::from_entries::<BitpackingUsageBuilder, _, _>([(
+                "/99999999999",
+                OwnedValue::Number(1.0)
+            )]),
+            Err(FromEntriesError::IndexOutOfRange(p)) if p == "/99999999999"
+        ));
+        // entries out of order within the gap are fine
+        let gap = format!("/a/{MAX_ARRAY_GAP}");
+        let doc = Document::from_entries::<BitpackingUsageBuilder, _, _>([
+            (gap.as_str(), OwnedValue::Boolean(true)),
+            ("/a/0", OwnedValue::Boolean(false)),
+        ])
+        .unwrap();
+        let a = doc
+            .first_child(doc.first_child(doc.root()).unwrap())
+            .unwrap();
+        assert_eq!(doc.child_count(a), MAX_ARRAY_GAP + 1);
+        let beyond = format!("/a/{}", MAX_ARRAY_GAP + 2);
+        assert!(matches!(
+            Document::from_entries::<BitpackingUsageBuilder, _, _>([
+                ("/a/0", OwnedValue::Null),
+                (beyond.as_str(), OwnedValue::Null)
+            ]),
+            Err(FromEntriesError::IndexOutOfRange(_))
+        ));
+    }
+}
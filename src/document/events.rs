@@ -0,0 +1,148 @@
+use std::{iter::FusedIterator, sync::Arc};
+
+use crate::{info::NodeType, numbers::Number, usage::UsageIndex};
+
+use super::{Document, Node};
+
+/// A token of a document, as [`Document::events`] gives them.
+///
+/// Numbers are split into integers and floats as in [`Value`](super::Value).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event<'a> {
+    StartObject,
+    EndObject,
+    StartArray,
+    EndArray,
+    /// The name of a field; its value follows.
+    Key(&'a str),
+    String(Arc<str>),
+    Number(f64),
+    Integer(i64),
+    Boolean(bool),
+    Null,
+}
+
+/// An iterator over the events of a subtree.
+///
+/// Created by [`Document::events`].
+pub struct Events<'a, U: UsageIndex> {
+    document: &'a Document<U>,
+    // the next parenthesis
+    position: usize,
+    // the closing parenthesis of the subtree
+    end: usize,
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// The events of the subtree of `node` in document order, such as
+    /// `StartObject, Key("a"), Integer(1), EndObject` for `{"a": 1}`.
+    ///
+    /// They are read off the parentheses of the tree one after another,
+    /// so any depth of nesting takes no stack. For a field node the events
+    /// start with its key.
+    pub fn events(&self, node: Node) -> Events<'_, U> {
+        Events {
+            document: self,
+            position: node.get(),
+            end: self.primitive_close(node),
+        }
+    }
+}
+
+impl<'a, U: UsageIndex> Iterator for Events<'a, U> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let document = self.document;
+        let structure = &document.structure;
+        while self.position <= self.end {
+            let position = self.position;
+            self.position += 1;
+            let node_info = structure.node_info(position);
+            if !node_info.is_open_tag {
+                match node_info.node_type {
+                    NodeType::Object => return Some(Event::EndObject),
+                    NodeType::Array => return Some(Event::EndArray),
+                    // fields end with their value
+                    _ => continue,
+                }
+            }
+            let node = Node::new(position);
+            let event = match &node_info.node_type {
+                NodeType::Object => Event::StartObject,
+                NodeType::Array => Event::StartArray,
+                NodeType::Field(name) => Event::Key(name),
+                scalar => {
+                    // a scalar closes right after it opens
+                    self.position += 1;
+                    match scalar {
+                        NodeType::String => Event::String(document.string_value(node)),
+                        NodeType::Number => match document.number_value(node) {
+                            Number::Float(n) => Event::Number(n),
+                            Number::Integer(i) => Event::Integer(i),
+                            Number::Unsigned(u) => Event::Number(u as f64),
+                        },
+                        NodeType::Boolean => Event::Boolean(document.boolean_value(node)),
+                        _ => Event::Null,
+                    }
+                }
+            };
+            return Some(event);
+        }
+        None
+    }
+}
+
+impl<U: UsageIndex> FusedIterator for Events<'_, U> {}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::{BitpackingUsageBuilder, UsageBuilder};
+
+    use super::*;
+
+    #[test]
+    fn test_events() {
+        let doc = BitpackingUsageBuilder::parse(
+            r#"{"a": [1, 2.5, "x", true, null, {}], "b": {"c": []}}"#.as_bytes(),
+        )
+        .unwrap();
+        let events = doc.events(doc.root()).collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            [
+                Event::StartObject,
+                Event::Key("a"),
+                Event::StartArray,
+                Event::Integer(1),
+                Event::Number(2.5),
+                Event::String("x".into()),
+                Event::Boolean(true),
+                Event::Null,
+                Event::StartObject,
+                Event::EndObject,
+                Event::EndArray,
+                Event::Key("b"),
+                Event::StartObject,
+                Event::Key("c"),
+                Event::StartArray,
+                Event::EndArray,
+                Event::EndObject,
+                Event::EndObject,
+            ]
+        );
+
+        let b = doc.nth_child(doc.root(), 1).unwrap();
+        let events = doc.events(b).collect::<Vec<_>>();
+        assert_eq!(events[0], Event::Key("b"));
+        assert_eq!(events.len(), 6);
+        let one = doc.first_child(
+            doc.first_child(doc.first_child(doc.root()).unwrap())
+                .unwrap(),
+        );
+        assert_eq!(
+            doc.events(one.unwrap()).collect::<Vec<_>>(),
+            [Event::Integer(1)]
+        );
+    }
+}
@@ -0,0 +1,424 @@
+use std::{fmt, sync::Arc};
+
+use ahash::HashMap;
+
+#[cfg(feature = "regex")]
+use crate::text::regex::Regex;
+use crate::usage::UsageIndex;
+
+use super::{Document, Node, Value};
+
+/// An argument of a function extension.
+#[derive(Debug)]
+pub enum FunctionArg<'a, U: UsageIndex> {
+    /// The nodes a query selected, such as the node of a compiled pointer.
+    Nodes(Vec<Node>),
+    /// A value, or `None` for no value.
+    Value(Option<Value<'a, U>>),
+}
+
+// implemented manually so we don't require U: Clone
+impl<U: UsageIndex> Clone for FunctionArg<'_, U> {
+    fn clone(&self) -> Self {
+        match self {
+            FunctionArg::Nodes(nodes) => FunctionArg::Nodes(nodes.clone()),
+            FunctionArg::Value(value) => FunctionArg::Value(value.clone()),
+        }
+    }
+}
+
+impl<'a, U: UsageIndex> FunctionArg<'a, U> {
+    /// The argument as a value: a value as it is, or the value of the only
+    /// node selected; `None` if no or several nodes were selected.
+    pub fn value(&self, document: &'a Document<U>) -> Option<Value<'a, U>> {
+        match self {
+            FunctionArg::Nodes(nodes) => match nodes.as_slice() {
+                [node] => Some(document.value(*node)),
+                _ => None,
+            },
+            FunctionArg::Value(value) => value.clone(),
+        }
+    }
+}
+
+impl<U: UsageIndex> From<Option<Node>> for FunctionArg<'_, U> {
+    /// The node found by a lookup such as [`Document::get_compiled`].
+    fn from(node: Option<Node>) -> Self {
+        FunctionArg::Nodes(node.into_iter().collect())
+    }
+}
+
+/// The result of a function extension.
+#[derive(Debug)]
+pub enum FunctionResult<'a, U: UsageIndex> {
+    /// A value, or `None` for no value.
+    Value(Option<Value<'a, U>>),
+    Logical(bool),
+}
+
+// implemented manually so we don't require U: Clone or U: PartialEq
+impl<U: UsageIndex> Clone for FunctionResult<'_, U> {
+    fn clone(&self) -> Self {
+        match self {
+            FunctionResult::Value(value) => FunctionResult::Value(value.clone()),
+            FunctionResult::Logical(b) => FunctionResult::Logical(*b),
+        }
+    }
+}
+
+impl<U: UsageIndex> PartialEq for FunctionResult<'_, U> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (FunctionResult::Value(a), FunctionResult::Value(b)) => a == b,
+            (FunctionResult::Logical(a), FunctionResult::Logical(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// A function extension could not be called.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FunctionError {
+    /// No function with the name is registered.
+    Unknown(String),
+    /// The function takes another number of arguments.
+    ArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    /// An argument has a type the function doesn't take, such as a value
+    /// where it takes nodes.
+    ArgumentType { name: String, index: usize },
+}
+
+impl fmt::Display for FunctionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FunctionError::Unknown(name) => write!(f, "unknown function {name}()"),
+            FunctionError::ArgumentCount {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{name}() takes {expected} argument(s) but was given {found}"
+            ),
+            FunctionError::ArgumentType { name, index } => {
+                write!(f, "argument {index} of {name}() has the wrong type")
+            }
+        }
+    }
+}
+
+impl std::error::Error for FunctionError {}
+
+/// A function extension: it gets the document and the arguments.
+pub type Function<U> = dyn for<'a> Fn(
+        &'a Document<U>,
+        &[FunctionArg<'a, U>],
+    ) -> Result<FunctionResult<'a, U>, FunctionError>
+    + Send
+    + Sync;
+
+/// Function extensions by name, as RFC 9535 defines them for JSONPath
+/// filters.
+///
+/// [`FunctionRegistry::new`] has the standard functions:
+///
+/// - `length(value)`: the number of characters of a string or of items or
+///   fields of an array or object; counting children skips ahead over the
+///   recorded child positions of large containers.
+/// - `count(nodes)`: the number of nodes.
+/// - `match(value, regex)` and `search(value, regex)`: whether the whole
+///   string or some part of it matches the regex, with the DFA of
+///   [`Document::strings_matching_regex`]; only with the `regex` feature.
+/// - `value(nodes)`: the value of the only node.
+///
+/// Other functions can be added with [`FunctionRegistry::register`]. The
+/// crate has no JSONPath filter parser, so the functions are called with
+/// [`FunctionRegistry::call`], on the nodes of compiled pointers or on
+/// values.
+pub struct FunctionRegistry<U: UsageIndex> {
+    functions: HashMap<String, Arc<Function<U>>>,
+}
+
+impl<U: UsageIndex> Clone for FunctionRegistry<U> {
+    fn clone(&self) -> Self {
+        Self {
+            functions: self.functions.clone(),
+        }
+    }
+}
+
+impl<U: UsageIndex> fmt::Debug for FunctionRegistry<U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = self.functions.keys().collect::<Vec<_>>();
+        names.sort();
+        f.debug_struct("FunctionRegistry")
+            .field("functions", &names)
+            .finish()
+    }
+}
+
+impl<U: UsageIndex + 'static> Default for FunctionRegistry<U> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn check_count<U: UsageIndex>(
+    name: &str,
+    args: &[FunctionArg<'_, U>],
+    expected: usize,
+) -> Result<(), FunctionError> {
+    if args.len() == expected {
+        Ok(())
+    } else {
+        Err(FunctionError::ArgumentCount {
+            name: name.to_string(),
+            expected,
+            found: args.len(),
+        })
+    }
+}
+
+fn nodes<'b, U: UsageIndex>(
+    name: &str,
+    args: &'b [FunctionArg<'_, U>],
+    index: usize,
+) -> Result<&'b [Node], FunctionError> {
+    match &args[index] {
+        FunctionArg::Nodes(nodes) => Ok(nodes),
+        FunctionArg::Value(_) => Err(FunctionError::ArgumentType {
+            name: name.to_string(),
+            index,
+        }),
+    }
+}
+
+fn length<'a, U: UsageIndex>(
+    document: &'a Document<U>,
+    args: &[FunctionArg<'a, U>],
+) -> Result<FunctionResult<'a, U>, FunctionError> {
+    check_count("length", args, 1)?;
+    let length = match args[0].value(document) {
+        Some(Value::String(s)) => Some(s.chars().count()),
+        Some(Value::Array(array)) => Some(array.len()),
+        Some(Value::Object(object)) => Some(object.len()),
+        _ => None,
+    };
+    Ok(FunctionResult::Value(
+        length.map(|length| Value::Integer(length as i64)),
+    ))
+}
+
+fn count<'a, U: UsageIndex>(
+    _document: &'a Document<U>,
+    args: &[FunctionArg<'a, U>],
+) -> Result<FunctionResult<'a, U>, FunctionError> {
+    check_count("count", args, 1)?;
+    let count = nodes("count", args, 0)?.len();
+    Ok(FunctionResult::Value(Some(Value::Integer(count as i64))))
+}
+
+fn value<'a, U: UsageIndex>(
+    document: &'a Document<U>,
+    args: &[FunctionArg<'a, U>],
+) -> Result<FunctionResult<'a, U>, FunctionError> {
+    check_count("value", args, 1)?;
+    let value = match nodes("value", args, 0)? {
+        [node] => Some(document.value(*node)),
+        _ => None,
+    };
+    Ok(FunctionResult::Value(value))
+}
+
+// whether the string matches the regex; anything that isn't a string or a
+// valid regex doesn't match
+#[cfg(feature = "regex")]
+fn regex_match<'a, U: UsageIndex>(
+    name: &str,
+    document: &'a Document<U>,
+    args: &[FunctionArg<'a, U>],
+    pattern: impl FnOnce(&str) -> String,
+) -> Result<FunctionResult<'a, U>, FunctionError> {
+    check_count(name, args, 2)?;
+    let matches = match (args[0].value(document), args[1].value(document)) {
+        (Some(Value::String(s)), Some(Value::String(regex))) => {
+            Regex::new(&pattern(&regex)).is_ok_and(|regex| regex.is_match(&s))
+        }
+        _ => false,
+    };
+    Ok(FunctionResult::Logical(matches))
+}
+
+impl<U: UsageIndex + 'static> FunctionRegistry<U> {
+    /// A registry with the standard functions.
+    pub fn new() -> Self {
+        let registry = Self {
+            functions: HashMap::default(),
+        }
+        .register("length", length)
+        .register("count", count)
+        .register("value", value);
+        #[cfg(feature = "regex")]
+        let registry = registry
+            .register("match", |document, args| {
+                regex_match("match", document, args, str::to_string)
+            })
+            .register("search", |document, args| {
+                regex_match("search", document, args, |regex| {
+                    format!("(?s:.)*(?:{regex})(?s:.)*")
+                })
+            });
+        registry
+    }
+
+    /// Add a function, replacing any function with the same name.
+    pub fn register<F>(mut self, name: &str, function: F) -> Self
+    where
+        F: for<'a> Fn(
+                &'a Document<U>,
+                &[FunctionArg<'a, U>],
+            ) -> Result<FunctionResult<'a, U>, FunctionError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.functions.insert(name.to_string(), Arc::new(function));
+        self
+    }
+
+    /// Whether a function with the name is registered.
+    pub fn contains(&self, name: &str) -> bool {
+        self.functions.contains_key(name)
+    }
+
+    /// Call the function with the name.
+    ///
+    /// The number of items in the field `items`:
+    ///
+    /// ```
+    /// use colchis::{BitpackingUsageBuilder, Document, FunctionRegistry, FunctionResult, Value};
+    ///
+    /// let json = r#"{"items": [1, 2, 3]}"#;
+    /// let doc = Document::parse::<BitpackingUsageBuilder, _>(json.as_bytes()).unwrap();
+    /// let items = doc.compile_pointer("/items").unwrap();
+    /// let length = FunctionRegistry::new()
+    ///     .call(&doc, "length", &[doc.get_compiled(doc.root(), &items).into()])
+    ///     .unwrap();
+    /// assert_eq!(length, FunctionResult::Value(Some(Value::Integer(3))));
+    /// ```
+    pub fn call<'a>(
+        &self,
+        document: &'a Document<U>,
+        name: &str,
+        args: &[FunctionArg<'a, U>],
+    ) -> Result<FunctionResult<'a, U>, FunctionError> {
+        let function = self
+            .functions
+            .get(name)
+            .ok_or_else(|| FunctionError::Unknown(name.to_string()))?;
+        function(document, args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::{BitpackingUsageBuilder, UsageBuilder};
+
+    use super::*;
+
+    #[test]
+    fn test_functions() {
+        let doc = BitpackingUsageBuilder::parse(
+            r#"{"name": "Ünïcode", "tags": ["a", "b"], "meta": {"x": 1}, "n": 5}"#.as_bytes(),
+        )
+        .unwrap();
+        let registry = FunctionRegistry::new();
+        let at = |pointer: &str| {
+            let pointer = doc.compile_pointer(pointer).unwrap();
+            FunctionArg::from(doc.get_compiled(doc.root(), &pointer))
+        };
+        let integer = |n| Ok(FunctionResult::Value(Some(Value::Integer(n))));
+
+        assert_eq!(registry.call(&doc, "length", &[at("/name")]), integer(7));
+        assert_eq!(registry.call(&doc, "length", &[at("/tags")]), integer(2));
+        assert_eq!(registry.call(&doc, "length", &[at("/meta")]), integer(1));
+        assert_eq!(
+            registry.call(&doc, "length", &[at("/n")]),
+            Ok(FunctionResult::Value(None))
+        );
+        assert_eq!(
+            registry.call(
+                &doc,
+                "length",
+                &[FunctionArg::Value(Some(Value::String("ab".into())))]
+            ),
+            integer(2)
+        );
+
+        let children = || FunctionArg::Nodes(doc.children(doc.root()).collect());
+        assert_eq!(registry.call(&doc, "count", &[children()]), integer(4));
+        assert_eq!(registry.call(&doc, "count", &[at("/missing")]), integer(0));
+        assert_eq!(
+            registry.call(&doc, "value", &[at("/n")]),
+            Ok(FunctionResult::Value(Some(Value::Integer(5))))
+        );
+        assert_eq!(
+            registry.call(&doc, "value", &[children()]),
+            Ok(FunctionResult::Value(None))
+        );
+
+        assert_eq!(
+            registry.call(&doc, "size", &[]),
+            Err(FunctionError::Unknown("size".to_string()))
+        );
+        assert!(matches!(
+            registry.call(&doc, "length", &[]),
+            Err(FunctionError::ArgumentCount {
+                expected: 1,
+                found: 0,
+                ..
+            })
+        ));
+        assert!(matches!(
+            registry.call(&doc, "count", &[FunctionArg::Value(None)]),
+            Err(FunctionError::ArgumentType { index: 0, .. })
+        ));
+
+        // custom functions
+        let registry = registry.register("is_number", |document, args| {
+            Ok(FunctionResult::Logical(matches!(
+                args.first().and_then(|arg| arg.value(document)),
+                Some(Value::Integer(_) | Value::Number(_))
+            )))
+        });
+        assert!(registry.contains("is_number"));
+        assert_eq!(
+            registry.call(&doc, "is_number", &[at("/n")]),
+            Ok(FunctionResult::Logical(true))
+        );
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_regex_functions() {
+        let doc = BitpackingUsageBuilder::parse(r#"{"id": "abc-123"}"#.as_bytes()).unwrap();
+        let registry = FunctionRegistry::new();
+        let id = doc.compile_pointer("/id").unwrap();
+        let id = FunctionArg::from(doc.get_compiled(doc.root(), &id));
+        let regex = |pattern: &str| FunctionArg::Value(Some(Value::String(pattern.into())));
+        let call = |name, pattern| registry.call(&doc, name, &[id.clone(), regex(pattern)]);
+        assert_eq!(
+            call("match", "[a-z]+-[0-9]+"),
+            Ok(FunctionResult::Logical(true))
+        );
+        assert_eq!(call("match", "[0-9]+"), Ok(FunctionResult::Logical(false)));
+        assert_eq!(call("search", "[0-9]+"), Ok(FunctionResult::Logical(true)));
+        assert_eq!(call("search", "x"), Ok(FunctionResult::Logical(false)));
+        // an invalid regex doesn't match
+        assert_eq!(call("search", "("), Ok(FunctionResult::Logical(false)));
+    }
+}
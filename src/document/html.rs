@@ -0,0 +1,174 @@
+use std::io::Write;
+
+use crate::usage::UsageIndex;
+
+use super::{Document, Node, Value, dot::truncate};
+
+// strings longer than this are collapsed, showing only their start
+const MAX_INLINE_CHARS: usize = 80;
+
+const STYLE: &str = "body { font-family: monospace; }
+details { margin-left: 1.5em; }
+summary { cursor: pointer; }
+.leaf { margin-left: 1.5em; }
+.key { color: #881391; }
+.string { color: #c41a16; }
+.number { color: #1c00cf; }
+.boolean, .null { color: #0d22aa; }
+.count { color: #888; }";
+
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// Render the subtree under `node` as a standalone HTML page.
+    ///
+    /// Objects and arrays become collapsible sections; long strings are
+    /// shown truncated and can be expanded to their full value.
+    pub fn to_html<W: Write>(&self, node: Node, mut w: W) -> std::io::Result<()> {
+        writeln!(w, "<!DOCTYPE html>")?;
+        writeln!(
+            w,
+            "<html><head><meta charset=\"utf-8\"><title>colchis</title><style>{STYLE}</style></head><body>"
+        )?;
+        self.html_value(&mut w, None, self.value(node))?;
+        writeln!(w, "</body></html>")
+    }
+
+    fn html_value<W: Write>(
+        &self,
+        w: &mut W,
+        key: Option<&str>,
+        value: Value<'_, U>,
+    ) -> std::io::Result<()> {
+        let key = match key {
+            Some(key) => format!("<span class=\"key\">{}</span>: ", escape_html(key)),
+            None => String::new(),
+        };
+        match value {
+            Value::Object(object) => {
+                let entries = object.iter().collect::<Vec<_>>();
+                writeln!(
+                    w,
+                    "<details open><summary>{key}{{}} <span class=\"count\">{} fields</span></summary>",
+                    entries.len()
+                )?;
+                for (name, value) in entries {
+                    self.html_value(w, Some(name), value)?;
+                }
+                writeln!(w, "</details>")
+            }
+            Value::Array(array) => {
+                let values = array.into_iter().collect::<Vec<_>>();
+                writeln!(
+                    w,
+                    "<details open><summary>{key}[] <span class=\"count\">{} items</span></summary>",
+                    values.len()
+                )?;
+                for value in values {
+                    self.html_value(w, None, value)?;
+                }
+                writeln!(w, "</details>")
+            }
+            Value::String(s) => {
+                let truncated = truncate(&s, MAX_INLINE_CHARS);
+                if truncated.len() < s.len() {
+                    writeln!(
+                        w,
+                        "<details class=\"leaf\"><summary>{key}<span class=\"string\">\"{}\"</span></summary><span class=\"string\">\"{}\"</span></details>",
+                        escape_html(&truncated),
+                        escape_html(&s)
+                    )
+                } else {
+                    writeln!(
+                        w,
+                        "<div class=\"leaf\">{key}<span class=\"string\">\"{}\"</span></div>",
+                        escape_html(&s)
+                    )
+                }
+            }
+            Value::Number(n) => writeln!(
+                w,
+                "<div class=\"leaf\">{key}<span class=\"number\">{n}</span></div>"
+            ),
+            Value::Integer(i) => writeln!(
+                w,
+                "<div class=\"leaf\">{key}<span class=\"number\">{i}</span></div>"
+            ),
+            Value::Boolean(b) => writeln!(
+                w,
+                "<div class=\"leaf\">{key}<span class=\"boolean\">{b}</span></div>"
+            ),
+            Value::Null => writeln!(
+                w,
+                "<div class=\"leaf\">{key}<span class=\"null\">null</span></div>"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::{BitpackingUsageBuilder, UsageBuilder};
+
+    use super::*;
+
+    fn render(json: &str) -> String {
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+        let mut out = Vec::new();
+        doc.to_html(doc.root(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(
+            escape_html("<a href=\"x\">&</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn test_to_html() {
+        let html = render(r#"{"a": [1, true], "<b>": null}"#);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(
+            html.contains(
+                "<details open><summary>{} <span class=\"count\">2 fields</span></summary>"
+            )
+        );
+        assert!(html.contains(
+            "<details open><summary><span class=\"key\">a</span>: [] <span class=\"count\">2 items</span></summary>"
+        ));
+        assert!(html.contains("<div class=\"leaf\"><span class=\"number\">1</span></div>"));
+        assert!(html.contains(
+            "<div class=\"leaf\"><span class=\"key\">&lt;b&gt;</span>: <span class=\"null\">null</span></div>"
+        ));
+        assert!(html.trim_end().ends_with("</body></html>"));
+    }
+
+    #[test]
+    fn test_to_html_long_string() {
+        let long = "x".repeat(100);
+        let html = render(&format!("\"{long}\""));
+        assert!(html.contains(&format!(
+            "<summary><span class=\"string\">\"{}…\"</span></summary>",
+            "x".repeat(80)
+        )));
+        assert!(html.contains(&format!(
+            "<span class=\"string\">\"{long}\"</span></details>"
+        )));
+    }
+}
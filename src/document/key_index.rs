@@ -0,0 +1,154 @@
+use std::sync::{Arc, Mutex};
+
+use ahash::HashMap;
+
+use crate::{info::NodeInfoId, usage::UsageIndex};
+
+use super::{Document, Node};
+
+// the fields of an indexed object, by the node info of their name; of
+// duplicate names the first field is kept
+type Fields = HashMap<NodeInfoId, Node>;
+
+/// The fields of large objects by name, built for every object the first
+/// time a field of it is looked up.
+#[derive(Debug, Default)]
+pub(crate) struct KeyIndex {
+    // a mutex rather than a refcell so that documents can be read from
+    // multiple threads
+    objects: Mutex<HashMap<usize, Arc<Fields>>>,
+}
+
+impl KeyIndex {
+    pub(crate) fn heap_size(&self) -> usize {
+        let objects = self.lock();
+        objects.capacity() * std::mem::size_of::<(usize, Arc<Fields>)>()
+            + objects
+                .values()
+                .map(|fields| fields.capacity() * std::mem::size_of::<(NodeInfoId, Node)>())
+                .sum::<usize>()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<usize, Arc<Fields>>> {
+        // the index holds no invariants a panic could break, so we can
+        // recover from poisoning
+        self.objects
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// Look up the fields of large objects through a hash table per
+    /// object, rather than by going through the fields one by one. This
+    /// applies to [`ObjectValue::get`](super::ObjectValue::get) and
+    /// [`Document::get_path`].
+    ///
+    /// Tables are built for objects of at least 64 fields, the first time
+    /// a field of the object is looked up, and are kept for the lifetime of
+    /// the document. They are not saved with it.
+    pub fn with_key_index(mut self) -> Self {
+        self.key_index = Some(KeyIndex::default());
+        self
+    }
+
+    /// The field of an object with a name; the first one if there are
+    /// several.
+    pub(crate) fn field_by_name(&self, object: Node, name: &str) -> Option<Node> {
+        // a name that occurs nowhere has no node info
+        let node_info_id = self
+            .structure
+            .usage_index()
+            .node_lookup()
+            .by_field_name(name)?;
+        self.field_by_id(object, node_info_id)
+    }
+
+    /// The field of an object with the node info of its name; the first
+    /// one if there are several.
+    pub(crate) fn field_by_id(&self, object: Node, node_info_id: NodeInfoId) -> Option<Node> {
+        let Some(key_index) = self
+            .key_index
+            .as_ref()
+            .filter(|_| self.uses_key_index(object))
+        else {
+            return self.scan_fields(object, node_info_id);
+        };
+        let cached = key_index.lock().get(&object.get()).cloned();
+        let fields = match cached {
+            Some(fields) => fields,
+            None => {
+                #[cfg(feature = "tracing")]
+                let _span =
+                    tracing::debug_span!("build_index", index = "key", object = object.get())
+                        .entered();
+                // build without holding the lock, so other threads are not
+                // blocked on it
+                let mut fields = Fields::default();
+                for field in self.children(object) {
+                    fields
+                        .entry(self.structure.node_info_id(field.get()))
+                        .or_insert(field);
+                }
+                let fields = Arc::new(fields);
+                key_index.lock().insert(object.get(), fields.clone());
+                fields
+            }
+        };
+        fields.get(&node_info_id).copied()
+    }
+
+    /// Whether fields of the object are looked up in the key index rather
+    /// than one by one.
+    pub(crate) fn uses_key_index(&self, object: Node) -> bool {
+        // objects with skips recorded for their children are large
+        self.key_index.is_some() && !self.structure.child_skips(object.get()).is_empty()
+    }
+
+    fn scan_fields(&self, object: Node, node_info_id: NodeInfoId) -> Option<Node> {
+        self.children(object)
+            .find(|field| self.structure.node_info_id(field.get()) == node_info_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Value,
+        usage::{BitpackingUsageBuilder, UsageBuilder},
+    };
+
+    #[test]
+    fn test_key_index() {
+        let fields = (0..500)
+            .map(|i| format!(r#""id{i}": {i}"#))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let json = format!(r#"{{"big": {{{fields}, "id7": "again"}}, "small": {{"a": 1}}}}"#);
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes())
+            .unwrap()
+            .with_key_index();
+        let Value::Object(root) = doc.root_value() else {
+            panic!("Expected object");
+        };
+        let Some(Value::Object(big)) = root.get("big") else {
+            panic!("Expected object");
+        };
+        assert_eq!(big.get("id321"), Some(Value::Integer(321)));
+        // the first of duplicate fields
+        assert_eq!(big.get("id7"), Some(Value::Integer(7)));
+        assert_eq!(big.get("a"), None);
+        assert_eq!(big.get("nope"), None);
+        assert_eq!(
+            doc.get_path(doc.root(), &["small", "a"])
+                .map(|node| doc.value(node)),
+            Some(Value::Integer(1))
+        );
+        assert_eq!(
+            doc.get_path(doc.root(), &["big", "id499"])
+                .map(|node| doc.value(node)),
+            Some(Value::Integer(499))
+        );
+        assert!(doc.key_index.as_ref().unwrap().heap_size() > 0);
+    }
+}
@@ -0,0 +1,212 @@
+use std::sync::Arc;
+
+use ahash::HashMap;
+use indexmap::IndexMap;
+
+use crate::{
+    builder::Builder,
+    info::NodeType,
+    numbers::float_equals_integer,
+    usage::{UsageBuilder, UsageIndex},
+};
+
+use super::{ArrayValue, Document, Value};
+
+/// How [`Document::deep_merge`] combines two arrays.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArrayMergeStrategy {
+    /// The array of the other document replaces the array.
+    Replace,
+    /// The items of the other array are appended.
+    Concat,
+    /// Object items with the same scalar value for this field are merged;
+    /// other items of the other array are appended.
+    MergeByKey(String),
+}
+
+// the scalar values we can match array items by
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum MergeKey {
+    String(Arc<str>),
+    Number(u64),
+    // integers that don't fit in a float exactly
+    Integer(i64),
+    Boolean(bool),
+    Null,
+}
+
+fn merge_key<U: UsageIndex>(item: &Value<'_, U>, key: &str) -> Option<MergeKey> {
+    let Value::Object(object) = item else {
+        return None;
+    };
+    match object.get(key)? {
+        Value::String(s) => Some(MergeKey::String(s)),
+        // normalize -0.0 to 0.0 so they match like they compare
+        Value::Number(n) => Some(MergeKey::Number((n + 0.0).to_bits())),
+        // integers match the same number as a float
+        Value::Integer(i) if float_equals_integer(i as f64, i) => {
+            Some(MergeKey::Number((i as f64).to_bits()))
+        }
+        Value::Integer(i) => Some(MergeKey::Integer(i)),
+        Value::Boolean(b) => Some(MergeKey::Boolean(b)),
+        Value::Null => Some(MergeKey::Null),
+        Value::Object(_) | Value::Array(_) => None,
+    }
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// Merge another document into this one, producing a new document.
+    ///
+    /// Objects are merged recursively: fields only in one of them are kept,
+    /// fields in both are merged. Arrays are combined according to the
+    /// strategy. In all other cases the value of the other document wins.
+    pub fn deep_merge<B: UsageBuilder<Index = U>, O: UsageIndex>(
+        &self,
+        other: &Document<O>,
+        strategy: &ArrayMergeStrategy,
+    ) -> Document<U> {
+        let mut builder = Builder::<B>::new();
+        merge(
+            &mut builder,
+            self.root_value(),
+            other.root_value(),
+            strategy,
+        );
+        builder.build()
+    }
+}
+
+fn merge<B: UsageBuilder, U: UsageIndex, O: UsageIndex>(
+    builder: &mut Builder<B>,
+    a: Value<'_, U>,
+    b: Value<'_, O>,
+    strategy: &ArrayMergeStrategy,
+) {
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            let mut b_fields = b.iter().collect::<IndexMap<_, _>>();
+            builder.open(NodeType::Object);
+            for (key, a_value) in a.iter() {
+                let close_field_id = builder.open_field(key);
+                match b_fields.shift_remove(key) {
+                    Some(b_value) => merge(builder, a_value, b_value, strategy),
+                    None => builder.value(&a_value),
+                }
+                builder.close_field(close_field_id);
+            }
+            for (key, b_value) in b_fields {
+                let close_field_id = builder.open_field(key);
+                builder.value(&b_value);
+                builder.close_field(close_field_id);
+            }
+            builder.close(NodeType::Object);
+        }
+        (Value::Array(a), Value::Array(b)) => match strategy {
+            ArrayMergeStrategy::Replace => builder.value(&Value::Array(b)),
+            ArrayMergeStrategy::Concat => {
+                builder.open(NodeType::Array);
+                for item in a.iter() {
+                    builder.value(&item);
+                }
+                for item in b.iter() {
+                    builder.value(&item);
+                }
+                builder.close(NodeType::Array);
+            }
+            ArrayMergeStrategy::MergeByKey(key) => merge_by_key(builder, a, b, key, strategy),
+        },
+        (_, b) => builder.value(&b),
+    }
+}
+
+fn merge_by_key<B: UsageBuilder, U: UsageIndex, O: UsageIndex>(
+    builder: &mut Builder<B>,
+    a: ArrayValue<'_, U>,
+    b: ArrayValue<'_, O>,
+    key: &str,
+    strategy: &ArrayMergeStrategy,
+) {
+    let mut b_items = b.iter().map(Some).collect::<Vec<_>>();
+    // the first item in b for each key
+    let mut b_by_key = HashMap::default();
+    for (i, item) in b_items.iter().enumerate() {
+        if let Some(k) = item.as_ref().and_then(|item| merge_key(item, key)) {
+            b_by_key.entry(k).or_insert(i);
+        }
+    }
+    builder.open(NodeType::Array);
+    for a_item in a.iter() {
+        let matched = merge_key(&a_item, key)
+            .and_then(|k| b_by_key.remove(&k))
+            .and_then(|i| b_items[i].take());
+        match matched {
+            Some(b_item) => merge(builder, a_item, b_item, strategy),
+            None => builder.value(&a_item),
+        }
+    }
+    for b_item in b_items.into_iter().flatten() {
+        builder.value(&b_item);
+    }
+    builder.close(NodeType::Array);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::BitpackingUsageBuilder;
+
+    use super::*;
+
+    fn merged(a: &str, b: &str, strategy: ArrayMergeStrategy) -> String {
+        let a = BitpackingUsageBuilder::parse(a.as_bytes()).unwrap();
+        let b = BitpackingUsageBuilder::parse(b.as_bytes()).unwrap();
+        let doc = a.deep_merge::<BitpackingUsageBuilder, _>(&b, &strategy);
+        let mut out = Vec::new();
+        doc.serialize(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_merge_objects() {
+        assert_eq!(
+            merged(
+                r#"{"a": 1, "b": {"c": 2, "d": 3}}"#,
+                r#"{"b": {"d": 4, "e": 5}, "f": 6}"#,
+                ArrayMergeStrategy::Replace
+            ),
+            r#"{"a":1,"b":{"c":2,"d":4,"e":5},"f":6}"#
+        );
+    }
+
+    #[test]
+    fn test_merge_type_change() {
+        assert_eq!(
+            merged(
+                r#"{"a": {"b": 1}}"#,
+                r#"{"a": [1]}"#,
+                ArrayMergeStrategy::Concat
+            ),
+            r#"{"a":[1]}"#
+        );
+    }
+
+    #[test]
+    fn test_merge_arrays() {
+        assert_eq!(merged("[1, 2]", "[3]", ArrayMergeStrategy::Replace), "[3]");
+        assert_eq!(
+            merged("[1, 2]", "[3]", ArrayMergeStrategy::Concat),
+            "[1,2,3]"
+        );
+    }
+
+    #[test]
+    fn test_merge_by_key() {
+        assert_eq!(
+            merged(
+                r#"[{"id": 1, "a": 1}, {"id": 2, "a": 2}, 7]"#,
+                r#"[{"id": 2, "b": 3}, {"id": 3}, 8]"#,
+                ArrayMergeStrategy::MergeByKey("id".to_string())
+            ),
+            r#"[{"id":1,"a":1},{"id":2,"a":2,"b":3},7,{"id":3},8]"#
+        );
+    }
+}
@@ -1,10 +1,72 @@
+mod aggregate;
 mod array;
+mod axis;
+#[cfg(feature = "unstable")]
+mod bp;
+mod compare;
+mod compiled;
 mod core;
+#[cfg(feature = "serde")]
+mod de;
+mod debug;
+mod descendants;
+mod diff;
+mod dot;
+mod edit;
+mod entries;
+mod events;
+mod functions;
+mod html;
+mod key_index;
+mod merge;
+#[cfg(feature = "msgpack")]
+mod msgpack;
 mod nav;
 mod object;
+mod owned;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod patch;
+mod range;
+mod save;
+mod schema;
+mod search;
+#[cfg(feature = "serde")]
+mod ser;
+#[cfg(feature = "serde_json")]
+mod serde_value;
 mod serialize;
+mod toml;
 mod value;
+mod verify;
+mod walk;
+mod xml;
 
+pub use aggregate::Agg;
+pub use array::ArrayValue;
+pub use axis::AxisIter;
+pub use compiled::{CompiledPointer, Explain, ExplainStep, LookupStrategy};
 pub use core::{Document, Node};
+#[cfg(feature = "serde")]
+pub use de::DeserializeError;
+pub use descendants::TypedDescendants;
+pub use diff::{Change, Diff};
+pub use edit::EditError;
+pub use entries::FromEntriesError;
+pub use events::{Event, Events};
+pub use functions::{Function, FunctionArg, FunctionError, FunctionRegistry, FunctionResult};
+pub use merge::ArrayMergeStrategy;
+pub use nav::{Ancestors, Children};
 pub use object::ObjectValue;
+pub use owned::{OwnedValue, SizeLimitExceeded};
+#[cfg(feature = "parquet")]
+pub use parquet::{ParquetOptions, ParquetWriteError};
+pub use patch::{PatchError, PatchOperation};
+pub use range::NumbersInRange;
+pub use schema::{FieldPath, PathSchema, Schema, ValueType};
+pub use serialize::{Projection, SerializeOptions};
+pub use toml::TomlError;
 pub use value::Value;
+pub use verify::IntegrityError;
+pub use walk::{Visitor, Walk};
+pub use xml::{ArrayConvention, XmlOptions};
@@ -0,0 +1,97 @@
+use std::io::{self, Write};
+
+use rmp::encode;
+
+use crate::{info::NodeType, numbers::Number, usage::UsageIndex};
+
+use super::{Document, Node};
+
+impl<U: UsageIndex> Document<U> {
+    /// Write the subtree under `node` as MessagePack, the binary
+    /// counterpart of [`Document::serialize`]. For a field node this writes
+    /// the value of the field.
+    ///
+    /// Integers are written as the smallest MessagePack integer that holds
+    /// them and other numbers as 64-bit floats. Containers of more than
+    /// `u32::MAX` entries and strings longer than that can't be written.
+    pub fn to_msgpack<W: Write>(&self, node: Node, mut w: W) -> io::Result<()> {
+        self.write_msgpack(self.field_value(node), &mut w)
+    }
+
+    fn write_msgpack<W: Write>(&self, node: Node, w: &mut W) -> io::Result<()> {
+        match self.node_type(node) {
+            NodeType::Object => {
+                encode::write_map_len(w, msgpack_len(self.child_count(node))?)?;
+                for field in self.children(node) {
+                    let NodeType::Field(name) = self.node_type(field) else {
+                        unreachable!("Object children should be fields");
+                    };
+                    write_msgpack_str(w, name)?;
+                    self.write_msgpack(self.field_value(field), w)?;
+                }
+            }
+            NodeType::Array => {
+                encode::write_array_len(w, msgpack_len(self.child_count(node))?)?;
+                for item in self.children(node) {
+                    self.write_msgpack(item, w)?;
+                }
+            }
+            NodeType::String => {
+                let text = self.as_str(node).expect("Node should be a string");
+                write_msgpack_str(w, &text)?;
+            }
+            NodeType::Number => match self.number_value(node) {
+                Number::Integer(i) => {
+                    encode::write_sint(w, i)?;
+                }
+                Number::Unsigned(u) => {
+                    encode::write_uint(w, u)?;
+                }
+                Number::Float(n) => encode::write_f64(w, n)?,
+            },
+            NodeType::Boolean => encode::write_bool(w, self.boolean_value(node))?,
+            NodeType::Null => encode::write_nil(w)?,
+            NodeType::Field(_) => unreachable!("Fields should be resolved to their value"),
+        }
+        Ok(())
+    }
+}
+
+fn write_msgpack_str<W: Write>(w: &mut W, s: &str) -> io::Result<()> {
+    encode::write_str_len(w, msgpack_len(s.len())?)?;
+    w.write_all(s.as_bytes())
+}
+
+fn msgpack_len(len: usize) -> io::Result<u32> {
+    u32::try_from(len).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "too long to write as MessagePack",
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::{BitpackingUsageBuilder, UsageBuilder};
+
+    #[test]
+    fn test_to_msgpack() {
+        let doc = BitpackingUsageBuilder::parse(
+            r#"{"a": [1, -2, 1.5, 300, true, null, "x"], "b": {}}"#.as_bytes(),
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        doc.to_msgpack(doc.root(), &mut out).unwrap();
+        let mut expected = vec![0x82, 0xa1, b'a', 0x97, 0x01, 0xfe, 0xcb];
+        expected.extend(1.5f64.to_be_bytes());
+        expected.extend([0xcd, 0x01, 0x2c, 0xc3, 0xc0, 0xa1, b'x', 0xa1, b'b', 0x80]);
+        assert_eq!(out, expected);
+
+        // a field writes its value
+        let field = doc.first_child(doc.root()).unwrap();
+        let mut out = Vec::new();
+        doc.to_msgpack(field, &mut out).unwrap();
+        assert_eq!(out[0], 0x97);
+    }
+}
@@ -1,9 +1,66 @@
-use vers_vecs::Tree;
+use std::{cmp::Ordering, iter::FusedIterator};
 
-use crate::usage::UsageIndex;
+use vers_vecs::{IsAncestor, SubtreeSize, Tree};
+
+use crate::{info::NodeType, pointer, tree_builder::CHILD_SKIP_INTERVAL, usage::UsageIndex};
 
 use super::{Document, Node};
 
+/// A size hint for iterating over the siblings starting at `node`, up to the
+/// closing parenthesis of their parent at `end`. Each sibling takes at least
+/// `min_positions` positions in the structure.
+pub(crate) fn siblings_size_hint(
+    node: Option<Node>,
+    end: usize,
+    min_positions: usize,
+) -> (usize, Option<usize>) {
+    match node {
+        Some(node) => (1, Some((end - node.get()) / min_positions)),
+        None => (0, Some(0)),
+    }
+}
+
+/// An iterator over the children of a node.
+///
+/// Created by [`Document::children`].
+pub struct Children<'a, U: UsageIndex> {
+    document: &'a Document<U>,
+    next: Option<Node>,
+}
+
+impl<U: UsageIndex> Iterator for Children<'_, U> {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next?;
+        self.next = self.document.next_sibling(node);
+        Some(node)
+    }
+}
+
+impl<U: UsageIndex> FusedIterator for Children<'_, U> {}
+
+/// An iterator over the ancestors of a node, from its parent up to the
+/// root.
+///
+/// Created by [`Document::ancestors`].
+pub struct Ancestors<'a, U: UsageIndex> {
+    document: &'a Document<U>,
+    next: Option<Node>,
+}
+
+impl<U: UsageIndex> Iterator for Ancestors<'_, U> {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next?;
+        self.next = self.document.parent(node);
+        Some(node)
+    }
+}
+
+impl<U: UsageIndex> FusedIterator for Ancestors<'_, U> {}
+
 impl<U: UsageIndex> Document<U> {
     pub fn root(&self) -> Node {
         Node::new(
@@ -14,19 +71,412 @@ impl<U: UsageIndex> Document<U> {
         )
     }
 
-    #[allow(dead_code)]
-    pub(crate) fn primitive_parent(&self, node: Node) -> Option<Node> {
+    /// The parent of a node, or `None` for the root.
+    ///
+    /// The values of an object are held by field nodes, so the parent of an
+    /// object's value is a field node, whose parent is the object.
+    pub fn parent(&self, node: Node) -> Option<Node> {
         self.structure.tree().parent(node.get()).map(Node::new)
     }
 
-    pub(crate) fn primitive_first_child(&self, node: Node) -> Option<Node> {
+    /// The position of the closing parenthesis of a node.
+    pub(crate) fn primitive_close(&self, node: Node) -> usize {
+        self.structure
+            .tree()
+            .close(node.get())
+            .expect("Node should be closed")
+    }
+
+    /// The first child of a node, or `None` if it has no children.
+    ///
+    /// The children of an object are field nodes, and a field node has the
+    /// field's value as its only child. The children of an array are its
+    /// items. Scalars have no children.
+    pub fn first_child(&self, node: Node) -> Option<Node> {
         self.structure.tree().first_child(node.get()).map(Node::new)
     }
 
-    pub(crate) fn primitive_next_sibling(&self, node: Node) -> Option<Node> {
+    /// The last child of a node, or `None` if it has no children.
+    pub fn last_child(&self, node: Node) -> Option<Node> {
+        self.structure.tree().last_child(node.get()).map(Node::new)
+    }
+
+    /// The sibling after a node, or `None` if it is the last child.
+    pub fn next_sibling(&self, node: Node) -> Option<Node> {
         self.structure
             .tree()
             .next_sibling(node.get())
             .map(Node::new)
     }
+
+    /// The sibling before a node, or `None` if it is the first child.
+    pub fn previous_sibling(&self, node: Node) -> Option<Node> {
+        self.structure
+            .tree()
+            .previous_sibling(node.get())
+            .map(Node::new)
+    }
+
+    /// Iterate over the ancestors of a node, starting with its parent and
+    /// ending with the root. Field nodes are included.
+    pub fn ancestors(&self, node: Node) -> Ancestors<'_, U> {
+        Ancestors {
+            document: self,
+            next: self.parent(node),
+        }
+    }
+
+    /// The JSON Pointer from the root to a node.
+    ///
+    /// The path to a field node is the path to its value.
+    pub fn path(&self, node: Node) -> String {
+        let mut tokens = Vec::new();
+        let mut current = node;
+        loop {
+            if let NodeType::Field(name) = self.node_type(current) {
+                tokens.push(pointer::escape(name));
+            } else if let Some(parent) = self.parent(current)
+                && self.node_type(parent) == &NodeType::Array
+            {
+                let index = std::iter::successors(self.previous_sibling(current), |node| {
+                    self.previous_sibling(*node)
+                })
+                .count();
+                tokens.push(index.to_string());
+            }
+            match self.parent(current) {
+                Some(parent) => current = parent,
+                None => break,
+            }
+        }
+        tokens
+            .iter()
+            .rev()
+            .map(|token| format!("/{token}"))
+            .collect()
+    }
+
+    /// The number of nodes in the subtree of a node, including the node
+    /// itself. Field nodes are counted too, so an object with one scalar
+    /// field has a subtree size of 3.
+    pub fn subtree_size(&self, node: Node) -> usize {
+        self.structure
+            .tree()
+            .subtree_size(node.get())
+            .expect("Node should be closed")
+    }
+
+    /// The depth of a node, which is 0 for the root. Field nodes are a
+    /// level of their own, so the value of a field of the root object has
+    /// depth 2.
+    pub fn depth(&self, node: Node) -> usize {
+        self.structure.tree().depth(node.get()) as usize
+    }
+
+    /// The child of a node at index `i`, or `None` if it has fewer
+    /// children.
+    ///
+    /// Positions of every 64th child of large containers are recorded when
+    /// the document is built, so this visits at most 63 siblings rather
+    /// than all children before it.
+    pub fn nth_child(&self, node: Node, i: usize) -> Option<Node> {
+        let skip = i / CHILD_SKIP_INTERVAL;
+        let (mut child, remaining) = if skip == 0 {
+            (self.first_child(node)?, i)
+        } else {
+            let position = *self.structure.child_skips(node.get()).get(skip - 1)?;
+            (Node::new(position), i % CHILD_SKIP_INTERVAL)
+        };
+        for _ in 0..remaining {
+            child = self.next_sibling(child)?;
+        }
+        Some(child)
+    }
+
+    /// The number of children of a node. Like [`Document::nth_child`],
+    /// this starts from the last recorded child of large containers, so it
+    /// visits at most 63 siblings.
+    pub fn child_count(&self, node: Node) -> usize {
+        let skips = self.structure.child_skips(node.get());
+        let (start, counted) = match skips.last() {
+            Some(&position) => (Some(Node::new(position)), skips.len() * CHILD_SKIP_INTERVAL),
+            None => (self.first_child(node), 0),
+        };
+        counted + std::iter::successors(start, |child| self.next_sibling(*child)).count()
+    }
+
+    /// The node reached from `node` by following `path`: a field name for
+    /// every object on the way and an index for every array, as in
+    /// `&["items", "0", "id"]`. This walks the nodes directly, without
+    /// making a [`Value`](super::Value) for every level.
+    ///
+    /// Tokens are not escaped as in a JSON Pointer. A path through a field
+    /// node continues at its value, and the node found is a value rather
+    /// than a field.
+    pub fn get_path(&self, node: Node, path: &[&str]) -> Option<Node> {
+        let mut current = self.field_value(node);
+        for token in path {
+            current = match self.node_type(current) {
+                NodeType::Object => self
+                    .field_by_name(current, token)
+                    .and_then(|field| self.first_child(field))?,
+                NodeType::Array => self.nth_child(current, pointer::array_index(token)?)?,
+                _ => return None,
+            };
+        }
+        Some(current)
+    }
+
+    // the value of a field node, or the node itself
+    pub(super) fn field_value(&self, node: Node) -> Node {
+        match self.node_type(node) {
+            NodeType::Field(_) => self.first_child(node).expect("Field should have a value"),
+            _ => node,
+        }
+    }
+
+    /// Compare two nodes by their position in document order. An ancestor
+    /// comes before its descendants.
+    pub fn cmp_document_order(&self, a: Node, b: Node) -> Ordering {
+        a.get().cmp(&b.get())
+    }
+
+    /// Whether `ancestor` is an ancestor of `node`. A node is considered an
+    /// ancestor of itself.
+    pub fn is_ancestor(&self, ancestor: Node, node: Node) -> bool {
+        self.structure
+            .tree()
+            .is_ancestor(ancestor.get(), node.get())
+            .expect("Node should be closed")
+    }
+
+    /// The deepest node that is an ancestor of both `a` and `b`. If one is
+    /// an ancestor of the other, that is the result.
+    pub fn lowest_common_ancestor(&self, a: Node, b: Node) -> Node {
+        let mut candidate = a;
+        while !self.is_ancestor(candidate, b) {
+            candidate = self
+                .parent(candidate)
+                .expect("The root is an ancestor of every node");
+        }
+        candidate
+    }
+
+    /// Iterate over the children of a node, in order.
+    pub fn children(&self, node: Node) -> Children<'_, U> {
+        Children {
+            document: self,
+            next: self.first_child(node),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use crate::{
+        NodeType, Value,
+        usage::{BitpackingUsageBuilder, UsageBuilder},
+    };
+
+    #[test]
+    fn test_navigation() {
+        let doc =
+            BitpackingUsageBuilder::parse(r#"{"a": [1, 2, 3], "b": null}"#.as_bytes()).unwrap();
+        let root = doc.root();
+        assert_eq!(doc.parent(root), None);
+
+        let a = doc.first_child(root).unwrap();
+        assert_eq!(doc.node_type(a), &NodeType::Field("a".to_string()));
+        assert_eq!(doc.parent(a), Some(root));
+        let b = doc.last_child(root).unwrap();
+        assert_eq!(doc.node_type(b), &NodeType::Field("b".to_string()));
+        assert_eq!(doc.value(b), Value::Null);
+        assert_eq!(doc.next_sibling(a), Some(b));
+        assert_eq!(doc.previous_sibling(b), Some(a));
+        assert_eq!(doc.previous_sibling(a), None);
+        assert_eq!(doc.next_sibling(b), None);
+
+        let array = doc.first_child(a).unwrap();
+        let items = doc
+            .children(array)
+            .map(|node| doc.value(node))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            items,
+            vec![Value::Number(1.0), Value::Number(2.0), Value::Number(3.0)]
+        );
+        let last = doc.last_child(array).unwrap();
+        assert_eq!(doc.first_child(last), None);
+        assert_eq!(doc.children(last).count(), 0);
+    }
+
+    #[test]
+    fn test_ancestors() {
+        let doc = BitpackingUsageBuilder::parse(r#"{"a": [true]}"#.as_bytes()).unwrap();
+        let root = doc.root();
+        let field = doc.first_child(root).unwrap();
+        let array = doc.first_child(field).unwrap();
+        let item = doc.first_child(array).unwrap();
+        assert_eq!(
+            doc.ancestors(item).collect::<Vec<_>>(),
+            vec![array, field, root]
+        );
+        assert_eq!(doc.ancestors(root).count(), 0);
+    }
+
+    #[test]
+    fn test_path() {
+        let doc =
+            BitpackingUsageBuilder::parse(r#"{"a": [0, {"b/c": [1, 2]}], "d": 3}"#.as_bytes())
+                .unwrap();
+        let root = doc.root();
+        assert_eq!(doc.path(root), "");
+        let paths = doc
+            .typed_descendants(root, NodeType::Number)
+            .map(|node| doc.path(node))
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["/a/0", "/a/1/b~1c/0", "/a/1/b~1c/1", "/d"]);
+        let field = doc.first_child(root).unwrap();
+        assert_eq!(doc.path(field), "/a");
+        assert_eq!(doc.path(doc.first_child(field).unwrap()), "/a");
+    }
+
+    #[test]
+    fn test_get_path() {
+        let doc =
+            BitpackingUsageBuilder::parse(r#"{"a": [0, {"b/c": [1, 2]}], "d": 3}"#.as_bytes())
+                .unwrap();
+        let root = doc.root();
+        let value = |path: &[&str]| doc.get_path(root, path).map(|node| doc.value(node));
+        assert_eq!(value(&["a", "1", "b/c", "1"]), Some(Value::Integer(2)));
+        assert_eq!(value(&["d"]), Some(Value::Integer(3)));
+        assert_eq!(doc.get_path(root, &[]), Some(root));
+        assert_eq!(value(&["a", "2"]), None);
+        assert_eq!(value(&["a", "01"]), None);
+        assert_eq!(value(&["d", "x"]), None);
+        assert_eq!(value(&["x"]), None);
+        // from a field, the path continues at its value
+        let field = doc.first_child(root).unwrap();
+        assert_eq!(
+            doc.get_path(field, &["0"]).map(|node| doc.path(node)),
+            Some("/a/0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_subtree_size_and_depth() {
+        let doc = BitpackingUsageBuilder::parse(r#"{"a": [1, [2]], "b": 3}"#.as_bytes()).unwrap();
+        let root = doc.root();
+        assert_eq!(doc.subtree_size(root), 8);
+        assert_eq!(doc.depth(root), 0);
+        let field = doc.first_child(root).unwrap();
+        assert_eq!(doc.subtree_size(field), 5);
+        assert_eq!(doc.depth(field), 1);
+        let array = doc.first_child(field).unwrap();
+        assert_eq!(doc.depth(array), 2);
+        let inner = doc.last_child(array).unwrap();
+        assert_eq!(doc.subtree_size(inner), 2);
+        assert_eq!(doc.depth(doc.first_child(inner).unwrap()), 4);
+    }
+
+    #[test]
+    fn test_nth_child() {
+        let json = format!(
+            "[{}]",
+            (0..200)
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+        let root = doc.root();
+        for i in [0, 1, 63, 64, 65, 127, 128, 199] {
+            let child = doc.nth_child(root, i).unwrap();
+            assert_eq!(doc.value(child), Value::Number(i as f64));
+        }
+        assert_eq!(doc.nth_child(root, 200), None);
+        assert_eq!(doc.nth_child(root, 1000), None);
+        let first = doc.first_child(root).unwrap();
+        assert_eq!(doc.nth_child(first, 0), None);
+    }
+
+    #[test]
+    fn test_child_count() {
+        for len in [0, 1, 63, 64, 65, 128, 200] {
+            let items = vec!["1"; len].join(",");
+            let fields = (0..len)
+                .map(|i| format!(r#""f{i}": [{i}]"#))
+                .collect::<Vec<_>>()
+                .join(",");
+            let json = format!(r#"[[{items}], {{{fields}}}]"#);
+            let doc = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+            let Value::Array(root) = doc.root_value() else {
+                panic!("Expected array");
+            };
+            assert_eq!(root.len(), 2);
+            let (Some(Value::Array(array)), Some(Value::Object(object))) =
+                (root.get(0), root.get(1))
+            else {
+                panic!("Expected array and object");
+            };
+            assert_eq!((array.len(), object.len()), (len, len));
+            assert_eq!((array.is_empty(), object.is_empty()), (len == 0, len == 0));
+            assert_eq!(array.len(), array.iter().count());
+        }
+    }
+
+    #[test]
+    fn test_nth_child_nested() {
+        // children of different sizes, and large containers inside others
+        let items = (0..150)
+            .map(|i| {
+                if i % 3 == 0 {
+                    format!(r#"{{"i": {i}, "n": [{}]}}"#, vec!["0"; 70].join(","))
+                } else {
+                    i.to_string()
+                }
+            })
+            .collect::<Vec<_>>();
+        let doc =
+            BitpackingUsageBuilder::parse(format!("[{}]", items.join(",")).as_bytes()).unwrap();
+        let root = doc.root();
+        let children = doc.children(root).collect::<Vec<_>>();
+        for (i, child) in children.iter().enumerate() {
+            assert_eq!(doc.nth_child(root, i), Some(*child));
+        }
+        let object = doc.nth_child(root, 147).unwrap();
+        let n = doc.first_child(doc.nth_child(object, 1).unwrap()).unwrap();
+        assert_eq!(
+            doc.nth_child(n, 69).map(|node| doc.value(node)),
+            Some(Value::Number(0.0))
+        );
+        assert_eq!(doc.nth_child(n, 70), None);
+    }
+
+    #[test]
+    fn test_document_order_and_lca() {
+        let doc = BitpackingUsageBuilder::parse(r#"[[1, [2]], 3]"#.as_bytes()).unwrap();
+        let root = doc.root();
+        let first = doc.first_child(root).unwrap();
+        let one = doc.first_child(first).unwrap();
+        let inner = doc.last_child(first).unwrap();
+        let two = doc.first_child(inner).unwrap();
+        let three = doc.last_child(root).unwrap();
+
+        assert_eq!(doc.cmp_document_order(root, one), Ordering::Less);
+        assert_eq!(doc.cmp_document_order(three, two), Ordering::Greater);
+        assert_eq!(doc.cmp_document_order(two, two), Ordering::Equal);
+
+        assert!(doc.is_ancestor(first, two));
+        assert!(doc.is_ancestor(two, two));
+        assert!(!doc.is_ancestor(two, first));
+
+        assert_eq!(doc.lowest_common_ancestor(one, two), first);
+        assert_eq!(doc.lowest_common_ancestor(two, one), first);
+        assert_eq!(doc.lowest_common_ancestor(two, three), root);
+        assert_eq!(doc.lowest_common_ancestor(inner, two), inner);
+        assert_eq!(doc.lowest_common_ancestor(one, one), one);
+    }
 }
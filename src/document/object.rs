@@ -1,15 +1,27 @@
+use std::iter::FusedIterator;
+
 use struson::writer::{JsonStreamWriter, JsonWriter};
 
 use crate::{info::NodeType, usage::UsageIndex};
 
-use super::{Document, Node, Value};
+use super::{Document, Node, Value, nav::siblings_size_hint};
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct ObjectValue<'a, U: UsageIndex> {
     document: &'a Document<U>,
     node: Node,
 }
 
+// implemented manually so we don't require U: Clone
+impl<U: UsageIndex> Clone for ObjectValue<'_, U> {
+    fn clone(&self) -> Self {
+        Self {
+            document: self.document,
+            node: self.node,
+        }
+    }
+}
+
 impl<U: UsageIndex> PartialEq for ObjectValue<'_, U> {
     fn eq(&self, other: &Self) -> bool {
         // document reference equality
@@ -35,28 +47,46 @@ impl<'a, U: UsageIndex> ObjectValue<'a, U> {
         Self { document, node }
     }
 
+    /// The value of the first field named `key`. See
+    /// [`Document::with_key_index`] to find fields of large objects faster.
     pub fn get(&self, key: &str) -> Option<Value<'a, U>> {
-        self.iter().find(|(k, _)| *k == key).map(|(_, v)| v)
+        self.document
+            .field_by_name(self.node, key)
+            .and_then(|field| self.document.first_child(field))
+            .map(|value| self.document.value(value))
     }
 
     pub fn keys(&self) -> FieldKeyIterator<'a, U> {
         FieldKeyIterator {
             document: self.document,
-            node: self.document.primitive_first_child(self.node),
+            node: self.document.first_child(self.node),
+            end: self.document.primitive_close(self.node),
         }
     }
 
     pub fn values(&self) -> FieldValueIterator<'a, U> {
         FieldValueIterator {
             document: self.document,
-            node: self.document.primitive_first_child(self.node),
+            node: self.document.first_child(self.node),
+            end: self.document.primitive_close(self.node),
         }
     }
 
+    /// The number of fields, counted without visiting every one of them;
+    /// see [`Document::child_count`].
+    pub fn len(&self) -> usize {
+        self.document.child_count(self.node)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.document.first_child(self.node).is_none()
+    }
+
     pub fn iter(&self) -> FieldEntryIterator<'a, U> {
         FieldEntryIterator {
             document: self.document,
-            node: self.document.primitive_first_child(self.node),
+            node: self.document.first_child(self.node),
+            end: self.document.primitive_close(self.node),
         }
     }
 
@@ -65,9 +95,18 @@ impl<'a, U: UsageIndex> ObjectValue<'a, U> {
         writer: &mut JsonStreamWriter<W>,
     ) -> std::io::Result<()> {
         writer.begin_object()?;
-        for (key, value) in self.iter() {
+        let mut field = self.document.first_child(self.node);
+        while let Some(node) = field {
+            let NodeType::Field(key) = self.document.node_type(node) else {
+                unreachable!()
+            };
             writer.name(key)?;
-            value.serialize(writer)?;
+            let value = self
+                .document
+                .first_child(node)
+                .expect("Field should have a value");
+            self.document.serialize_node(value, writer)?;
+            field = self.document.next_sibling(node);
         }
         writer.end_object()
     }
@@ -76,6 +115,8 @@ impl<'a, U: UsageIndex> ObjectValue<'a, U> {
 pub struct FieldKeyIterator<'a, U: UsageIndex> {
     document: &'a Document<U>,
     node: Option<Node>,
+    // the closing position of the object
+    end: usize,
 }
 
 impl<'a, U: UsageIndex> Iterator for FieldKeyIterator<'a, U> {
@@ -83,7 +124,7 @@ impl<'a, U: UsageIndex> Iterator for FieldKeyIterator<'a, U> {
 
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(node) = self.node {
-            self.node = self.document.primitive_next_sibling(node);
+            self.node = self.document.next_sibling(node);
             let node_type = self.document.node_type(node);
             if let NodeType::Field(key) = node_type {
                 Some(key)
@@ -94,11 +135,21 @@ impl<'a, U: UsageIndex> Iterator for FieldKeyIterator<'a, U> {
             None
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // every field takes at least an open and a close position for
+        // itself and for its value
+        siblings_size_hint(self.node, self.end, 4)
+    }
 }
 
+impl<U: UsageIndex> FusedIterator for FieldKeyIterator<'_, U> {}
+
 pub struct FieldValueIterator<'a, U: UsageIndex> {
     document: &'a Document<U>,
     node: Option<Node>,
+    // the closing position of the object
+    end: usize,
 }
 
 impl<'a, U: UsageIndex> Iterator for FieldValueIterator<'a, U> {
@@ -107,19 +158,29 @@ impl<'a, U: UsageIndex> Iterator for FieldValueIterator<'a, U> {
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(node) = self.node {
             // we go to the next field
-            self.node = self.document.primitive_next_sibling(node);
+            self.node = self.document.next_sibling(node);
             // now we get the value of the first child of the field node
-            let value_node = self.document.primitive_first_child(node).unwrap();
+            let value_node = self.document.first_child(node).unwrap();
             Some(self.document.value(value_node))
         } else {
             None
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // every field takes at least an open and a close position for
+        // itself and for its value
+        siblings_size_hint(self.node, self.end, 4)
+    }
 }
 
+impl<U: UsageIndex> FusedIterator for FieldValueIterator<'_, U> {}
+
 pub struct FieldEntryIterator<'a, U: UsageIndex> {
     document: &'a Document<U>,
     node: Option<Node>,
+    // the closing position of the object
+    end: usize,
 }
 
 impl<'a, U: UsageIndex> Iterator for FieldEntryIterator<'a, U> {
@@ -128,11 +189,11 @@ impl<'a, U: UsageIndex> Iterator for FieldEntryIterator<'a, U> {
     fn next(&mut self) -> Option<Self::Item> {
         if let Some(node) = self.node {
             // we go to the next field
-            self.node = self.document.primitive_next_sibling(node);
+            self.node = self.document.next_sibling(node);
             // now we get the key and value of the field node
             let node_type = self.document.node_type(node);
             if let NodeType::Field(key) = node_type {
-                let value_node = self.document.primitive_first_child(node).unwrap();
+                let value_node = self.document.first_child(node).unwrap();
                 Some((key, self.document.value(value_node)))
             } else {
                 unreachable!()
@@ -141,4 +202,12 @@ impl<'a, U: UsageIndex> Iterator for FieldEntryIterator<'a, U> {
             None
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // every field takes at least an open and a close position for
+        // itself and for its value
+        siblings_size_hint(self.node, self.end, 4)
+    }
 }
+
+impl<U: UsageIndex> FusedIterator for FieldEntryIterator<'_, U> {}
@@ -0,0 +1,206 @@
+use std::{fmt, sync::Arc};
+
+use indexmap::IndexMap;
+
+use crate::{numbers::float_equals_integer, pointer, usage::UsageIndex};
+
+use super::{ObjectValue, Value, array::ArrayValue};
+
+/// A value materialized out of the document, independent of it.
+///
+/// As with [`Value`], an integer and a number compare equal if they are the
+/// same number.
+#[derive(Debug, Clone)]
+pub enum OwnedValue {
+    Object(IndexMap<Arc<str>, OwnedValue>),
+    Array(Vec<OwnedValue>),
+    String(Arc<str>),
+    Number(f64),
+    Integer(i64),
+    Boolean(bool),
+    Null,
+}
+
+impl PartialEq for OwnedValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (OwnedValue::Object(a), OwnedValue::Object(b)) => a == b,
+            (OwnedValue::Array(a), OwnedValue::Array(b)) => a == b,
+            (OwnedValue::String(a), OwnedValue::String(b)) => a == b,
+            (OwnedValue::Number(a), OwnedValue::Number(b)) => a == b,
+            (OwnedValue::Integer(a), OwnedValue::Integer(b)) => a == b,
+            (OwnedValue::Number(a), OwnedValue::Integer(b))
+            | (OwnedValue::Integer(b), OwnedValue::Number(a)) => float_equals_integer(*a, *b),
+            (OwnedValue::Boolean(a), OwnedValue::Boolean(b)) => a == b,
+            (OwnedValue::Null, OwnedValue::Null) => true,
+            _ => false,
+        }
+    }
+}
+
+impl OwnedValue {
+    /// The value at the JSON Pointer, if there is one.
+    pub fn pointer(&self, pointer: &str) -> Option<&OwnedValue> {
+        let mut current = self;
+        for token in pointer::parse(pointer)? {
+            current = match current {
+                OwnedValue::Object(map) => map.get(token.as_str())?,
+                OwnedValue::Array(items) => items.get(pointer::array_index(&token)?)?,
+                _ => return None,
+            };
+        }
+        Some(current)
+    }
+}
+
+/// Materializing would take more values than the given limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeLimitExceeded {
+    pub limit: usize,
+}
+
+impl fmt::Display for SizeLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the value has more than {} values", self.limit)
+    }
+}
+
+impl std::error::Error for SizeLimitExceeded {}
+
+// the amount of values we can still materialize before we hit the limit
+struct Budget {
+    limit: Option<usize>,
+    used: usize,
+}
+
+impl Budget {
+    fn new(limit: Option<usize>) -> Self {
+        Self { limit, used: 0 }
+    }
+
+    fn take(&mut self) -> Result<(), SizeLimitExceeded> {
+        self.used += 1;
+        match self.limit {
+            Some(limit) if self.used > limit => Err(SizeLimitExceeded { limit }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<U: UsageIndex> Value<'_, U> {
+    /// Materialize this value and everything under it.
+    pub fn to_owned_value(&self) -> OwnedValue {
+        self.to_owned_value_limited(&mut Budget::new(None))
+            .expect("No limit was set")
+    }
+
+    fn to_owned_value_limited(&self, budget: &mut Budget) -> Result<OwnedValue, SizeLimitExceeded> {
+        budget.take()?;
+        Ok(match self {
+            Value::Object(object) => OwnedValue::Object(object.to_map_limited(budget)?),
+            Value::Array(array) => OwnedValue::Array(array.to_vec_limited(budget)?),
+            Value::String(s) => OwnedValue::String(s.clone()),
+            Value::Number(n) => OwnedValue::Number(*n),
+            Value::Integer(i) => OwnedValue::Integer(*i),
+            Value::Boolean(b) => OwnedValue::Boolean(*b),
+            Value::Null => OwnedValue::Null,
+        })
+    }
+}
+
+impl<U: UsageIndex> ObjectValue<'_, U> {
+    /// Materialize the fields of this object, in document order.
+    pub fn to_map(&self) -> IndexMap<Arc<str>, OwnedValue> {
+        self.to_map_limited(&mut Budget::new(None))
+            .expect("No limit was set")
+    }
+
+    /// Materialize the fields of this object, failing if that takes more
+    /// than `limit` values (counting every nested value).
+    pub fn to_map_bounded(
+        &self,
+        limit: usize,
+    ) -> Result<IndexMap<Arc<str>, OwnedValue>, SizeLimitExceeded> {
+        self.to_map_limited(&mut Budget::new(Some(limit)))
+    }
+
+    fn to_map_limited(
+        &self,
+        budget: &mut Budget,
+    ) -> Result<IndexMap<Arc<str>, OwnedValue>, SizeLimitExceeded> {
+        let mut map = IndexMap::new();
+        for (key, value) in self.iter() {
+            map.insert(Arc::from(key), value.to_owned_value_limited(budget)?);
+        }
+        Ok(map)
+    }
+}
+
+impl<U: UsageIndex> ArrayValue<'_, U> {
+    /// Materialize the items of this array.
+    pub fn to_vec(&self) -> Vec<OwnedValue> {
+        self.to_vec_limited(&mut Budget::new(None))
+            .expect("No limit was set")
+    }
+
+    /// Materialize the items of this array, failing if that takes more than
+    /// `limit` values (counting every nested value).
+    pub fn to_vec_bounded(&self, limit: usize) -> Result<Vec<OwnedValue>, SizeLimitExceeded> {
+        self.to_vec_limited(&mut Budget::new(Some(limit)))
+    }
+
+    fn to_vec_limited(&self, budget: &mut Budget) -> Result<Vec<OwnedValue>, SizeLimitExceeded> {
+        self.iter()
+            .map(|value| value.to_owned_value_limited(budget))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::{BitpackingUsageBuilder, UsageBuilder};
+
+    use super::*;
+
+    #[test]
+    fn test_to_map() {
+        let doc = BitpackingUsageBuilder::parse(r#"{"b": [1, null], "a": {"c": "x"}}"#.as_bytes())
+            .unwrap();
+        let Value::Object(object) = doc.root_value() else {
+            panic!("Expected an object value");
+        };
+        let map = object.to_map();
+        let keys = map.keys().map(|k| k.as_ref()).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["b", "a"]);
+        assert_eq!(
+            map["b"],
+            OwnedValue::Array(vec![OwnedValue::Number(1.0), OwnedValue::Null])
+        );
+        let mut inner = IndexMap::new();
+        inner.insert(Arc::from("c"), OwnedValue::String("x".into()));
+        assert_eq!(map["a"], OwnedValue::Object(inner));
+    }
+
+    #[test]
+    fn test_pointer() {
+        let doc = BitpackingUsageBuilder::parse(r#"{"a": [1, {"b": true}]}"#.as_bytes()).unwrap();
+        let value = doc.root_value().to_owned_value();
+        assert_eq!(value.pointer(""), Some(&value));
+        assert_eq!(value.pointer("/a/0"), Some(&OwnedValue::Number(1.0)));
+        assert_eq!(value.pointer("/a/1/b"), Some(&OwnedValue::Boolean(true)));
+        assert_eq!(value.pointer("/a/2"), None);
+        assert_eq!(value.pointer("/x"), None);
+    }
+
+    #[test]
+    fn test_to_vec_bounded() {
+        let doc = BitpackingUsageBuilder::parse(r#"[1, [2, 3], true]"#.as_bytes()).unwrap();
+        let Value::Array(array) = doc.root_value() else {
+            panic!("Expected an array value");
+        };
+        assert_eq!(array.to_vec().len(), 3);
+        // 1, [2, 3], 2, 3, true makes 5 values
+        assert!(array.to_vec_bounded(5).is_ok());
+        assert_eq!(array.to_vec_bounded(4), Err(SizeLimitExceeded { limit: 4 }));
+    }
+}
@@ -0,0 +1,422 @@
+use std::{collections::HashMap, fmt, fs::File, io::Write, path::Path, sync::Arc};
+
+use parquet::{
+    basic::{Compression, LogicalType, Repetition, Type as PhysicalType},
+    data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, Int64Type},
+    errors::ParquetError,
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::types::Type,
+};
+use struson::writer::{JsonStreamWriter, JsonWriter};
+
+use crate::{info::NodeType, numbers::Number, usage::UsageIndex};
+
+use super::{Document, Node};
+
+/// Options for [`Document::write_parquet`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParquetOptions {
+    /// The most records in a row group; all the values of a row group are
+    /// held in memory while it is written.
+    pub row_group_size: usize,
+    /// Whether to compress the columns with Snappy.
+    pub compress: bool,
+}
+
+impl Default for ParquetOptions {
+    fn default() -> Self {
+        Self {
+            row_group_size: 64 * 1024,
+            compress: true,
+        }
+    }
+}
+
+/// An error while writing records as Parquet.
+#[derive(Debug)]
+pub enum ParquetWriteError {
+    Io(std::io::Error),
+    Parquet(ParquetError),
+    /// The records are the items of an array, so the node must be one.
+    NotAnArray,
+    /// An item of the array is not an object, at this node.
+    NotARecord(Node),
+    /// The records have no fields, so there are no columns.
+    NoColumns,
+}
+
+impl fmt::Display for ParquetWriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParquetWriteError::Io(err) => write!(f, "I/O error: {err}"),
+            ParquetWriteError::Parquet(err) => write!(f, "Parquet error: {err}"),
+            ParquetWriteError::NotAnArray => write!(f, "the records should be in an array"),
+            ParquetWriteError::NotARecord(node) => {
+                write!(f, "item {node:?} of the array is not an object")
+            }
+            ParquetWriteError::NoColumns => write!(f, "the records have no fields"),
+        }
+    }
+}
+
+impl std::error::Error for ParquetWriteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParquetWriteError::Io(err) => Some(err),
+            ParquetWriteError::Parquet(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ParquetWriteError {
+    fn from(err: std::io::Error) -> Self {
+        ParquetWriteError::Io(err)
+    }
+}
+
+impl From<ParquetError> for ParquetWriteError {
+    fn from(err: ParquetError) -> Self {
+        ParquetWriteError::Parquet(err)
+    }
+}
+
+// the type of a column, widened as more values are seen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColumnType {
+    // only nulls so far
+    Null,
+    Boolean,
+    Integer,
+    Double,
+    String,
+    // nested or mixed values, written as JSON text
+    Json,
+}
+
+impl ColumnType {
+    fn widen(self, other: ColumnType) -> ColumnType {
+        use ColumnType::*;
+        match (self, other) {
+            (Null, other) | (other, Null) => other,
+            (a, b) if a == b => a,
+            (Integer, Double) | (Double, Integer) => Double,
+            _ => Json,
+        }
+    }
+
+    fn parquet_type(self, name: &str) -> parquet::errors::Result<Type> {
+        let (physical, logical) = match self {
+            ColumnType::Boolean => (PhysicalType::BOOLEAN, None),
+            ColumnType::Integer => (PhysicalType::INT64, None),
+            ColumnType::Double => (PhysicalType::DOUBLE, None),
+            ColumnType::Null | ColumnType::String => {
+                (PhysicalType::BYTE_ARRAY, Some(LogicalType::String))
+            }
+            ColumnType::Json => (PhysicalType::BYTE_ARRAY, Some(LogicalType::Json)),
+        };
+        Type::primitive_type_builder(name, physical)
+            .with_repetition(Repetition::OPTIONAL)
+            .with_logical_type(logical)
+            .build()
+    }
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// Write the objects in an array as the rows of a Parquet file, for
+    /// analytical queries. For a field node the items of its value are
+    /// written.
+    ///
+    /// Every field name found in the records becomes a nullable column, in
+    /// the order the names are first found; a record without the field or
+    /// with a null has no value there. A column of booleans, integers or
+    /// strings gets that type, and one of integers and floats is written
+    /// as doubles. Columns of objects, arrays or mixed types are written as
+    /// JSON text. Of duplicate fields only the first counts.
+    ///
+    /// The records are read twice: once to find the columns and once to
+    /// write them.
+    pub fn write_parquet<P: AsRef<Path>>(
+        &self,
+        node: Node,
+        path: P,
+        options: &ParquetOptions,
+    ) -> Result<(), ParquetWriteError> {
+        self.write_parquet_to(node, File::create(path)?, options)
+    }
+
+    fn write_parquet_to<W: Write + Send>(
+        &self,
+        node: Node,
+        w: W,
+        options: &ParquetOptions,
+    ) -> Result<(), ParquetWriteError> {
+        let node = self.field_value(node);
+        if self.node_type(node) != &NodeType::Array {
+            return Err(ParquetWriteError::NotAnArray);
+        }
+        let (names, types) = self.record_columns(node)?;
+        if names.is_empty() {
+            return Err(ParquetWriteError::NoColumns);
+        }
+        let indexes = names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (*name, index))
+            .collect::<HashMap<_, _>>();
+        let fields = names
+            .iter()
+            .zip(&types)
+            .map(|(name, column_type)| column_type.parquet_type(name).map(Arc::new))
+            .collect::<Result<Vec<_>, _>>()?;
+        let schema = Type::group_type_builder("schema")
+            .with_fields(fields)
+            .build()?;
+        let compression = if options.compress {
+            Compression::SNAPPY
+        } else {
+            Compression::UNCOMPRESSED
+        };
+        let properties = WriterProperties::builder()
+            .set_compression(compression)
+            .build();
+        let mut writer = SerializedFileWriter::new(w, Arc::new(schema), Arc::new(properties))?;
+
+        let mut items = self.children(node).peekable();
+        while items.peek().is_some() {
+            // the value of every column in every row of the group
+            let mut columns = vec![Vec::new(); names.len()];
+            for item in items.by_ref().take(options.row_group_size.max(1)) {
+                for column in &mut columns {
+                    column.push(None);
+                }
+                for field in self.children(item) {
+                    let NodeType::Field(name) = self.node_type(field) else {
+                        unreachable!("Object children should be fields");
+                    };
+                    let value = &mut columns[indexes[name.as_str()]];
+                    let last = value.last_mut().expect("Row should have been added");
+                    if last.is_none() {
+                        *last = Some(self.field_value(field));
+                    }
+                }
+            }
+            let mut row_group = writer.next_row_group()?;
+            for (values, column_type) in columns.iter().zip(&types) {
+                let mut column = row_group
+                    .next_column()?
+                    .expect("Schema should have a column for every name");
+                self.write_column(&mut column, values, *column_type)?;
+                column.close()?;
+            }
+            row_group.close()?;
+        }
+        writer.close()?;
+        Ok(())
+    }
+
+    // the names of the fields of the records and the types of their columns
+    fn record_columns(
+        &self,
+        array: Node,
+    ) -> Result<(Vec<&str>, Vec<ColumnType>), ParquetWriteError> {
+        let mut names = Vec::new();
+        let mut types = Vec::new();
+        let mut indexes = HashMap::new();
+        for item in self.children(array) {
+            if self.node_type(item) != &NodeType::Object {
+                return Err(ParquetWriteError::NotARecord(item));
+            }
+            for field in self.children(item) {
+                let NodeType::Field(name) = self.node_type(field) else {
+                    unreachable!("Object children should be fields");
+                };
+                let index = *indexes.entry(name.as_str()).or_insert_with(|| {
+                    names.push(name.as_str());
+                    types.push(ColumnType::Null);
+                    names.len() - 1
+                });
+                let column_type = match self.node_type(self.field_value(field)) {
+                    NodeType::Boolean => ColumnType::Boolean,
+                    NodeType::Number => match self.number_value(self.field_value(field)) {
+                        Number::Integer(_) => ColumnType::Integer,
+                        // beyond an Int64 column
+                        Number::Float(_) | Number::Unsigned(_) => ColumnType::Double,
+                    },
+                    NodeType::String => ColumnType::String,
+                    NodeType::Null => ColumnType::Null,
+                    NodeType::Object | NodeType::Array => ColumnType::Json,
+                    NodeType::Field(_) => unreachable!("Fields should be resolved to their value"),
+                };
+                types[index] = types[index].widen(column_type);
+            }
+        }
+        Ok((names, types))
+    }
+
+    fn write_column(
+        &self,
+        column: &mut parquet::file::writer::SerializedColumnWriter<'_>,
+        values: &[Option<Node>],
+        column_type: ColumnType,
+    ) -> Result<(), ParquetWriteError> {
+        // a null is written as a missing value
+        let values = values
+            .iter()
+            .map(|value| value.filter(|&node| self.node_type(node) != &NodeType::Null))
+            .collect::<Vec<_>>();
+        let levels = values
+            .iter()
+            .map(|value| i16::from(value.is_some()))
+            .collect::<Vec<_>>();
+        let present = values.iter().flatten().copied();
+        match column_type {
+            ColumnType::Boolean => {
+                let values = present
+                    .map(|node| self.boolean_value(node))
+                    .collect::<Vec<_>>();
+                column
+                    .typed::<BoolType>()
+                    .write_batch(&values, Some(&levels), None)?;
+            }
+            ColumnType::Integer => {
+                let values = present
+                    .map(|node| match self.number_value(node) {
+                        Number::Integer(i) => i,
+                        Number::Float(_) | Number::Unsigned(_) => {
+                            unreachable!("Integer column should have no floats")
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                column
+                    .typed::<Int64Type>()
+                    .write_batch(&values, Some(&levels), None)?;
+            }
+            ColumnType::Double => {
+                let values = present
+                    .map(|node| self.number_value(node).to_f64())
+                    .collect::<Vec<_>>();
+                column
+                    .typed::<DoubleType>()
+                    .write_batch(&values, Some(&levels), None)?;
+            }
+            ColumnType::Null | ColumnType::String => {
+                let values = present
+                    .map(|node| {
+                        let text = self.as_str(node).expect("Node should be a string");
+                        ByteArray::from(text.as_bytes().to_vec())
+                    })
+                    .collect::<Vec<_>>();
+                column
+                    .typed::<ByteArrayType>()
+                    .write_batch(&values, Some(&levels), None)?;
+            }
+            ColumnType::Json => {
+                let values = present
+                    .map(|node| {
+                        let mut out = Vec::new();
+                        let mut writer = JsonStreamWriter::new(&mut out);
+                        self.serialize_node(node, &mut writer)?;
+                        writer.finish_document()?;
+                        Ok(ByteArray::from(out))
+                    })
+                    .collect::<std::io::Result<Vec<_>>>()?;
+                column
+                    .typed::<ByteArrayType>()
+                    .write_batch(&values, Some(&levels), None)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parquet::{
+        file::reader::{FileReader, SerializedFileReader},
+        record::Field,
+    };
+
+    use crate::usage::{BitpackingUsageBuilder, UsageBuilder};
+
+    use super::*;
+
+    fn read_rows(path: &Path) -> (usize, Vec<Vec<(String, Field)>>) {
+        let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+        let row_groups = reader.metadata().num_row_groups();
+        let rows = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| {
+                row.unwrap()
+                    .get_column_iter()
+                    .map(|(name, field)| (name.clone(), field.clone()))
+                    .collect()
+            })
+            .collect();
+        (row_groups, rows)
+    }
+
+    #[test]
+    fn test_write_parquet() {
+        let doc = BitpackingUsageBuilder::parse(
+            r#"{"records": [
+                {"id": 1, "name": "a", "score": 1, "ok": true, "tags": ["x"]},
+                {"id": 2, "score": 2.5, "ok": null, "extra": "e", "id": 9},
+                {"id": 3, "name": "c", "score": 3, "ok": false, "tags": {"y": 1}}
+            ]}"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("records.parquet");
+        let records = doc.first_child(doc.root()).unwrap();
+        let options = ParquetOptions {
+            row_group_size: 2,
+            ..ParquetOptions::default()
+        };
+        doc.write_parquet(records, &path, &options).unwrap();
+
+        let (row_groups, rows) = read_rows(&path);
+        assert_eq!(row_groups, 2);
+        let names = rows[0]
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["id", "name", "score", "ok", "tags", "extra"]);
+        assert_eq!(
+            rows.iter().map(|row| row[0].1.clone()).collect::<Vec<_>>(),
+            [Field::Long(1), Field::Long(2), Field::Long(3)]
+        );
+        assert_eq!(rows[1][1].1, Field::Null);
+        assert_eq!(rows[0][1].1, Field::Str("a".to_string()));
+        assert_eq!(rows[1][2].1, Field::Double(2.5));
+        assert_eq!(rows[2][2].1, Field::Double(3.0));
+        assert_eq!(rows[1][3].1, Field::Null);
+        assert_eq!(rows[2][3].1, Field::Bool(false));
+        assert_eq!(rows[0][4].1, Field::Str(r#"["x"]"#.to_string()));
+        assert_eq!(rows[2][4].1, Field::Str(r#"{"y":1}"#.to_string()));
+        assert_eq!(rows[1][5].1, Field::Str("e".to_string()));
+    }
+
+    #[test]
+    fn test_write_parquet_errors() {
+        let doc = BitpackingUsageBuilder::parse(r#"[{"a": 1}, 2]"#.as_bytes()).unwrap();
+        let mut out = Vec::new();
+        let options = ParquetOptions::default();
+        let item = doc.nth_child(doc.root(), 1).unwrap();
+        assert!(matches!(
+            doc.write_parquet_to(doc.root(), &mut out, &options),
+            Err(ParquetWriteError::NotARecord(node)) if node == item
+        ));
+        assert!(matches!(
+            doc.write_parquet_to(item, &mut out, &options),
+            Err(ParquetWriteError::NotAnArray)
+        ));
+        let doc = BitpackingUsageBuilder::parse("[{}]".as_bytes()).unwrap();
+        assert!(matches!(
+            doc.write_parquet_to(doc.root(), &mut out, &options),
+            Err(ParquetWriteError::NoColumns)
+        ));
+    }
+}
@@ -0,0 +1,632 @@
+use std::{fmt, io::Write, sync::Arc};
+
+use ahash::HashSet;
+use struson::writer::{JsonStreamWriter, JsonWriter};
+
+use crate::{
+    builder::Builder,
+    info::NodeType,
+    pointer,
+    usage::{UsageBuilder, UsageIndex},
+};
+
+use super::{Change, Document, Node, OwnedValue};
+
+/// An operation of a JSON Patch (RFC 6902), with its JSON Pointers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchOperation {
+    Add { path: String, value: OwnedValue },
+    Remove { path: String },
+    Replace { path: String, value: OwnedValue },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test { path: String, value: OwnedValue },
+}
+
+/// An error reading or applying a JSON Patch with
+/// [`Document::apply_patch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchError {
+    /// The operation at this index of the patch is not a valid operation,
+    /// or the patch is not an array.
+    InvalidOperation(usize),
+    /// The pointer is not a valid JSON Pointer.
+    InvalidPointer(String),
+    /// The pointer does not point to a value, or for adding, to a place
+    /// where a value can be added.
+    NotFound(String),
+    /// The whole document cannot be removed or moved.
+    RemoveRoot,
+    /// The value at the pointer is not the value a test operation expects.
+    TestFailed(String),
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::InvalidOperation(index) => write!(f, "invalid patch operation {index}"),
+            PatchError::InvalidPointer(pointer) => write!(f, "invalid JSON Pointer '{pointer}'"),
+            PatchError::NotFound(pointer) => write!(f, "no value at '{pointer}'"),
+            PatchError::RemoveRoot => write!(f, "the root cannot be removed or moved"),
+            PatchError::TestFailed(pointer) => write!(f, "test failed at '{pointer}'"),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+impl PatchOperation {
+    /// The operations of a JSON Patch, which is an array of operation
+    /// objects such as `{"op": "add", "path": "/a", "value": 1}`.
+    pub fn from_patch(patch: &OwnedValue) -> Result<Vec<PatchOperation>, PatchError> {
+        let OwnedValue::Array(operations) = patch else {
+            return Err(PatchError::InvalidOperation(0));
+        };
+        operations
+            .iter()
+            .enumerate()
+            .map(|(index, operation)| {
+                Self::from_value(operation).ok_or(PatchError::InvalidOperation(index))
+            })
+            .collect()
+    }
+
+    fn from_value(operation: &OwnedValue) -> Option<PatchOperation> {
+        let OwnedValue::Object(members) = operation else {
+            return None;
+        };
+        let string = |name: &str| match members.get(name)? {
+            OwnedValue::String(s) => Some(s.to_string()),
+            _ => None,
+        };
+        let value = || members.get("value").cloned();
+        let path = string("path")?;
+        Some(match string("op")?.as_str() {
+            "add" => PatchOperation::Add {
+                path,
+                value: value()?,
+            },
+            "remove" => PatchOperation::Remove { path },
+            "replace" => PatchOperation::Replace {
+                path,
+                value: value()?,
+            },
+            "move" => PatchOperation::Move {
+                from: string("from")?,
+                path,
+            },
+            "copy" => PatchOperation::Copy {
+                from: string("from")?,
+                path,
+            },
+            "test" => PatchOperation::Test {
+                path,
+                value: value()?,
+            },
+            _ => return None,
+        })
+    }
+}
+
+// the document as patched so far: containers on the paths that operations
+// touched are taken apart, everything else still refers to the document
+#[derive(Debug, Clone)]
+enum Patched {
+    Original(Node),
+    Owned(OwnedValue),
+    Object(Vec<(Arc<str>, Patched)>),
+    Array(Vec<Patched>),
+}
+
+impl Patched {
+    // take a container apart into its fields or items
+    fn expand<U: UsageIndex>(&mut self, document: &Document<U>) {
+        let expanded = match self {
+            Patched::Original(node) => match document.node_type(*node) {
+                NodeType::Object => Patched::Object(
+                    document
+                        .children(*node)
+                        .map(|field| {
+                            let NodeType::Field(name) = document.node_type(field) else {
+                                unreachable!("Object children should be fields");
+                            };
+                            (
+                                Arc::from(name.as_str()),
+                                Patched::Original(document.field_value(field)),
+                            )
+                        })
+                        .collect(),
+                ),
+                NodeType::Array => {
+                    Patched::Array(document.children(*node).map(Patched::Original).collect())
+                }
+                _ => return,
+            },
+            Patched::Owned(OwnedValue::Object(members)) => Patched::Object(
+                members
+                    .iter()
+                    .map(|(name, value)| (name.clone(), Patched::Owned(value.clone())))
+                    .collect(),
+            ),
+            Patched::Owned(OwnedValue::Array(items)) => {
+                Patched::Array(items.iter().cloned().map(Patched::Owned).collect())
+            }
+            _ => return,
+        };
+        *self = expanded;
+    }
+
+    // the value at the tokens, taking apart the containers on the way
+    fn get_mut<U: UsageIndex>(
+        &mut self,
+        document: &Document<U>,
+        tokens: &[String],
+    ) -> Option<&mut Patched> {
+        let Some((token, rest)) = tokens.split_first() else {
+            return Some(self);
+        };
+        self.expand(document);
+        let child = match self {
+            Patched::Object(fields) => fields
+                .iter_mut()
+                .find(|(name, _)| **name == **token)
+                .map(|(_, value)| value)?,
+            Patched::Array(items) => items.get_mut(pointer::array_index(token)?)?,
+            _ => return None,
+        };
+        child.get_mut(document, rest)
+    }
+
+    fn to_owned_value<U: UsageIndex>(&self, document: &Document<U>) -> OwnedValue {
+        match self {
+            Patched::Original(node) => document.value(*node).to_owned_value(),
+            Patched::Owned(value) => value.clone(),
+            Patched::Object(fields) => OwnedValue::Object(
+                fields
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.to_owned_value(document)))
+                    .collect(),
+            ),
+            Patched::Array(items) => OwnedValue::Array(
+                items
+                    .iter()
+                    .map(|item| item.to_owned_value(document))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn build<B: UsageBuilder, U: UsageIndex>(
+        &self,
+        builder: &mut Builder<B>,
+        document: &Document<U>,
+    ) {
+        match self {
+            Patched::Original(node) => builder.value(&document.value(*node)),
+            Patched::Owned(value) => builder.owned_value(value),
+            Patched::Object(fields) => {
+                builder.open(NodeType::Object);
+                for (name, value) in fields {
+                    let close_field_id = builder.open_field(name);
+                    value.build(builder, document);
+                    builder.close_field(close_field_id);
+                }
+                builder.close(NodeType::Object);
+            }
+            Patched::Array(items) => {
+                builder.open(NodeType::Array);
+                for item in items {
+                    item.build(builder, document);
+                }
+                builder.close(NodeType::Array);
+            }
+        }
+    }
+}
+
+// applies operations to a document without changing it
+struct Patcher<'a, U: UsageIndex> {
+    document: &'a Document<U>,
+    root: Patched,
+}
+
+impl<U: UsageIndex> Patcher<'_, U> {
+    fn apply(&mut self, index: usize, operation: &PatchOperation) -> Result<(), PatchError> {
+        match operation {
+            PatchOperation::Add { path, value } => self.add(path, Patched::Owned(value.clone())),
+            PatchOperation::Remove { path } => self.remove(path).map(|_| ()),
+            PatchOperation::Replace { path, value } => {
+                *self.get_mut(path)? = Patched::Owned(value.clone());
+                Ok(())
+            }
+            PatchOperation::Move { from, path } => {
+                if from == path {
+                    return Ok(());
+                }
+                // a value can't be moved into itself
+                if path.starts_with(&format!("{from}/")) {
+                    return Err(PatchError::InvalidOperation(index));
+                }
+                let value = self.remove(from)?;
+                self.add(path, value)
+            }
+            PatchOperation::Copy { from, path } => {
+                let value = self.get_mut(from)?.clone();
+                self.add(path, value)
+            }
+            PatchOperation::Test { path, value } => {
+                let document = self.document;
+                if self.get_mut(path)?.to_owned_value(document) == *value {
+                    Ok(())
+                } else {
+                    Err(PatchError::TestFailed(path.clone()))
+                }
+            }
+        }
+    }
+
+    fn get_mut(&mut self, path: &str) -> Result<&mut Patched, PatchError> {
+        let tokens = tokens(path)?;
+        self.root
+            .get_mut(self.document, &tokens)
+            .ok_or_else(|| PatchError::NotFound(path.to_string()))
+    }
+
+    // the container a path is in, taken apart, and the last token of the
+    // path; `None` for the root
+    fn parent(&mut self, path: &str) -> Result<Option<(&mut Patched, String)>, PatchError> {
+        let mut tokens = tokens(path)?;
+        let Some(last) = tokens.pop() else {
+            return Ok(None);
+        };
+        let parent = self
+            .root
+            .get_mut(self.document, &tokens)
+            .ok_or_else(|| PatchError::NotFound(path.to_string()))?;
+        parent.expand(self.document);
+        Ok(Some((parent, last)))
+    }
+
+    fn add(&mut self, path: &str, value: Patched) -> Result<(), PatchError> {
+        let not_found = || PatchError::NotFound(path.to_string());
+        let Some((parent, token)) = self.parent(path)? else {
+            self.root = value;
+            return Ok(());
+        };
+        match parent {
+            Patched::Object(fields) => match fields.iter_mut().find(|(name, _)| **name == *token) {
+                Some((_, existing)) => *existing = value,
+                None => fields.push((Arc::from(token), value)),
+            },
+            Patched::Array(items) if token == "-" => items.push(value),
+            Patched::Array(items) => {
+                let index = pointer::array_index(&token).filter(|&index| index <= items.len());
+                items.insert(index.ok_or_else(not_found)?, value);
+            }
+            _ => return Err(not_found()),
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &str) -> Result<Patched, PatchError> {
+        let not_found = || PatchError::NotFound(path.to_string());
+        let Some((parent, token)) = self.parent(path)? else {
+            return Err(PatchError::RemoveRoot);
+        };
+        match parent {
+            Patched::Object(fields) => {
+                let position = fields
+                    .iter()
+                    .position(|(name, _)| **name == *token)
+                    .ok_or_else(not_found)?;
+                Ok(fields.remove(position).1)
+            }
+            Patched::Array(items) => {
+                let index = pointer::array_index(&token).filter(|&index| index < items.len());
+                Ok(items.remove(index.ok_or_else(not_found)?))
+            }
+            _ => Err(not_found()),
+        }
+    }
+}
+
+fn tokens(path: &str) -> Result<Vec<String>, PatchError> {
+    pointer::parse(path).ok_or_else(|| PatchError::InvalidPointer(path.to_string()))
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// A copy of this document with a JSON Patch (RFC 6902) applied.
+    ///
+    /// The operations are applied in order, and the patch fails as a whole
+    /// if one of them fails. Only the containers on the paths that the
+    /// operations touch are taken apart; all other values are copied from
+    /// this document into the new one as they are.
+    pub fn apply_patch<B: UsageBuilder<Index = U>>(
+        &self,
+        operations: &[PatchOperation],
+    ) -> Result<Document<U>, PatchError> {
+        let mut patcher = Patcher {
+            document: self,
+            root: Patched::Original(self.root()),
+        };
+        for (index, operation) in operations.iter().enumerate() {
+            patcher.apply(index, operation)?;
+        }
+        let mut builder = Builder::<B>::new();
+        patcher.root.build(&mut builder, self);
+        Ok(builder.build())
+    }
+
+    /// Write a JSON Patch (RFC 6902) that turns this document into
+    /// `other`, as found by [`Document::diff`].
+    ///
+    /// Changed values are replaced and items only in one of the documents
+    /// are added or removed. A field removed from an object is moved
+    /// instead if a field added to the same object has an equal value, as
+    /// when a field is renamed. Operations are written as the documents
+    /// are compared, and values are copied straight from `other`.
+    pub fn write_json_patch<V: UsageIndex, W: Write>(
+        &self,
+        other: &Document<V>,
+        mut w: W,
+    ) -> std::io::Result<()> {
+        let mut writer = JsonStreamWriter::new(&mut w);
+        writer.begin_array()?;
+        let mut diff = self.diff(other);
+        // the values of added fields that were moved there instead
+        let mut moved = HashSet::default();
+        // the array whose last items are being removed and the path of the
+        // first of them, where the others end up as it is removed
+        let mut removing: Option<(Node, String)> = None;
+        while let Some(change) = diff.next() {
+            match change {
+                Change::Changed { path, new, .. } => {
+                    write_operation(&mut writer, "replace", None, &path, Some((other, new)))?;
+                }
+                Change::Added { path, node } => {
+                    if !moved.contains(&node) {
+                        write_operation(&mut writer, "add", None, &path, Some((other, node)))?;
+                    }
+                }
+                Change::Removed { path, node } => {
+                    let parent = self
+                        .parent(node)
+                        .expect("Removed value should have a parent");
+                    if let NodeType::Field(_) = self.node_type(parent) {
+                        let (old_object, new_object) = diff
+                            .objects()
+                            .expect("Removed field should be in an object");
+                        match self.moved_field(node, old_object, other, new_object, &moved) {
+                            Some((name, value)) => {
+                                moved.insert(value);
+                                // the last token is escaped, so it has no `/`
+                                let object_path = &path[..path.rfind('/').unwrap_or(0)];
+                                let to = pointer::push(object_path, name);
+                                write_operation::<_, V>(
+                                    &mut writer,
+                                    "move",
+                                    Some(&path),
+                                    &to,
+                                    None,
+                                )?;
+                            }
+                            None => {
+                                write_operation::<_, V>(&mut writer, "remove", None, &path, None)?;
+                            }
+                        }
+                    } else {
+                        let path = match &removing {
+                            Some((array, first)) if *array == parent => first.clone(),
+                            _ => {
+                                removing = Some((parent, path.clone()));
+                                path
+                            }
+                        };
+                        write_operation::<_, V>(&mut writer, "remove", None, &path, None)?;
+                    }
+                }
+            }
+        }
+        writer.end_array()?;
+        writer.finish_document()?;
+        Ok(())
+    }
+
+    // a field added to the new object whose value equals the value of a
+    // field removed from the old object, as its name and value
+    fn moved_field<'a, V: UsageIndex>(
+        &self,
+        removed: Node,
+        old_object: Node,
+        other: &'a Document<V>,
+        new_object: Node,
+        moved: &HashSet<Node>,
+    ) -> Option<(&'a str, Node)> {
+        let hash = self.subtree_hash(removed);
+        other.children(new_object).find_map(|field| {
+            let NodeType::Field(name) = other.node_type(field) else {
+                unreachable!("Object children should be fields");
+            };
+            let value = other.field_value(field);
+            let added = other.field_by_name(new_object, name) == Some(field)
+                && self.field_by_name(old_object, name).is_none()
+                && !moved.contains(&value);
+            (added
+                && other.subtree_hash(value) == hash
+                && self.diff_nodes(removed, other, value).next().is_none())
+            .then_some((name.as_str(), value))
+        })
+    }
+}
+
+fn write_operation<W: Write, V: UsageIndex>(
+    writer: &mut JsonStreamWriter<W>,
+    op: &str,
+    from: Option<&str>,
+    path: &str,
+    value: Option<(&Document<V>, Node)>,
+) -> std::io::Result<()> {
+    writer.begin_object()?;
+    writer.name("op")?;
+    writer.string_value(op)?;
+    if let Some(from) = from {
+        writer.name("from")?;
+        writer.string_value(from)?;
+    }
+    writer.name("path")?;
+    writer.string_value(path)?;
+    if let Some((document, node)) = value {
+        writer.name("value")?;
+        document.serialize_node(node, writer)?;
+    }
+    writer.end_object()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::{BitpackingUsageBuilder, RoaringUsageBuilder, UsageBuilder};
+
+    use super::*;
+
+    fn apply(json: &str, patch: &str) -> Result<String, PatchError> {
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+        let patch = BitpackingUsageBuilder::parse(patch.as_bytes())
+            .unwrap()
+            .root_value()
+            .to_owned_value();
+        let operations = PatchOperation::from_patch(&patch)?;
+        let patched = doc.apply_patch::<BitpackingUsageBuilder>(&operations)?;
+        let mut out = Vec::new();
+        patched.serialize(&mut out).unwrap();
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    fn patch(old: &str, new: &str) -> String {
+        let old = BitpackingUsageBuilder::parse(old.as_bytes()).unwrap();
+        let new = RoaringUsageBuilder::parse(new.as_bytes()).unwrap();
+        let mut out = Vec::new();
+        old.write_json_patch(&new, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_write_json_patch() {
+        assert_eq!(
+            patch(
+                r#"{"id": 1, "name": "a", "tags": ["x", "y", "z", "w"], "meta": {"v": [1, 2]}}"#,
+                r#"{"id": 1, "name": "b", "tags": ["x"], "meta": {"w": [1, 2], "n": null}, "new": {}}"#,
+            ),
+            concat!(
+                r#"[{"op":"replace","path":"/name","value":"b"},"#,
+                r#"{"op":"remove","path":"/tags/1"},"#,
+                r#"{"op":"remove","path":"/tags/1"},"#,
+                r#"{"op":"remove","path":"/tags/1"},"#,
+                r#"{"op":"move","from":"/meta/v","path":"/meta/w"},"#,
+                r#"{"op":"add","path":"/meta/n","value":null},"#,
+                r#"{"op":"add","path":"/new","value":{}}]"#
+            )
+        );
+        assert_eq!(patch(r#"[1, {"a": 2}]"#, r#"[1.0, {"a": 2}]"#), "[]");
+        assert_eq!(
+            patch(r#"{"a": 1}"#, r#"[1, 2]"#),
+            r#"[{"op":"replace","path":"","value":[1,2]}]"#
+        );
+    }
+
+    #[test]
+    fn test_write_json_patch_moves_once() {
+        // two removed fields with the same value move to two new fields
+        assert_eq!(
+            patch(
+                r#"{"a/b": 1, "c": 1, "d": 1}"#,
+                r#"{"x": 1, "y": 1, "d": 1}"#
+            ),
+            concat!(
+                r#"[{"op":"move","from":"/a~1b","path":"/x"},"#,
+                r#"{"op":"move","from":"/c","path":"/y"}]"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_apply_patch() {
+        let json = r#"{"a": {"b": [1, 2, 3]}, "c": "x", "d": [{"e": true}]}"#;
+        assert_eq!(
+            apply(
+                json,
+                r#"[{"op": "test", "path": "/a/b", "value": [1, 2.0, 3]},
+                    {"op": "add", "path": "/a/b/1", "value": 9},
+                    {"op": "add", "path": "/a/b/-", "value": {"f": null}},
+                    {"op": "replace", "path": "/c", "value": "y"},
+                    {"op": "remove", "path": "/a/b/0"},
+                    {"op": "move", "from": "/d/0/e", "path": "/e"},
+                    {"op": "copy", "from": "/a/b/3", "path": "/d/0/g"},
+                    {"op": "add", "path": "/a/b/3/f", "value": 1}]"#
+            ),
+            Ok(
+                r#"{"a":{"b":[9,2,3,{"f":1}]},"c":"y","d":[{"g":{"f":null}}],"e":true}"#
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            apply(json, r#"[{"op": "replace", "path": "", "value": 1}]"#),
+            Ok("1".to_string())
+        );
+        assert_eq!(
+            apply(json, "[]"),
+            Ok(r#"{"a":{"b":[1,2,3]},"c":"x","d":[{"e":true}]}"#.to_string())
+        );
+    }
+
+    #[test]
+    fn test_apply_patch_errors() {
+        let json = r#"{"a": [1], "b": 2}"#;
+        let error = |patch: &str| apply(json, patch).unwrap_err();
+        assert_eq!(error("{}"), PatchError::InvalidOperation(0));
+        assert_eq!(
+            error(r#"[{"op": "test", "path": "/b", "value": 2}, {"op": "add", "path": "/a"}]"#),
+            PatchError::InvalidOperation(1)
+        );
+        assert_eq!(
+            error(r#"[{"op": "nope", "path": "/a"}]"#),
+            PatchError::InvalidOperation(0)
+        );
+        assert_eq!(
+            error(r#"[{"op": "move", "from": "/a", "path": "/a/0"}]"#),
+            PatchError::InvalidOperation(0)
+        );
+        assert_eq!(
+            error(r#"[{"op": "remove", "path": "a"}]"#),
+            PatchError::InvalidPointer("a".to_string())
+        );
+        assert_eq!(
+            error(r#"[{"op": "add", "path": "/a/2", "value": 1}]"#),
+            PatchError::NotFound("/a/2".to_string())
+        );
+        assert_eq!(
+            error(r#"[{"op": "remove", "path": "/b/c"}]"#),
+            PatchError::NotFound("/b/c".to_string())
+        );
+        assert_eq!(
+            error(r#"[{"op": "remove", "path": ""}]"#),
+            PatchError::RemoveRoot
+        );
+        assert_eq!(
+            error(r#"[{"op": "test", "path": "/a", "value": [2]}]"#),
+            PatchError::TestFailed("/a".to_string())
+        );
+    }
+
+    #[test]
+    fn test_apply_written_patch() {
+        let old = r#"{"id": 1, "tags": ["x", "y", "z"], "meta": {"v": [1, 2], "k": {}}, "s": [1]}"#;
+        let new =
+            r#"{"id": 2, "tags": ["x"], "meta": {"w": [1, 2], "k": {"n": null}}, "s": [1, 2, 3]}"#;
+        let applied = apply(old, &patch(old, new)).unwrap();
+        let applied = BitpackingUsageBuilder::parse(applied.as_bytes()).unwrap();
+        let new = BitpackingUsageBuilder::parse(new.as_bytes()).unwrap();
+        // fields that moved or were added come last, so only their order
+        // differs
+        assert_eq!(applied.diff(&new).next(), None);
+    }
+}
@@ -0,0 +1,114 @@
+use std::iter::FusedIterator;
+
+use crate::{
+    info::{NodeInfo, NodeInfoId, NodeType},
+    usage::UsageIndex,
+};
+
+use super::{Document, Node};
+
+/// An iterator over the number nodes of a document whose value is within a
+/// range, in document order.
+///
+/// Created by [`Document::numbers_in_range`].
+pub struct NumbersInRange<'a, U: UsageIndex> {
+    document: &'a Document<U>,
+    node_info_id: Option<NodeInfoId>,
+    low: f64,
+    high: f64,
+    // the number id to continue searching from
+    number_id: usize,
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// Iterate over the number nodes with a value from `low` up to and
+    /// including `high`, such as the matches of a filter like
+    /// `?@.price > 100`.
+    ///
+    /// Numbers are compared as floats, so integers beyond 2^53 are rounded
+    /// first. The smallest and largest number of every chunk of numbers is
+    /// kept, so chunks that can't match are skipped without looking at
+    /// their numbers.
+    pub fn numbers_in_range(&self, low: f64, high: f64) -> NumbersInRange<'_, U> {
+        let node_info_id = self
+            .structure
+            .usage_index()
+            .node_lookup()
+            .by_node_info(&NodeInfo::open(NodeType::Number));
+        NumbersInRange {
+            document: self,
+            node_info_id,
+            low,
+            high,
+            number_id: 0,
+        }
+    }
+}
+
+impl<U: UsageIndex> Iterator for NumbersInRange<'_, U> {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // a document without numbers has no node info for them
+        let node_info_id = self.node_info_id?;
+        let number_id = self
+            .document
+            .numbers
+            .next_in_range(self.number_id, self.low, self.high)?;
+        self.number_id = number_id + 1;
+        let position = self
+            .document
+            .structure
+            .usage_index()
+            .select(number_id, node_info_id)?;
+        Some(Node::new(position))
+    }
+}
+
+impl<U: UsageIndex> FusedIterator for NumbersInRange<'_, U> {}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Value,
+        usage::{BitpackingUsageBuilder, UsageBuilder},
+    };
+
+    #[test]
+    fn test_numbers_in_range() {
+        let json = format!(
+            r#"{{"items": [{}], "total": 150.5}}"#,
+            (0..1000)
+                .map(|i| format!(r#"{{"id": {i}, "price": {}}}"#, i % 7))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+        let found = doc
+            .numbers_in_range(100.0, 102.0)
+            .map(|node| doc.value(node))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                Value::Integer(100),
+                Value::Integer(101),
+                Value::Integer(102)
+            ]
+        );
+        // the ids from 150 and the total
+        assert_eq!(doc.numbers_in_range(150.0, f64::INFINITY).count(), 851);
+        assert_eq!(doc.numbers_in_range(7.0, 7.5).count(), 1);
+        assert_eq!(doc.numbers_in_range(2000.0, 3000.0).next(), None);
+    }
+
+    #[test]
+    fn test_numbers_in_range_no_numbers() {
+        let doc = BitpackingUsageBuilder::parse(r#"["a", true]"#.as_bytes()).unwrap();
+        assert_eq!(
+            doc.numbers_in_range(f64::NEG_INFINITY, f64::INFINITY)
+                .next(),
+            None
+        );
+    }
+}
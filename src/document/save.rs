@@ -0,0 +1,359 @@
+use std::io::{self, BufWriter, Read, Write};
+
+use crate::{
+    container::{Container, ContainerWriter, Section},
+    numbers::Numbers,
+    persist::{self, LoadError},
+    source_spans::SourceSpans,
+    structure::Structure,
+    text::{TextUsage, TextValidation},
+    usage::UsageIndex,
+};
+
+use super::{Document, IntegrityError};
+
+// read a section completely, so trailing garbage is caught too
+fn read_section<T>(
+    container: &Container,
+    section: Section,
+    read: impl FnOnce(&mut &[u8]) -> Result<T, LoadError>,
+) -> Result<T, LoadError> {
+    let mut data = container.section(section)?;
+    let value = read(&mut data)?;
+    if !data.is_empty() {
+        return Err(LoadError::Corrupt("trailing data in section"));
+    }
+    Ok(value)
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// Save the document in a compact binary [container](crate::container)
+    /// that [`Document::load`] reads back.
+    ///
+    /// Text is saved in its compressed blocks and the succinct structure is
+    /// saved as is, so this is much cheaper than serializing to JSON and
+    /// parsing it again.
+    pub fn save<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut writer = ContainerWriter::new();
+        self.write_structure(&mut writer)?;
+        self.write_values(&mut writer)?;
+        writer.write(BufWriter::new(w))
+    }
+
+    /// Save only the structure of the document: its tree, the node types
+    /// and field names, and where each of them is used. Together with
+    /// [`Document::save_values`] this is what [`Document::save`] saves,
+    /// split in two so the structure can be cached apart from the text,
+    /// numbers and booleans. [`Document::load_parts`] reads them back.
+    pub fn save_structure<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut writer = ContainerWriter::new();
+        self.write_structure(&mut writer)?;
+        writer.write(BufWriter::new(w))
+    }
+
+    /// Save everything but the structure that [`Document::save_structure`]
+    /// saves.
+    pub fn save_values<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut writer = ContainerWriter::new();
+        self.write_values(&mut writer)?;
+        writer.write(BufWriter::new(w))
+    }
+
+    fn write_structure(&self, writer: &mut ContainerWriter) -> io::Result<()> {
+        writer.section(Section::Structure, |w| self.structure.write(w))
+    }
+
+    fn write_values(&self, writer: &mut ContainerWriter) -> io::Result<()> {
+        writer.section(Section::Text, |w| self.text_usage.write(w))?;
+        writer.section(Section::Numbers, |w| self.numbers.write(w))?;
+        writer.section(Section::Booleans, |w| {
+            persist::write_bit_vec(w, &self.booleans)
+        })?;
+        if let Some(lexemes) = &self.number_lexemes {
+            writer.section(Section::NumberLexemes, |w| lexemes.write(w))?;
+        }
+        if let Some(spans) = &self.source_spans {
+            writer.section(Section::SourceSpans, |w| spans.write(w))?;
+        }
+        Ok(())
+    }
+
+    /// Load a document saved by [`Document::save`].
+    ///
+    /// Reads are not buffered, so wrap files in a
+    /// [`BufReader`](std::io::BufReader). Loading checks the checksum of
+    /// every section and that the parts of the document fit together, and
+    /// text is checked with [`TextValidation::Checked`] as it is
+    /// decompressed. Use [`Document::verify`] to check everything up front.
+    ///
+    /// ```
+    /// use colchis::{BitpackingUsageBuilder, Document, EliasFanoUsageIndex};
+    ///
+    /// let doc = Document::parse::<BitpackingUsageBuilder, _>(r#"{"a": 1}"#.as_bytes()).unwrap();
+    /// let mut saved = Vec::new();
+    /// doc.save(&mut saved).unwrap();
+    ///
+    /// let loaded = Document::<EliasFanoUsageIndex>::load(saved.as_slice()).unwrap();
+    /// let mut json = Vec::new();
+    /// loaded.serialize(&mut json).unwrap();
+    /// assert_eq!(json, br#"{"a":1}"#);
+    /// ```
+    pub fn load<R: Read>(r: R) -> Result<Self, LoadError> {
+        let container = Container::read(r)?;
+        Self::from_containers(&container, &container)
+    }
+
+    /// Load a document saved in two parts by [`Document::save_structure`]
+    /// and [`Document::save_values`], checked as [`Document::load`] checks
+    /// it. The parts have to come from the same document.
+    ///
+    /// ```
+    /// use colchis::{BitpackingUsageBuilder, Document, EliasFanoUsageIndex};
+    ///
+    /// let doc = Document::parse::<BitpackingUsageBuilder, _>(r#"["a", 1]"#.as_bytes()).unwrap();
+    /// let (mut structure, mut values) = (Vec::new(), Vec::new());
+    /// doc.save_structure(&mut structure).unwrap();
+    /// doc.save_values(&mut values).unwrap();
+    ///
+    /// let loaded =
+    ///     Document::<EliasFanoUsageIndex>::load_parts(structure.as_slice(), values.as_slice())
+    ///         .unwrap();
+    /// assert_eq!(loaded.child_count(loaded.root()), 2);
+    /// ```
+    pub fn load_parts<S: Read, V: Read>(structure: S, values: V) -> Result<Self, LoadError> {
+        let structure = Container::read(structure)?;
+        let values = Container::read(values)?;
+        Self::from_containers(&structure, &values)
+    }
+
+    fn from_containers(
+        structure_container: &Container,
+        container: &Container,
+    ) -> Result<Self, LoadError> {
+        let structure = read_section(structure_container, Section::Structure, |r| {
+            Structure::<U>::read(r)
+        })?;
+        let text_usage = read_section(container, Section::Text, |r| TextUsage::read(r))?;
+        let numbers = read_section(container, Section::Numbers, |r| Numbers::read(r))?;
+        let booleans = read_section(container, Section::Booleans, |r| persist::read_bit_vec(r))?;
+        let number_lexemes = if container.has_section(Section::NumberLexemes) {
+            Some(read_section(container, Section::NumberLexemes, |r| {
+                TextUsage::read(r)
+            })?)
+        } else {
+            None
+        };
+        let source_spans = if container.has_section(Section::SourceSpans) {
+            Some(read_section(container, Section::SourceSpans, |r| {
+                SourceSpans::read(r)
+            })?)
+        } else {
+            None
+        };
+
+        let len = structure.len();
+        if len > 0 && structure.tree().excess(len - 1) != 0 {
+            return Err(LoadError::Invalid(IntegrityError::Unclosed {
+                excess: structure.tree().excess(len - 1),
+            }));
+        }
+        let mut document = Self::new(structure, text_usage, numbers, booleans);
+        document.number_lexemes = number_lexemes;
+        document.source_spans = source_spans;
+        let document = document.with_text_validation(TextValidation::Checked);
+        document.verify_counts().map_err(LoadError::Invalid)?;
+        Ok(document)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        OwnedValue,
+        container::{FORMAT_VERSION, MAGIC},
+        usage::{BitpackingUsageBuilder, EliasFanoUsageIndex, UsageBuilder},
+    };
+
+    use super::*;
+
+    fn round_trip(json: &str) -> Document<EliasFanoUsageIndex> {
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+        let mut out = Vec::new();
+        doc.save(&mut out).unwrap();
+        let loaded = Document::<EliasFanoUsageIndex>::load(out.as_slice()).unwrap();
+        assert_eq!(
+            loaded.root_value().to_owned_value(),
+            doc.root_value().to_owned_value()
+        );
+        assert_eq!(loaded.verify(), Ok(()));
+        loaded
+    }
+
+    #[test]
+    fn test_save_load() {
+        round_trip(r#"{"a": [1, 2.5, true, false, null], "b": {"c": "hello", "d": ""}}"#);
+        round_trip("1");
+        round_trip("[9007199254740993, 0.5, -3]");
+        round_trip(r#""text""#);
+        round_trip("[]");
+    }
+
+    #[test]
+    fn test_save_load_number_lexemes() {
+        let json = "[1.50,2,3e0]";
+        let options = crate::ParseOptions::new().preserve_number_lexemes(true);
+        let doc =
+            Document::parse_with::<BitpackingUsageBuilder, _>(json.as_bytes(), &options).unwrap();
+        let mut out = Vec::new();
+        doc.save(&mut out).unwrap();
+        let loaded = Document::<EliasFanoUsageIndex>::load(out.as_slice()).unwrap();
+        assert_eq!(loaded.verify(), Ok(()));
+        let mut serialized = Vec::new();
+        loaded.serialize(&mut serialized).unwrap();
+        assert_eq!(String::from_utf8(serialized).unwrap(), json);
+    }
+
+    #[test]
+    fn test_save_load_source_spans() {
+        let json = r#"{"a": [1, "x"]}"#;
+        let options = crate::ParseOptions::new().source_spans(true);
+        let doc =
+            Document::parse_with::<BitpackingUsageBuilder, _>(json.as_bytes(), &options).unwrap();
+        let mut out = Vec::new();
+        doc.save(&mut out).unwrap();
+        let loaded = Document::<EliasFanoUsageIndex>::load(out.as_slice()).unwrap();
+        assert_eq!(loaded.verify(), Ok(()));
+        let array = loaded.first_child(loaded.first_child(loaded.root()).unwrap());
+        assert_eq!(loaded.source_span(array.unwrap()), Some(6..14));
+        assert_eq!(loaded.source_span(loaded.root()), Some(0..15));
+
+        // saves without spans load without them
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+        let mut out = Vec::new();
+        doc.save(&mut out).unwrap();
+        let loaded = Document::<EliasFanoUsageIndex>::load(out.as_slice()).unwrap();
+        assert_eq!(loaded.source_span(loaded.root()), None);
+    }
+
+    #[test]
+    fn test_save_load_many_children() {
+        let json = format!(
+            "[{}]",
+            (0..200)
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        let doc = round_trip(&json);
+        let root = doc.root();
+        assert_eq!(
+            doc.value(doc.nth_child(root, 150).unwrap())
+                .to_owned_value(),
+            OwnedValue::Number(150.0)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Text block should not be corrupt")]
+    fn test_load_tampered_text_is_checked() {
+        let options = crate::ParseOptions::new().compression_level(Some(0));
+        let doc =
+            Document::parse_with::<BitpackingUsageBuilder, _>(br#"["hello"]"#.as_slice(), &options)
+                .unwrap();
+        // a save with invalid UTF-8 in its text, and valid section checksums
+        let mut text = Vec::new();
+        doc.text_usage.write(&mut text).unwrap();
+        let start = text.windows(5).position(|w| w == b"hello").unwrap();
+        text[start] = 0xff;
+        let mut writer = ContainerWriter::new();
+        writer
+            .section(Section::Structure, |w| doc.structure.write(w))
+            .unwrap();
+        writer
+            .section(Section::Text, |w| w.write_all(&text))
+            .unwrap();
+        writer
+            .section(Section::Numbers, |w| doc.numbers.write(w))
+            .unwrap();
+        writer
+            .section(Section::Booleans, |w| {
+                persist::write_bit_vec(w, &doc.booleans)
+            })
+            .unwrap();
+        let mut out = Vec::new();
+        writer.write(&mut out).unwrap();
+
+        let loaded = Document::<EliasFanoUsageIndex>::load(out.as_slice())
+            .unwrap()
+            .with_text_validation(TextValidation::Unchecked);
+        let string = loaded.first_child(loaded.root()).unwrap();
+        loaded.string_value(string);
+    }
+
+    #[test]
+    fn test_save_load_parts() {
+        let json = r#"{"a": [1, 2.5, true], "b": "text"}"#;
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+        let (mut structure, mut values) = (Vec::new(), Vec::new());
+        doc.save_structure(&mut structure).unwrap();
+        doc.save_values(&mut values).unwrap();
+        let loaded =
+            Document::<EliasFanoUsageIndex>::load_parts(structure.as_slice(), values.as_slice())
+                .unwrap();
+        assert_eq!(loaded.verify(), Ok(()));
+        assert_eq!(
+            loaded.root_value().to_owned_value(),
+            doc.root_value().to_owned_value()
+        );
+
+        // a part on its own is not a document, and parts must fit together
+        assert!(Document::<EliasFanoUsageIndex>::load(structure.as_slice()).is_err());
+        let other = BitpackingUsageBuilder::parse(r#"["x"]"#.as_bytes()).unwrap();
+        let mut other_values = Vec::new();
+        other.save_values(&mut other_values).unwrap();
+        assert!(
+            Document::<EliasFanoUsageIndex>::load_parts(
+                structure.as_slice(),
+                other_values.as_slice()
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_load_errors() {
+        assert!(matches!(
+            Document::<EliasFanoUsageIndex>::load(b"not a document".as_slice()),
+            Err(LoadError::NotADocument)
+        ));
+
+        let doc = BitpackingUsageBuilder::parse(r#"{"a": "b"}"#.as_bytes()).unwrap();
+        let mut out = Vec::new();
+        doc.save(&mut out).unwrap();
+
+        let mut future = out.clone();
+        future[MAGIC.len()] = 99;
+        assert!(matches!(
+            Document::<EliasFanoUsageIndex>::load(future.as_slice()),
+            Err(LoadError::UnsupportedVersion(99))
+        ));
+
+        let mut corrupt = out.clone();
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 1;
+        assert!(matches!(
+            Document::<EliasFanoUsageIndex>::load(corrupt.as_slice()),
+            Err(LoadError::ChecksumMismatch { .. })
+        ));
+
+        out.truncate(out.len() - 4);
+        assert!(matches!(
+            Document::<EliasFanoUsageIndex>::load(out.as_slice()),
+            Err(LoadError::Corrupt(_))
+        ));
+        assert_eq!(
+            crate::container::read_version(out.as_slice()).unwrap(),
+            FORMAT_VERSION
+        );
+    }
+}
@@ -0,0 +1,354 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::{info::NodeType, numbers::Number, usage::UsageIndex};
+
+use super::{Document, Node};
+
+/// The type of a value, as [`Document::infer_schema`] tells them apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ValueType {
+    Object,
+    Array,
+    String,
+    Integer,
+    Float,
+    Boolean,
+    Null,
+}
+
+/// A summary of the values found at one path of a document.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PathSchema {
+    /// How many values are at the path.
+    pub count: usize,
+    /// How many values of each type are at the path.
+    pub types: BTreeMap<ValueType, usize>,
+    /// For the path of a field, how many of the objects that could have
+    /// the field don't have it; 0 for other paths.
+    pub missing: usize,
+    /// The smallest and largest number at the path, if there are any
+    /// numbers other than NaN.
+    pub number_range: Option<(f64, f64)>,
+    /// The fewest and most items of an array at the path, if there are
+    /// any arrays.
+    pub length_range: Option<(usize, usize)>,
+}
+
+impl PathSchema {
+    /// Whether some objects lack the field of this path.
+    pub fn is_optional(&self) -> bool {
+        self.missing > 0
+    }
+
+    fn add(&mut self, value_type: ValueType) {
+        self.count += 1;
+        *self.types.entry(value_type).or_default() += 1;
+    }
+}
+
+/// The paths of a document with a summary of the values at each, as
+/// inferred by [`Document::infer_schema`].
+///
+/// Paths are written with a `.` before every field name and `[]` for the
+/// items of an array, such as `items[].price`; the path of the root is the
+/// empty string, and the path of a field of the root is its name. Field
+/// names are not escaped.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schema {
+    // in the order in which the paths are first found in the document
+    paths: Vec<(String, PathSchema)>,
+    indexes: HashMap<String, usize>,
+    // whether each path ends in a field rather than the items of an array
+    // or the root
+    is_field: Vec<bool>,
+}
+
+/// A distinct path of a field in a document, as yielded by
+/// [`Document::field_paths`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldPath {
+    /// The path, written as for [`Schema`].
+    pub path: String,
+    /// How many times the field occurs on the path.
+    pub count: usize,
+    /// The types of its values.
+    pub types: BTreeSet<ValueType>,
+}
+
+impl Schema {
+    /// The summary of the values at a path, if there are any.
+    pub fn get(&self, path: &str) -> Option<&PathSchema> {
+        self.indexes.get(path).map(|&index| &self.paths[index].1)
+    }
+
+    /// The paths and their summaries, in the order in which the paths are
+    /// first found in the document, so every path comes after its parent.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = (&str, &PathSchema)> {
+        self.paths
+            .iter()
+            .map(|(path, schema)| (path.as_str(), schema))
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+}
+
+// the paths found so far, as a tree, so that a node can find the path of
+// a child without making the text of the path
+#[derive(Default)]
+struct Inference {
+    paths: Vec<(String, PathSchema)>,
+    // for every path, the paths of its fields by name
+    fields: Vec<HashMap<String, usize>>,
+    // for every path, the path of its items
+    items: Vec<Option<usize>>,
+    // for every path of a field, the path of its object
+    objects: Vec<Option<usize>>,
+}
+
+impl Inference {
+    fn add_path(&mut self, path: String, object: Option<usize>) -> usize {
+        self.paths.push((path, PathSchema::default()));
+        self.fields.push(HashMap::new());
+        self.items.push(None);
+        self.objects.push(object);
+        self.paths.len() - 1
+    }
+
+    fn field_path(&mut self, object: usize, name: &str) -> usize {
+        if let Some(&index) = self.fields[object].get(name) {
+            return index;
+        }
+        let path = if self.paths[object].0.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{name}", self.paths[object].0)
+        };
+        let index = self.add_path(path, Some(object));
+        self.fields[object].insert(name.to_string(), index);
+        index
+    }
+
+    fn items_path(&mut self, array: usize) -> usize {
+        if let Some(index) = self.items[array] {
+            return index;
+        }
+        let index = self.add_path(format!("{}[]", self.paths[array].0), None);
+        self.items[array] = Some(index);
+        index
+    }
+
+    fn finish(mut self) -> Schema {
+        for index in 0..self.paths.len() {
+            if let Some(object) = self.objects[index] {
+                let objects = self.paths[object].1.types.get(&ValueType::Object);
+                let present = self.paths[index].1.count;
+                self.paths[index].1.missing = objects.copied().unwrap_or(0).saturating_sub(present);
+            }
+        }
+        let indexes = self
+            .paths
+            .iter()
+            .enumerate()
+            .map(|(index, (path, _))| (path.clone(), index))
+            .collect();
+        let is_field = self.objects.iter().map(Option::is_some).collect();
+        Schema {
+            paths: self.paths,
+            indexes,
+            is_field,
+        }
+    }
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// Infer the schema of the document: the distinct paths in it, with
+    /// for every path the types of the values there, how often a field is
+    /// missing from its objects, and the range of the numbers and array
+    /// lengths.
+    ///
+    /// This visits every node once; strings are not decompressed. Of
+    /// duplicate fields every one counts as a value of the path.
+    pub fn infer_schema(&self) -> Schema {
+        let mut inference = Inference::default();
+        let root = inference.add_path(String::new(), None);
+        self.infer_node(self.root(), root, &mut inference);
+        inference.finish()
+    }
+
+    /// Iterate over the distinct paths of fields in the document, in the
+    /// order in which they are first found, with how often each occurs and
+    /// the types of the values under it.
+    ///
+    /// Paths are written as for [`Schema`], so the field `price` of the
+    /// objects in the array in the field `items` has the path
+    /// `items[].price`. The paths are found by [`Document::infer_schema`]
+    /// before the first one is yielded.
+    pub fn field_paths(&self) -> impl Iterator<Item = FieldPath> {
+        let schema = self.infer_schema();
+        schema
+            .paths
+            .into_iter()
+            .zip(schema.is_field)
+            .filter(|(_, is_field)| *is_field)
+            .map(|((path, schema), _)| FieldPath {
+                path,
+                count: schema.count,
+                types: schema.types.into_keys().collect(),
+            })
+    }
+
+    fn infer_node(&self, node: Node, path: usize, inference: &mut Inference) {
+        let value_type = match self.node_type(node) {
+            NodeType::Object => {
+                for field in self.children(node) {
+                    let NodeType::Field(name) = self.node_type(field) else {
+                        unreachable!("Object children should be fields");
+                    };
+                    let field_path = inference.field_path(path, name);
+                    self.infer_node(self.field_value(field), field_path, inference);
+                }
+                ValueType::Object
+            }
+            NodeType::Array => {
+                let mut length = 0;
+                for item in self.children(node) {
+                    let items_path = inference.items_path(path);
+                    self.infer_node(item, items_path, inference);
+                    length += 1;
+                }
+                let schema = &mut inference.paths[path].1;
+                schema.length_range = Some(match schema.length_range {
+                    Some((min, max)) => (min.min(length), max.max(length)),
+                    None => (length, length),
+                });
+                ValueType::Array
+            }
+            NodeType::String => ValueType::String,
+            NodeType::Number => {
+                let (value_type, n) = match self.number_value(node) {
+                    Number::Integer(i) => (ValueType::Integer, i as f64),
+                    Number::Unsigned(u) => (ValueType::Integer, u as f64),
+                    Number::Float(n) => (ValueType::Float, n),
+                };
+                let schema = &mut inference.paths[path].1;
+                if !n.is_nan() {
+                    schema.number_range = Some(match schema.number_range {
+                        Some((min, max)) => (min.min(n), max.max(n)),
+                        None => (n, n),
+                    });
+                }
+                value_type
+            }
+            NodeType::Boolean => ValueType::Boolean,
+            NodeType::Null => ValueType::Null,
+            NodeType::Field(_) => unreachable!("Fields should be resolved to their value"),
+        };
+        inference.paths[path].1.add(value_type);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::{BitpackingUsageBuilder, UsageBuilder};
+
+    use super::*;
+
+    #[test]
+    fn test_infer_schema() {
+        let doc = BitpackingUsageBuilder::parse(
+            r#"{"items": [{"price": 3, "tags": ["a"]},
+                          {"price": 2.5, "tags": [], "note": null},
+                          {"price": null, "tags": ["b", "c", "d"]}],
+                "total": -1}"#
+                .as_bytes(),
+        )
+        .unwrap();
+        let schema = doc.infer_schema();
+        let paths = schema.iter().map(|(path, _)| path).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                "",
+                "items",
+                "items[]",
+                "items[].price",
+                "items[].tags",
+                "items[].tags[]",
+                "items[].note",
+                "total"
+            ]
+        );
+        assert_eq!(schema.len(), 8);
+        let price = schema.get("items[].price").unwrap();
+        assert_eq!(price.count, 3);
+        assert_eq!(
+            price.types,
+            BTreeMap::from([
+                (ValueType::Integer, 1),
+                (ValueType::Float, 1),
+                (ValueType::Null, 1)
+            ])
+        );
+        assert_eq!(price.number_range, Some((2.5, 3.0)));
+        assert!(!price.is_optional());
+        let note = schema.get("items[].note").unwrap();
+        assert_eq!((note.count, note.missing), (1, 2));
+        assert!(note.is_optional());
+        assert_eq!(
+            schema.get("items[].tags").unwrap().length_range,
+            Some((0, 3))
+        );
+        assert_eq!(schema.get("items[].tags[]").unwrap().count, 4);
+        assert_eq!(schema.get("items").unwrap().length_range, Some((3, 3)));
+        assert_eq!(schema.get("").unwrap().types.len(), 1);
+        assert_eq!(schema.get("items[].missing"), None);
+    }
+
+    #[test]
+    fn test_infer_schema_of_array() {
+        let doc =
+            BitpackingUsageBuilder::parse(r#"[{"a": 1}, {"b": 2}, 3, []]"#.as_bytes()).unwrap();
+        let schema = doc.infer_schema();
+        let a = schema.get("[].a").unwrap();
+        // both objects could have the field
+        assert_eq!((a.count, a.missing), (1, 1));
+        let items = schema.get("[]").unwrap();
+        assert_eq!(items.count, 4);
+        assert_eq!(items.length_range, Some((0, 0)));
+        assert_eq!(items.number_range, Some((3.0, 3.0)));
+    }
+
+    #[test]
+    fn test_field_paths() {
+        let doc = BitpackingUsageBuilder::parse(
+            r#"{"items": [{"price": 3}, {"price": "free"}, [{"price": null}]], "id": 1}"#
+                .as_bytes(),
+        )
+        .unwrap();
+        let paths = doc
+            .field_paths()
+            .map(|field| (field.path, field.count, field.types.into_iter().collect()))
+            .collect::<Vec<(String, usize, Vec<ValueType>)>>();
+        assert_eq!(
+            paths,
+            [
+                ("items".to_string(), 1, vec![ValueType::Array]),
+                (
+                    "items[].price".to_string(),
+                    2,
+                    vec![ValueType::String, ValueType::Integer]
+                ),
+                ("items[][].price".to_string(), 1, vec![ValueType::Null]),
+                ("id".to_string(), 1, vec![ValueType::Integer]),
+            ]
+        );
+        let doc = BitpackingUsageBuilder::parse("[1, [2]]".as_bytes()).unwrap();
+        assert_eq!(doc.field_paths().next(), None);
+    }
+}
@@ -0,0 +1,381 @@
+use std::sync::Arc;
+
+#[cfg(feature = "regex")]
+use crate::text::regex::{Regex, RegexError};
+use crate::{
+    info::STRING_OPEN_ID,
+    text::{
+        Normalization, TextGuard, TextId, equality_index::EqualityIndex, fm_index::FmIndex,
+        text_fst::TextFst,
+    },
+    usage::UsageIndex,
+};
+
+use super::{Document, Node};
+
+impl<U: UsageIndex> Document<U> {
+    /// Normalize strings and queries before comparing them, for instance
+    /// to search regardless of case.
+    ///
+    /// This applies to the indexes built after it, which keep the
+    /// normalization they were built with and normalize queries the same
+    /// way, and to searches without an index.
+    pub fn with_search_normalization(mut self, normalization: Normalization) -> Self {
+        self.search_normalization = normalization;
+        self
+    }
+
+    /// Build an FM-index over all strings, so that
+    /// [`Document::strings_containing`] finds them without decompressing
+    /// every text block.
+    ///
+    /// This decompresses all strings once and sorts all their suffixes,
+    /// which takes O(n log² n) time for n bytes of strings, and about 14
+    /// bytes per byte of memory while it runs, twice that beyond 4 GiB of
+    /// strings. The index itself takes less than two bytes per byte of
+    /// string. It is not saved with the document.
+    pub fn with_substring_index(mut self) -> Self {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("build_index", index = "substring").entered();
+        let normalization = self.search_normalization;
+        self.substring_index = Some(FmIndex::new(
+            self.normalized_texts(normalization),
+            normalization,
+        ));
+        self
+    }
+
+    /// Whether the document has the index built by
+    /// [`Document::with_substring_index`].
+    pub fn has_substring_index(&self) -> bool {
+        self.substring_index.is_some()
+    }
+
+    /// The string nodes whose value contains `fragment`, in document order.
+    ///
+    /// Field names are not searched. Without the index of
+    /// [`Document::with_substring_index`] every string is decompressed and
+    /// searched. With it this takes a rank per byte of `fragment` and a
+    /// short walk per occurrence, but building the index costs far more
+    /// than a single search does, so it pays off for repeated searches
+    /// only.
+    pub fn strings_containing(&self, fragment: &str) -> Vec<Node> {
+        let text_ids = match &self.substring_index {
+            Some(index) => index.texts_containing(index.normalization().apply(fragment).as_bytes()),
+            None => {
+                let fragment = self.search_normalization.apply(fragment);
+                self.scan_texts(|text| text.contains(&*fragment))
+            }
+        };
+        self.string_nodes(text_ids)
+    }
+
+    /// Build a hash table of the strings, so that
+    /// [`Document::nodes_with_string_value`] finds them without
+    /// decompressing every text block.
+    ///
+    /// This decompresses all strings once; the table takes 8 bytes per
+    /// string and a few dozen per distinct string. It is not saved with the
+    /// document.
+    pub fn with_equality_index(mut self) -> Self {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("build_index", index = "equality").entered();
+        self.equality_index = Some(EqualityIndex::new(
+            &self.text_usage,
+            self.search_normalization,
+        ));
+        self
+    }
+
+    /// The string nodes whose value is `value`, in document order.
+    ///
+    /// Field names are not matched. With the index of
+    /// [`Document::with_equality_index`] only one of the matching strings
+    /// is decompressed, to rule out a hash collision; without it every
+    /// string is decompressed and compared.
+    pub fn nodes_with_string_value(&self, value: &str) -> Vec<Node> {
+        let text_ids = match &self.equality_index {
+            Some(index) => index.texts_equal_to(&self.text_usage, value),
+            None => {
+                let value = self.search_normalization.apply(value);
+                self.scan_texts(|text| text == value)
+            }
+        };
+        self.string_nodes(text_ids)
+    }
+
+    /// Build FSTs of the distinct strings and of the distinct strings
+    /// reversed, so that [`Document::strings_with_prefix`] and
+    /// [`Document::strings_with_suffix`] find strings without
+    /// decompressing them. They are not saved with the document.
+    pub fn with_affix_index(mut self) -> Self {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("build_index", index = "affix").entered();
+        self.build_text_fst();
+        let normalization = self.search_normalization;
+        let reversed = self
+            .normalized_texts(normalization)
+            .map(|text| text.bytes().rev().collect::<Vec<_>>());
+        self.reversed_text_fst = Some(TextFst::new(reversed, normalization));
+        self
+    }
+
+    /// The string nodes whose value starts with `prefix`, in document
+    /// order. Without the index of [`Document::with_affix_index`] every
+    /// string is decompressed and checked.
+    pub fn strings_with_prefix(&self, prefix: &str) -> Vec<Node> {
+        let text_ids = match &self.text_fst {
+            Some(texts) => texts.texts_with_prefix(texts.normalization().apply(prefix).as_bytes()),
+            None => {
+                let prefix = self.search_normalization.apply(prefix);
+                self.scan_texts(|text| text.starts_with(&*prefix))
+            }
+        };
+        self.string_nodes(text_ids)
+    }
+
+    /// The string nodes whose value ends with `suffix`, in document order.
+    /// Without the index of [`Document::with_affix_index`] every string is
+    /// decompressed and checked.
+    pub fn strings_with_suffix(&self, suffix: &str) -> Vec<Node> {
+        let text_ids = match &self.reversed_text_fst {
+            Some(texts) => {
+                let suffix = texts.normalization().apply(suffix);
+                texts.texts_with_prefix(&suffix.bytes().rev().collect::<Vec<_>>())
+            }
+            None => {
+                let suffix = self.search_normalization.apply(suffix);
+                self.scan_texts(|text| text.ends_with(&*suffix))
+            }
+        };
+        self.string_nodes(text_ids)
+    }
+
+    /// Build an FST of the distinct strings, so that
+    /// [`Document::strings_matching_regex`] matches a regex against all of
+    /// them at once. It is not saved with the document.
+    #[cfg(feature = "regex")]
+    pub fn with_regex_index(mut self) -> Self {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("build_index", index = "regex").entered();
+        self.build_text_fst();
+        self
+    }
+
+    /// The string nodes whose whole value matches the regex, in document
+    /// order. Use `.*` around the pattern to match part of a string.
+    ///
+    /// With the index of [`Document::with_regex_index`], the regex is
+    /// evaluated once over the FST of distinct strings instead of once per
+    /// string, and strings are not decompressed. Field names are not
+    /// matched.
+    ///
+    /// The regex is matched against the strings as they are normalized,
+    /// but is not normalized itself; it should be written in lowercase
+    /// when searching regardless of case.
+    #[cfg(feature = "regex")]
+    pub fn strings_matching_regex(&self, pattern: &str) -> Result<Vec<Node>, RegexError> {
+        let regex = Regex::new(pattern)?;
+        let text_ids = match &self.text_fst {
+            Some(texts) => texts.texts_matching(&regex),
+            None => self.scan_texts(|text| regex.is_match(text)),
+        };
+        Ok(self.string_nodes(text_ids))
+    }
+
+    // the FST of the distinct strings is shared by the affix and regex
+    // indexes, so it is only built again for another normalization
+    fn build_text_fst(&mut self) {
+        let normalization = self.search_normalization;
+        if self
+            .text_fst
+            .as_ref()
+            .is_none_or(|texts| texts.normalization() != normalization)
+        {
+            self.text_fst = Some(TextFst::new(
+                self.normalized_texts(normalization)
+                    .map(|text| Arc::<[u8]>::from(text.as_bytes())),
+                normalization,
+            ));
+        }
+    }
+
+    // all strings, in document order
+    fn texts(&self) -> impl Iterator<Item = TextGuard<'_>> + '_ {
+        (0..self.text_usage.len()).map(|id| self.text_usage.get_str(TextId::new(id)))
+    }
+
+    fn normalized_texts(&self, normalization: Normalization) -> impl Iterator<Item = String> + '_ {
+        self.texts()
+            .map(move |text| normalization.apply(&text).into_owned())
+    }
+
+    // the text ids of the strings that match once they are normalized,
+    // decompressing every string
+    fn scan_texts(&self, matches: impl Fn(&str) -> bool) -> Vec<usize> {
+        self.texts()
+            .enumerate()
+            .filter(|(_, text)| matches(&self.search_normalization.apply(text)))
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    // the string nodes of text ids, which are in document order
+    fn string_nodes(&self, text_ids: Vec<usize>) -> Vec<Node> {
+        let usage_index = self.structure.usage_index();
+        text_ids
+            .into_iter()
+            .map(|id| {
+                let position = usage_index
+                    .select(id, STRING_OPEN_ID)
+                    .expect("Every text should belong to a string node");
+                Node::new(position)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Value,
+        text::Normalization,
+        usage::{BitpackingUsageBuilder, UsageBuilder},
+    };
+
+    #[test]
+    fn test_strings_containing() {
+        let doc = BitpackingUsageBuilder::parse(
+            r#"{"name": "banana", "tags": ["bandana", "apple", "ananas"], "ana": 1}"#.as_bytes(),
+        )
+        .unwrap();
+        let values = |nodes: Vec<crate::Node>| {
+            nodes
+                .into_iter()
+                .map(|node| doc.value(node))
+                .collect::<Vec<_>>()
+        };
+        let scanned = doc.strings_containing("ana");
+        assert_eq!(
+            values(scanned.clone()),
+            vec![
+                Value::String("banana".into()),
+                Value::String("bandana".into()),
+                Value::String("ananas".into())
+            ]
+        );
+        assert!(doc.strings_containing("pear").is_empty());
+
+        let doc = doc.with_substring_index();
+        assert!(doc.has_substring_index());
+        assert_eq!(doc.strings_containing("ana"), scanned);
+        assert_eq!(doc.strings_containing("ppl").len(), 1);
+        assert_eq!(doc.strings_containing("").len(), 4);
+        assert!(doc.strings_containing("pear").is_empty());
+    }
+
+    #[test]
+    fn test_strings_with_affix() {
+        let doc = BitpackingUsageBuilder::parse(
+            r#"["http://a.png", "https://b.jpg", "c.png", "http://a.png", ".png"]"#.as_bytes(),
+        )
+        .unwrap();
+        let scanned = (
+            doc.strings_with_prefix("http://"),
+            doc.strings_with_suffix(".png"),
+        );
+        assert_eq!((scanned.0.len(), scanned.1.len()), (2, 4));
+        assert!(doc.strings_with_suffix("gif").is_empty());
+
+        let doc = doc.with_affix_index();
+        assert_eq!(doc.strings_with_prefix("http://"), scanned.0);
+        assert_eq!(doc.strings_with_suffix(".png"), scanned.1);
+        assert_eq!(doc.strings_with_prefix("").len(), 5);
+        assert_eq!(doc.strings_with_suffix("g").len(), 5);
+        assert!(doc.strings_with_suffix("gif").is_empty());
+    }
+
+    #[test]
+    fn test_nodes_with_string_value() {
+        let doc = BitpackingUsageBuilder::parse(
+            r#"{"city": "Berlin", "trips": [{"to": "Paris"}, {"to": "Berlin"}], "Berlin": "berlin"}"#
+                .as_bytes(),
+        )
+        .unwrap();
+        let scanned = doc.nodes_with_string_value("Berlin");
+        assert_eq!(scanned.len(), 2);
+        assert!(
+            scanned
+                .iter()
+                .all(|&node| doc.value(node) == Value::String("Berlin".into()))
+        );
+        assert!(doc.nodes_with_string_value("Rome").is_empty());
+
+        let doc = doc.with_equality_index();
+        assert_eq!(doc.nodes_with_string_value("Berlin"), scanned);
+        assert_eq!(doc.nodes_with_string_value("berlin").len(), 1);
+        assert!(doc.nodes_with_string_value("Rome").is_empty());
+        assert!(doc.nodes_with_string_value("").is_empty());
+        assert!(doc.memory_report().indexes > 0);
+    }
+
+    #[test]
+    fn test_search_normalization() {
+        let json = r#"["Berlin", "BERLIN", "berlin", "Cafe\u0301", "Café", "Bern"]"#;
+        let normalization = Normalization::new().case_insensitive(true).nfc(true);
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes())
+            .unwrap()
+            .with_search_normalization(normalization);
+        let scanned = (
+            doc.nodes_with_string_value("berlin"),
+            doc.strings_containing("ERL"),
+            doc.strings_with_prefix("caf\u{e9}"),
+            doc.strings_with_suffix("É"),
+        );
+        assert_eq!(scanned.0.len(), 3);
+        assert_eq!(scanned.1.len(), 3);
+        assert_eq!(scanned.2.len(), 2);
+        assert_eq!(scanned.3.len(), 2);
+
+        let doc = doc
+            .with_equality_index()
+            .with_substring_index()
+            .with_affix_index();
+        let indexed = (
+            doc.nodes_with_string_value("berlin"),
+            doc.strings_containing("ERL"),
+            doc.strings_with_prefix("caf\u{e9}"),
+            doc.strings_with_suffix("É"),
+        );
+        assert_eq!(indexed, scanned);
+
+        // the indexes keep normalizing queries the way they were built
+        let doc = doc.with_search_normalization(Normalization::new());
+        assert_eq!(doc.nodes_with_string_value("berlin").len(), 3);
+        assert_eq!(doc.strings_containing("ERL").len(), 3);
+        let doc = doc.with_equality_index();
+        assert_eq!(doc.nodes_with_string_value("berlin").len(), 1);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_strings_matching_regex() {
+        let doc = BitpackingUsageBuilder::parse(
+            r#"{"ids": ["u-17", "u-4", "x-17", "u-17"], "note": "u-"}"#.as_bytes(),
+        )
+        .unwrap();
+        let scanned = doc.strings_matching_regex("u-[0-9]+").unwrap();
+        assert_eq!(scanned.len(), 3);
+        assert!(
+            scanned
+                .iter()
+                .all(|&node| matches!(doc.value(node), Value::String(s) if s.starts_with("u-")))
+        );
+        assert!(doc.strings_matching_regex("[").is_err());
+
+        let doc = doc.with_regex_index();
+        assert_eq!(doc.strings_matching_regex("u-[0-9]+").unwrap(), scanned);
+        assert_eq!(doc.strings_matching_regex(".*17").unwrap().len(), 3);
+        assert!(doc.strings_matching_regex("y.*").unwrap().is_empty());
+    }
+}
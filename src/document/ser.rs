@@ -0,0 +1,75 @@
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+
+use crate::usage::UsageIndex;
+
+use super::{ArrayValue, ObjectValue, Value};
+
+/// Values serialize as the JSON data model: objects as maps with string
+/// keys in document order, arrays as sequences, and null as unit. To hand
+/// a node to a serializer, serialize [`Document::value`](super::Document::value)
+/// of it.
+impl<U: UsageIndex> Serialize for Value<'_, U> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // the inherent `serialize` methods, which write JSON, shadow the
+        // trait method
+        match self {
+            Value::Object(object) => Serialize::serialize(object, serializer),
+            Value::Array(array) => Serialize::serialize(array, serializer),
+            Value::String(s) => serializer.serialize_str(s),
+            Value::Number(n) => serializer.serialize_f64(*n),
+            Value::Integer(i) => serializer.serialize_i64(*i),
+            Value::Boolean(b) => serializer.serialize_bool(*b),
+            Value::Null => serializer.serialize_unit(),
+        }
+    }
+}
+
+/// Duplicate fields are all passed on to the serializer, in document order.
+impl<U: UsageIndex> Serialize for ObjectValue<'_, U> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+        for (key, value) in self.iter() {
+            map.serialize_entry(key, &value)?;
+        }
+        map.end()
+    }
+}
+
+impl<U: UsageIndex> Serialize for ArrayValue<'_, U> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.len()))?;
+        for item in self.iter() {
+            seq.serialize_element(&item)?;
+        }
+        seq.end()
+    }
+}
+
+#[cfg(all(test, feature = "serde_json"))]
+mod tests {
+    use serde_json::json;
+
+    use crate::usage::{BitpackingUsageBuilder, UsageBuilder};
+
+    #[test]
+    fn test_serialize_value() {
+        let json = r#"{"z": [1, 2.5, "x\n", true, null], "a": {"b": {}, "c": []}, "n": -7}"#;
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+        let value = doc.value(doc.root());
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            json.replace(' ', "")
+        );
+        assert_eq!(
+            serde_json::to_value(&value).unwrap(),
+            doc.to_serde_value(doc.root())
+        );
+        let z = doc
+            .first_child(doc.first_child(doc.root()).unwrap())
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(doc.value(z)).unwrap(),
+            json!([1, 2.5, "x\n", true, null])
+        );
+    }
+}
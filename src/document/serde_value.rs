@@ -0,0 +1,60 @@
+use serde_json::{Map, Number};
+
+use crate::usage::UsageIndex;
+
+use super::{Document, Node, Value};
+
+impl<U: UsageIndex> Document<U> {
+    /// Convert the value at a node and everything under it to a
+    /// [`serde_json::Value`], without writing it as JSON text and parsing
+    /// that again.
+    ///
+    /// Object fields keep their document order; of duplicate field names
+    /// the last one wins. Floats that JSON can't hold, such as NaN, become
+    /// null.
+    pub fn to_serde_value(&self, node: Node) -> serde_json::Value {
+        serde_value(self.value(node))
+    }
+}
+
+fn serde_value<U: UsageIndex>(value: Value<'_, U>) -> serde_json::Value {
+    match value {
+        Value::Object(object) => serde_json::Value::Object(
+            object
+                .iter()
+                .map(|(key, value)| (key.to_string(), serde_value(value)))
+                .collect::<Map<_, _>>(),
+        ),
+        Value::Array(array) => serde_json::Value::Array(array.iter().map(serde_value).collect()),
+        Value::String(s) => serde_json::Value::String(s.to_string()),
+        Value::Number(n) => Number::from_f64(n).map_or(serde_json::Value::Null, Into::into),
+        Value::Integer(i) => i.into(),
+        Value::Boolean(b) => b.into(),
+        Value::Null => serde_json::Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::usage::{BitpackingUsageBuilder, UsageBuilder};
+
+    #[test]
+    fn test_to_serde_value() {
+        let json = r#"{"z": [1, 2.5, "x", true, null], "a": {"b": {}}, "c": -7}"#;
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+        let value = doc.to_serde_value(doc.root());
+        assert_eq!(
+            value,
+            json!({"z": [1, 2.5, "x", true, null], "a": {"b": {}}, "c": -7})
+        );
+        // fields stay in document order
+        let keys = value.as_object().unwrap().keys().collect::<Vec<_>>();
+        assert_eq!(keys, ["z", "a", "c"]);
+        assert_eq!(value.to_string(), json.replace(' ', ""));
+
+        let a = doc.nth_child(doc.root(), 1).unwrap();
+        assert_eq!(doc.to_serde_value(a), json!({"b": {}}));
+    }
+}
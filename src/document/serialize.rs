@@ -1,20 +1,351 @@
-use std::io::Write;
+use std::{collections::HashMap, io::Write};
 
 use struson::writer::{JsonStreamWriter, JsonWriter};
 
-use crate::usage::UsageIndex;
+use crate::{info::NodeType, numbers::Number, usage::UsageIndex};
 
-use super::Document;
+#[cfg(feature = "parallel")]
+use super::Value;
+use super::{Document, Node, value::number_result};
+
+/// Options for [`Document::serialize_with`]. The default writes compact
+/// JSON, as [`Document::serialize`] does.
+#[derive(Debug, Clone, Default)]
+pub struct SerializeOptions {
+    /// What to indent every level of nesting with, putting every field and
+    /// item on a line of its own, or `None` to write everything on one
+    /// line.
+    pub indent: Option<String>,
+    /// Whether to write a space after the colon of a field.
+    pub space_after_colon: bool,
+    /// Whether to write the fields of objects sorted by name rather than in
+    /// document order. Fields with the same name keep their order.
+    pub sort_keys: bool,
+    /// Whether to end the output with a newline.
+    pub trailing_newline: bool,
+    /// Which fields to write, or `None` to write all of them.
+    pub projection: Option<Projection>,
+}
+
+impl SerializeOptions {
+    /// Options for JSON that is easy to read: indented by two spaces, with
+    /// a space after colons and a trailing newline.
+    pub fn pretty() -> Self {
+        Self {
+            indent: Some("  ".to_string()),
+            space_after_colon: true,
+            sort_keys: false,
+            trailing_newline: true,
+            projection: None,
+        }
+    }
+
+    /// Write only the fields on the given paths; see
+    /// [`Projection::include`].
+    pub fn project<P: AsRef<str>>(mut self, paths: impl IntoIterator<Item = P>) -> Self {
+        self.projection = Some(Projection::include(paths));
+        self
+    }
+
+    /// Leave out the fields on the given paths; see
+    /// [`Projection::exclude`].
+    pub fn exclude<P: AsRef<str>>(mut self, paths: impl IntoIterator<Item = P>) -> Self {
+        self.projection = Some(Projection::exclude(paths));
+        self
+    }
+}
+
+/// The fields [`SerializeOptions`] writes, given as paths of field names
+/// separated by dots, such as `metrics.cpu` for the field `cpu` of the
+/// object in the field `metrics`.
+///
+/// Paths go through arrays, so they apply to every item of an array; the
+/// paths of the records of an array of records are the same as those of a
+/// single record. Scalars are written whatever the paths say about fields
+/// under them. Fields that aren't written are skipped without visiting
+/// their subtrees.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Projection {
+    // whether the paths are the fields to write or the fields to leave out
+    include: bool,
+    paths: PathTree,
+}
+
+// paths of field names, merged by their common prefixes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct PathTree {
+    fields: HashMap<String, PathTree>,
+    // whether a path ends here, so that it covers the whole value
+    end: bool,
+}
+
+impl Projection {
+    /// Write only the fields on the paths: the fields a path ends at with
+    /// everything under them, and the objects on the way to them with only
+    /// the fields that lead there.
+    pub fn include<P: AsRef<str>>(paths: impl IntoIterator<Item = P>) -> Self {
+        Self {
+            include: true,
+            paths: PathTree::new(paths),
+        }
+    }
+
+    /// Leave out the fields a path ends at, and write everything else.
+    pub fn exclude<P: AsRef<str>>(paths: impl IntoIterator<Item = P>) -> Self {
+        Self {
+            include: false,
+            paths: PathTree::new(paths),
+        }
+    }
+
+    // how to write the field `name` of an object that `paths` applies to:
+    // not at all, whole as `Some(None)`, or with the paths under it
+    fn field<'a>(&self, paths: &'a PathTree, name: &str) -> Option<Option<&'a PathTree>> {
+        match paths.fields.get(name) {
+            Some(paths) if paths.end => self.include.then_some(None),
+            Some(paths) => Some(Some(paths)),
+            None if self.include => None,
+            None => Some(None),
+        }
+    }
+}
+
+impl PathTree {
+    fn new<P: AsRef<str>>(paths: impl IntoIterator<Item = P>) -> Self {
+        let mut tree = Self::default();
+        for path in paths {
+            let mut node = &mut tree;
+            for name in path.as_ref().split('.') {
+                node = node.fields.entry(name.to_string()).or_default();
+            }
+            node.end = true;
+        }
+        tree
+    }
+}
 
 impl<U: UsageIndex> Document<U> {
     pub fn serialize<W: Write>(&self, mut w: W) -> std::io::Result<()> {
         let mut writer = JsonStreamWriter::new(&mut w);
 
-        let root_value = self.root_value();
-        root_value.serialize(&mut writer)?;
+        self.serialize_node(self.root(), &mut writer)?;
         writer.finish_document()?;
         Ok(())
     }
+
+    /// The document as indented JSON, as written by
+    /// [`SerializeOptions::pretty`].
+    pub fn to_string_pretty(&self) -> String {
+        let mut out = Vec::new();
+        self.serialize_with(self.root(), &mut out, &SerializeOptions::pretty())
+            .expect("Writing to a vector should not fail");
+        String::from_utf8(out).expect("JSON should be UTF-8")
+    }
+
+    /// Serialize the value at a node, writing numbers as the text they were
+    /// parsed from if the document keeps number lexemes.
+    pub(crate) fn serialize_node<W: Write>(
+        &self,
+        node: Node,
+        writer: &mut JsonStreamWriter<W>,
+    ) -> std::io::Result<()> {
+        if self.node_type(node) == &NodeType::Number {
+            if let Some(lexeme) = self.number_lexeme(node) {
+                return number_result(writer.number_value_from_string(&lexeme));
+            }
+            // the value of these is a float, which would round them
+            if let Number::Unsigned(u) = self.number_value(node) {
+                return writer.number_value(u);
+            }
+        }
+        self.value(node).serialize(writer)
+    }
+
+    /// Write the subtree under `node` as JSON, laid out as the options say.
+    /// For a field node this writes the value of the field.
+    pub fn serialize_with<W: Write>(
+        &self,
+        node: Node,
+        mut w: W,
+        options: &SerializeOptions,
+    ) -> std::io::Result<()> {
+        let paths = options
+            .projection
+            .as_ref()
+            .map(|projection| &projection.paths);
+        self.serialize_formatted(self.field_value(node), &mut w, options, paths, 0)?;
+        if options.trailing_newline {
+            w.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Write the items of an array as newline-delimited JSON: every item as
+    /// compact JSON on a line of its own, each line ending with a newline.
+    /// For a field node the items of its value are written; a node that
+    /// isn't an array is written as a single line.
+    pub fn serialize_ndjson<W: Write>(&self, node: Node, mut w: W) -> std::io::Result<()> {
+        let node = self.field_value(node);
+        let write_line = |w: &mut W, node| -> std::io::Result<()> {
+            let mut writer = JsonStreamWriter::new(&mut *w);
+            self.serialize_node(node, &mut writer)?;
+            writer.finish_document()?;
+            w.write_all(b"\n")
+        };
+        if self.node_type(node) != &NodeType::Array {
+            return write_line(&mut w, node);
+        }
+        for item in self.children(node) {
+            write_line(&mut w, item)?;
+        }
+        Ok(())
+    }
+
+    // write a value, with only the fields of `paths` of the projection if
+    // it isn't `None`
+    fn serialize_formatted<W: Write>(
+        &self,
+        node: Node,
+        w: &mut W,
+        options: &SerializeOptions,
+        paths: Option<&PathTree>,
+        depth: usize,
+    ) -> std::io::Result<()> {
+        match self.node_type(node) {
+            NodeType::Object => {
+                let mut fields = self
+                    .children(node)
+                    .filter_map(|field| {
+                        let NodeType::Field(name) = self.node_type(field) else {
+                            unreachable!("Object children should be fields");
+                        };
+                        let paths = match (&options.projection, paths) {
+                            (Some(projection), Some(paths)) => projection.field(paths, name)?,
+                            _ => None,
+                        };
+                        Some((name.as_str(), self.field_value(field), paths))
+                    })
+                    .collect::<Vec<_>>();
+                if options.sort_keys {
+                    fields.sort_by_key(|&(name, _, _)| name);
+                }
+                let colon: &[u8] = if options.space_after_colon {
+                    b": "
+                } else {
+                    b":"
+                };
+                write_container(
+                    w,
+                    b"{",
+                    b"}",
+                    fields,
+                    options,
+                    depth,
+                    |w, (name, value, paths)| {
+                        // the writer escapes the name like it escapes strings
+                        let mut writer = JsonStreamWriter::new(&mut *w);
+                        writer.string_value(name)?;
+                        writer.finish_document()?;
+                        w.write_all(colon)?;
+                        self.serialize_formatted(value, w, options, paths, depth + 1)
+                    },
+                )
+            }
+            NodeType::Array => write_container(
+                w,
+                b"[",
+                b"]",
+                self.children(node),
+                options,
+                depth,
+                |w, item| self.serialize_formatted(item, w, options, paths, depth + 1),
+            ),
+            _ => {
+                let mut writer = JsonStreamWriter::new(w);
+                self.serialize_node(node, &mut writer)?;
+                writer.finish_document()?;
+                Ok(())
+            }
+        }
+    }
+}
+
+// write the entries of a container between its brackets, each on a line
+// of its own if we indent
+fn write_container<W: Write, T>(
+    w: &mut W,
+    open: &[u8],
+    close: &[u8],
+    entries: impl IntoIterator<Item = T>,
+    options: &SerializeOptions,
+    depth: usize,
+    mut write_entry: impl FnMut(&mut W, T) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let newline = |w: &mut W, depth: usize| -> std::io::Result<()> {
+        if let Some(indent) = &options.indent {
+            w.write_all(b"\n")?;
+            for _ in 0..depth {
+                w.write_all(indent.as_bytes())?;
+            }
+        }
+        Ok(())
+    };
+    w.write_all(open)?;
+    let mut empty = true;
+    for entry in entries {
+        if !empty {
+            w.write_all(b",")?;
+        }
+        empty = false;
+        newline(w, depth + 1)?;
+        write_entry(w, entry)?;
+    }
+    // empty containers stay on one line
+    if !empty {
+        newline(w, depth)?;
+    }
+    w.write_all(close)
+}
+
+#[cfg(feature = "parallel")]
+impl<U: UsageIndex + Sync> Document<U> {
+    /// Serialize the document like [`Document::serialize`], but if the root
+    /// is an array, serialize its items in parallel.
+    ///
+    /// The items are split into chunks of `chunk_size` items, each of which
+    /// is serialized into its own buffer; the buffers are then written in
+    /// order. The output is identical to that of [`Document::serialize`].
+    pub fn serialize_parallel<W: Write>(&self, mut w: W, chunk_size: usize) -> std::io::Result<()> {
+        use rayon::prelude::*;
+
+        if !matches!(self.root_value(), Value::Array(_)) {
+            return self.serialize(w);
+        }
+        let items = self.children(self.root()).collect::<Vec<_>>();
+        let chunks = items
+            .par_chunks(chunk_size.max(1))
+            .map(|chunk| {
+                let mut buffer = Vec::new();
+                for (i, item) in chunk.iter().enumerate() {
+                    if i > 0 {
+                        buffer.push(b',');
+                    }
+                    let mut writer = JsonStreamWriter::new(&mut buffer);
+                    self.serialize_node(*item, &mut writer)?;
+                    writer.finish_document()?;
+                }
+                Ok(buffer)
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+        w.write_all(b"[")?;
+        for (i, chunk) in chunks.iter().enumerate() {
+            if i > 0 {
+                w.write_all(b",")?;
+            }
+            w.write_all(chunk)?;
+        }
+        w.write_all(b"]")
+    }
 }
 
 #[cfg(test)]
@@ -39,6 +370,23 @@ mod tests {
         assert_round_trip("42");
     }
 
+    #[test]
+    fn test_round_trip_unsigned() {
+        assert_round_trip("[9223372036854775807,9223372036854775808,18446744073709551615]");
+        let doc = BitpackingUsageBuilder::parse("[18446744073709551615, -1]".as_bytes()).unwrap();
+        let max = doc.first_child(doc.root()).unwrap();
+        assert_eq!(doc.as_u64(max), Some(u64::MAX));
+        assert_eq!(doc.as_u64(doc.next_sibling(max).unwrap()), None);
+
+        // saved and loaded
+        let mut saved = Vec::new();
+        doc.save(&mut saved).unwrap();
+        let loaded = Document::<crate::EliasFanoUsageIndex>::load(saved.as_slice()).unwrap();
+        let mut output = Vec::new();
+        loaded.serialize(&mut output).unwrap();
+        assert_eq!(output, b"[18446744073709551615,-1]");
+    }
+
     #[test]
     fn test_round_trip_boolean() {
         assert_round_trip("true");
@@ -64,4 +412,163 @@ mod tests {
     fn test_round_trip_object() {
         assert_round_trip(r#"{"key1":"value1","key2":"value2"}"#);
     }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_serialize_parallel() {
+        for input in [
+            r#"[]"#,
+            r#"{"a":[1,2]}"#,
+            r#"[1,"two",{"three":[3]},null,true,[]]"#,
+        ] {
+            let doc = BitpackingUsageBuilder::parse(input.as_bytes()).unwrap();
+            for chunk_size in [0, 1, 2, 100] {
+                let mut output = Vec::new();
+                doc.serialize_parallel(&mut output, chunk_size).unwrap();
+                assert_eq!(String::from_utf8(output).unwrap(), input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_serialize_with() {
+        let doc = BitpackingUsageBuilder::parse(
+            r#"{"b": [1, {"y": "\n", "x": []}], "a": {}, "c": null}"#.as_bytes(),
+        )
+        .unwrap();
+        let serialized = |node, options: &SerializeOptions| {
+            let mut output = Vec::new();
+            doc.serialize_with(node, &mut output, options).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        let root = doc.root();
+        assert_eq!(
+            serialized(root, &SerializeOptions::default()),
+            r#"{"b":[1,{"y":"\n","x":[]}],"a":{},"c":null}"#
+        );
+        assert_eq!(
+            serialized(root, &SerializeOptions::pretty()),
+            concat!(
+                "{\n",
+                "  \"b\": [\n",
+                "    1,\n",
+                "    {\n",
+                "      \"y\": \"\\n\",\n",
+                "      \"x\": []\n",
+                "    }\n",
+                "  ],\n",
+                "  \"a\": {},\n",
+                "  \"c\": null\n",
+                "}\n"
+            )
+        );
+        let sorted = SerializeOptions {
+            sort_keys: true,
+            space_after_colon: true,
+            ..SerializeOptions::default()
+        };
+        assert_eq!(
+            serialized(root, &sorted),
+            r#"{"a": {},"b": [1,{"x": [],"y": "\n"}],"c": null}"#
+        );
+        // a subtree, through its field
+        let tabs = SerializeOptions {
+            indent: Some("\t".to_string()),
+            ..SerializeOptions::default()
+        };
+        let field = doc.first_child(root).unwrap();
+        assert_eq!(
+            serialized(field, &tabs),
+            "[\n\t1,\n\t{\n\t\t\"y\":\"\\n\",\n\t\t\"x\":[]\n\t}\n]"
+        );
+    }
+
+    #[test]
+    fn test_display_and_pretty() {
+        let doc = BitpackingUsageBuilder::parse(
+            r#"{"a": [1, 2.5, "x\"y"], "b": {"c": null, "d": true}}"#.as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            doc.root_value().to_string(),
+            r#"{"a":[1,2.5,"x\"y"],"b":{"c":null,"d":true}}"#
+        );
+        let b = doc.get_path(doc.root(), &["b"]).unwrap();
+        assert_eq!(format!("{}", doc.value(b)), r#"{"c":null,"d":true}"#);
+        assert_eq!(
+            doc.to_string_pretty(),
+            concat!(
+                "{\n",
+                "  \"a\": [\n    1,\n    2.5,\n    \"x\\\"y\"\n  ],\n",
+                "  \"b\": {\n    \"c\": null,\n    \"d\": true\n  }\n",
+                "}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_serialize_ndjson() {
+        let doc = BitpackingUsageBuilder::parse(
+            r#"{"rows": [{"id": 1, "text": "a\nb"}, [], 2.50, null], "empty": [], "n": 3}"#
+                .as_bytes(),
+        )
+        .unwrap();
+        let ndjson = |node| {
+            let mut output = Vec::new();
+            doc.serialize_ndjson(node, &mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        let mut fields = doc.children(doc.root());
+        let (rows, empty, n) = (
+            fields.next().unwrap(),
+            fields.next().unwrap(),
+            fields.next().unwrap(),
+        );
+        assert_eq!(
+            ndjson(rows),
+            "{\"id\":1,\"text\":\"a\\nb\"}\n[]\n2.5\nnull\n"
+        );
+        assert_eq!(ndjson(empty), "");
+        assert_eq!(ndjson(n), "3\n");
+    }
+
+    #[test]
+    fn test_serialize_projected() {
+        let doc = BitpackingUsageBuilder::parse(
+            r#"[{"id": 1, "name": "a", "metrics": {"cpu": 0.5, "mem": [1, 2]}},
+                {"id": 2, "metrics": [{"cpu": 1, "disk": 3}, 4]},
+                {"name": "c", "metrics": 7},
+                5]"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let serialized = |options: &SerializeOptions| {
+            let mut output = Vec::new();
+            doc.serialize_with(doc.root(), &mut output, options)
+                .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        assert_eq!(
+            serialized(&SerializeOptions::default().project(["id", "metrics.cpu"])),
+            r#"[{"id":1,"metrics":{"cpu":0.5}},{"id":2,"metrics":[{"cpu":1},4]},{"metrics":7},5]"#
+        );
+        assert_eq!(
+            serialized(&SerializeOptions::default().exclude([
+                "name",
+                "metrics.mem",
+                "metrics.cpu"
+            ])),
+            r#"[{"id":1,"metrics":{}},{"id":2,"metrics":[{"disk":3},4]},{"metrics":7},5]"#
+        );
+        // a path covers everything under it, and projections combine with
+        // the other options
+        let options = SerializeOptions {
+            sort_keys: true,
+            ..SerializeOptions::default().project(["metrics.cpu", "name", "metrics"])
+        };
+        assert_eq!(
+            serialized(&options),
+            r#"[{"metrics":{"cpu":0.5,"mem":[1,2]},"name":"a"},{"metrics":[{"cpu":1,"disk":3},4]},{"metrics":7,"name":"c"},5]"#
+        );
+    }
 }
@@ -0,0 +1,287 @@
+use std::collections::HashSet;
+use std::fmt::{self, Write as _};
+use std::io::Write;
+
+use crate::{pointer, usage::UsageIndex};
+
+use super::{Document, Node, ObjectValue, Value, array::ArrayValue};
+
+/// An error while writing a document as TOML.
+#[derive(Debug)]
+pub enum TomlError {
+    Io(std::io::Error),
+    /// TOML documents are tables, so the value written must be an object.
+    RootNotTable,
+    /// TOML has no null; the path is a JSON pointer to the null value.
+    Null {
+        path: String,
+    },
+    /// TOML keys must be unique; the path is a JSON pointer to the second
+    /// occurrence of the key.
+    DuplicateKey {
+        path: String,
+    },
+}
+
+impl fmt::Display for TomlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TomlError::Io(err) => write!(f, "I/O error: {err}"),
+            TomlError::RootNotTable => write!(f, "TOML can only be written for an object"),
+            TomlError::Null { path } => write!(f, "TOML has no null, at '{path}'"),
+            TomlError::DuplicateKey { path } => write!(f, "duplicate TOML key at '{path}'"),
+        }
+    }
+}
+
+impl std::error::Error for TomlError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TomlError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for TomlError {
+    fn from(err: std::io::Error) -> Self {
+        TomlError::Io(err)
+    }
+}
+
+fn toml_key(key: &str) -> String {
+    if !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        key.to_string()
+    } else {
+        toml_string(key)
+    }
+}
+
+fn toml_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04X}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn toml_float(n: f64) -> String {
+    if n.is_nan() {
+        "nan".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else if n.abs() >= 1e16 || (n != 0.0 && n.abs() < 1e-5) {
+        format!("{n:e}")
+    } else {
+        let s = n.to_string();
+        // whole floats need a fraction, or TOML reads them as integers
+        if s.contains('.') { s } else { format!("{s}.0") }
+    }
+}
+
+// an array becomes an array of tables if it is not empty and only holds
+// objects
+fn is_array_of_tables<U: UsageIndex>(array: &ArrayValue<'_, U>) -> bool {
+    let mut items = array.iter().peekable();
+    items.peek().is_some() && items.all(|item| matches!(item, Value::Object(_)))
+}
+
+fn is_table<U: UsageIndex>(value: &Value<'_, U>) -> bool {
+    match value {
+        Value::Object(_) => true,
+        Value::Array(array) => is_array_of_tables(array),
+        _ => false,
+    }
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// Write the object under `node` as a TOML document.
+    ///
+    /// Nested objects become tables, and arrays that only contain objects
+    /// become arrays of tables; everything else is written inline. This
+    /// fails if the node is not an object, if there is a null anywhere in it
+    /// or if an object has the same key twice, as TOML cannot represent
+    /// those.
+    pub fn to_toml<W: Write>(&self, node: Node, mut w: W) -> Result<(), TomlError> {
+        let value = self.value(node);
+        let Value::Object(object) = &value else {
+            return Err(TomlError::RootNotTable);
+        };
+        // check everything first so we don't write partial output
+        check_value(&value, "")?;
+        let mut keys = Vec::new();
+        toml_table(&mut w, &mut keys, object)
+    }
+}
+
+fn check_value<U: UsageIndex>(value: &Value<'_, U>, path: &str) -> Result<(), TomlError> {
+    match value {
+        Value::Null => Err(TomlError::Null {
+            path: path.to_string(),
+        }),
+        Value::Object(object) => {
+            let mut seen = HashSet::new();
+            for (key, value) in object.iter() {
+                let path = pointer::push(path, key);
+                if !seen.insert(key) {
+                    return Err(TomlError::DuplicateKey { path });
+                }
+                check_value(&value, &path)?;
+            }
+            Ok(())
+        }
+        Value::Array(array) => {
+            for (i, value) in array.iter().enumerate() {
+                check_value(&value, &format!("{path}/{i}"))?;
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn toml_table<W: Write, U: UsageIndex>(
+    w: &mut W,
+    keys: &mut Vec<String>,
+    object: &ObjectValue<'_, U>,
+) -> Result<(), TomlError> {
+    // key/value pairs have to come before any sub tables
+    for (key, value) in object.iter() {
+        if !is_table(&value) {
+            writeln!(w, "{} = {}", toml_key(key), toml_inline(&value))?;
+        }
+    }
+    for (key, value) in object.iter() {
+        match value {
+            Value::Object(object) => {
+                keys.push(toml_key(key));
+                writeln!(w, "\n[{}]", keys.join("."))?;
+                toml_table(w, keys, &object)?;
+                keys.pop();
+            }
+            Value::Array(array) if is_array_of_tables(&array) => {
+                keys.push(toml_key(key));
+                for item in array {
+                    if let Value::Object(object) = item {
+                        writeln!(w, "\n[[{}]]", keys.join("."))?;
+                        toml_table(w, keys, &object)?;
+                    }
+                }
+                keys.pop();
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn toml_inline<U: UsageIndex>(value: &Value<'_, U>) -> String {
+    match value {
+        Value::String(s) => toml_string(s),
+        Value::Number(n) => toml_float(*n),
+        Value::Integer(i) => i.to_string(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Array(array) => {
+            let items = array
+                .iter()
+                .map(|item| toml_inline(&item))
+                .collect::<Vec<_>>();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Object(object) => {
+            let entries = object
+                .iter()
+                .map(|(key, value)| format!("{} = {}", toml_key(key), toml_inline(&value)))
+                .collect::<Vec<_>>();
+            if entries.is_empty() {
+                "{}".to_string()
+            } else {
+                format!("{{ {} }}", entries.join(", "))
+            }
+        }
+        Value::Null => unreachable!("Nulls are rejected before writing"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::{BitpackingUsageBuilder, UsageBuilder};
+
+    use super::*;
+
+    fn render(json: &str) -> Result<String, TomlError> {
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+        let mut out = Vec::new();
+        doc.to_toml(doc.root(), &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_toml_scalars() {
+        assert_eq!(
+            render(r#"{"a": 1, "b": 1.5, "c": "x\"y", "d": true, "e f": [1, "two"]}"#).unwrap(),
+            "a = 1\nb = 1.5\nc = \"x\\\"y\"\nd = true\n\"e f\" = [1, \"two\"]\n"
+        );
+    }
+
+    #[test]
+    fn test_toml_floats() {
+        assert_eq!(
+            render(r#"{"a": 1.0, "b": -0.0, "c": 1e20, "d": [2.0, 3]}"#).unwrap(),
+            "a = 1.0\nb = -0.0\nc = 1e20\nd = [2.0, 3]\n"
+        );
+    }
+
+    #[test]
+    fn test_toml_tables() {
+        assert_eq!(
+            render(r#"{"a": {"b": {"c": 1}, "d": 2}, "e": 3}"#).unwrap(),
+            "e = 3\n\n[a]\nd = 2\n\n[a.b]\nc = 1\n"
+        );
+    }
+
+    #[test]
+    fn test_toml_array_of_tables() {
+        assert_eq!(
+            render(r#"{"items": [{"a": 1}, {"a": 2}], "mixed": [{"a": 1}, 2]}"#).unwrap(),
+            "mixed = [{ a = 1 }, 2]\n\n[[items]]\na = 1\n\n[[items]]\na = 2\n"
+        );
+    }
+
+    #[test]
+    fn test_toml_root_not_table() {
+        assert!(matches!(render("[1]"), Err(TomlError::RootNotTable)));
+    }
+
+    #[test]
+    fn test_toml_null() {
+        match render(r#"{"a": [1, {"b/c": null}]}"#) {
+            Err(TomlError::Null { path }) => assert_eq!(path, "/a/1/b~1c"),
+            _ => panic!("Expected null error"),
+        }
+    }
+
+    #[test]
+    fn test_toml_duplicate_key() {
+        match render(r#"{"a": {"b": 1, "c": 2, "b": 3}}"#) {
+            Err(TomlError::DuplicateKey { path }) => assert_eq!(path, "/a/b"),
+            _ => panic!("Expected duplicate key error"),
+        }
+    }
+}
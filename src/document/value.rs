@@ -1,22 +1,54 @@
+use std::fmt;
 use std::io::Write;
 use std::sync::Arc;
 
 use struson::writer::{JsonStreamWriter, JsonWriter};
 
-use crate::{info::NodeType, text::TextId, usage::UsageIndex};
+use crate::{
+    error::Error,
+    info::NodeType,
+    numbers::{Number, float_equals_integer},
+    text::{TextGuard, TextId, TextReader},
+    usage::UsageIndex,
+};
 
 use super::{Document, Node, ObjectValue, array::ArrayValue};
 
-#[derive(Debug, Clone)]
+/// A value in a document.
+///
+/// Numbers written as integers in the JSON that fit in an `i64` are
+/// [`Value::Integer`], so they keep their precision; other numbers are
+/// [`Value::Number`]. The two compare equal if they are the same number.
+///
+/// Integers above `i64::MAX` that fit in a `u64` are stored exactly, and
+/// serializing the document writes them exactly, but as a value they are
+/// a [`Value::Number`]; use [`Document::as_u64`] to get them exactly.
+#[derive(Debug)]
 pub enum Value<'a, U: UsageIndex> {
     Object(ObjectValue<'a, U>),
     Array(ArrayValue<'a, U>),
     String(Arc<str>),
     Number(f64),
+    Integer(i64),
     Boolean(bool),
     Null,
 }
 
+// implemented manually so we don't require U: Clone
+impl<U: UsageIndex> Clone for Value<'_, U> {
+    fn clone(&self) -> Self {
+        match self {
+            Value::Object(object) => Value::Object(object.clone()),
+            Value::Array(array) => Value::Array(array.clone()),
+            Value::String(s) => Value::String(s.clone()),
+            Value::Number(n) => Value::Number(*n),
+            Value::Integer(i) => Value::Integer(*i),
+            Value::Boolean(b) => Value::Boolean(*b),
+            Value::Null => Value::Null,
+        }
+    }
+}
+
 impl<U: UsageIndex> PartialEq for Value<'_, U> {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
@@ -24,6 +56,10 @@ impl<U: UsageIndex> PartialEq for Value<'_, U> {
             (Value::Array(a), Value::Array(b)) => a == b,
             (Value::String(a), Value::String(b)) => a == b,
             (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::Integer(a), Value::Integer(b)) => a == b,
+            (Value::Number(a), Value::Integer(b)) | (Value::Integer(b), Value::Number(a)) => {
+                float_equals_integer(*a, *b)
+            }
             (Value::Boolean(a), Value::Boolean(b)) => a == b,
             (Value::Null, Value::Null) => true,
             _ => false,
@@ -31,28 +67,61 @@ impl<U: UsageIndex> PartialEq for Value<'_, U> {
     }
 }
 
+pub(super) fn number_result(
+    result: Result<(), struson::writer::JsonNumberError>,
+) -> std::io::Result<()> {
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) => match e {
+            struson::writer::JsonNumberError::IoError(e) => Err(e),
+            _ => {
+                unreachable!();
+            }
+        },
+    }
+}
+
+// whole floats from 2^53 on are written with an exponent; their digits
+// would otherwise be read back as an integer that is not quite the float
+fn write_float<W: Write>(
+    writer: &mut JsonStreamWriter<W>,
+    n: f64,
+) -> Result<(), struson::writer::JsonNumberError> {
+    if n.fract() == 0.0 && n.abs() >= (1u64 << 53) as f64 {
+        writer.number_value_from_string(&format!("{n:e}"))
+    } else {
+        writer.fp_number_value(n)
+    }
+}
+
 impl<U: UsageIndex> Value<'_, U> {
     pub fn serialize<W: Write>(&self, writer: &mut JsonStreamWriter<W>) -> std::io::Result<()> {
         match self {
             Value::Object(object) => object.serialize(writer),
             Value::Array(array) => array.serialize(writer),
             Value::String(s) => writer.string_value(s),
-            Value::Number(n) => match writer.fp_number_value(*n) {
-                Ok(_) => Ok(()),
-                Err(e) => match e {
-                    struson::writer::JsonNumberError::IoError(e) => Err(e),
-                    _ => {
-                        unreachable!();
-                    }
-                },
-            },
+            Value::Number(n) => number_result(write_float(writer, *n)),
+            Value::Integer(i) => writer.number_value(*i),
             Value::Boolean(b) => writer.bool_value(*b),
             Value::Null => writer.null_value(),
         }
     }
 }
 
+/// Writes the value as compact JSON.
+impl<U: UsageIndex> fmt::Display for Value<'_, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = Vec::new();
+        let mut writer = JsonStreamWriter::new(&mut out);
+        self.serialize(&mut writer)
+            .and_then(|()| writer.finish_document())
+            .map_err(|_| fmt::Error)?;
+        f.write_str(std::str::from_utf8(&out).expect("JSON should be UTF-8"))
+    }
+}
+
 impl<U: UsageIndex> Document<U> {
+    /// The value at a node. For a field node this is the value of the field.
     pub fn value(&self, node: Node) -> Value<'_, U> {
         match self.node_type(node) {
             NodeType::Object => {
@@ -67,11 +136,16 @@ impl<U: UsageIndex> Document<U> {
                 let s = self.string_value(node);
                 Value::String(s)
             }
-            NodeType::Number => Value::Number(self.number_value(node)),
+            NodeType::Number => match self.number_value(node) {
+                Number::Float(n) => Value::Number(n),
+                Number::Integer(i) => Value::Integer(i),
+                Number::Unsigned(u) => Value::Number(u as f64),
+            },
             NodeType::Boolean => Value::Boolean(self.boolean_value(node)),
             NodeType::Null => Value::Null,
-            NodeType::Field(_s) => {
-                unreachable!()
+            // the value of a field is the value it holds
+            NodeType::Field(_) => {
+                self.value(self.first_child(node).expect("Field should have a value"))
             }
         }
     }
@@ -80,18 +154,123 @@ impl<U: UsageIndex> Document<U> {
         self.value(root)
     }
 
-    fn string_value(&self, node: Node) -> Arc<str> {
+    pub(super) fn string_value(&self, node: Node) -> Arc<str> {
         let text_id = self.structure.text_id(node.get()).unwrap();
         let text_id = TextId::new(text_id);
         self.text_usage.get_string(text_id)
     }
 
-    fn number_value(&self, node: Node) -> f64 {
+    /// The string at a node, or `None` if the node is not a string.
+    ///
+    /// Unlike [`Document::value`] this borrows the string from its
+    /// decompressed block instead of copying it.
+    pub fn as_str(&self, node: Node) -> Option<TextGuard<'_>> {
+        if self.node_type(node) != &NodeType::String {
+            return None;
+        }
+        let text_id = TextId::new(self.structure.text_id(node.get())?);
+        Some(self.text_usage.get_str(text_id))
+    }
+
+    /// The string at a node as for [`Document::as_str`], or the reason it
+    /// can't be read: the node is not in the document, or its text is
+    /// missing or corrupt.
+    pub fn try_as_str(&self, node: Node) -> Result<Option<TextGuard<'_>>, Error> {
+        if self.try_node_type(node)? != &NodeType::String {
+            return Ok(None);
+        }
+        let text_id = self
+            .structure
+            .text_id(node.get())
+            .ok_or(Error::NodeNotFound(node))?;
+        self.text_usage.try_get_str(TextId::new(text_id)).map(Some)
+    }
+
+    /// The number at a node as a float, or `None` if the node is not a
+    /// number. Integers beyond 2^53 are rounded.
+    pub fn as_f64(&self, node: Node) -> Option<f64> {
+        if self.node_type(node) != &NodeType::Number {
+            return None;
+        }
+        Some(self.number_value(node).to_f64())
+    }
+
+    /// The number at a node as a `u64`, or `None` if the node is not a
+    /// number or not an integer from 0 to `u64::MAX`.
+    pub fn as_u64(&self, node: Node) -> Option<u64> {
+        if self.node_type(node) != &NodeType::Number {
+            return None;
+        }
+        match self.number_value(node) {
+            Number::Integer(i) => u64::try_from(i).ok(),
+            Number::Unsigned(u) => Some(u),
+            Number::Float(_) => None,
+        }
+    }
+
+    /// The boolean at a node, or `None` if the node is not a boolean.
+    pub fn as_bool(&self, node: Node) -> Option<bool> {
+        if self.node_type(node) != &NodeType::Boolean {
+            return None;
+        }
+        Some(self.boolean_value(node))
+    }
+
+    /// Whether the node is `null`.
+    pub fn is_null(&self, node: Node) -> bool {
+        self.node_type(node) == &NodeType::Null
+    }
+
+    /// Read the string at a node as a stream of bytes, or `None` if the
+    /// node is not a string.
+    ///
+    /// Unlike [`Document::value`], this doesn't hold the whole string in
+    /// memory, which suits string values of many megabytes such as
+    /// embedded base64 data; see [`TextUsage::text_reader`](crate::text::TextUsage::text_reader).
+    pub fn string_reader(&self, node: Node) -> Option<TextReader<'_>> {
+        if self.node_type(node) != &NodeType::String {
+            return None;
+        }
+        let text_id = TextId::new(self.structure.text_id(node.get())?);
+        Some(self.text_usage.text_reader(text_id))
+    }
+
+    /// Whether the strings at two nodes are equal, or `None` if either node
+    /// is not a string.
+    ///
+    /// This compares the stored strings directly. If the document was
+    /// parsed with [`ParseOptions::string_hashes`](crate::ParseOptions::string_hashes)
+    /// enabled, strings that differ are told apart without decompressing
+    /// them.
+    pub fn strings_equal(&self, a: Node, b: Node) -> Option<bool> {
+        if self.node_type(a) != &NodeType::String || self.node_type(b) != &NodeType::String {
+            return None;
+        }
+        let a = TextId::new(self.structure.text_id(a.get())?);
+        let b = TextId::new(self.structure.text_id(b.get())?);
+        Some(self.text_usage.texts_equal(a, b))
+    }
+
+    pub(super) fn number_value(&self, node: Node) -> Number {
         let number_id = self.structure.number_id(node.get()).unwrap();
-        self.numbers[number_id]
+        self.numbers.get(number_id)
     }
 
-    fn boolean_value(&self, node: Node) -> bool {
+    /// The text a number was written as, if the document keeps number
+    /// lexemes and the text differs from how the value serializes.
+    ///
+    /// See [`ParseOptions::preserve_number_lexemes`](crate::ParseOptions::preserve_number_lexemes).
+    pub fn number_lexeme(&self, node: Node) -> Option<Arc<str>> {
+        let lexemes = self.number_lexemes.as_ref()?;
+        if self.node_type(node) != &NodeType::Number {
+            return None;
+        }
+        let number_id = self.structure.number_id(node.get())?;
+        let lexeme = lexemes.get_string(TextId::new(number_id));
+        (!lexeme.is_empty()).then_some(lexeme)
+    }
+
+    pub(super) fn boolean_value(&self, node: Node) -> bool {
         let boolean_id = self.structure.boolean_id(node.get()).unwrap();
         self.booleans.is_bit_set_unchecked(boolean_id)
     }
@@ -107,10 +286,74 @@ impl<U: UsageIndex> Document<U> {
 
 #[cfg(test)]
 mod tests {
+    use std::io::Read;
+
     use crate::usage::{BitpackingUsageBuilder, UsageBuilder};
 
     use super::*;
 
+    #[test]
+    fn test_strings_equal() {
+        let json = r#"["a", "b", "a", 1]"#;
+        for options in [
+            crate::ParseOptions::new(),
+            crate::ParseOptions::new().string_hashes(true),
+        ] {
+            let doc = Document::parse_with::<BitpackingUsageBuilder, _>(json.as_bytes(), &options)
+                .unwrap();
+            let nodes =
+                std::iter::successors(doc.first_child(doc.root()), |node| doc.next_sibling(*node))
+                    .collect::<Vec<_>>();
+            assert_eq!(doc.strings_equal(nodes[0], nodes[2]), Some(true));
+            assert_eq!(doc.strings_equal(nodes[0], nodes[1]), Some(false));
+            assert_eq!(doc.strings_equal(nodes[0], nodes[3]), None);
+        }
+    }
+
+    #[test]
+    fn test_scalar_accessors() {
+        let doc =
+            BitpackingUsageBuilder::parse(r#"["a", 1.5, 2, true, null, {}]"#.as_bytes()).unwrap();
+        let nodes = doc.children(doc.root()).collect::<Vec<_>>();
+        assert_eq!(doc.as_str(nodes[0]).as_deref(), Some("a"));
+        assert_eq!(doc.as_f64(nodes[1]), Some(1.5));
+        assert_eq!(doc.as_f64(nodes[2]), Some(2.0));
+        assert_eq!(doc.as_bool(nodes[3]), Some(true));
+        assert!(doc.is_null(nodes[4]));
+        assert!(doc.as_str(nodes[1]).is_none());
+        assert_eq!(doc.as_f64(nodes[0]), None);
+        assert_eq!(doc.as_bool(nodes[4]), None);
+        assert_eq!(doc.as_f64(doc.root()), None);
+        assert_eq!(doc.as_bool(doc.root()), None);
+        assert!(!doc.is_null(doc.root()));
+    }
+
+    #[test]
+    fn test_string_reader() {
+        let blob = "QUJD".repeat(500_000);
+        let json = format!(r#"["x", "{blob}"]"#);
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+        let mut read = String::new();
+        doc.string_reader(doc.nth_child(doc.root(), 1).unwrap())
+            .unwrap()
+            .read_to_string(&mut read)
+            .unwrap();
+        assert_eq!(read, blob);
+        assert!(doc.string_reader(doc.root()).is_none());
+    }
+
+    #[test]
+    fn test_array_get() {
+        let doc = BitpackingUsageBuilder::parse(r#"[1, "two", [3]]"#.as_bytes()).unwrap();
+        let Value::Array(array) = doc.root_value() else {
+            panic!("Expected array");
+        };
+        assert_eq!(array.get(0), Some(Value::Number(1.0)));
+        assert_eq!(array.get(1), Some(Value::String("two".into())));
+        assert!(matches!(array.get(2), Some(Value::Array(_))));
+        assert_eq!(array.get(3), None);
+    }
+
     #[test]
     fn test_number_value() {
         let doc = BitpackingUsageBuilder::parse("42".as_bytes()).unwrap();
@@ -253,4 +496,40 @@ mod tests {
             panic!("Expected an object value");
         }
     }
+
+    #[test]
+    fn test_array_size_hint() {
+        let doc = BitpackingUsageBuilder::parse(r#"[1, [2, 3], 4]"#.as_bytes()).unwrap();
+        let Value::Array(array_value) = doc.root_value() else {
+            panic!("Expected an array value");
+        };
+        let mut iter = array_value.iter();
+        assert_eq!(iter.size_hint(), (1, Some(5)));
+        assert_eq!(iter.by_ref().count(), 3);
+        assert_eq!(iter.size_hint(), (0, Some(0)));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn test_empty_array_size_hint() {
+        let doc = BitpackingUsageBuilder::parse(r#"[]"#.as_bytes()).unwrap();
+        let Value::Array(array_value) = doc.root_value() else {
+            panic!("Expected an array value");
+        };
+        assert_eq!(array_value.iter().size_hint(), (0, Some(0)));
+    }
+
+    #[test]
+    fn test_object_size_hint() {
+        let doc = BitpackingUsageBuilder::parse(r#"{"a": 1}"#.as_bytes()).unwrap();
+        let Value::Object(object_value) = doc.root_value() else {
+            panic!("Expected an object value");
+        };
+        assert_eq!(object_value.keys().size_hint(), (1, Some(1)));
+        assert_eq!(object_value.values().size_hint(), (1, Some(1)));
+        let mut iter = object_value.iter();
+        assert_eq!(iter.size_hint(), (1, Some(1)));
+        iter.next();
+        assert_eq!(iter.size_hint(), (0, Some(0)));
+    }
 }
@@ -0,0 +1,395 @@
+use std::fmt;
+
+use vers_vecs::Tree;
+
+use crate::{info::NodeType, text::TextBlockError, usage::UsageIndex};
+
+use super::Document;
+
+/// A violated invariant found by [`Document::verify`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntegrityError {
+    /// The parentheses sequence closes more than it opened at this position.
+    Unbalanced { position: usize },
+    /// The parentheses sequence does not close all nodes it opened.
+    Unclosed { excess: i64 },
+    /// The document has more than one root node.
+    MultipleRoots { position: usize },
+    /// The usage index does not cover the same number of positions as the
+    /// parentheses sequence.
+    LengthMismatch { parentheses: usize, usage: usize },
+    /// The usage index has no node info for this position.
+    MissingNodeInfo { position: usize },
+    /// The node info is an opening tag where the parentheses close, or the
+    /// other way around.
+    TagMismatch { position: usize },
+    /// The node info of a closing parenthesis is not the closing counterpart
+    /// of the node info at its opening parenthesis.
+    PairMismatch { open: usize, close: usize },
+    /// A node has children it should not have: scalars must be leaves,
+    /// objects may only contain fields and fields and arrays may not.
+    InvalidChild { parent: usize, child: usize },
+    /// A field does not have exactly one value.
+    InvalidField { position: usize },
+    /// The number of string nodes does not match the number of stored texts.
+    TextCountMismatch { nodes: usize, stored: usize },
+    /// The number of number nodes does not match the number of stored numbers.
+    NumberCountMismatch { nodes: usize, stored: usize },
+    /// The number of boolean nodes does not match the number of stored
+    /// booleans.
+    BooleanCountMismatch { nodes: usize, stored: usize },
+    /// The number of number nodes does not match the number of stored
+    /// number lexemes.
+    NumberLexemeCountMismatch { nodes: usize, stored: usize },
+    /// The parentheses sequence does not have as many positions as there
+    /// are stored source offsets.
+    SourceSpanCountMismatch { parentheses: usize, stored: usize },
+    /// A block of stored text is corrupt.
+    CorruptText(TextBlockError),
+}
+
+impl fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntegrityError::Unbalanced { position } => {
+                write!(f, "unbalanced parentheses at position {position}")
+            }
+            IntegrityError::Unclosed { excess } => {
+                write!(f, "{excess} nodes are never closed")
+            }
+            IntegrityError::MultipleRoots { position } => {
+                write!(f, "another root node at position {position}")
+            }
+            IntegrityError::LengthMismatch { parentheses, usage } => write!(
+                f,
+                "the usage index covers {usage} positions instead of {parentheses}"
+            ),
+            IntegrityError::MissingNodeInfo { position } => {
+                write!(f, "no node info at position {position}")
+            }
+            IntegrityError::TagMismatch { position } => {
+                write!(
+                    f,
+                    "the node info does not match the parenthesis at position {position}"
+                )
+            }
+            IntegrityError::PairMismatch { open, close } => write!(
+                f,
+                "the node info at position {close} does not close the one at position {open}"
+            ),
+            IntegrityError::InvalidChild { parent, child } => write!(
+                f,
+                "the node at position {parent} cannot have the child at position {child}"
+            ),
+            IntegrityError::InvalidField { position } => {
+                write!(
+                    f,
+                    "the field at position {position} does not have one value"
+                )
+            }
+            IntegrityError::TextCountMismatch { nodes, stored } => {
+                write!(f, "{nodes} string nodes but {stored} stored texts")
+            }
+            IntegrityError::NumberCountMismatch { nodes, stored } => {
+                write!(f, "{nodes} number nodes but {stored} stored numbers")
+            }
+            IntegrityError::BooleanCountMismatch { nodes, stored } => {
+                write!(f, "{nodes} boolean nodes but {stored} stored booleans")
+            }
+            IntegrityError::NumberLexemeCountMismatch { nodes, stored } => {
+                write!(f, "{nodes} number nodes but {stored} stored number lexemes")
+            }
+            IntegrityError::SourceSpanCountMismatch {
+                parentheses,
+                stored,
+            } => write!(
+                f,
+                "{parentheses} parentheses but {stored} stored source offsets"
+            ),
+            IntegrityError::CorruptText(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for IntegrityError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IntegrityError::CorruptText(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// Check the internal invariants of this document.
+    ///
+    /// This visits every position in the structure, so it is slow on big
+    /// documents. It is meant for debugging custom builders and for
+    /// validating documents that were loaded from elsewhere.
+    pub fn verify(&self) -> Result<(), IntegrityError> {
+        self.verify_balanced()?;
+        self.verify_node_infos()?;
+        self.verify_pairing()?;
+        self.verify_counts()?;
+        self.text_usage
+            .verify()
+            .map_err(IntegrityError::CorruptText)?;
+        match &self.number_lexemes {
+            Some(lexemes) => lexemes.verify().map_err(IntegrityError::CorruptText),
+            None => Ok(()),
+        }
+    }
+
+    fn verify_balanced(&self) -> Result<(), IntegrityError> {
+        let len = self.structure.len();
+        let mut excess = 0i64;
+        for position in 0..len {
+            if excess == 0 && position > 0 {
+                return Err(IntegrityError::MultipleRoots { position });
+            }
+            if self.structure.is_open(position) {
+                excess += 1;
+            } else {
+                excess -= 1;
+            }
+            if excess < 0 {
+                return Err(IntegrityError::Unbalanced { position });
+            }
+        }
+        if excess != 0 {
+            return Err(IntegrityError::Unclosed { excess });
+        }
+        Ok(())
+    }
+
+    fn verify_node_infos(&self) -> Result<(), IntegrityError> {
+        let len = self.structure.len();
+        let usage_index = self.structure.usage_index();
+        if usage_index.len() != len {
+            return Err(IntegrityError::LengthMismatch {
+                parentheses: len,
+                usage: usage_index.len(),
+            });
+        }
+        for position in 0..len {
+            let node_info_id = usage_index
+                .node_info_id(position)
+                .ok_or(IntegrityError::MissingNodeInfo { position })?;
+            let node_info = self.structure.lookup_node_info(node_info_id);
+            if node_info.is_open_tag != self.structure.is_open(position) {
+                return Err(IntegrityError::TagMismatch { position });
+            }
+        }
+        Ok(())
+    }
+
+    fn verify_pairing(&self) -> Result<(), IntegrityError> {
+        let tree = self.structure.tree();
+        for open in tree.dfs_iter() {
+            let close = tree
+                .close(open)
+                .ok_or(IntegrityError::Unbalanced { position: open })?;
+            let open_info = self.structure.node_info(open);
+            let close_info = self.structure.node_info(close);
+            if close_info.is_open_tag || open_info.node_type != close_info.node_type {
+                return Err(IntegrityError::PairMismatch { open, close });
+            }
+            match &open_info.node_type {
+                NodeType::Object => {
+                    for child in tree.children(open) {
+                        if !matches!(
+                            self.structure.node_info(child).node_type,
+                            NodeType::Field(_)
+                        ) {
+                            return Err(IntegrityError::InvalidChild {
+                                parent: open,
+                                child,
+                            });
+                        }
+                    }
+                }
+                NodeType::Array => {
+                    for child in tree.children(open) {
+                        if matches!(
+                            self.structure.node_info(child).node_type,
+                            NodeType::Field(_)
+                        ) {
+                            return Err(IntegrityError::InvalidChild {
+                                parent: open,
+                                child,
+                            });
+                        }
+                    }
+                }
+                NodeType::Field(_) => {
+                    let mut children = tree.children(open);
+                    let child = children
+                        .next()
+                        .ok_or(IntegrityError::InvalidField { position: open })?;
+                    if children.next().is_some() {
+                        return Err(IntegrityError::InvalidField { position: open });
+                    }
+                    if matches!(
+                        self.structure.node_info(child).node_type,
+                        NodeType::Field(_)
+                    ) {
+                        return Err(IntegrityError::InvalidChild {
+                            parent: open,
+                            child,
+                        });
+                    }
+                }
+                NodeType::String | NodeType::Number | NodeType::Boolean | NodeType::Null => {
+                    if let Some(child) = tree.first_child(open) {
+                        return Err(IntegrityError::InvalidChild {
+                            parent: open,
+                            child,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn verify_counts(&self) -> Result<(), IntegrityError> {
+        let len = self.structure.len();
+        let texts = self.structure.text_id(len).unwrap_or(0);
+        if texts != self.text_usage.len() {
+            return Err(IntegrityError::TextCountMismatch {
+                nodes: texts,
+                stored: self.text_usage.len(),
+            });
+        }
+        let numbers = self.structure.number_id(len).unwrap_or(0);
+        if numbers != self.numbers.len() {
+            return Err(IntegrityError::NumberCountMismatch {
+                nodes: numbers,
+                stored: self.numbers.len(),
+            });
+        }
+        let booleans = self.structure.boolean_id(len).unwrap_or(0);
+        if booleans != self.booleans.len() {
+            return Err(IntegrityError::BooleanCountMismatch {
+                nodes: booleans,
+                stored: self.booleans.len(),
+            });
+        }
+        if let Some(lexemes) = &self.number_lexemes
+            && lexemes.len() != numbers
+        {
+            return Err(IntegrityError::NumberLexemeCountMismatch {
+                nodes: numbers,
+                stored: lexemes.len(),
+            });
+        }
+        if let Some(spans) = &self.source_spans
+            && spans.len() != len
+        {
+            return Err(IntegrityError::SourceSpanCountMismatch {
+                parentheses: len,
+                stored: spans.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vers_vecs::BitVec;
+
+    use crate::{
+        numbers::NumbersBuilder,
+        structure::Structure,
+        text::TextUsageBuilder,
+        tree_builder::TreeBuilder,
+        usage::{BitpackingUsageBuilder, EliasFanoUsageIndex, UsageBuilder},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_verify_parsed() {
+        let doc = BitpackingUsageBuilder::parse(
+            r#"{"a": [1, "two", true, null], "b": {"c": false}}"#.as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(doc.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_verify_scalar_root() {
+        // documents without booleans have no vector for them in the index
+        for json in ["0", r#""a""#, "null", "[]"] {
+            let doc = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+            assert_eq!(doc.verify(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_verify_number_count_mismatch() {
+        let mut builder = TreeBuilder::<BitpackingUsageBuilder>::new();
+        builder.open(NodeType::Array);
+        builder.open(NodeType::Number);
+        builder.close(NodeType::Number);
+        builder.close(NodeType::Array);
+        let structure = Structure::<EliasFanoUsageIndex>::new(builder);
+        let doc = Document::new(
+            structure,
+            TextUsageBuilder::new(100, 1).build(),
+            NumbersBuilder::default().build(),
+            BitVec::new(),
+        );
+        assert_eq!(
+            doc.verify(),
+            Err(IntegrityError::NumberCountMismatch {
+                nodes: 1,
+                stored: 0
+            })
+        );
+    }
+
+    #[test]
+    fn test_verify_pair_mismatch() {
+        let mut builder = TreeBuilder::<BitpackingUsageBuilder>::new();
+        builder.open(NodeType::Array);
+        builder.open(NodeType::Null);
+        builder.close(NodeType::Null);
+        builder.close(NodeType::Object);
+        let structure = Structure::<EliasFanoUsageIndex>::new(builder);
+        let doc = Document::new(
+            structure,
+            TextUsageBuilder::new(100, 1).build(),
+            NumbersBuilder::default().build(),
+            BitVec::new(),
+        );
+        assert_eq!(
+            doc.verify(),
+            Err(IntegrityError::PairMismatch { open: 0, close: 3 })
+        );
+    }
+
+    #[test]
+    fn test_verify_invalid_child() {
+        let mut builder = TreeBuilder::<BitpackingUsageBuilder>::new();
+        builder.open(NodeType::Object);
+        builder.open(NodeType::Null);
+        builder.close(NodeType::Null);
+        builder.close(NodeType::Object);
+        let structure = Structure::<EliasFanoUsageIndex>::new(builder);
+        let doc = Document::new(
+            structure,
+            TextUsageBuilder::new(100, 1).build(),
+            NumbersBuilder::default().build(),
+            BitVec::new(),
+        );
+        assert_eq!(
+            doc.verify(),
+            Err(IntegrityError::InvalidChild {
+                parent: 0,
+                child: 1
+            })
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use crate::{info::NodeType, usage::UsageIndex};
+
+use super::{Document, Node};
+
+/// What a [`Visitor`] wants [`Document::walk`] to do after entering a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Walk {
+    /// Go on with the children of the node.
+    #[default]
+    Continue,
+    /// Leave out the children of the node; it is still exited.
+    SkipChildren,
+    /// End the walk without exiting any more nodes.
+    Stop,
+}
+
+/// Callbacks for the nodes of a subtree, given to [`Document::walk`].
+///
+/// Every node is entered before its children and exited after them, so
+/// the calls nest like the document does. Fields are nodes of their own,
+/// with their value as their only child.
+pub trait Visitor<U: UsageIndex> {
+    /// Called before the children of `node`.
+    fn enter(&mut self, document: &Document<U>, node: Node, node_type: &NodeType) -> Walk {
+        let _ = (document, node, node_type);
+        Walk::Continue
+    }
+
+    /// Called after the children of `node`, or right after entering it if
+    /// they were skipped.
+    fn exit(&mut self, document: &Document<U>, node: Node, node_type: &NodeType) {
+        let _ = (document, node, node_type);
+    }
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// Walk the subtree of `node` in document order, entering and exiting
+    /// every node with `visitor`, which can skip the children of a node or
+    /// stop the walk.
+    ///
+    /// The walk moves along the tree itself rather than recursing, so deep
+    /// documents need no stack. Returns whether the visitor stopped it.
+    pub fn walk<V: Visitor<U> + ?Sized>(&self, node: Node, visitor: &mut V) -> bool {
+        let mut current = node;
+        loop {
+            let descend = match visitor.enter(self, current, self.node_type(current)) {
+                Walk::Continue => true,
+                Walk::SkipChildren => false,
+                Walk::Stop => return true,
+            };
+            if descend && let Some(child) = self.first_child(current) {
+                current = child;
+                continue;
+            }
+            // exit nodes until one has a next sibling to enter
+            loop {
+                visitor.exit(self, current, self.node_type(current));
+                if current == node {
+                    return false;
+                }
+                if let Some(sibling) = self.next_sibling(current) {
+                    current = sibling;
+                    break;
+                }
+                current = self
+                    .parent(current)
+                    .expect("Node below the walked node should have a parent");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::{BitpackingUsageBuilder, EliasFanoUsageIndex, UsageBuilder};
+
+    use super::*;
+
+    // records the walk, skipping the fields named `skip` and stopping at
+    // the field named `stop`
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<String>,
+    }
+
+    impl Visitor<EliasFanoUsageIndex> for Recorder {
+        fn enter(
+            &mut self,
+            _document: &Document<EliasFanoUsageIndex>,
+            _node: Node,
+            node_type: &NodeType,
+        ) -> Walk {
+            self.events.push(format!("+{node_type:?}"));
+            match node_type {
+                NodeType::Field(name) if name == "skip" => Walk::SkipChildren,
+                NodeType::Field(name) if name == "stop" => Walk::Stop,
+                _ => Walk::Continue,
+            }
+        }
+
+        fn exit(
+            &mut self,
+            _document: &Document<EliasFanoUsageIndex>,
+            _node: Node,
+            node_type: &NodeType,
+        ) {
+            self.events.push(format!("-{node_type:?}"));
+        }
+    }
+
+    #[test]
+    fn test_walk() {
+        let doc = BitpackingUsageBuilder::parse(
+            r#"{"a": [1, {}], "skip": [true], "b": null, "stop": 2, "c": 3}"#.as_bytes(),
+        )
+        .unwrap();
+        let mut recorder = Recorder::default();
+        assert!(doc.walk(doc.root(), &mut recorder));
+        assert_eq!(
+            recorder.events,
+            [
+                "+Object",
+                "+Field(\"a\")",
+                "+Array",
+                "+Number",
+                "-Number",
+                "+Object",
+                "-Object",
+                "-Array",
+                "-Field(\"a\")",
+                "+Field(\"skip\")",
+                "-Field(\"skip\")",
+                "+Field(\"b\")",
+                "+Null",
+                "-Null",
+                "-Field(\"b\")",
+                "+Field(\"stop\")",
+            ]
+        );
+
+        // a subtree, ending at the node itself rather than its sibling
+        let a = doc.first_child(doc.root()).unwrap();
+        let array = doc.first_child(a).unwrap();
+        let mut recorder = Recorder::default();
+        assert!(!doc.walk(array, &mut recorder));
+        assert_eq!(
+            recorder.events,
+            [
+                "+Array", "+Number", "-Number", "+Object", "-Object", "-Array"
+            ]
+        );
+    }
+}
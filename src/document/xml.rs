@@ -0,0 +1,305 @@
+use std::io::Write;
+
+use crate::usage::UsageIndex;
+
+use super::{Document, Node, ObjectValue, Value};
+
+/// How arrays are mapped to XML elements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArrayConvention {
+    /// The array becomes a single element, with every item nested in an
+    /// element named by [`XmlOptions::item_name`].
+    Wrapped,
+    /// An array held by an object field becomes a repeated element named
+    /// after that field, one per item. Arrays not held by a field fall back
+    /// to [`ArrayConvention::Wrapped`].
+    Repeated,
+}
+
+/// Options for [`Document::to_xml`].
+#[derive(Debug, Clone)]
+pub struct XmlOptions {
+    /// The name of the root element.
+    pub root_name: String,
+    /// The name of the elements for array items that have no field name.
+    pub item_name: String,
+    pub array_convention: ArrayConvention,
+    /// Object fields starting with this prefix that hold a scalar become
+    /// attributes of the element of the object.
+    pub attribute_prefix: Option<String>,
+    /// An object field with this name that holds a scalar becomes the text
+    /// content of the element of the object.
+    pub text_key: Option<String>,
+    /// Whether to write an XML declaration.
+    pub declaration: bool,
+}
+
+impl Default for XmlOptions {
+    fn default() -> Self {
+        Self {
+            root_name: "root".to_string(),
+            item_name: "item".to_string(),
+            array_convention: ArrayConvention::Wrapped,
+            attribute_prefix: Some("@".to_string()),
+            text_key: Some("#text".to_string()),
+            declaration: true,
+        }
+    }
+}
+
+// escape markup, and replace the characters that XML 1.0 doesn't allow
+// at all, even as character references, by U+FFFD
+fn escape_xml(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            '\t' | '\n' | '\r' => out.push(c),
+            '\0'..='\x1f' | '\u{fffe}' | '\u{ffff}' => out.push(char::REPLACEMENT_CHARACTER),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Turn a field name into a valid XML element or attribute name, replacing
+/// invalid characters by underscores.
+fn element_name(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for (i, c) in name.chars().enumerate() {
+        let valid =
+            c.is_alphabetic() || c == '_' || (i > 0 && (c.is_numeric() || c == '-' || c == '.'));
+        if i == 0 && (c.is_numeric() || c == '-' || c == '.') {
+            out.push('_');
+            out.push(c);
+        } else if valid {
+            out.push(c);
+        } else {
+            out.push('_');
+        }
+    }
+    if out.is_empty() {
+        out.push('_');
+    }
+    out
+}
+
+fn scalar_text<U: UsageIndex>(value: &Value<'_, U>) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Integer(i) => Some(i.to_string()),
+        Value::Boolean(b) => Some(b.to_string()),
+        Value::Null => Some(String::new()),
+        Value::Object(_) | Value::Array(_) => None,
+    }
+}
+
+impl<U: UsageIndex> Document<U> {
+    /// Write the subtree under `node` as XML.
+    ///
+    /// The value is written as the content of the root element. Objects
+    /// become elements named after their fields; arrays are mapped according
+    /// to the [`ArrayConvention`]. Scalars become text, with `null` as an
+    /// empty element. Characters that XML 1.0 does not allow are replaced
+    /// by U+FFFD.
+    ///
+    /// Of fields that map to the same attribute, only the first becomes
+    /// one; the others are written as child elements, as an element can't
+    /// have an attribute twice.
+    pub fn to_xml<W: Write>(
+        &self,
+        node: Node,
+        mut w: W,
+        options: &XmlOptions,
+    ) -> std::io::Result<()> {
+        if options.declaration {
+            write!(w, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        }
+        xml_element(&mut w, &options.root_name, self.value(node), options)
+    }
+}
+
+fn xml_element<W: Write, U: UsageIndex>(
+    w: &mut W,
+    name: &str,
+    value: Value<'_, U>,
+    options: &XmlOptions,
+) -> std::io::Result<()> {
+    let name = element_name(name);
+    match value {
+        Value::Object(object) => xml_object(w, &name, object, options),
+        Value::Array(array) => {
+            write!(w, "<{name}>")?;
+            for item in array {
+                xml_element(w, &options.item_name, item, options)?;
+            }
+            write!(w, "</{name}>")
+        }
+        Value::Null => write!(w, "<{name}/>"),
+        value => {
+            let text = scalar_text(&value).expect("Scalar should have text");
+            write!(w, "<{name}>{}</{name}>", escape_xml(&text))
+        }
+    }
+}
+
+fn xml_object<W: Write, U: UsageIndex>(
+    w: &mut W,
+    name: &str,
+    object: ObjectValue<'_, U>,
+    options: &XmlOptions,
+) -> std::io::Result<()> {
+    let mut text = None;
+    let mut children = Vec::new();
+    let mut attributes = Vec::new();
+    write!(w, "<{name}")?;
+    for (key, value) in object.iter() {
+        if let Some(prefix) = &options.attribute_prefix
+            && let Some(attribute) = key.strip_prefix(prefix.as_str())
+            && let Some(attribute_value) = scalar_text(&value)
+        {
+            let attribute = element_name(attribute);
+            if !attributes.contains(&attribute) {
+                write!(w, " {attribute}=\"{}\"", escape_xml(&attribute_value))?;
+                attributes.push(attribute);
+                continue;
+            }
+        }
+        if options.text_key.as_deref() == Some(key)
+            && let Some(t) = scalar_text(&value)
+        {
+            text = Some(t);
+            continue;
+        }
+        children.push((key, value));
+    }
+    if text.is_none() && children.is_empty() {
+        return write!(w, "/>");
+    }
+    write!(w, ">")?;
+    if let Some(text) = text {
+        write!(w, "{}", escape_xml(&text))?;
+    }
+    for (key, value) in children {
+        match value {
+            Value::Array(array) if options.array_convention == ArrayConvention::Repeated => {
+                for item in array {
+                    xml_element(w, key, item, options)?;
+                }
+            }
+            value => xml_element(w, key, value, options)?,
+        }
+    }
+    write!(w, "</{name}>")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::{BitpackingUsageBuilder, UsageBuilder};
+
+    use super::*;
+
+    fn render(json: &str, options: &XmlOptions) -> String {
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+        let mut out = Vec::new();
+        doc.to_xml(doc.root(), &mut out, options).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn no_declaration() -> XmlOptions {
+        XmlOptions {
+            declaration: false,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_element_name() {
+        assert_eq!(element_name("foo"), "foo");
+        assert_eq!(element_name("foo bar"), "foo_bar");
+        assert_eq!(element_name("1st"), "_1st");
+        assert_eq!(element_name(""), "_");
+        assert_eq!(element_name("a-b.c"), "a-b.c");
+    }
+
+    #[test]
+    fn test_to_xml_scalar() {
+        assert_eq!(
+            render("42", &XmlOptions::default()),
+            r#"<?xml version="1.0" encoding="UTF-8"?><root>42</root>"#
+        );
+        assert_eq!(render("null", &no_declaration()), "<root/>");
+        assert_eq!(render(r#""a<b""#, &no_declaration()), "<root>a&lt;b</root>");
+    }
+
+    #[test]
+    fn test_to_xml_object() {
+        assert_eq!(
+            render(
+                r##"{"@id": 1, "name": "x", "#text": "t", "child": {"a": true}}"##,
+                &no_declaration()
+            ),
+            r#"<root id="1">t<name>x</name><child><a>true</a></child></root>"#
+        );
+    }
+
+    #[test]
+    fn test_to_xml_duplicate_attributes() {
+        assert_eq!(
+            render(
+                r#"{"@id": 1, "@id": 2, "@i d": 3, "@i_d": 4}"#,
+                &no_declaration()
+            ),
+            r#"<root id="1" i_d="3"><_id>2</_id><_i_d>4</_i_d></root>"#
+        );
+    }
+
+    #[test]
+    fn test_to_xml_invalid_characters() {
+        assert_eq!(
+            render(
+                r#"{"@a": "x\u0001y", "b": "\u0000\t\n\uffff"}"#,
+                &no_declaration()
+            ),
+            "<root a=\"x\u{fffd}y\"><b>\u{fffd}\t\n\u{fffd}</b></root>"
+        );
+    }
+
+    #[test]
+    fn test_to_xml_no_attribute_mapping() {
+        let options = XmlOptions {
+            attribute_prefix: None,
+            text_key: None,
+            ..no_declaration()
+        };
+        assert_eq!(
+            render(r#"{"@id": 1}"#, &options),
+            r#"<root><_id>1</_id></root>"#
+        );
+    }
+
+    #[test]
+    fn test_to_xml_wrapped_array() {
+        assert_eq!(
+            render(r#"{"a": [1, [2]]}"#, &no_declaration()),
+            r#"<root><a><item>1</item><item><item>2</item></item></a></root>"#
+        );
+    }
+
+    #[test]
+    fn test_to_xml_repeated_array() {
+        let options = XmlOptions {
+            array_convention: ArrayConvention::Repeated,
+            ..no_declaration()
+        };
+        assert_eq!(
+            render(r#"{"a": [1, {"b": 2}], "c": [[3]]}"#, &options),
+            r#"<root><a>1</a><a><b>2</b></a><c><item>3</item></c></root>"#
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use std::fmt;
+
+use crate::{
+    builder::Builder,
+    document::Document,
+    info::{NodeInfoId, NodeType},
+    memory_report::MemoryReport,
+    usage::UsageBuilder,
+};
+
+/// A call to [`DocumentBuilder`] that doesn't fit in the document so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildError {
+    /// A value was added where an object expects a field name, or after the
+    /// root value was complete.
+    UnexpectedValue,
+    /// A field was added outside of an object, or before the previous field
+    /// got its value.
+    UnexpectedField,
+    /// `end` was called with no object or array open, or while a field
+    /// had no value yet.
+    UnexpectedEnd,
+    /// The document was finished before its root value was complete.
+    Incomplete,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BuildError::UnexpectedValue => "a value is not expected here",
+            BuildError::UnexpectedField => "a field is not expected here",
+            BuildError::UnexpectedEnd => "there is nothing to end here",
+            BuildError::Incomplete => "the root value is not complete",
+        })
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+// an object or array that is still open
+enum Open {
+    // the field that waits for its value, if any
+    Object(Option<NodeInfoId>),
+    Array,
+}
+
+/// Construction of a document from code, without JSON text.
+///
+/// This wraps [`Builder`] and checks every call against the document built
+/// so far: objects contain fields that each have exactly one value, every
+/// `begin_object` or `begin_array` is matched by an `end`, and there is
+/// exactly one root value. A call that doesn't fit returns an error and
+/// leaves the builder as it was.
+///
+/// ```
+/// use colchis::{BitpackingUsageBuilder, DocumentBuilder};
+///
+/// let mut builder = DocumentBuilder::<BitpackingUsageBuilder>::new();
+/// builder.begin_object()?;
+/// builder.field("name")?;
+/// builder.string("colchis")?;
+/// builder.field("tags")?;
+/// builder.begin_array()?;
+/// builder.string("json")?;
+/// builder.end()?;
+/// builder.end()?;
+/// let doc = builder.finish()?;
+/// # Ok::<(), colchis::BuildError>(())
+/// ```
+pub struct DocumentBuilder<B: UsageBuilder> {
+    builder: Builder<B>,
+    open: Vec<Open>,
+    complete: bool,
+}
+
+impl<B: UsageBuilder> Default for DocumentBuilder<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: UsageBuilder> DocumentBuilder<B> {
+    pub fn new() -> Self {
+        Self::with_builder(Builder::new())
+    }
+
+    /// Build into a [`Builder`] that was configured, for instance with
+    /// [`Builder::deterministic`]. Nothing should have been added to it.
+    pub fn with_builder(builder: Builder<B>) -> Self {
+        Self {
+            builder,
+            open: Vec::new(),
+            complete: false,
+        }
+    }
+
+    pub fn begin_object(&mut self) -> Result<(), BuildError> {
+        self.expect_value()?;
+        self.builder.open(NodeType::Object);
+        self.open.push(Open::Object(None));
+        Ok(())
+    }
+
+    pub fn begin_array(&mut self) -> Result<(), BuildError> {
+        self.expect_value()?;
+        self.builder.open(NodeType::Array);
+        self.open.push(Open::Array);
+        Ok(())
+    }
+
+    /// The heap used so far by each part of the document being built.
+    pub fn memory_report(&self) -> MemoryReport {
+        self.builder.memory_report()
+    }
+
+    /// Start a field of the innermost open object. The next value added
+    /// becomes its value.
+    pub fn field(&mut self, name: &str) -> Result<(), BuildError> {
+        let Some(Open::Object(field @ None)) = self.open.last_mut() else {
+            return Err(BuildError::UnexpectedField);
+        };
+        *field = Some(self.builder.open_field(name));
+        Ok(())
+    }
+
+    /// Close the innermost open object or array.
+    pub fn end(&mut self) -> Result<(), BuildError> {
+        let node_type = match self.open.last() {
+            Some(Open::Object(None)) => NodeType::Object,
+            Some(Open::Array) => NodeType::Array,
+            _ => return Err(BuildError::UnexpectedEnd),
+        };
+        self.end_of(node_type)
+    }
+
+    /// Close the innermost open container, which has to be of the given
+    /// type.
+    pub(crate) fn end_of(&mut self, node_type: NodeType) -> Result<(), BuildError> {
+        match (self.open.last(), &node_type) {
+            (Some(Open::Object(None)), NodeType::Object) | (Some(Open::Array), NodeType::Array) => {
+            }
+            _ => return Err(BuildError::UnexpectedEnd),
+        }
+        self.open.pop();
+        self.builder.close(node_type);
+        self.value_added();
+        Ok(())
+    }
+
+    pub fn string(&mut self, s: &str) -> Result<(), BuildError> {
+        self.expect_value()?;
+        self.builder.string(s);
+        self.value_added();
+        Ok(())
+    }
+
+    pub fn number(&mut self, n: f64) -> Result<(), BuildError> {
+        self.expect_value()?;
+        self.builder.number(n);
+        self.value_added();
+        Ok(())
+    }
+
+    /// Add an integer, which is stored exactly rather than as a float.
+    pub fn integer(&mut self, i: i64) -> Result<(), BuildError> {
+        self.expect_value()?;
+        self.builder.integer(i);
+        self.value_added();
+        Ok(())
+    }
+
+    pub fn boolean(&mut self, b: bool) -> Result<(), BuildError> {
+        self.expect_value()?;
+        self.builder.boolean(b);
+        self.value_added();
+        Ok(())
+    }
+
+    pub fn null(&mut self) -> Result<(), BuildError> {
+        self.expect_value()?;
+        self.builder.null();
+        self.value_added();
+        Ok(())
+    }
+
+    /// Turn the complete root value into a document.
+    pub fn finish(self) -> Result<Document<B::Index>, BuildError> {
+        if !self.complete {
+            return Err(BuildError::Incomplete);
+        }
+        Ok(self.builder.build())
+    }
+
+    fn expect_value(&self) -> Result<(), BuildError> {
+        match self.open.last() {
+            None if !self.complete => Ok(()),
+            Some(Open::Array | Open::Object(Some(_))) => Ok(()),
+            _ => Err(BuildError::UnexpectedValue),
+        }
+    }
+
+    // a value is complete, which also completes the field it is in
+    fn value_added(&mut self) {
+        match self.open.last_mut() {
+            None => self.complete = true,
+            Some(Open::Object(field)) => {
+                if let Some(id) = field.take() {
+                    self.builder.close_field(id);
+                }
+            }
+            Some(Open::Array) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OwnedValue, usage::BitpackingUsageBuilder};
+
+    fn parsed(json: &str) -> OwnedValue {
+        BitpackingUsageBuilder::parse(json.as_bytes())
+            .unwrap()
+            .root_value()
+            .to_owned_value()
+    }
+
+    fn new_builder() -> DocumentBuilder<BitpackingUsageBuilder> {
+        DocumentBuilder::new()
+    }
+
+    #[test]
+    fn test_document_builder() {
+        let mut builder = new_builder();
+        builder.begin_object().unwrap();
+        builder.field("a").unwrap();
+        builder.begin_array().unwrap();
+        builder.number(1.5).unwrap();
+        builder.integer(9007199254740993).unwrap();
+        builder.boolean(true).unwrap();
+        builder.begin_object().unwrap();
+        builder.end().unwrap();
+        builder.end().unwrap();
+        builder.field("b").unwrap();
+        builder.string("text").unwrap();
+        builder.field("c").unwrap();
+        builder.null().unwrap();
+        builder.end().unwrap();
+        let doc = builder.finish().unwrap();
+        assert_eq!(doc.verify(), Ok(()));
+        assert_eq!(
+            doc.root_value().to_owned_value(),
+            parsed(r#"{"a": [1.5, 9007199254740993, true, {}], "b": "text", "c": null}"#)
+        );
+    }
+
+    #[test]
+    fn test_document_builder_scalar_root() {
+        let mut builder = new_builder();
+        builder.string("alone").unwrap();
+        assert_eq!(builder.null(), Err(BuildError::UnexpectedValue));
+        let doc = builder.finish().unwrap();
+        assert_eq!(
+            doc.root_value().to_owned_value(),
+            OwnedValue::String("alone".into())
+        );
+    }
+
+    #[test]
+    fn test_document_builder_errors() {
+        let mut builder = new_builder();
+        assert_eq!(builder.field("a"), Err(BuildError::UnexpectedField));
+        assert_eq!(builder.end(), Err(BuildError::UnexpectedEnd));
+        builder.begin_object().unwrap();
+        // a value needs a field first
+        assert_eq!(builder.number(1.0), Err(BuildError::UnexpectedValue));
+        builder.field("a").unwrap();
+        assert_eq!(builder.field("b"), Err(BuildError::UnexpectedField));
+        assert_eq!(builder.end(), Err(BuildError::UnexpectedEnd));
+        builder.begin_array().unwrap();
+        assert_eq!(builder.field("b"), Err(BuildError::UnexpectedField));
+        builder.end().unwrap();
+        builder.end().unwrap();
+        assert_eq!(builder.end(), Err(BuildError::UnexpectedEnd));
+        assert_eq!(builder.begin_array(), Err(BuildError::UnexpectedValue));
+        // the failed calls left no trace
+        let doc = builder.finish().unwrap();
+        assert_eq!(doc.verify(), Ok(()));
+        assert_eq!(doc.root_value().to_owned_value(), parsed(r#"{"a": []}"#));
+    }
+
+    #[test]
+    fn test_document_builder_incomplete() {
+        assert_eq!(new_builder().finish().err(), Some(BuildError::Incomplete));
+        let mut builder = new_builder();
+        builder.begin_array().unwrap();
+        assert_eq!(builder.finish().err(), Some(BuildError::Incomplete));
+        let mut builder = new_builder();
+        builder.begin_object().unwrap();
+        builder.field("a").unwrap();
+        assert_eq!(builder.finish().err(), Some(BuildError::Incomplete));
+    }
+}
@@ -0,0 +1,266 @@
+use std::{fmt, io};
+
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+use crate::binary::BinaryError;
+#[cfg(feature = "serde")]
+use crate::document::DeserializeError;
+#[cfg(feature = "parquet")]
+use crate::document::ParquetWriteError;
+#[cfg(feature = "regex")]
+use crate::text::regex::RegexError;
+use crate::{
+    document::{
+        EditError, FromEntriesError, FunctionError, IntegrityError, Node, PatchError,
+        SizeLimitExceeded, TomlError,
+    },
+    document_builder::BuildError,
+    parse_error::JsonParseError,
+    persist::LoadError,
+    text::{TextBlockError, TextId},
+};
+
+/// Any error colchis reports, so the errors of parsing, loading, querying
+/// and editing can all be passed on with `?`.
+///
+/// Every more specific error converts into this one, and the variant keeps
+/// it as it was, so it can still be matched on.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The input could not be parsed as JSON.
+    Parse(JsonParseError),
+    /// A saved document could not be loaded.
+    Load(LoadError),
+    /// A [`DocumentBuilder`](crate::DocumentBuilder) was called out of
+    /// order.
+    Build(BuildError),
+    Edit(EditError),
+    Patch(PatchError),
+    FromEntries(FromEntriesError),
+    /// A function extension could not be called.
+    Function(FunctionError),
+    /// The document is not internally consistent.
+    Integrity(IntegrityError),
+    SizeLimit(SizeLimitExceeded),
+    Toml(TomlError),
+    /// Stored text is corrupt.
+    CorruptText(TextBlockError),
+    /// The node is not in the document; it may be a node of another one.
+    NodeNotFound(Node),
+    /// There is no stored text with the id.
+    TextNotFound(TextId),
+    #[cfg(feature = "regex")]
+    Regex(RegexError),
+    #[cfg(feature = "serde")]
+    Deserialize(DeserializeError),
+    #[cfg(feature = "parquet")]
+    Parquet(ParquetWriteError),
+    #[cfg(any(feature = "msgpack", feature = "cbor"))]
+    Binary(BinaryError<BuildError>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "I/O error: {err}"),
+            Error::Parse(err) => err.fmt(f),
+            Error::Load(err) => write!(f, "could not load document: {err}"),
+            Error::Build(err) => write!(f, "could not build document: {err}"),
+            Error::Edit(err) => write!(f, "could not edit document: {err}"),
+            Error::Patch(err) => write!(f, "could not apply patch: {err}"),
+            Error::FromEntries(err) => write!(f, "could not assemble document: {err}"),
+            Error::Function(err) => err.fmt(f),
+            Error::Integrity(err) => write!(f, "inconsistent document: {err}"),
+            Error::SizeLimit(err) => err.fmt(f),
+            Error::Toml(err) => write!(f, "could not write TOML: {err}"),
+            Error::CorruptText(err) => err.fmt(f),
+            Error::NodeNotFound(node) => write!(f, "{node:?} is not in the document"),
+            Error::TextNotFound(text_id) => write!(f, "{text_id:?} is not in the document"),
+            #[cfg(feature = "regex")]
+            Error::Regex(err) => err.fmt(f),
+            #[cfg(feature = "serde")]
+            Error::Deserialize(err) => write!(f, "could not deserialize: {err}"),
+            #[cfg(feature = "parquet")]
+            Error::Parquet(err) => write!(f, "could not write Parquet: {err}"),
+            #[cfg(any(feature = "msgpack", feature = "cbor"))]
+            Error::Binary(err) => write!(f, "could not read binary input: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Parse(err) => Some(err),
+            Error::Load(err) => Some(err),
+            Error::Build(err) => Some(err),
+            Error::Edit(err) => Some(err),
+            Error::Patch(err) => Some(err),
+            Error::FromEntries(err) => Some(err),
+            Error::Function(err) => Some(err),
+            Error::Integrity(err) => Some(err),
+            Error::SizeLimit(err) => Some(err),
+            Error::Toml(err) => Some(err),
+            Error::CorruptText(err) => Some(err),
+            Error::NodeNotFound(_) | Error::TextNotFound(_) => None,
+            #[cfg(feature = "regex")]
+            Error::Regex(err) => Some(err),
+            #[cfg(feature = "serde")]
+            Error::Deserialize(err) => Some(err),
+            #[cfg(feature = "parquet")]
+            Error::Parquet(err) => Some(err),
+            #[cfg(any(feature = "msgpack", feature = "cbor"))]
+            Error::Binary(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<JsonParseError> for Error {
+    fn from(err: JsonParseError) -> Self {
+        Error::Parse(err)
+    }
+}
+
+impl From<LoadError> for Error {
+    fn from(err: LoadError) -> Self {
+        Error::Load(err)
+    }
+}
+
+impl From<BuildError> for Error {
+    fn from(err: BuildError) -> Self {
+        Error::Build(err)
+    }
+}
+
+impl From<EditError> for Error {
+    fn from(err: EditError) -> Self {
+        Error::Edit(err)
+    }
+}
+
+impl From<PatchError> for Error {
+    fn from(err: PatchError) -> Self {
+        Error::Patch(err)
+    }
+}
+
+impl From<FromEntriesError> for Error {
+    fn from(err: FromEntriesError) -> Self {
+        Error::FromEntries(err)
+    }
+}
+
+impl From<FunctionError> for Error {
+    fn from(err: FunctionError) -> Self {
+        Error::Function(err)
+    }
+}
+
+impl From<IntegrityError> for Error {
+    fn from(err: IntegrityError) -> Self {
+        Error::Integrity(err)
+    }
+}
+
+impl From<SizeLimitExceeded> for Error {
+    fn from(err: SizeLimitExceeded) -> Self {
+        Error::SizeLimit(err)
+    }
+}
+
+impl From<TomlError> for Error {
+    fn from(err: TomlError) -> Self {
+        Error::Toml(err)
+    }
+}
+
+impl From<TextBlockError> for Error {
+    fn from(err: TextBlockError) -> Self {
+        Error::CorruptText(err)
+    }
+}
+
+#[cfg(feature = "regex")]
+impl From<RegexError> for Error {
+    fn from(err: RegexError) -> Self {
+        Error::Regex(err)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<DeserializeError> for Error {
+    fn from(err: DeserializeError) -> Self {
+        Error::Deserialize(err)
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl From<ParquetWriteError> for Error {
+    fn from(err: ParquetWriteError) -> Self {
+        Error::Parquet(err)
+    }
+}
+
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+impl From<BinaryError<BuildError>> for Error {
+    fn from(err: BinaryError<BuildError>) -> Self {
+        Error::Binary(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as _;
+
+    use crate::{
+        document::Document,
+        info::NodeType,
+        usage::{BitpackingUsageBuilder, EliasFanoUsageIndex},
+    };
+
+    use super::*;
+
+    fn edit(json: &str, pointer: &str) -> Result<Document<EliasFanoUsageIndex>, Error> {
+        let doc = Document::parse::<BitpackingUsageBuilder, _>(json.as_bytes())?;
+        Ok(doc.without::<BitpackingUsageBuilder>(pointer)?)
+    }
+
+    #[test]
+    fn test_question_mark() {
+        assert!(edit(r#"{"a": 1}"#, "/a").is_ok());
+        let err = edit(r#"{"a": 1"#, "/a").unwrap_err();
+        assert!(matches!(err, Error::Parse(_)));
+        assert!(err.source().is_some());
+        let err = edit(r#"{"a": 1}"#, "/b").unwrap_err();
+        assert!(matches!(&err, Error::Edit(EditError::NotFound(pointer)) if pointer == "/b"));
+        assert_eq!(
+            err.to_string(),
+            "could not edit document: nothing to edit at '/b'"
+        );
+    }
+
+    #[test]
+    fn test_node_not_found() {
+        let small = Document::parse::<BitpackingUsageBuilder, _>("[]".as_bytes()).unwrap();
+        let big = Document::parse::<BitpackingUsageBuilder, _>(r#"["a", "b"]"#.as_bytes()).unwrap();
+        let node = big.nth_child(big.root(), 1).unwrap();
+        assert_eq!(big.try_as_str(node).unwrap().as_deref(), Some("b"));
+        assert!(matches!(
+            small.try_node_type(node),
+            Err(Error::NodeNotFound(n)) if n == node
+        ));
+        assert!(matches!(
+            small.try_as_str(node),
+            Err(Error::NodeNotFound(_))
+        ));
+        assert_eq!(small.try_node_type(small.root()).unwrap(), &NodeType::Array);
+    }
+}
@@ -1,12 +1,73 @@
 //
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+mod binary;
+mod builder;
+pub mod container;
+mod corpus;
 mod document;
+mod document_builder;
+mod error;
 mod info;
 mod lookup;
+#[cfg(feature = "memory-tracking")]
+pub mod memory;
+mod memory_report;
+mod numbers;
+mod options;
+mod parse_error;
 mod parser;
+mod persist;
+mod pointer;
+mod progress;
+#[cfg(feature = "python")]
+pub mod python;
+mod sink;
+mod source_spans;
+mod sparse;
+mod spill;
 mod structure;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod text;
 mod tree_builder;
 mod usage;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
-pub use document::{Document, Node, Value};
-pub use usage::{BitpackingUsageBuilder, RoaringUsageBuilder};
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+pub use binary::BinaryError;
+#[cfg(feature = "cbor")]
+pub use binary::read_cbor;
+#[cfg(feature = "msgpack")]
+pub use binary::read_msgpack;
+pub use builder::Builder;
+pub use corpus::{Corpus, DocumentId};
+#[cfg(feature = "serde")]
+pub use document::DeserializeError;
+pub use document::{
+    Agg, Ancestors, ArrayConvention, ArrayMergeStrategy, ArrayValue, AxisIter, Change, Children,
+    CompiledPointer, Diff, Document, EditError, Event, Events, Explain, ExplainStep, FieldPath,
+    FromEntriesError, Function, FunctionArg, FunctionError, FunctionRegistry, FunctionResult,
+    IntegrityError, LookupStrategy, Node, NumbersInRange, ObjectValue, OwnedValue, PatchError,
+    PatchOperation, PathSchema, Projection, Schema, SerializeOptions, SizeLimitExceeded, TomlError,
+    TypedDescendants, Value, ValueType, Visitor, Walk, XmlOptions,
+};
+#[cfg(feature = "parquet")]
+pub use document::{ParquetOptions, ParquetWriteError};
+pub use document_builder::{BuildError, DocumentBuilder};
+pub use error::Error;
+pub use info::{NodeInfoId, NodeType};
+pub use lookup::NodeLookup;
+pub use memory_report::MemoryReport;
+pub use options::{ParseOptions, Predicate};
+pub use parse_error::{JsonParseError, NdjsonReport, ParseErrorKind, ParseLimit, SkippedLine};
+pub use parser::Documents;
+pub use persist::LoadError;
+pub use progress::{CancellationToken, Progress};
+pub use sink::{DocumentSink, EventSink};
+pub use text::TextValidation;
+#[cfg(feature = "regex")]
+pub use text::regex::RegexError;
+pub use usage::{
+    BitpackingUsageBuilder, EliasFanoUsageIndex, HybridUsageBuilder, RoaringUsageBuilder,
+};
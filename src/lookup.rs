@@ -1,12 +1,26 @@
-use ahash::HashMap;
+use std::io::{self, Read, Write};
 
-use crate::info::{self, NodeInfo, NodeInfoId, NodeType};
+use ahash::HashMap;
 
-#[derive(Debug)]
+use crate::{
+    info::{self, NodeInfo, NodeInfoId, NodeType},
+    persist::{self, LoadError},
+};
+
+/// The dictionary of node types and field names of a document, which gives
+/// each of them a [`NodeInfoId`].
+///
+/// Documents parsed with the same lookup through
+/// [`ParseOptions::node_lookup`](crate::ParseOptions::node_lookup) give
+/// the same ids to the same field names; the parse only adds the names
+/// that are new. Get the lookup of a parsed document with
+/// [`Document::node_lookup`](crate::Document::node_lookup) to parse the
+/// next document with.
+#[derive(Debug, Clone)]
 pub struct NodeLookup {
     node_infos: Vec<NodeInfo>,
     node_info_lookup: HashMap<NodeInfo, NodeInfoId>,
-    field_info_lookup: HashMap<String, NodeInfoId>,
+    field_info_lookup: HashMap<String, (NodeInfoId, NodeInfoId)>,
 }
 
 impl NodeLookup {
@@ -67,7 +81,7 @@ impl NodeLookup {
             + self.node_info_lookup.len() * std::mem::size_of::<(NodeInfo, NodeInfoId)>()
     }
 
-    pub fn register(&mut self, node_info: NodeInfo) -> NodeInfoId {
+    pub(crate) fn register(&mut self, node_info: NodeInfo) -> NodeInfoId {
         if let Some(idx) = self.register_fast_path(&node_info) {
             return idx;
         }
@@ -76,25 +90,16 @@ impl NodeLookup {
 
     // an extra fast path for fields, so we can avoid allocation of the string
     // if we already have that field name registered
-    fn register_field(
-        &mut self,
-        name: &str,
-        make_node_info: impl Fn(&str) -> NodeInfo,
-    ) -> NodeInfoId {
-        if let Some(&idx) = self.field_info_lookup.get(name) {
-            return idx;
+    pub(crate) fn register_field_ids(&mut self, name: &str) -> (NodeInfoId, NodeInfoId) {
+        if let Some(&ids) = self.field_info_lookup.get(name) {
+            return ids;
         }
-        let node_info = make_node_info(name);
-        let idx = self.register_lookup(node_info);
-        self.field_info_lookup.insert(name.to_string(), idx);
-        idx
-    }
-
-    pub fn register_field_ids(&mut self, name: &str) -> (NodeInfoId, NodeInfoId) {
-        (
-            self.register_field(name, |n| NodeInfo::open(NodeType::Field(n.to_string()))),
-            self.register_field(name, |n| NodeInfo::close(NodeType::Field(n.to_string()))),
-        )
+        let ids = (
+            self.register_lookup(NodeInfo::open(NodeType::Field(name.to_string()))),
+            self.register_lookup(NodeInfo::close(NodeType::Field(name.to_string()))),
+        );
+        self.field_info_lookup.insert(name.to_string(), ids);
+        ids
     }
 
     fn register_fast_path(&mut self, node_info: &NodeInfo) -> Option<NodeInfoId> {
@@ -125,20 +130,122 @@ impl NodeLookup {
         idx
     }
 
-    #[allow(dead_code)]
+    /// Add a field name, so documents parsed with this lookup give the
+    /// field this id whether they have it or not.
+    pub fn register_field(&mut self, name: &str) -> NodeInfoId {
+        self.register_field_ids(name).0
+    }
+
+    /// Register the node infos of another lookup, returning the ids they
+    /// have in this one, indexed by their ids in the other.
+    pub(crate) fn register_all(&mut self, other: &NodeLookup) -> Vec<NodeInfoId> {
+        other
+            .node_infos
+            .iter()
+            .map(|node_info| match &node_info.node_type {
+                // through the field ids, so the fast path for fields knows them
+                NodeType::Field(name) => {
+                    let (open_id, close_id) = self.register_field_ids(name);
+                    if node_info.is_open_tag {
+                        open_id
+                    } else {
+                        close_id
+                    }
+                }
+                _ => self.register(node_info.clone()),
+            })
+            .collect()
+    }
+
     pub(crate) fn by_node_info(&self, node_info: &NodeInfo) -> Option<NodeInfoId> {
         self.node_info_lookup.get(node_info).copied()
     }
 
+    /// The id of a field name, if it is in the lookup.
+    pub fn by_field_name(&self, name: &str) -> Option<NodeInfoId> {
+        self.field_info_lookup.get(name).map(|&(open, _)| open)
+    }
+
     pub(crate) fn by_node_info_id(&self, node_info_id: NodeInfoId) -> &NodeInfo {
-        self.node_infos
-            .get(node_info_id.id() as usize)
+        self.get(node_info_id)
             .expect("Node info id does not exist in this document")
     }
 
+    pub(crate) fn get(&self, node_info_id: NodeInfoId) -> Option<&NodeInfo> {
+        self.node_infos.get(node_info_id.id() as usize)
+    }
+
     pub(crate) fn len(&self) -> usize {
         self.node_infos.len()
     }
+
+    pub(crate) fn write<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        persist::write_len(w, self.node_infos.len())?;
+        for node_info in &self.node_infos {
+            // the lowest bit is the open flag, the rest is the node type
+            let node_type = match &node_info.node_type {
+                NodeType::Object => 0,
+                NodeType::Array => 1,
+                NodeType::String => 2,
+                NodeType::Number => 3,
+                NodeType::Boolean => 4,
+                NodeType::Null => 5,
+                NodeType::Field(_) => 6,
+            };
+            persist::write_u8(w, node_type << 1 | u8::from(node_info.is_open_tag))?;
+            if let NodeType::Field(name) = &node_info.node_type {
+                persist::write_str(w, name)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn read<R: Read + ?Sized>(r: &mut R) -> Result<Self, LoadError> {
+        let mut node_lookup = Self::new();
+        let builtin = node_lookup.len();
+        let len = persist::read_len(r)?;
+        for i in 0..len {
+            let tag = persist::read_u8(r)?;
+            let node_type = match tag >> 1 {
+                0 => NodeType::Object,
+                1 => NodeType::Array,
+                2 => NodeType::String,
+                3 => NodeType::Number,
+                4 => NodeType::Boolean,
+                5 => NodeType::Null,
+                6 => NodeType::Field(persist::read_string(r)?),
+                _ => return Err(LoadError::Corrupt("unknown node type")),
+            };
+            let node_info = NodeInfo {
+                node_type,
+                is_open_tag: tag & 1 == 1,
+            };
+            if i < builtin {
+                if node_lookup.node_infos[i] != node_info {
+                    return Err(LoadError::Corrupt("builtin node info mismatch"));
+                }
+            } else if node_lookup.register_lookup(node_info).index() != i {
+                return Err(LoadError::Corrupt("duplicate node info"));
+            }
+        }
+        if len < builtin {
+            return Err(LoadError::Corrupt("missing builtin node infos"));
+        }
+        for node_info in &node_lookup.node_infos {
+            if let (true, NodeType::Field(name)) = (node_info.is_open_tag, &node_info.node_type) {
+                let close = NodeInfo::close(NodeType::Field(name.clone()));
+                if let (Some(open_id), Some(close_id)) = (
+                    node_lookup.by_node_info(node_info),
+                    node_lookup.by_node_info(&close),
+                ) {
+                    node_lookup
+                        .field_info_lookup
+                        .insert(name.clone(), (open_id, close_id));
+                }
+            }
+        }
+        Ok(node_lookup)
+    }
 }
 
 impl Default for NodeLookup {
@@ -235,6 +342,25 @@ mod tests {
         assert_eq!(field2_id, field2_id_again);
     }
 
+    #[test]
+    fn test_register_field_ids() {
+        let mut lookup = NodeLookup::new();
+
+        let (open_id, close_id) = lookup.register_field_ids("name");
+        assert_ne!(open_id, close_id);
+        assert_eq!(
+            lookup.by_node_info_id(open_id),
+            &NodeInfo::open(NodeType::Field("name".to_string()))
+        );
+        assert_eq!(
+            lookup.by_node_info_id(close_id),
+            &NodeInfo::close(NodeType::Field("name".to_string()))
+        );
+
+        // registering again gives the same ids
+        assert_eq!(lookup.register_field_ids("name"), (open_id, close_id));
+    }
+
     #[test]
     fn test_lookup_by_node_info() {
         let mut lookup = NodeLookup::new();
@@ -277,6 +403,38 @@ mod tests {
         assert_eq!(found_field, &field);
     }
 
+    #[test]
+    fn test_write_read() {
+        let mut lookup = NodeLookup::new();
+        let (open_id, close_id) = lookup.register_field_ids("name");
+
+        let mut out = Vec::new();
+        lookup.write(&mut out).unwrap();
+        let mut read = NodeLookup::read(&mut out.as_slice()).unwrap();
+
+        assert_eq!(read.len(), lookup.len());
+        assert_eq!(read.register_field_ids("name"), (open_id, close_id));
+        assert_eq!(read.len(), lookup.len());
+    }
+
+    #[test]
+    fn test_register_all() {
+        let mut lookup = NodeLookup::new();
+        lookup.register_field_ids("a");
+        let mut other = NodeLookup::new();
+        let (b_open, b_close) = other.register_field_ids("b");
+        let (a_open, _) = other.register_field_ids("a");
+
+        let ids = lookup.register_all(&other);
+        assert_eq!(ids.len(), other.len());
+        assert_eq!(ids[info::ARRAY_OPEN_ID.index()], info::ARRAY_OPEN_ID);
+        assert_eq!(ids[a_open.index()], lookup.register_field_ids("a").0);
+        assert_eq!(
+            (ids[b_open.index()], ids[b_close.index()]),
+            lookup.register_field_ids("b")
+        );
+    }
+
     #[test]
     fn test_edge_case_empty_fields() {
         let mut lookup = NodeLookup::new();
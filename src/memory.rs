@@ -0,0 +1,148 @@
+//! Tracking of peak heap usage while building documents.
+//!
+//! This is only available with the `memory-tracking` feature. Install
+//! [`TrackingAllocator`] as the global allocator, optionally wrapping
+//! another allocator such as jemalloc:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOC: colchis::memory::TrackingAllocator = colchis::memory::TrackingAllocator::system();
+//! ```
+//!
+//! Documents built afterwards record the peak heap usage of every build
+//! phase in [`Document::build_memory`](crate::Document::build_memory).
+//! The counters are global, so builds running concurrently on other threads
+//! show up in each other's numbers.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// A global allocator that counts the bytes allocated through it.
+pub struct TrackingAllocator<A = System> {
+    inner: A,
+}
+
+impl TrackingAllocator<System> {
+    /// Track the system allocator.
+    pub const fn system() -> Self {
+        Self { inner: System }
+    }
+}
+
+impl<A> TrackingAllocator<A> {
+    /// Track another allocator.
+    pub const fn wrap(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+fn allocated(size: usize) {
+    let current = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(current, Ordering::Relaxed);
+}
+
+fn deallocated(size: usize) {
+    CURRENT.fetch_sub(size, Ordering::Relaxed);
+}
+
+// SAFETY: all allocation is delegated to the inner allocator; we only count
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TrackingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        INSTALLED.store(true, Ordering::Relaxed);
+        let ptr = unsafe { self.inner.alloc(layout) };
+        if !ptr.is_null() {
+            allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        INSTALLED.store(true, Ordering::Relaxed);
+        let ptr = unsafe { self.inner.alloc_zeroed(layout) };
+        if !ptr.is_null() {
+            allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.inner.dealloc(ptr, layout) };
+        deallocated(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { self.inner.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            deallocated(layout.size());
+            allocated(new_size);
+        }
+        new_ptr
+    }
+}
+
+/// The number of bytes currently allocated through [`TrackingAllocator`].
+pub fn current_allocated() -> usize {
+    CURRENT.load(Ordering::Relaxed)
+}
+
+/// Peak heap usage in bytes during each phase of building a document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildMemory {
+    /// Feeding the builder: for parsing this is reading the JSON, building
+    /// the parentheses and compressing full text blocks.
+    pub tree_building: usize,
+    /// Turning the parentheses and usage into the succinct structure.
+    pub index_construction: usize,
+    /// Compressing the last text block and completing the text storage.
+    pub text_compression: usize,
+}
+
+impl BuildMemory {
+    /// The highest peak over all phases.
+    pub fn peak(&self) -> usize {
+        self.tree_building
+            .max(self.index_construction)
+            .max(self.text_compression)
+    }
+}
+
+/// Start measuring the peak of a new phase.
+pub(crate) fn start_phase() {
+    PEAK.store(CURRENT.load(Ordering::Relaxed), Ordering::Relaxed);
+}
+
+/// The peak since the current phase started, or `None` if the tracking
+/// allocator is not installed.
+pub(crate) fn phase_peak() -> Option<usize> {
+    INSTALLED
+        .load(Ordering::Relaxed)
+        .then(|| PEAK.load(Ordering::Relaxed))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::{BitpackingUsageBuilder, UsageBuilder};
+
+    use super::*;
+
+    #[global_allocator]
+    static ALLOC: TrackingAllocator = TrackingAllocator::system();
+
+    #[test]
+    fn test_build_memory() {
+        let json = format!("[{}]", vec![r#"{"a": "some text"}"#; 1000].join(","));
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+        let memory = doc.build_memory().expect("Tracking allocator is installed");
+        assert!(memory.tree_building > 0);
+        assert!(memory.index_construction > 0);
+        assert!(memory.text_compression > 0);
+        assert!(memory.peak() >= memory.tree_building);
+        assert!(current_allocated() > 0);
+    }
+}
@@ -0,0 +1,51 @@
+/// The heap used by the parts of a document, or of a document being
+/// built, in bytes.
+///
+/// Returned by [`Document::memory_report`](crate::Document::memory_report),
+/// [`Builder::memory_report`](crate::Builder::memory_report) and
+/// [`DocumentBuilder::memory_report`](crate::DocumentBuilder::memory_report).
+/// Parts stored in a spill file take no heap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// The balanced parentheses of the tree.
+    pub tree: usize,
+    /// The usage index, which finds the nodes of every type.
+    pub usage_index: usize,
+    /// The recorded positions of every 64th child of large containers.
+    pub child_skips: usize,
+    /// The compressed text blocks, with their dictionaries and the hashes
+    /// of the texts if they are kept.
+    pub text_blocks: usize,
+    /// The text that isn't compressed yet; only builders have this.
+    pub pending_text: usize,
+    /// The numbers.
+    pub numbers: usize,
+    /// The text of the numbers, if the document keeps number lexemes.
+    pub number_lexemes: usize,
+    /// The booleans.
+    pub booleans: usize,
+    /// The byte ranges of the nodes in the input, if the document records
+    /// source spans.
+    pub source_spans: usize,
+    /// The indexes built on request, such as the substring index.
+    pub indexes: usize,
+    /// The decompressed text blocks that are cached for reading.
+    pub caches: usize,
+}
+
+impl MemoryReport {
+    /// The heap used by all parts together.
+    pub fn total(&self) -> usize {
+        self.tree
+            + self.usage_index
+            + self.child_skips
+            + self.text_blocks
+            + self.pending_text
+            + self.numbers
+            + self.number_lexemes
+            + self.booleans
+            + self.source_spans
+            + self.indexes
+            + self.caches
+    }
+}
@@ -0,0 +1,406 @@
+//! Compact columns of numbers.
+//!
+//! Values are encoded in chunks of [`CHUNK_LEN`], and each chunk uses the
+//! encoding that packs it smallest: either every value minus the chunk
+//! minimum (frame of reference), or the differences between consecutive
+//! values minus the smallest difference, which packs runs of ids that go
+//! up by a fixed step into almost nothing. Either way the remainders are
+//! bit-packed at the width of the largest one.
+//!
+//! Floats are first turned into integers where that is exact: if every
+//! float in a chunk is a decimal with at most [`MAX_DECIMAL_EXPONENT`]
+//! digits after the point, the chunk holds those decimals scaled up to
+//! integers. Other chunks hold the bits of the floats.
+
+use std::io::{self, Read, Write};
+
+use vers_vecs::BitVec;
+
+use crate::persist::{self, LoadError};
+
+pub(crate) const CHUNK_LEN: usize = 128;
+const MAX_DECIMAL_EXPONENT: u8 = 15;
+// the exponent of chunks that hold the bits of the floats
+const RAW_FLOATS: u8 = u8::MAX;
+// integers up to this size convert to and from floats exactly
+const MAX_EXACT_FLOAT_INTEGER: f64 = (1u64 << 53) as f64;
+
+const POWERS_OF_TEN: [f64; MAX_DECIMAL_EXPONENT as usize + 1] = [
+    1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11, 1e12, 1e13, 1e14, 1e15,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    FrameOfReference = 0,
+    Delta = 1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Chunk {
+    // the position of the first packed value
+    start: usize,
+    // the minimum, or for deltas the first value
+    base: i64,
+    // the smallest difference, for deltas
+    step: i64,
+    width: u8,
+    encoding: Encoding,
+}
+
+fn width(max: u64) -> u8 {
+    (u64::BITS - max.leading_zeros()) as u8
+}
+
+/// A column of `i64` values, bit-packed in chunks.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct IntColumn {
+    bits: BitVec,
+    chunks: Vec<Chunk>,
+    len: usize,
+}
+
+impl IntColumn {
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Add a chunk of values; only the last chunk may be shorter than
+    /// [`CHUNK_LEN`].
+    pub(crate) fn push_chunk(&mut self, values: &[i64]) {
+        debug_assert!(self.len.is_multiple_of(CHUNK_LEN) && values.len() <= CHUNK_LEN);
+        let Some(&first) = values.first() else {
+            return;
+        };
+        let min = values.iter().copied().min().unwrap_or(first);
+        let max = values.iter().copied().max().unwrap_or(first);
+        let reference_width = width(max.wrapping_sub(min) as u64);
+        let steps = || values.windows(2).map(|pair| pair[1].wrapping_sub(pair[0]));
+        let min_step = steps().min().unwrap_or(0);
+        let max_step = steps().max().unwrap_or(0);
+        let delta_width = width(max_step.wrapping_sub(min_step) as u64);
+        let start = self.bits.len();
+        let chunk = if delta_width < reference_width {
+            for step in steps() {
+                self.append(step.wrapping_sub(min_step) as u64, delta_width);
+            }
+            Chunk {
+                start,
+                base: first,
+                step: min_step,
+                width: delta_width,
+                encoding: Encoding::Delta,
+            }
+        } else {
+            for value in values {
+                self.append(value.wrapping_sub(min) as u64, reference_width);
+            }
+            Chunk {
+                start,
+                base: min,
+                step: 0,
+                width: reference_width,
+                encoding: Encoding::FrameOfReference,
+            }
+        };
+        self.chunks.push(chunk);
+        self.len += values.len();
+    }
+
+    fn append(&mut self, value: u64, width: u8) {
+        if width > 0 {
+            self.bits.append_bits(value, width as usize);
+        }
+    }
+
+    fn packed(&self, chunk: &Chunk, index: usize) -> u64 {
+        if chunk.width == 0 {
+            return 0;
+        }
+        let width = chunk.width as usize;
+        self.bits
+            .get_bits_unchecked(chunk.start + index * width, width)
+    }
+
+    pub(crate) fn get(&self, index: usize) -> i64 {
+        let chunk = &self.chunks[index / CHUNK_LEN];
+        let offset = index % CHUNK_LEN;
+        match chunk.encoding {
+            Encoding::FrameOfReference => {
+                chunk.base.wrapping_add(self.packed(chunk, offset) as i64)
+            }
+            Encoding::Delta => (0..offset).fold(chunk.base, |value, i| {
+                value
+                    .wrapping_add(chunk.step)
+                    .wrapping_add(self.packed(chunk, i) as i64)
+            }),
+        }
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        self.bits.heap_size() + self.chunks.capacity() * std::mem::size_of::<Chunk>()
+    }
+
+    pub(crate) fn write<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        persist::write_len(w, self.len)?;
+        for chunk in &self.chunks {
+            persist::write_u64(w, chunk.base as u64)?;
+            persist::write_u64(w, chunk.step as u64)?;
+            persist::write_u8(w, chunk.width)?;
+            persist::write_u8(w, chunk.encoding as u8)?;
+        }
+        persist::write_bit_vec(w, &self.bits)
+    }
+
+    pub(crate) fn read<R: Read + ?Sized>(r: &mut R) -> Result<Self, LoadError> {
+        let len = persist::read_len(r)?;
+        let count = len.div_ceil(CHUNK_LEN);
+        let mut chunks = persist::vec_for(count);
+        let mut start = 0usize;
+        for i in 0..count {
+            let base = persist::read_u64(r)? as i64;
+            let step = persist::read_u64(r)? as i64;
+            let width = persist::read_u8(r)?;
+            let encoding = match persist::read_u8(r)? {
+                0 => Encoding::FrameOfReference,
+                1 => Encoding::Delta,
+                _ => return Err(LoadError::Corrupt("unknown number encoding")),
+            };
+            if width > 64 {
+                return Err(LoadError::Corrupt("number width too large"));
+            }
+            let values = CHUNK_LEN.min(len - i * CHUNK_LEN);
+            // deltas don't pack the first value
+            let packed = match encoding {
+                Encoding::FrameOfReference => values,
+                Encoding::Delta => values - 1,
+            };
+            chunks.push(Chunk {
+                start,
+                base,
+                step,
+                width,
+                encoding,
+            });
+            start = start
+                .checked_add(packed * width as usize)
+                .ok_or(LoadError::Corrupt("number chunks too large"))?;
+        }
+        let bits = persist::read_bit_vec(r)?;
+        if bits.len() != start {
+            return Err(LoadError::Corrupt("number bits length mismatch"));
+        }
+        Ok(Self { bits, chunks, len })
+    }
+}
+
+/// Collects values into an [`IntColumn`] a chunk at a time.
+#[derive(Debug, Default)]
+pub(crate) struct IntColumnBuilder {
+    column: IntColumn,
+    pending: Vec<i64>,
+}
+
+impl IntColumnBuilder {
+    pub(crate) fn push(&mut self, value: i64) {
+        self.pending.push(value);
+        if self.pending.len() == CHUNK_LEN {
+            self.column.push_chunk(&self.pending);
+            self.pending.clear();
+        }
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        self.column.heap_size() + self.pending.capacity() * std::mem::size_of::<i64>()
+    }
+
+    pub(crate) fn build(mut self) -> IntColumn {
+        self.column.push_chunk(&self.pending);
+        self.column
+    }
+}
+
+// the smallest exponent with which all floats are integers after scaling
+fn decimal_exponent(floats: &[f64]) -> Option<u8> {
+    (0..=MAX_DECIMAL_EXPONENT)
+        .find(|&exponent| floats.iter().all(|&float| scale(float, exponent).is_some()))
+}
+
+fn scale(float: f64, exponent: u8) -> Option<i64> {
+    let power = POWERS_OF_TEN[exponent as usize];
+    let scaled = (float * power).round();
+    // -0.0 would come back as 0.0
+    let exact = scaled.abs() < MAX_EXACT_FLOAT_INTEGER
+        && scaled / power == float
+        && (float != 0.0 || float.is_sign_positive());
+    exact.then_some(scaled as i64)
+}
+
+fn unscale(value: i64, exponent: u8) -> f64 {
+    if exponent == RAW_FLOATS {
+        f64::from_bits(value as u64)
+    } else {
+        value as f64 / POWERS_OF_TEN[exponent as usize]
+    }
+}
+
+/// A column of `f64` values, stored as scaled decimals where that is
+/// exact.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct FloatColumn {
+    values: IntColumn,
+    // the exponent of every chunk
+    exponents: Vec<u8>,
+}
+
+impl FloatColumn {
+    pub(crate) fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    fn push_chunk(&mut self, floats: &[f64]) {
+        if floats.is_empty() {
+            return;
+        }
+        let values = match decimal_exponent(floats) {
+            Some(exponent) => {
+                self.exponents.push(exponent);
+                floats
+                    .iter()
+                    .map(|&float| scale(float, exponent).unwrap_or_default())
+                    .collect::<Vec<_>>()
+            }
+            None => {
+                self.exponents.push(RAW_FLOATS);
+                floats.iter().map(|float| float.to_bits() as i64).collect()
+            }
+        };
+        self.values.push_chunk(&values);
+    }
+
+    pub(crate) fn get(&self, index: usize) -> f64 {
+        unscale(self.values.get(index), self.exponents[index / CHUNK_LEN])
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        self.values.heap_size() + self.exponents.capacity()
+    }
+
+    pub(crate) fn write<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        self.values.write(w)?;
+        for exponent in &self.exponents {
+            persist::write_u8(w, *exponent)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn read<R: Read + ?Sized>(r: &mut R) -> Result<Self, LoadError> {
+        let values = IntColumn::read(r)?;
+        let count = values.len().div_ceil(CHUNK_LEN);
+        let mut exponents = persist::vec_for(count);
+        for _ in 0..count {
+            let exponent = persist::read_u8(r)?;
+            if exponent > MAX_DECIMAL_EXPONENT && exponent != RAW_FLOATS {
+                return Err(LoadError::Corrupt("unknown float exponent"));
+            }
+            exponents.push(exponent);
+        }
+        Ok(Self { values, exponents })
+    }
+}
+
+/// Collects floats into a [`FloatColumn`] a chunk at a time.
+#[derive(Debug, Default)]
+pub(crate) struct FloatColumnBuilder {
+    column: FloatColumn,
+    pending: Vec<f64>,
+}
+
+impl FloatColumnBuilder {
+    pub(crate) fn push(&mut self, float: f64) {
+        self.pending.push(float);
+        if self.pending.len() == CHUNK_LEN {
+            self.column.push_chunk(&self.pending);
+            self.pending.clear();
+        }
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        self.column.heap_size() + self.pending.capacity() * std::mem::size_of::<f64>()
+    }
+
+    pub(crate) fn build(mut self) -> FloatColumn {
+        self.column.push_chunk(&self.pending);
+        self.column
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int_column(values: &[i64]) -> IntColumn {
+        let mut builder = IntColumnBuilder::default();
+        for &value in values {
+            builder.push(value);
+        }
+        builder.build()
+    }
+
+    fn float_column(floats: &[f64]) -> FloatColumn {
+        let mut builder = FloatColumnBuilder::default();
+        for &float in floats {
+            builder.push(float);
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn test_int_column() {
+        let ids = (0..1000).map(|i| 1_000_000 + 3 * i).collect::<Vec<_>>();
+        let column = int_column(&ids);
+        assert!((0..ids.len()).all(|i| column.get(i) == ids[i]));
+        // steps of 3 pack into nothing
+        assert_eq!(column.bits.len(), 0);
+
+        let mixed = [i64::MIN, 0, i64::MAX, -1, 42, i64::MIN + 1];
+        let column = int_column(&mixed);
+        assert!((0..mixed.len()).all(|i| column.get(i) == mixed[i]));
+
+        let small = (0..300).map(|i| (i * 7919) % 100).collect::<Vec<_>>();
+        let column = int_column(&small);
+        assert!((0..small.len()).all(|i| column.get(i) == small[i]));
+        assert_eq!(column.bits.len(), 300 * 7);
+    }
+
+    #[test]
+    fn test_float_column() {
+        let prices = (0..500)
+            .map(|i| (i * 25 + 999) as f64 / 100.0)
+            .collect::<Vec<_>>();
+        let column = float_column(&prices);
+        assert!((0..prices.len()).all(|i| column.get(i) == prices[i]));
+        assert!(column.exponents.iter().all(|&exponent| exponent == 2));
+
+        let odd = [0.1, -0.0, f64::MAX, 1e-300, f64::INFINITY, 3.0];
+        let column = float_column(&odd);
+        assert!((0..odd.len()).all(|i| column.get(i).to_bits() == odd[i].to_bits()));
+        assert_eq!(column.exponents, vec![RAW_FLOATS]);
+    }
+
+    #[test]
+    fn test_column_write_read() {
+        let floats = (0..300).map(|i| i as f64 / 8.0).collect::<Vec<_>>();
+        let column = float_column(&floats);
+        let mut out = Vec::new();
+        column.write(&mut out).unwrap();
+        let read = FloatColumn::read(&mut out.as_slice()).unwrap();
+        assert_eq!(read, column);
+
+        // a truncated bit vector is caught
+        let column = int_column(&[1, 1000, 5]);
+        let mut out = Vec::new();
+        column.write(&mut out).unwrap();
+        out.truncate(out.len() - 8);
+        assert!(IntColumn::read(&mut out.as_slice()).is_err());
+    }
+}
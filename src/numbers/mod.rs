@@ -0,0 +1,403 @@
+mod column;
+
+use std::cmp::Ordering;
+use std::io::{self, Read, Write};
+
+use vers_vecs::{BitVec, RsVec};
+
+use crate::persist::{self, LoadError};
+
+use column::{CHUNK_LEN, FloatColumn, FloatColumnBuilder, IntColumn, IntColumnBuilder};
+
+/// A number as it is stored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Number {
+    Float(f64),
+    Integer(i64),
+    /// An integer above `i64::MAX` that fits in a `u64`.
+    Unsigned(u64),
+}
+
+impl Number {
+    /// Parse the text of a JSON number. Numbers written without a fraction
+    /// or exponent that fit in an `i64` or a `u64` are integers, except for
+    /// `-0`, which only a float can hold.
+    pub(crate) fn parse(s: &str) -> Result<Self, std::num::ParseFloatError> {
+        if !s.contains(['.', 'e', 'E']) {
+            if let Ok(integer) = s.parse()
+                && (integer != 0 || !s.starts_with('-'))
+            {
+                return Ok(Number::Integer(integer));
+            }
+            if let Ok(unsigned) = s.parse() {
+                return Ok(Number::Unsigned(unsigned));
+            }
+        }
+        s.parse().map(Number::Float)
+    }
+
+    /// The number as a float, rounded if it is an integer that a float
+    /// can't hold.
+    pub(crate) fn to_f64(self) -> f64 {
+        match self {
+            Number::Float(float) => float,
+            Number::Integer(integer) => integer as f64,
+            Number::Unsigned(unsigned) => unsigned as f64,
+        }
+    }
+}
+
+/// Whether a float and an integer are the same number.
+pub(crate) fn float_equals_integer(float: f64, integer: i64) -> bool {
+    // comparing as i128 avoids the saturation of large floats at i64::MAX
+    float == integer as f64 && float as i128 == integer as i128
+}
+
+/// How a float compares to an integer, exactly. `None` if the float is NaN.
+pub(crate) fn compare_float_integer(float: f64, integer: i64) -> Option<Ordering> {
+    if float.is_nan() {
+        None
+    } else if float >= 9223372036854775808.0 {
+        Some(Ordering::Greater)
+    } else if float < -9223372036854775808.0 {
+        Some(Ordering::Less)
+    } else {
+        // the truncated float fits in an i64 here, so it converts exactly
+        match (float.trunc() as i64).cmp(&integer) {
+            Ordering::Equal => float.fract().partial_cmp(&0.0),
+            ordering => Some(ordering),
+        }
+    }
+}
+
+/// Whether a float and an unsigned integer are the same number.
+pub(crate) fn float_equals_unsigned(float: f64, unsigned: u64) -> bool {
+    float == unsigned as f64 && float as u128 == unsigned as u128
+}
+
+/// The smallest and largest number in a zone of [`CHUNK_LEN`] numbers, as
+/// floats. NaN is left out, so a zone of only NaN has no numbers in range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Zone {
+    min: f64,
+    max: f64,
+}
+
+impl Zone {
+    const EMPTY: Zone = Zone {
+        min: f64::INFINITY,
+        max: f64::NEG_INFINITY,
+    };
+
+    fn add(&mut self, float: f64) {
+        self.min = self.min.min(float);
+        self.max = self.max.max(float);
+    }
+
+    fn overlaps(&self, low: f64, high: f64) -> bool {
+        self.min <= high && low <= self.max
+    }
+}
+
+/// The numbers of a document in document order, with integers stored
+/// apart from floats so they keep their precision. Both are stored in
+/// compact [columns](column).
+#[derive(Debug, PartialEq)]
+pub(crate) struct Numbers {
+    floats: FloatColumn,
+    // integers above i64::MAX are stored with the same bits as an i64
+    integers: IntColumn,
+    // a bit for every number, set for integers
+    is_integer: RsVec,
+    // the sorted number ids of the integers above i64::MAX, which are rare
+    unsigned: Vec<usize>,
+    // a zone map, so range queries can skip zones that don't match
+    zones: Vec<Zone>,
+}
+
+impl Numbers {
+    pub(crate) fn len(&self) -> usize {
+        self.is_integer.len()
+    }
+
+    pub(crate) fn get(&self, number_id: usize) -> Number {
+        let rank = self.is_integer.rank1(number_id);
+        if self.is_integer.get_unchecked(number_id) == 1 {
+            let integer = self.integers.get(rank);
+            if self.unsigned.binary_search(&number_id).is_ok() {
+                Number::Unsigned(integer as u64)
+            } else {
+                Number::Integer(integer)
+            }
+        } else {
+            Number::Float(self.floats.get(number_id - rank))
+        }
+    }
+
+    /// The first number id from `number_id` on whose number is within
+    /// `low..=high`, compared as floats.
+    pub(crate) fn next_in_range(&self, number_id: usize, low: f64, high: f64) -> Option<usize> {
+        let mut number_id = number_id;
+        while number_id < self.len() {
+            let zone = number_id / CHUNK_LEN;
+            let zone_end = ((zone + 1) * CHUNK_LEN).min(self.len());
+            if self.zones[zone].overlaps(low, high) {
+                let found = (number_id..zone_end).find(|&id| {
+                    let float = self.get(id).to_f64();
+                    low <= float && float <= high
+                });
+                if found.is_some() {
+                    return found;
+                }
+            }
+            number_id = zone_end;
+        }
+        None
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        self.floats.heap_size()
+            + self.integers.heap_size()
+            + self.is_integer.heap_size()
+            + self.unsigned.capacity() * std::mem::size_of::<usize>()
+            + self.zones.capacity() * std::mem::size_of::<Zone>()
+    }
+
+    pub(crate) fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.floats.write(w)?;
+        self.integers.write(w)?;
+        // the rank/select vector doesn't expose its bits, but we can find
+        // the set ones
+        let mut is_integer = BitVec::from_zeros(self.len());
+        for position in self.is_integer.iter1() {
+            is_integer.flip_bit(position);
+        }
+        persist::write_bit_vec(w, &is_integer)?;
+        persist::write_len(w, self.unsigned.len())?;
+        for &number_id in &self.unsigned {
+            persist::write_len(w, number_id)?;
+        }
+        for zone in &self.zones {
+            persist::write_u64(w, zone.min.to_bits())?;
+            persist::write_u64(w, zone.max.to_bits())?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn read<R: Read>(r: &mut R) -> Result<Self, LoadError> {
+        let floats = FloatColumn::read(r)?;
+        let integers = IntColumn::read(r)?;
+        let is_integer = RsVec::from_bit_vec(persist::read_bit_vec(r)?);
+        let integer_count = is_integer.rank1(is_integer.len());
+        if integer_count != integers.len() || is_integer.len() - integer_count != floats.len() {
+            return Err(LoadError::Corrupt("number count mismatch"));
+        }
+        let unsigned_count = persist::read_len(r)?;
+        let mut unsigned = persist::vec_for(unsigned_count);
+        for _ in 0..unsigned_count {
+            let number_id = persist::read_len(r)?;
+            if unsigned.last().is_some_and(|&last| last >= number_id)
+                || is_integer.get(number_id) != Some(1)
+            {
+                return Err(LoadError::Corrupt("invalid unsigned number id"));
+            }
+            unsigned.push(number_id);
+        }
+        let count = is_integer.len().div_ceil(CHUNK_LEN);
+        let mut zones = persist::vec_for(count);
+        for _ in 0..count {
+            zones.push(Zone {
+                min: f64::from_bits(persist::read_u64(r)?),
+                max: f64::from_bits(persist::read_u64(r)?),
+            });
+        }
+        Ok(Self {
+            floats,
+            integers,
+            is_integer,
+            unsigned,
+            zones,
+        })
+    }
+}
+
+/// Collects the numbers of a document while it is built.
+#[derive(Debug, Default)]
+pub(crate) struct NumbersBuilder {
+    floats: FloatColumnBuilder,
+    integers: IntColumnBuilder,
+    is_integer: BitVec,
+    unsigned: Vec<usize>,
+    zones: Vec<Zone>,
+}
+
+impl NumbersBuilder {
+    pub(crate) fn push(&mut self, number: Number) {
+        if self.is_integer.len().is_multiple_of(CHUNK_LEN) {
+            self.zones.push(Zone::EMPTY);
+        }
+        if let Some(zone) = self.zones.last_mut() {
+            zone.add(number.to_f64());
+        }
+        match number {
+            Number::Float(float) => {
+                self.floats.push(float);
+                self.is_integer.append(false);
+            }
+            Number::Integer(integer) => {
+                self.integers.push(integer);
+                self.is_integer.append(true);
+            }
+            Number::Unsigned(unsigned) => {
+                self.unsigned.push(self.is_integer.len());
+                self.integers.push(unsigned as i64);
+                self.is_integer.append(true);
+            }
+        }
+    }
+
+    /// Append the numbers of a segment built in parallel.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn append(&mut self, other: NumbersBuilder) {
+        // the chunks of the other builder don't line up with ours
+        let other = other.build();
+        for number_id in 0..other.len() {
+            self.push(other.get(number_id));
+        }
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        self.floats.heap_size()
+            + self.integers.heap_size()
+            + self.is_integer.heap_size()
+            + self.unsigned.capacity() * std::mem::size_of::<usize>()
+            + self.zones.capacity() * std::mem::size_of::<Zone>()
+    }
+
+    pub(crate) fn build(self) -> Numbers {
+        Numbers {
+            floats: self.floats.build(),
+            integers: self.integers.build(),
+            is_integer: RsVec::from_bit_vec(self.is_integer),
+            unsigned: self.unsigned,
+            zones: self.zones,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(Number::parse("42"), Ok(Number::Integer(42)));
+        assert_eq!(Number::parse("-7"), Ok(Number::Integer(-7)));
+        assert_eq!(
+            Number::parse("9007199254740993"),
+            Ok(Number::Integer(9007199254740993))
+        );
+        assert_eq!(Number::parse("42.0"), Ok(Number::Float(42.0)));
+        assert!(matches!(Number::parse("-0"), Ok(Number::Float(n)) if n.is_sign_negative()));
+        assert_eq!(Number::parse("1e3"), Ok(Number::Float(1000.0)));
+        // too big for an i64, but not for a u64
+        assert_eq!(
+            Number::parse("9223372036854775808"),
+            Ok(Number::Unsigned(9223372036854775808))
+        );
+        assert_eq!(
+            Number::parse("18446744073709551615"),
+            Ok(Number::Unsigned(u64::MAX))
+        );
+        // too big for either
+        assert_eq!(
+            Number::parse("18446744073709551616"),
+            Ok(Number::Float(18446744073709551616.0))
+        );
+        assert_eq!(
+            Number::parse("-9223372036854775809"),
+            Ok(Number::Float(-9223372036854775809.0))
+        );
+    }
+
+    #[test]
+    fn test_numbers() {
+        let mut builder = NumbersBuilder::default();
+        builder.push(Number::Float(0.5));
+        builder.push(Number::Integer(i64::MAX));
+        builder.push(Number::Integer(-1));
+        builder.push(Number::Float(2.0));
+        builder.push(Number::Unsigned(u64::MAX));
+        builder.push(Number::Integer(-1));
+        let numbers = builder.build();
+        assert_eq!(numbers.len(), 6);
+        assert_eq!(numbers.get(1), Number::Integer(i64::MAX));
+        assert_eq!(numbers.get(3), Number::Float(2.0));
+        assert_eq!(numbers.get(4), Number::Unsigned(u64::MAX));
+        assert_eq!(numbers.get(5), Number::Integer(-1));
+
+        let mut out = Vec::new();
+        numbers.write(&mut out).unwrap();
+        assert_eq!(Numbers::read(&mut out.as_slice()).unwrap(), numbers);
+    }
+
+    #[test]
+    fn test_next_in_range() {
+        let mut builder = NumbersBuilder::default();
+        for i in 0..1000 {
+            builder.push(Number::Integer(i));
+        }
+        builder.push(Number::Float(f64::NAN));
+        builder.push(Number::Float(500.5));
+        let numbers = builder.build();
+        let in_range = |low, high| {
+            std::iter::successors(numbers.next_in_range(0, low, high), |&id| {
+                numbers.next_in_range(id + 1, low, high)
+            })
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(in_range(499.0, 501.0), vec![499, 500, 501, 1001]);
+        assert_eq!(in_range(2000.0, 3000.0), Vec::<usize>::new());
+        assert_eq!(in_range(-1.0, 0.0), vec![0]);
+
+        let mut out = Vec::new();
+        numbers.write(&mut out).unwrap();
+        let read = Numbers::read(&mut out.as_slice()).unwrap();
+        assert_eq!(read.next_in_range(0, 999.0, f64::INFINITY), Some(999));
+    }
+
+    #[test]
+    fn test_float_equals_integer() {
+        assert!(float_equals_integer(3.0, 3));
+        assert!(!float_equals_integer(3.5, 3));
+        assert!(!float_equals_integer(9007199254740992.0, 9007199254740993));
+        assert!(!float_equals_integer(9223372036854775808.0, i64::MAX));
+        assert!(float_equals_unsigned(9223372036854775808.0, 1 << 63));
+        assert!(!float_equals_unsigned(18446744073709551616.0, u64::MAX));
+    }
+
+    #[test]
+    fn test_compare_float_integer() {
+        use Ordering::*;
+        assert_eq!(compare_float_integer(3.0, 3), Some(Equal));
+        assert_eq!(compare_float_integer(2.5, 3), Some(Less));
+        assert_eq!(compare_float_integer(-2.5, -3), Some(Greater));
+        assert_eq!(
+            compare_float_integer(9007199254740992.0, 9007199254740993),
+            Some(Less)
+        );
+        assert_eq!(
+            compare_float_integer(9223372036854775808.0, i64::MAX),
+            Some(Greater)
+        );
+        assert_eq!(
+            compare_float_integer(-9223372036854775808.0, i64::MIN),
+            Some(Equal)
+        );
+        assert_eq!(
+            compare_float_integer(f64::NEG_INFINITY, i64::MIN),
+            Some(Less)
+        );
+        assert_eq!(compare_float_integer(f64::NAN, 0), None);
+    }
+}
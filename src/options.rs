@@ -0,0 +1,623 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+use crate::{
+    document::OwnedValue,
+    lookup::NodeLookup,
+    numbers::compare_float_integer,
+    pointer,
+    progress::{CancellationToken, Progress, ProgressCallback},
+};
+
+// the default nesting limit; the parser recurses, so nesting that is
+// much deeper than this could overflow the stack
+const DEFAULT_MAX_DEPTH: u32 = 128;
+const DEFAULT_PROGRESS_INTERVAL: usize = 1_000_000;
+
+/// Options for parsing a document.
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    pub(crate) record_filters: Vec<RecordFilter>,
+    pub(crate) string_hashes: bool,
+    pub(crate) deterministic: bool,
+    pub(crate) spill_to_disk: bool,
+    pub(crate) jsonc: bool,
+    pub(crate) number_lexemes: bool,
+    pub(crate) source_spans: bool,
+    pub(crate) text_dictionary: Option<usize>,
+    pub(crate) text_block_size: Option<usize>,
+    pub(crate) text_cache_blocks: Option<usize>,
+    pub(crate) compression_level: Option<u32>,
+    pub(crate) background_compression: bool,
+    pub(crate) max_depth: Option<u32>,
+    pub(crate) max_nodes: Option<usize>,
+    pub(crate) max_string_length: Option<usize>,
+    pub(crate) memory_budget: Option<usize>,
+    pub(crate) progress: Option<ProgressCallback>,
+    pub(crate) progress_interval: usize,
+    pub(crate) cancellation: Option<CancellationToken>,
+    pub(crate) node_lookup: Option<Arc<NodeLookup>>,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            record_filters: Vec::new(),
+            string_hashes: false,
+            deterministic: false,
+            spill_to_disk: false,
+            jsonc: false,
+            number_lexemes: false,
+            source_spans: false,
+            text_dictionary: None,
+            text_block_size: None,
+            text_cache_blocks: None,
+            compression_level: None,
+            background_compression: false,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            max_nodes: None,
+            max_string_length: None,
+            memory_budget: None,
+            progress: None,
+            progress_interval: DEFAULT_PROGRESS_INTERVAL,
+            cancellation: None,
+            node_lookup: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct RecordFilter {
+    // the path to the array holding the records; `*` matches any index
+    pub(crate) path: Vec<String>,
+    pub(crate) predicate: Predicate,
+}
+
+impl RecordFilter {
+    pub(crate) fn applies_to(&self, path: &[PathSegment]) -> bool {
+        self.path.len() == path.len()
+            && self
+                .path
+                .iter()
+                .zip(path)
+                .all(|(token, segment)| match segment {
+                    PathSegment::Key(key) => token == key,
+                    PathSegment::Index(index) => {
+                        token == "*" || pointer::array_index(token) == Some(*index)
+                    }
+                })
+    }
+}
+
+/// A step in the path from the root to a value being parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// A condition on a record, used to filter records during parsing.
+///
+/// Fields are JSON Pointers relative to the record. A record without the
+/// field does not match.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    /// The field holds exactly this value.
+    Equals { field: String, value: OwnedValue },
+    /// The field holds a number within the inclusive bounds.
+    Range {
+        field: String,
+        min: Option<f64>,
+        max: Option<f64>,
+    },
+}
+
+impl Predicate {
+    pub fn equals(field: &str, value: OwnedValue) -> Self {
+        Predicate::Equals {
+            field: field.to_string(),
+            value,
+        }
+    }
+
+    pub fn range(field: &str, min: Option<f64>, max: Option<f64>) -> Self {
+        Predicate::Range {
+            field: field.to_string(),
+            min,
+            max,
+        }
+    }
+
+    pub(crate) fn matches(&self, record: &OwnedValue) -> bool {
+        match self {
+            Predicate::Equals { field, value } => record.pointer(field) == Some(value),
+            Predicate::Range { field, min, max } => match record.pointer(field) {
+                Some(OwnedValue::Number(n)) => {
+                    min.is_none_or(|min| *n >= min) && max.is_none_or(|max| *n <= max)
+                }
+                Some(OwnedValue::Integer(i)) => {
+                    // compare exactly, as large integers don't survive a cast
+                    // to f64
+                    min.is_none_or(|min| {
+                        compare_float_integer(min, *i).is_some_and(Ordering::is_le)
+                    }) && max.is_none_or(|max| {
+                        compare_float_integer(max, *i).is_some_and(Ordering::is_ge)
+                    })
+                }
+                _ => false,
+            },
+        }
+    }
+}
+
+impl ParseOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only keep the items of the array at `record_path` that match the
+    /// predicate; the others are skipped during parsing and never end up in
+    /// the document. The path is a JSON Pointer in which `*` matches any
+    /// array index. Adding multiple filters for the same path keeps only
+    /// the records that match all of them.
+    ///
+    /// # Panics
+    ///
+    /// If `record_path` is not a valid JSON Pointer.
+    pub fn filter_records(mut self, record_path: &str, predicate: Predicate) -> Self {
+        let path = pointer::parse(record_path).expect("Record path should be a JSON Pointer");
+        self.record_filters.push(RecordFilter { path, predicate });
+        self
+    }
+
+    /// Guarantee that parsing the same input with the same options results
+    /// in a byte-identical document, for the same version of colchis.
+    ///
+    /// This fixes the text block boundaries and compression parameters,
+    /// overriding any tuning that would otherwise apply, so built documents
+    /// can be cached by content.
+    pub fn deterministic(mut self, enabled: bool) -> Self {
+        self.deterministic = enabled;
+        self
+    }
+
+    /// Store a hash of every string, so that
+    /// [`Document::strings_equal`](crate::Document::strings_equal) can
+    /// tell most unequal strings apart without decompressing them. This
+    /// costs 8 bytes per string.
+    pub fn string_hashes(mut self, enabled: bool) -> Self {
+        self.string_hashes = enabled;
+        self
+    }
+
+    /// Keep the text of every number as it is written in the input, so
+    /// that serializing writes it back verbatim, for instance
+    /// `0.1000000000000000055` or `1.50`, which a float can't hold. Values
+    /// still read as the nearest float or integer; see
+    /// [`Document::number_lexeme`](crate::Document::number_lexeme).
+    pub fn preserve_number_lexemes(mut self, enabled: bool) -> Self {
+        self.number_lexemes = enabled;
+        self
+    }
+
+    /// Record the byte range of every node in the input, so
+    /// [`Document::source_span`](crate::Document::source_span) can point
+    /// back into it, for instance to report where a value came from. This
+    /// costs a few bytes per node.
+    pub fn source_spans(mut self, enabled: bool) -> Self {
+        self.source_spans = enabled;
+        self
+    }
+
+    /// Compress strings with a dictionary trained on the first
+    /// `training_blocks` text blocks, which improves compression a lot for
+    /// many short, similar strings such as UUIDs, enum values or URLs. The
+    /// dictionary is stored with the document. Off by default.
+    pub fn text_dictionary(mut self, training_blocks: Option<usize>) -> Self {
+        self.text_dictionary = training_blocks;
+        self
+    }
+
+    /// Compress strings in blocks of about this many bytes; the default is
+    /// 1 MiB. Reading a string decompresses its whole block, so smaller
+    /// blocks lower the latency of reading single strings, while larger
+    /// blocks compress better. [`deterministic`](Self::deterministic)
+    /// overrides this.
+    pub fn text_block_size(mut self, bytes: Option<usize>) -> Self {
+        self.text_block_size = bytes;
+        self
+    }
+
+    /// The number of decompressed text blocks the document keeps cached;
+    /// the default is 10. Each cached block takes about the block size in
+    /// memory.
+    pub fn text_cache_blocks(mut self, blocks: Option<usize>) -> Self {
+        self.text_cache_blocks = blocks;
+        self
+    }
+
+    /// The deflate level strings are compressed with, from 0 (none) to 9
+    /// (smallest); the default is 6. [`deterministic`](Self::deterministic)
+    /// overrides this.
+    ///
+    /// # Panics
+    ///
+    /// If `level` is above 9.
+    pub fn compression_level(mut self, level: Option<u32>) -> Self {
+        assert!(
+            level.is_none_or(|level| level <= 9),
+            "Compression level should be at most 9"
+        );
+        self.compression_level = level;
+        self
+    }
+
+    /// Compress text blocks on a worker thread while parsing continues,
+    /// which takes compression off the parse thread for text-heavy input.
+    /// The document is the same as without it. Off by default.
+    pub fn background_compression(mut self, enabled: bool) -> Self {
+        self.background_compression = enabled;
+        self
+    }
+
+    /// Accept JSON with comments (JSONC): `//` and `/* */` comments are
+    /// skipped, and trailing commas in arrays and objects are allowed. The
+    /// resulting document is the same as for the JSON without them.
+    pub fn jsonc(mut self, enabled: bool) -> Self {
+        self.jsonc = enabled;
+        self
+    }
+
+    /// Fail with [`ParseLimit::Depth`](crate::ParseLimit::Depth) when
+    /// arrays and objects are nested deeper than this. The default is 128.
+    /// Parsing recurses, so without a limit deeply nested input can
+    /// overflow the stack.
+    pub fn max_depth(mut self, max: Option<u32>) -> Self {
+        self.max_depth = max;
+        self
+    }
+
+    /// Fail with [`ParseLimit::Nodes`](crate::ParseLimit::Nodes) when the
+    /// input has more nodes than this, counting every value and every
+    /// object field. There is no limit by default.
+    pub fn max_nodes(mut self, max: Option<usize>) -> Self {
+        self.max_nodes = max;
+        self
+    }
+
+    /// Fail with [`ParseLimit::StringLength`](crate::ParseLimit::StringLength)
+    /// when a string or a field name is longer than this many bytes. There
+    /// is no limit by default. The string is read before it is checked, so
+    /// this does not bound the memory used for a single string.
+    pub fn max_string_length(mut self, max: Option<usize>) -> Self {
+        self.max_string_length = max;
+        self
+    }
+
+    /// Keep the heap used by the document being built below this many
+    /// bytes. There is no budget by default.
+    ///
+    /// The memory is checked periodically while parsing. Over budget, the
+    /// pending text is compressed into a block early, which takes it off
+    /// the heap entirely with [`spill_to_disk`](Self::spill_to_disk); if
+    /// that is not enough, or the build is
+    /// [`deterministic`](Self::deterministic), parsing fails with
+    /// [`ParseLimit::Memory`](crate::ParseLimit::Memory). Near the budget
+    /// text blocks get smaller, which compresses less well. A parallel
+    /// parse checks every segment on its own.
+    pub fn memory_budget(mut self, max: Option<usize>) -> Self {
+        self.memory_budget = max;
+        self
+    }
+
+    /// Call `callback` every time another
+    /// [`progress_interval`](Self::progress_interval) nodes have been
+    /// read, with the progress of the parse so far.
+    pub fn on_progress(mut self, callback: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        self.progress = Some(ProgressCallback::new(callback));
+        self
+    }
+
+    /// The number of nodes between calls of the progress callback. The
+    /// default is a million.
+    ///
+    /// # Panics
+    ///
+    /// If `nodes` is 0.
+    pub fn progress_interval(mut self, nodes: usize) -> Self {
+        assert!(nodes > 0, "Progress interval should not be 0");
+        self.progress_interval = nodes;
+        self
+    }
+
+    /// Abort the parse with
+    /// [`ParseErrorKind::Cancelled`](crate::ParseErrorKind::Cancelled) once
+    /// the token is cancelled. The token is checked periodically, so a few
+    /// more nodes may be read after cancelling.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Start from the node types and field names of this lookup, so the
+    /// document gives the same [`NodeInfoId`](crate::NodeInfoId) to the
+    /// field names it shares with the lookup. The lookup isn't changed;
+    /// the document gets a copy with its new field names added.
+    pub fn node_lookup(mut self, node_lookup: Arc<NodeLookup>) -> Self {
+        self.node_lookup = Some(node_lookup);
+        self
+    }
+
+    /// The limit a string of this length exceeds, if any.
+    pub(crate) fn string_too_long(&self, len: usize) -> Option<usize> {
+        self.max_string_length.filter(|&max| len > max)
+    }
+
+    /// Write compressed text blocks and usage blocks to a temporary file as
+    /// soon as they are finished, instead of keeping them in memory. This
+    /// bounds the peak memory of parsing big files, at the cost of reading
+    /// text back from disk when it is used; the file is deleted when the
+    /// document is dropped.
+    pub fn spill_to_disk(mut self, enabled: bool) -> Self {
+        self.spill_to_disk = enabled;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{document::Document, usage::BitpackingUsageBuilder};
+
+    use super::*;
+
+    fn parse_to_json(json: &str, options: &ParseOptions) -> String {
+        let doc =
+            Document::parse_with::<BitpackingUsageBuilder, _>(json.as_bytes(), options).unwrap();
+        let mut out = Vec::new();
+        doc.serialize(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_applies_to() {
+        let filter = RecordFilter {
+            path: vec!["a".to_string(), "*".to_string(), "b".to_string()],
+            predicate: Predicate::equals("", OwnedValue::Null),
+        };
+        assert!(filter.applies_to(&[
+            PathSegment::Key("a".to_string()),
+            PathSegment::Index(3),
+            PathSegment::Key("b".to_string())
+        ]));
+        assert!(!filter.applies_to(&[
+            PathSegment::Key("a".to_string()),
+            PathSegment::Key("x".to_string()),
+            PathSegment::Key("b".to_string())
+        ]));
+        assert!(!filter.applies_to(&[PathSegment::Key("a".to_string())]));
+    }
+
+    #[test]
+    fn test_predicate_matches() {
+        let mut map = indexmap::IndexMap::new();
+        map.insert("n".into(), OwnedValue::Number(5.0));
+        map.insert("s".into(), OwnedValue::String("x".into()));
+        let record = OwnedValue::Object(map);
+
+        assert!(Predicate::equals("/s", OwnedValue::String("x".into())).matches(&record));
+        assert!(!Predicate::equals("/s", OwnedValue::String("y".into())).matches(&record));
+        assert!(!Predicate::equals("/missing", OwnedValue::Null).matches(&record));
+        assert!(Predicate::range("/n", Some(5.0), None).matches(&record));
+        assert!(Predicate::range("/n", None, Some(5.0)).matches(&record));
+        assert!(!Predicate::range("/n", Some(6.0), None).matches(&record));
+        assert!(!Predicate::range("/s", None, None).matches(&record));
+    }
+
+    #[test]
+    fn test_predicate_range_large_integer() {
+        // 2^53 + 1 rounds to 2^53 as a float
+        let mut map = indexmap::IndexMap::new();
+        map.insert("n".into(), OwnedValue::Integer(9007199254740993));
+        let record = OwnedValue::Object(map);
+        assert!(!Predicate::range("/n", None, Some(9007199254740992.0)).matches(&record));
+        assert!(Predicate::range("/n", Some(9007199254740992.0), None).matches(&record));
+        assert!(Predicate::range("/n", None, Some(9007199254740994.0)).matches(&record));
+        assert!(!Predicate::range("/n", Some(f64::NAN), None).matches(&record));
+    }
+
+    #[test]
+    fn test_parse_with_filter() {
+        let options = ParseOptions::new().filter_records(
+            "/items",
+            Predicate::equals("/kind", OwnedValue::String("a".into())),
+        );
+        assert_eq!(
+            parse_to_json(
+                r#"{"items": [{"kind": "a", "n": 1}, {"kind": "b"}, 3, {"kind": "a", "n": 2}], "other": [{"kind": "b"}]}"#,
+                &options
+            ),
+            r#"{"items":[{"kind":"a","n":1},{"kind":"a","n":2}],"other":[{"kind":"b"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_parse_with_filter_wildcard_and_range() {
+        let options = ParseOptions::new()
+            .filter_records("/*", Predicate::range("/n", Some(1.0), Some(2.0)))
+            .filter_records("/*", Predicate::equals("/ok", OwnedValue::Boolean(true)));
+        assert_eq!(
+            parse_to_json(
+                r#"[[{"n": 1, "ok": true}, {"n": 3, "ok": true}], [{"n": 2, "ok": false}, {"n": 2, "ok": true}]]"#,
+                &options
+            ),
+            r#"[[{"n":1,"ok":true}],[{"n":2,"ok":true}]]"#
+        );
+    }
+
+    #[test]
+    fn test_parse_with_filter_root() {
+        let options = ParseOptions::new().filter_records("", Predicate::range("", None, Some(0.0)));
+        assert_eq!(parse_to_json("[-1, 1, 0, \"x\"]", &options), "[-1,0]");
+    }
+
+    #[test]
+    fn test_parse_with_nested_filters() {
+        let json = r#"[{"n": 1, "items": [{"k": 1}, {"k": 5}]}, {"n": 9, "items": [{"k": 2}]}]"#;
+        let inner =
+            ParseOptions::new().filter_records("/*/items", Predicate::range("/k", None, Some(2.0)));
+        let expected = r#"[{"n":1,"items":[{"k":1}]},{"n":9,"items":[{"k":2}]}]"#;
+        assert_eq!(parse_to_json(json, &inner), expected);
+        // the records that are kept are filtered inside as well
+        let both = inner.filter_records("", Predicate::range("/n", None, Some(5.0)));
+        assert_eq!(parse_to_json(json, &both), r#"[{"n":1,"items":[{"k":1}]}]"#);
+    }
+
+    #[test]
+    fn test_parse_with_filter_keeps_records_intact() {
+        let json = r#"[{"a": 1, "a": 2, "x": 1.50}, {"a": 3}]"#;
+        let options = ParseOptions::new()
+            .preserve_number_lexemes(true)
+            .filter_records("", Predicate::range("/a", None, Some(2.0)));
+        // of duplicate fields the last one is matched, and all are kept
+        assert_eq!(parse_to_json(json, &options), r#"[{"a":1,"a":2,"x":1.50}]"#);
+    }
+
+    #[test]
+    fn test_parse_deterministic() {
+        // enough items for the structure to record child skips
+        let items = (0..200)
+            .map(|i| format!(r#"["x", "y{i}", {i}.5, true]"#))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let json = format!(r#"{{"a": [{items}], "b": "x"}}"#);
+        let options = ParseOptions::new().deterministic(true);
+        let build = || {
+            Document::parse_with::<BitpackingUsageBuilder, _>(json.as_bytes(), &options).unwrap()
+        };
+        let (first, second) = (build(), build());
+        assert!(first.memory_report().child_skips > 0);
+        assert_eq!(first.debug_dump(None), second.debug_dump(None));
+        assert_eq!(
+            first.text_usage.compressed_blocks(),
+            second.text_usage.compressed_blocks()
+        );
+        assert_eq!(first.numbers, second.numbers);
+        let save = |doc: &Document<_>| {
+            let mut out = Vec::new();
+            doc.save(&mut out).unwrap();
+            out
+        };
+        assert_eq!(save(&first), save(&second));
+    }
+
+    #[test]
+    fn test_parse_spill_to_disk() {
+        // enough items to fill several usage blocks
+        let json = format!(
+            "[{}]",
+            (0..1000)
+                .map(|i| format!(r#"{{"n": {i}, "s": "text {i}"}}"#))
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        let options = ParseOptions::new().spill_to_disk(true);
+        assert_eq!(
+            parse_to_json(&json, &options),
+            parse_to_json(&json, &ParseOptions::new())
+        );
+    }
+
+    #[test]
+    fn test_parse_text_tuning() {
+        let json = r#"{"a": ["some text", "more text"], "b": "text"}"#;
+        let options = ParseOptions::new()
+            .text_block_size(Some(8))
+            .text_cache_blocks(Some(1))
+            .compression_level(Some(1));
+        let doc =
+            Document::parse_with::<BitpackingUsageBuilder, _>(json.as_bytes(), &options).unwrap();
+        assert_eq!(doc.text_usage.stats().total_blocks, 3);
+        assert_eq!(
+            parse_to_json(json, &options),
+            parse_to_json(json, &ParseOptions::new())
+        );
+        // deterministic builds ignore the tuning
+        let deterministic = |options: ParseOptions| {
+            let doc = Document::parse_with::<BitpackingUsageBuilder, _>(
+                json.as_bytes(),
+                &options.deterministic(true),
+            )
+            .unwrap();
+            doc.text_usage.compressed_blocks()
+        };
+        assert_eq!(
+            deterministic(options.text_cache_blocks(None)),
+            deterministic(ParseOptions::new())
+        );
+    }
+
+    #[test]
+    fn test_parse_text_dictionary() {
+        let json = r#"{"ids": ["a-1", "a-2", "a-1"], "empty": ""}"#;
+        let options = ParseOptions::new().text_dictionary(Some(2));
+        assert_eq!(
+            parse_to_json(json, &options),
+            parse_to_json(json, &ParseOptions::new())
+        );
+    }
+
+    #[test]
+    fn test_parse_background_compression() {
+        let json = format!(
+            "[{}]",
+            (0..2000)
+                .map(|i| format!(r#"{{"name": "item {i}", "price": {i}.5}}"#))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let blocks = |options: ParseOptions| {
+            let options = options.text_block_size(Some(256));
+            let doc = Document::parse_with::<BitpackingUsageBuilder, _>(json.as_bytes(), &options)
+                .unwrap();
+            doc.text_usage.compressed_blocks()
+        };
+        let background = blocks(ParseOptions::new().background_compression(true));
+        assert!(background.len() > 10);
+        assert_eq!(background, blocks(ParseOptions::new()));
+        let options = ParseOptions::new()
+            .background_compression(true)
+            .text_dictionary(Some(3));
+        assert_eq!(
+            parse_to_json(&json, &options),
+            parse_to_json(&json, &ParseOptions::new())
+        );
+    }
+
+    #[test]
+    fn test_parse_with_node_lookup() {
+        let mut lookup = NodeLookup::new();
+        let id = lookup.register_field("id");
+        let options = ParseOptions::new().node_lookup(Arc::new(lookup));
+        let parse = |json: &str| {
+            Document::parse_with::<BitpackingUsageBuilder, _>(json.as_bytes(), &options).unwrap()
+        };
+        let first = parse(r#"{"name": "a", "id": 1}"#);
+        let second = parse(r#"{"id": 2, "name": "b"}"#);
+        let field_id = |doc: &Document<_>, name: &str| {
+            let value = doc.get_path(doc.root(), &[name]).unwrap();
+            doc.node_info_id(doc.parent(value).unwrap())
+        };
+        assert_eq!(field_id(&first, "id"), id);
+        assert_eq!(field_id(&second, "id"), id);
+        // the lookup of a document carries on with the names it added
+        let name = first.node_lookup().by_field_name("name").unwrap();
+        let third = Document::parse_with::<BitpackingUsageBuilder, _>(
+            r#"{"other": [], "name": "c"}"#.as_bytes(),
+            &ParseOptions::new().node_lookup(first.node_lookup().clone()),
+        )
+        .unwrap();
+        assert_eq!(field_id(&third, "name"), name);
+        assert_eq!(third.node_lookup().by_field_name("id"), Some(id));
+        assert_eq!(options.node_lookup.unwrap().by_field_name("name"), None);
+    }
+}
@@ -0,0 +1,250 @@
+use std::{fmt, io, num::ParseFloatError};
+
+use struson::reader::{JsonReaderPosition, ReaderError, json_path::JsonPathPiece};
+
+use crate::pointer;
+
+/// What went wrong while parsing JSON.
+#[derive(Debug)]
+pub enum ParseErrorKind {
+    /// The JSON is malformed or could not be read.
+    Reader(ReaderError),
+    /// A number could not be parsed as a 64-bit float.
+    Number(ParseFloatError),
+    /// The spill file could not be created.
+    Io(io::Error),
+    /// The input exceeds a limit set in the
+    /// [`ParseOptions`](crate::ParseOptions).
+    LimitExceeded { limit: ParseLimit, max: usize },
+    /// The parse was cancelled with a
+    /// [`CancellationToken`](crate::CancellationToken).
+    Cancelled,
+}
+
+/// A limit on the input set in the [`ParseOptions`](crate::ParseOptions).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseLimit {
+    /// The nesting depth of arrays and objects.
+    Depth,
+    /// The number of nodes, counting values and object fields.
+    Nodes,
+    /// The length in bytes of a string or a field name.
+    StringLength,
+    /// The heap in bytes used by the document being built.
+    Memory,
+}
+
+/// An error while parsing JSON, with the position in the input where it
+/// happened, as far as it is known.
+#[derive(Debug)]
+pub struct JsonParseError(Box<ErrorDetails>);
+
+// boxed, so that results of parsing stay small
+#[derive(Debug)]
+struct ErrorDetails {
+    kind: ParseErrorKind,
+    byte_offset: Option<u64>,
+    line_column: Option<(u64, u64)>,
+    path: Option<String>,
+}
+
+impl JsonParseError {
+    fn new(kind: ParseErrorKind) -> Self {
+        Self(Box::new(ErrorDetails {
+            kind,
+            byte_offset: None,
+            line_column: None,
+            path: None,
+        }))
+    }
+
+    pub(crate) fn limit(limit: ParseLimit, max: usize) -> Self {
+        Self::new(ParseErrorKind::LimitExceeded { limit, max })
+    }
+
+    pub(crate) fn cancelled() -> Self {
+        Self::new(ParseErrorKind::Cancelled)
+    }
+
+    pub(crate) fn at(mut self, position: &JsonReaderPosition) -> Self {
+        self.0.byte_offset = position.data_pos;
+        self.0.line_column = position
+            .line_pos
+            .as_ref()
+            .map(|line_pos| (line_pos.line, line_pos.column));
+        self.0.path = position.path.as_deref().map(json_pointer);
+        self
+    }
+
+    pub fn kind(&self) -> &ParseErrorKind {
+        &self.0.kind
+    }
+
+    /// The offset in bytes from the start of the input.
+    pub fn byte_offset(&self) -> Option<u64> {
+        self.0.byte_offset
+    }
+
+    /// The line and the column, both starting at 0. The column counts
+    /// characters.
+    pub fn line_column(&self) -> Option<(u64, u64)> {
+        self.0.line_column
+    }
+
+    /// A JSON Pointer to the value being parsed. For an object member whose
+    /// name could not be read yet, this points to the previous member.
+    pub fn path(&self) -> Option<&str> {
+        self.0.path.as_deref()
+    }
+}
+
+/// The lines that were skipped by
+/// [`Document::parse_ndjson_lenient`](crate::Document::parse_ndjson_lenient).
+#[derive(Debug, Default)]
+pub struct NdjsonReport {
+    /// The number of lines read, including blank and skipped ones.
+    pub lines: u64,
+    /// The malformed lines, in the order they were read.
+    pub skipped: Vec<SkippedLine>,
+}
+
+impl NdjsonReport {
+    /// Whether every line was parsed.
+    pub fn is_complete(&self) -> bool {
+        self.skipped.is_empty()
+    }
+}
+
+/// A malformed line of NDJSON that was skipped.
+#[derive(Debug)]
+pub struct SkippedLine {
+    /// The line number, starting at 1.
+    pub line: u64,
+    /// Why the line was skipped; its position is within the line.
+    pub error: JsonParseError,
+}
+
+fn json_pointer(path: &[JsonPathPiece]) -> String {
+    path.iter()
+        .fold(String::new(), |pointer: String, piece| match piece {
+            JsonPathPiece::ArrayItem(index) => pointer::push(&pointer, &index.to_string()),
+            JsonPathPiece::ObjectMember(name) => pointer::push(&pointer, name),
+        })
+}
+
+fn reader_position(err: &ReaderError) -> Option<&JsonReaderPosition> {
+    match err {
+        ReaderError::SyntaxError(err) => Some(&err.location),
+        ReaderError::UnexpectedValueType { location, .. }
+        | ReaderError::UnexpectedStructure { location, .. }
+        | ReaderError::MaxNestingDepthExceeded { location, .. }
+        | ReaderError::UnsupportedNumberValue { location, .. }
+        | ReaderError::IoError { location, .. } => Some(location),
+        _ => None,
+    }
+}
+
+impl From<ReaderError> for JsonParseError {
+    fn from(err: ReaderError) -> Self {
+        let position = reader_position(&err).cloned();
+        let kind = match err {
+            // we set the nesting depth, so report it like our other limits
+            ReaderError::MaxNestingDepthExceeded {
+                max_nesting_depth, ..
+            } => ParseErrorKind::LimitExceeded {
+                limit: ParseLimit::Depth,
+                max: max_nesting_depth as usize,
+            },
+            err => ParseErrorKind::Reader(err),
+        };
+        let error = JsonParseError::new(kind);
+        match position {
+            Some(position) => error.at(&position),
+            None => error,
+        }
+    }
+}
+
+impl From<ParseFloatError> for JsonParseError {
+    fn from(err: ParseFloatError) -> Self {
+        JsonParseError::new(ParseErrorKind::Number(err))
+    }
+}
+
+impl From<io::Error> for JsonParseError {
+    fn from(err: io::Error) -> Self {
+        JsonParseError::new(ParseErrorKind::Io(err))
+    }
+}
+
+impl fmt::Display for JsonParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind() {
+            // the reader error already describes where it happened
+            ParseErrorKind::Reader(err) => return write!(f, "invalid JSON: {err}"),
+            ParseErrorKind::Number(err) => write!(f, "invalid number: {err}")?,
+            ParseErrorKind::Io(err) => write!(f, "I/O error: {err}")?,
+            ParseErrorKind::LimitExceeded { limit, max } => {
+                let limit = match limit {
+                    ParseLimit::Depth => "nesting depth",
+                    ParseLimit::Nodes => "number of nodes",
+                    ParseLimit::StringLength => "string length",
+                    ParseLimit::Memory => "memory use",
+                };
+                write!(f, "{limit} exceeds the limit of {max}")?
+            }
+            ParseErrorKind::Cancelled => write!(f, "parsing was cancelled")?,
+        }
+        if let Some((line, column)) = self.line_column() {
+            write!(f, " at line {line}, column {column}")?;
+        }
+        if let Some(byte_offset) = self.byte_offset() {
+            write!(f, " (byte {byte_offset})")?;
+        }
+        if let Some(path) = self.path() {
+            write!(f, " in value '{path}'")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for JsonParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self.kind() {
+            ParseErrorKind::Reader(err) => Some(err),
+            ParseErrorKind::Number(err) => Some(err),
+            ParseErrorKind::Io(err) => Some(err),
+            ParseErrorKind::LimitExceeded { .. } | ParseErrorKind::Cancelled => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::{BitpackingUsageBuilder, UsageBuilder};
+
+    use super::*;
+
+    #[test]
+    fn test_syntax_error_position() {
+        let json = "{\n  \"a\": [1, 2,\n    {\"b\": tru}]\n}";
+        let err = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ParseErrorKind::Reader(ReaderError::SyntaxError(_))
+        ));
+        assert_eq!(err.line_column(), Some((2, 10)));
+        assert_eq!(err.byte_offset(), Some(json.find("tru").unwrap() as u64));
+        assert_eq!(err.path(), Some("/a/2/b"));
+        assert!(err.to_string().contains("line 2, column 10"));
+    }
+
+    #[test]
+    fn test_json_pointer() {
+        let path = [
+            JsonPathPiece::ObjectMember("a/b".to_string()),
+            JsonPathPiece::ArrayItem(3),
+        ];
+        assert_eq!(json_pointer(&path), "/a~1b/3");
+    }
+}
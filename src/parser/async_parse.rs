@@ -0,0 +1,161 @@
+//! Parsing from an [`AsyncRead`] without blocking the runtime.
+//!
+//! The parser itself is synchronous, so it runs on the blocking thread pool
+//! of the runtime. The input is read asynchronously and handed to the
+//! parser in chunks through a bounded channel, so no more than a few chunks
+//! of input are held in memory at a time.
+
+use std::io::{self, Read};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    sync::mpsc,
+};
+
+use super::parse_with;
+use crate::{
+    document::Document, options::ParseOptions, parse_error::JsonParseError, usage::UsageBuilder,
+};
+
+const CHUNK_SIZE: usize = 64 * 1024; // 64 KiB
+// the number of chunks read ahead of the parser
+const CHANNEL_CHUNKS: usize = 4;
+
+pub(crate) async fn parse_async_with<R, B>(
+    mut json: R,
+    options: &ParseOptions,
+) -> Result<Document<B::Index>, JsonParseError>
+where
+    R: AsyncRead + Unpin,
+    B: UsageBuilder + Send + 'static,
+    B::Index: Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel(CHANNEL_CHUNKS);
+    let options = options.clone();
+    let parse = tokio::task::spawn_blocking(move || {
+        parse_with::<_, B>(ChannelReader::new(receiver), &options)
+    });
+    loop {
+        let mut chunk = vec![0; CHUNK_SIZE];
+        let read = tokio::select! {
+            read = json.read(&mut chunk) => read,
+            // the parser is done before the end of the input, either
+            // because of an error or because the value is complete
+            _ = sender.closed() => break,
+        };
+        let chunk = match read {
+            Ok(0) => break,
+            Ok(len) => {
+                chunk.truncate(len);
+                Ok(chunk)
+            }
+            Err(err) => Err(err),
+        };
+        let failed = chunk.is_err();
+        if sender.send(chunk).await.is_err() || failed {
+            break;
+        }
+    }
+    // the parser sees the end of the input
+    drop(sender);
+    match parse.await {
+        Ok(result) => result,
+        Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+        Err(err) => Err(io::Error::other(err).into()),
+    }
+}
+
+/// A reader over the chunks sent through a channel, for use on a blocking
+/// thread.
+struct ChannelReader {
+    receiver: mpsc::Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl ChannelReader {
+    fn new(receiver: mpsc::Receiver<io::Result<Vec<u8>>>) -> Self {
+        Self {
+            receiver,
+            chunk: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.chunk.len() {
+            match self.receiver.blocking_recv() {
+                Some(chunk) => {
+                    self.chunk = chunk?;
+                    self.position = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len() - self.position);
+        buf[..len].copy_from_slice(&self.chunk[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OwnedValue, ParseErrorKind, usage::BitpackingUsageBuilder};
+
+    #[tokio::test]
+    async fn test_parse_async() {
+        let json = format!(
+            "[{}]",
+            vec![r#"{"a": "text", "b": [1, true]}"#; 30_000].join(",")
+        );
+        // more than a few chunks
+        assert!(json.len() > CHUNK_SIZE * CHANNEL_CHUNKS * 2);
+        let doc =
+            parse_async_with::<_, BitpackingUsageBuilder>(json.as_bytes(), &ParseOptions::new())
+                .await
+                .unwrap();
+        let value = doc.root_value().to_owned_value();
+        assert_eq!(
+            value.pointer("/29999/a"),
+            Some(&OwnedValue::String("text".into()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parse_async_errors() {
+        let json = format!("[{}, tru]", vec!["1"; 100_000].join(","));
+        let err =
+            parse_async_with::<_, BitpackingUsageBuilder>(json.as_bytes(), &ParseOptions::new())
+                .await
+                .unwrap_err();
+        assert_eq!(err.byte_offset(), Some(json.find("tru").unwrap() as u64));
+
+        // errors reading the input end up in the parse error
+        let failing = fails_after(b"[1, 2");
+        let err = parse_async_with::<_, BitpackingUsageBuilder>(failing, &ParseOptions::new())
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind(), ParseErrorKind::Reader(_)));
+    }
+
+    // a reader that fails after the data
+    fn fails_after(data: &'static [u8]) -> impl AsyncRead + Unpin {
+        AsyncReadExt::chain(data, FailingReader)
+    }
+
+    struct FailingReader;
+
+    impl AsyncRead for FailingReader {
+        fn poll_read(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            _buf: &mut tokio::io::ReadBuf<'_>,
+        ) -> std::task::Poll<io::Result<()>> {
+            std::task::Poll::Ready(Err(io::Error::other("connection reset")))
+        }
+    }
+}
@@ -0,0 +1,1079 @@
+use std::{
+    io::{self, BufRead, BufReader, Read},
+    num::ParseFloatError,
+};
+
+use struson::reader::{JsonReader, JsonStreamReader, ReaderSettings, ValueType};
+
+use crate::{
+    builder::Builder,
+    document::Document,
+    info::NodeType,
+    lookup::NodeLookup,
+    numbers::Number,
+    options::{ParseOptions, PathSegment, Predicate},
+    parse_error::{JsonParseError, NdjsonReport, ParseLimit, SkippedLine},
+    progress::Progress,
+    usage::UsageBuilder,
+};
+
+pub(crate) struct Parser<'o, R: Read, B: UsageBuilder> {
+    reader: JsonStreamReader<R>,
+    builder: Builder<B>,
+    options: &'o ParseOptions,
+    // the path to the current value, only tracked when filtering records
+    path: Vec<PathSegment>,
+    // struson can't check for a first top-level value, so for records we
+    // check whether there is one before reading
+    first_record: Option<bool>,
+    // the number of nodes read, to check the limit
+    nodes: usize,
+    // where the input starts, when it is a part of the whole input
+    byte_offset: u64,
+}
+
+#[cfg(feature = "tokio")]
+mod async_parse;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod record;
+
+#[cfg(feature = "tokio")]
+pub(crate) use async_parse::parse_async_with;
+#[cfg(feature = "parallel")]
+pub(crate) use parallel::parse_parallel_with;
+use record::{Buffered, BufferedField};
+
+// the number of nodes between checks of the cancellation token
+const CANCELLATION_INTERVAL: usize = 1024;
+// the number of nodes between checks of the memory budget
+const BUDGET_INTERVAL: usize = 1024;
+
+pub(crate) fn parse<R: Read, B: UsageBuilder>(
+    json: R,
+) -> Result<Document<B::Index>, JsonParseError> {
+    parse_with::<R, B>(json, &ParseOptions::default())
+}
+
+pub(crate) fn parse_with<R: Read, B: UsageBuilder>(
+    json: R,
+    options: &ParseOptions,
+) -> Result<Document<B::Index>, JsonParseError> {
+    let parser = Parser::<R, B>::new(json, options, false)?;
+    parser.parse()
+}
+
+pub(crate) fn parse_ndjson<R: Read, B: UsageBuilder>(
+    json: R,
+) -> Result<Document<B::Index>, JsonParseError> {
+    parse_ndjson_with::<R, B>(json, &ParseOptions::default())
+}
+
+/// Parse newline-delimited JSON into a document with an array of the
+/// records as its root. Record filters with the root path `""` apply to the
+/// records.
+pub(crate) fn parse_ndjson_with<R: Read, B: UsageBuilder>(
+    json: R,
+    options: &ParseOptions,
+) -> Result<Document<B::Index>, JsonParseError> {
+    let mut json = BufReader::new(json);
+    let skipped = skip_whitespace(&mut json)?;
+    let mut parser = Parser::<_, B>::new(json, options, true)?;
+    parser.first_record = Some(skipped.is_some());
+    parser.byte_offset = skipped.unwrap_or(0);
+    parser.parse_records()
+}
+
+/// Parse newline-delimited JSON like [`parse_ndjson_with`], skipping the
+/// lines that are not valid JSON and reporting them. Every line is checked
+/// before its records are added, so a malformed line leaves nothing
+/// behind; exceeding a limit, cancelling and I/O errors still fail the
+/// whole parse.
+pub(crate) fn parse_ndjson_lenient<R: Read, B: UsageBuilder>(
+    json: R,
+    options: &ParseOptions,
+) -> Result<(Document<B::Index>, NdjsonReport), JsonParseError> {
+    #[cfg(feature = "tracing")]
+    let span = tracing::info_span!("parse", records = true, lenient = true).entered();
+    let mut json = BufReader::new(json);
+    let mut builder = builder_for::<B>(options)?;
+    builder.open(NodeType::Array);
+    builder.source_offset(0);
+    let mut report = NdjsonReport::default();
+    let mut nodes = 0;
+    let mut byte_offset = 0;
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let len = json.read_until(b'\n', &mut line)?;
+        if len == 0 {
+            break;
+        }
+        report.lines += 1;
+        let line_start = byte_offset;
+        byte_offset += len as u64;
+        let mut record = line.as_slice();
+        let Some(skipped) = skip_whitespace(&mut record)? else {
+            continue;
+        };
+        if let Err(error) = check_line(record, options) {
+            report.skipped.push(SkippedLine {
+                line: report.lines,
+                error,
+            });
+            continue;
+        }
+        let mut parser = Parser::with_reader(json_reader(record, options, true), options, builder);
+        parser.first_record = Some(true);
+        parser.nodes = nodes;
+        parser.byte_offset = line_start + skipped;
+        if parser.is_filtering() {
+            parser.parse_filtered_items(0)?;
+        } else {
+            while parser.has_next()? {
+                parser.parse_item()?;
+            }
+        }
+        nodes = parser.nodes;
+        builder = parser.builder;
+    }
+    builder.close(NodeType::Array);
+    builder.source_offset(byte_offset);
+    #[cfg(feature = "tracing")]
+    span.exit();
+    Ok((builder.build(), report))
+}
+
+// whether a line that is not blank holds only valid JSON values
+fn check_line(record: &[u8], options: &ParseOptions) -> Result<(), JsonParseError> {
+    let mut reader = json_reader(record, options, true);
+    loop {
+        reader.skip_value()?;
+        if !reader.has_next()? {
+            return Ok(());
+        }
+    }
+}
+
+// skip whitespace at the start, returning how many bytes were skipped if
+// anything follows it
+fn skip_whitespace<R: BufRead>(json: &mut R) -> io::Result<Option<u64>> {
+    let mut skipped = 0;
+    loop {
+        let buf = json.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(None);
+        }
+        let whitespace = buf
+            .iter()
+            .take_while(|b| matches!(b, b' ' | b'\t' | b'\n' | b'\r'))
+            .count();
+        let found = whitespace < buf.len();
+        json.consume(whitespace);
+        skipped += whitespace as u64;
+        if found {
+            return Ok(Some(skipped));
+        }
+    }
+}
+
+fn builder_for<B: UsageBuilder>(options: &ParseOptions) -> io::Result<Builder<B>> {
+    let mut builder = Builder::new();
+    if options.number_lexemes {
+        builder = builder.with_number_lexemes();
+    }
+    if let Some(block_size) = options.text_block_size {
+        builder = builder.with_text_block_size(block_size);
+    }
+    if let Some(blocks) = options.text_cache_blocks {
+        builder = builder.with_text_cache_capacity(blocks);
+    }
+    if let Some(level) = options.compression_level {
+        builder = builder.with_compression_level(level);
+    }
+    if options.deterministic {
+        builder = builder.deterministic();
+    }
+    if options.string_hashes {
+        builder = builder.with_string_hashes();
+    }
+    if let Some(training_blocks) = options.text_dictionary {
+        builder = builder.with_text_dictionary(training_blocks);
+    }
+    if options.background_compression {
+        builder = builder.with_background_compression();
+    }
+    if options.source_spans {
+        builder = builder.with_source_spans();
+    }
+    if let Some(node_lookup) = &options.node_lookup {
+        builder = builder.with_node_lookup(NodeLookup::clone(node_lookup));
+    }
+    if options.spill_to_disk {
+        builder = builder.spill_to_disk()?;
+    }
+    Ok(builder)
+}
+
+fn json_reader<R: Read>(
+    json: R,
+    options: &ParseOptions,
+    multiple_values: bool,
+) -> JsonStreamReader<R> {
+    JsonStreamReader::new_custom(
+        json,
+        ReaderSettings {
+            // numbers are parsed as i64 or f64, so long numbers are no risk;
+            // the serializer writes them for very large and very small values
+            restrict_number_values: false,
+            allow_multiple_top_level: multiple_values,
+            allow_comments: options.jsonc,
+            allow_trailing_comma: options.jsonc,
+            max_nesting_depth: options.max_depth,
+            ..Default::default()
+        },
+    )
+}
+
+enum StreamState<R: Read> {
+    Start(R),
+    // the reader is big, so it is boxed
+    Reading(Box<JsonStreamReader<BufReader<R>>>),
+    Done,
+}
+
+/// An iterator over the documents in a stream of concatenated JSON values,
+/// such as `{"a": 1} {"a": 2}`. Created by
+/// [`Document::parse_stream_with`].
+///
+/// Each value is parsed into its own document when the iterator advances,
+/// so the stream can be processed without holding all of it in memory.
+/// The iterator ends after the first error.
+pub struct Documents<'o, R: Read, B: UsageBuilder> {
+    state: StreamState<R>,
+    options: &'o ParseOptions,
+    // the whitespace skipped before the reader started
+    byte_offset: u64,
+    _builder: std::marker::PhantomData<B>,
+}
+
+impl<'o, R: Read, B: UsageBuilder> Documents<'o, R, B> {
+    pub(crate) fn new(json: R, options: &'o ParseOptions) -> Self {
+        Self {
+            state: StreamState::Start(json),
+            options,
+            byte_offset: 0,
+            _builder: std::marker::PhantomData,
+        }
+    }
+
+    fn next_document(&mut self) -> Result<Option<Document<B::Index>>, JsonParseError> {
+        let reader = match std::mem::replace(&mut self.state, StreamState::Done) {
+            StreamState::Start(json) => {
+                let mut json = BufReader::new(json);
+                let Some(skipped) = skip_whitespace(&mut json)? else {
+                    return Ok(None);
+                };
+                self.byte_offset = skipped;
+                json_reader(json, self.options, true)
+            }
+            StreamState::Reading(mut reader) => {
+                if !reader.has_next()? {
+                    return Ok(None);
+                }
+                *reader
+            }
+            StreamState::Done => return Ok(None),
+        };
+        let builder = builder_for::<B>(self.options)?;
+        let mut parser = Parser::with_reader(reader, self.options, builder);
+        parser.byte_offset = self.byte_offset;
+        parser.parse_item()?;
+        self.state = StreamState::Reading(Box::new(parser.reader));
+        Ok(Some(parser.builder.build()))
+    }
+}
+
+impl<R: Read, B: UsageBuilder> Iterator for Documents<'_, R, B> {
+    type Item = Result<Document<B::Index>, JsonParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // the state is left at done on errors
+        self.next_document().transpose()
+    }
+}
+
+impl<R: Read, B: UsageBuilder> std::iter::FusedIterator for Documents<'_, R, B> {}
+
+impl<'o, R: Read, B: UsageBuilder> Parser<'o, R, B> {
+    fn new(json: R, options: &'o ParseOptions, multiple_values: bool) -> io::Result<Self> {
+        Ok(Self::with_reader(
+            json_reader(json, options, multiple_values),
+            options,
+            builder_for(options)?,
+        ))
+    }
+
+    fn with_reader(
+        reader: JsonStreamReader<R>,
+        options: &'o ParseOptions,
+        builder: Builder<B>,
+    ) -> Self {
+        Self {
+            reader,
+            builder,
+            options,
+            path: Vec::new(),
+            first_record: None,
+            nodes: 0,
+            byte_offset: 0,
+        }
+    }
+
+    fn is_filtering(&self) -> bool {
+        !self.options.record_filters.is_empty()
+    }
+
+    fn number_error(&self, err: ParseFloatError) -> JsonParseError {
+        JsonParseError::from(err).at(&self.reader.current_position(true))
+    }
+
+    fn limit_error(&self, limit: ParseLimit, max: usize) -> JsonParseError {
+        JsonParseError::limit(limit, max).at(&self.reader.current_position(true))
+    }
+
+    fn count_node(&mut self) -> Result<(), JsonParseError> {
+        self.nodes += 1;
+        if let Some(max) = self.options.max_nodes
+            && self.nodes > max
+        {
+            return Err(self.limit_error(ParseLimit::Nodes, max));
+        }
+        if let Some(token) = &self.options.cancellation
+            && self.nodes.is_multiple_of(CANCELLATION_INTERVAL)
+            && token.is_cancelled()
+        {
+            return Err(JsonParseError::cancelled().at(&self.reader.current_position(true)));
+        }
+        if let Some(max) = self.options.memory_budget
+            && self.nodes.is_multiple_of(BUDGET_INTERVAL)
+        {
+            self.check_budget(max)?;
+        }
+        if let Some(callback) = &self.options.progress
+            && self.nodes.is_multiple_of(self.options.progress_interval)
+        {
+            callback.call(&self.progress());
+        }
+        Ok(())
+    }
+
+    fn check_budget(&mut self, max: usize) -> Result<(), JsonParseError> {
+        if self.builder.memory_report().total() <= max {
+            return Ok(());
+        }
+        // flushing changes the block boundaries
+        if !self.options.deterministic {
+            self.builder.flush_text();
+            if self.builder.memory_report().total() <= max {
+                return Ok(());
+            }
+        }
+        Err(self.limit_error(ParseLimit::Memory, max))
+    }
+
+    fn progress(&self) -> Progress {
+        self.builder.progress(self.nodes, self.offset())
+    }
+
+    // where the reader is in the whole input
+    fn offset(&self) -> u64 {
+        self.byte_offset + self.reader.current_position(false).data_pos.unwrap_or(0)
+    }
+
+    // the node added last was a scalar that started at `start`
+    fn scalar_span(&mut self, start: u64) {
+        self.builder.source_offset(start);
+        self.builder.source_offset(self.offset());
+    }
+
+    fn has_next(&mut self) -> Result<bool, JsonParseError> {
+        if let Some(has_record) = self.first_record.take() {
+            return Ok(has_record);
+        }
+        Ok(self.reader.has_next()?)
+    }
+
+    fn parse(mut self) -> Result<Document<B::Index>, JsonParseError> {
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!("parse").entered();
+        self.parse_item()?;
+        #[cfg(feature = "tracing")]
+        span.exit();
+        Ok(self.builder.build())
+    }
+
+    // the top-level values become the items of the root array
+    fn parse_records(mut self) -> Result<Document<B::Index>, JsonParseError> {
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!("parse", records = true).entered();
+        // the array of the records spans the whole input
+        self.builder.open(NodeType::Array);
+        self.builder.source_offset(0);
+        if self.is_filtering() {
+            self.parse_filtered_items(0)?;
+        } else {
+            while self.has_next()? {
+                self.parse_item()?;
+            }
+        }
+        self.builder.close(NodeType::Array);
+        self.builder.source_offset(self.offset());
+        #[cfg(feature = "tracing")]
+        span.exit();
+        Ok(self.builder.build())
+    }
+
+    fn parse_item(&mut self) -> Result<(), JsonParseError> {
+        self.count_node()?;
+        let value_type = self.reader.peek()?;
+        let start = self.offset();
+        match value_type {
+            ValueType::Array => {
+                self.reader.begin_array()?;
+                self.builder.open(NodeType::Array);
+                self.builder.source_offset(start);
+                if self.is_filtering() {
+                    self.parse_filtered_items(0)?;
+                } else {
+                    while self.reader.has_next()? {
+                        self.parse_item()?;
+                    }
+                }
+                self.reader.end_array()?;
+                self.builder.close(NodeType::Array);
+                self.builder.source_offset(self.offset());
+            }
+            ValueType::Object => {
+                self.reader.begin_object()?;
+                self.builder.open(NodeType::Object);
+                self.builder.source_offset(start);
+                while self.reader.has_next()? {
+                    self.count_node()?;
+                    let name_start = self.offset();
+                    let key = self.reader.next_name()?;
+                    if let Some(max) = self.options.string_too_long(key.len()) {
+                        return Err(self.limit_error(ParseLimit::StringLength, max));
+                    }
+                    let close_field_id = self.builder.open_field(key);
+                    self.builder.source_offset(name_start);
+                    if !self.options.record_filters.is_empty() {
+                        self.path.push(PathSegment::Key(key.to_string()));
+                        self.parse_item()?;
+                        self.path.pop();
+                    } else {
+                        self.parse_item()?;
+                    }
+                    self.builder.close_field(close_field_id);
+                    self.builder.source_offset(self.offset());
+                }
+                self.reader.end_object()?;
+                self.builder.close(NodeType::Object);
+                self.builder.source_offset(self.offset());
+            }
+            ValueType::String => {
+                let str = self.reader.next_str()?;
+                if let Some(max) = self.options.string_too_long(str.len()) {
+                    return Err(self.limit_error(ParseLimit::StringLength, max));
+                }
+                self.builder.string(str);
+                self.scalar_span(start);
+            }
+            ValueType::Number => {
+                let lexeme = self.reader.next_number_as_str()?;
+                match Number::parse(lexeme) {
+                    Ok(number) => self.builder.push_number(number, Some(lexeme)),
+                    Err(err) => return Err(self.number_error(err)),
+                }
+                self.scalar_span(start);
+            }
+            ValueType::Boolean => {
+                let boolean = self.reader.next_bool()?;
+                self.builder.boolean(boolean);
+                self.scalar_span(start);
+            }
+            ValueType::Null => {
+                self.reader.next_null()?;
+                self.builder.null();
+                self.scalar_span(start);
+            }
+        }
+        Ok(())
+    }
+
+    // the predicates of the filters on the items of the array at the
+    // current path
+    fn item_predicates(&self) -> Vec<&'o Predicate> {
+        let options = self.options;
+        options
+            .record_filters
+            .iter()
+            .filter(|filter| filter.applies_to(&self.path))
+            .map(|filter| &filter.predicate)
+            .collect()
+    }
+
+    // `first_index` is the index in the input of the first item
+    fn parse_filtered_items(&mut self, first_index: usize) -> Result<(), JsonParseError> {
+        let predicates = self.item_predicates();
+        let mut index = first_index;
+        while self.has_next()? {
+            if predicates.is_empty() {
+                self.path.push(PathSegment::Index(index));
+                self.parse_item()?;
+                self.path.pop();
+            } else {
+                // records are small, so we can afford to read one ahead to
+                // decide whether to keep it
+                let record = self.read_buffered()?;
+                let value = record.to_owned_value();
+                if predicates.iter().all(|predicate| predicate.matches(&value)) {
+                    self.path.push(PathSegment::Index(index));
+                    self.add_buffered(&record);
+                    self.path.pop();
+                }
+            }
+            index += 1;
+        }
+        Ok(())
+    }
+
+    fn read_buffered(&mut self) -> Result<Buffered, JsonParseError> {
+        self.count_node()?;
+        let value_type = self.reader.peek()?;
+        let start = self.offset();
+        Ok(match value_type {
+            ValueType::Array => {
+                self.reader.begin_array()?;
+                let mut items = Vec::new();
+                while self.reader.has_next()? {
+                    items.push(self.read_buffered()?);
+                }
+                self.reader.end_array()?;
+                Buffered::Array {
+                    items,
+                    span: start..self.offset(),
+                }
+            }
+            ValueType::Object => {
+                self.reader.begin_object()?;
+                let mut fields = Vec::new();
+                while self.reader.has_next()? {
+                    self.count_node()?;
+                    let name_start = self.offset();
+                    let name = self.reader.next_name()?;
+                    if let Some(max) = self.options.string_too_long(name.len()) {
+                        return Err(self.limit_error(ParseLimit::StringLength, max));
+                    }
+                    let name = name.to_string();
+                    fields.push(BufferedField {
+                        name,
+                        start: name_start,
+                        value: self.read_buffered()?,
+                    });
+                }
+                self.reader.end_object()?;
+                Buffered::Object {
+                    fields,
+                    span: start..self.offset(),
+                }
+            }
+            ValueType::String => {
+                let str = self.reader.next_str()?;
+                if let Some(max) = self.options.string_too_long(str.len()) {
+                    return Err(self.limit_error(ParseLimit::StringLength, max));
+                }
+                let str = str.to_string();
+                Buffered::String(str, start..self.offset())
+            }
+            ValueType::Number => {
+                let lexeme = self.reader.next_number_as_str()?.to_string();
+                let number = Number::parse(&lexeme).map_err(|err| self.number_error(err))?;
+                Buffered::Number {
+                    number,
+                    lexeme,
+                    span: start..self.offset(),
+                }
+            }
+            ValueType::Boolean => Buffered::Boolean(self.reader.next_bool()?, start..self.offset()),
+            ValueType::Null => {
+                self.reader.next_null()?;
+                Buffered::Null(start..self.offset())
+            }
+        })
+    }
+
+    // add a value that was read ahead as parsing it would have, filtering
+    // the arrays in it
+    fn add_buffered(&mut self, value: &Buffered) {
+        let span = value.span();
+        match value {
+            Buffered::Array { items, .. } => {
+                self.builder.open(NodeType::Array);
+                self.builder.source_offset(span.start);
+                let predicates = self.item_predicates();
+                for (index, item) in items.iter().enumerate() {
+                    if !predicates.is_empty() {
+                        let item_value = item.to_owned_value();
+                        if !predicates
+                            .iter()
+                            .all(|predicate| predicate.matches(&item_value))
+                        {
+                            continue;
+                        }
+                    }
+                    self.path.push(PathSegment::Index(index));
+                    self.add_buffered(item);
+                    self.path.pop();
+                }
+                self.builder.close(NodeType::Array);
+                self.builder.source_offset(span.end);
+            }
+            Buffered::Object { fields, .. } => {
+                self.builder.open(NodeType::Object);
+                self.builder.source_offset(span.start);
+                for field in fields {
+                    let close_field_id = self.builder.open_field(&field.name);
+                    self.builder.source_offset(field.start);
+                    self.path.push(PathSegment::Key(field.name.clone()));
+                    self.add_buffered(&field.value);
+                    self.path.pop();
+                    self.builder.close_field(close_field_id);
+                    self.builder.source_offset(field.value.span().end);
+                }
+                self.builder.close(NodeType::Object);
+                self.builder.source_offset(span.end);
+            }
+            Buffered::String(s, _) => self.builder.string(s),
+            Buffered::Number { number, lexeme, .. } => {
+                self.builder.push_number(*number, Some(lexeme));
+            }
+            Buffered::Boolean(b, _) => self.builder.boolean(*b),
+            Buffered::Null(_) => self.builder.null(),
+        }
+        if value.is_scalar() {
+            self.builder.source_offset(span.start);
+            self.builder.source_offset(span.end);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{CancellationToken, OwnedValue, usage::BitpackingUsageBuilder};
+
+    #[test]
+    fn test_parse_long_numbers() {
+        let doc = BitpackingUsageBuilder::parse(format!("[1e300, {}]", 5e-300).as_bytes()).unwrap();
+        let mut output = Vec::new();
+        doc.serialize(&mut output).unwrap();
+        let reparsed = BitpackingUsageBuilder::parse(output.as_slice()).unwrap();
+        assert_eq!(
+            reparsed.root_value().to_owned_value(),
+            doc.root_value().to_owned_value()
+        );
+    }
+
+    #[test]
+    fn test_parse_integers() {
+        let json = "[9007199254740993,-9223372036854775808,1.0,-0,1e2,18446744073709551615]";
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+        let crate::Value::Array(array) = doc.root_value() else {
+            panic!("root should be an array");
+        };
+        // beyond 2^53, so a float would round it
+        assert!(matches!(
+            array.get(0),
+            Some(crate::Value::Integer(9007199254740993))
+        ));
+        assert!(matches!(array.get(2), Some(crate::Value::Number(_))));
+        let mut output = Vec::new();
+        doc.serialize(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("[9007199254740993,-9223372036854775808,"));
+        assert_eq!(
+            BitpackingUsageBuilder::parse(output.as_bytes())
+                .unwrap()
+                .root_value()
+                .to_owned_value(),
+            doc.root_value().to_owned_value()
+        );
+    }
+
+    #[test]
+    fn test_preserve_number_lexemes() {
+        let json = r#"{"a":0.1000000000000000055,"b":[1.50,-0,1E3,42]}"#;
+        let options = ParseOptions::new().preserve_number_lexemes(true);
+        let doc = parse_with::<_, BitpackingUsageBuilder>(json.as_bytes(), &options).unwrap();
+        assert_eq!(doc.verify(), Ok(()));
+        let mut output = Vec::new();
+        doc.serialize(&mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), json);
+
+        // the value is still the nearest float
+        let a = doc
+            .first_child(doc.first_child(doc.root()).unwrap())
+            .unwrap();
+        assert_eq!(doc.value(a), crate::Value::Number(0.1));
+        assert_eq!(
+            doc.number_lexeme(a).as_deref(),
+            Some("0.1000000000000000055")
+        );
+
+        // without the option, the float is written
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+        let mut output = Vec::new();
+        doc.serialize(&mut output).unwrap();
+        assert!(
+            String::from_utf8(output)
+                .unwrap()
+                .starts_with(r#"{"a":0.1,"#)
+        );
+    }
+
+    #[test]
+    fn test_parse_ndjson() {
+        let json = "{\"a\": 1}\n[true, null]\n\n\"text\"\n";
+        let doc = BitpackingUsageBuilder::parse_ndjson(json.as_bytes()).unwrap();
+        let mut output = Vec::new();
+        doc.serialize(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"[{"a":1},[true,null],"text"]"#
+        );
+        assert_eq!(doc.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_parse_ndjson_empty() {
+        let doc = BitpackingUsageBuilder::parse_ndjson("\n".as_bytes()).unwrap();
+        assert_eq!(doc.root_value().to_owned_value(), OwnedValue::Array(vec![]));
+    }
+
+    #[test]
+    fn test_parse_ndjson_filtered() {
+        let json = "{\"n\": 1}\n{\"n\": 2}\n{\"n\": 3}\n";
+        let options =
+            ParseOptions::new().filter_records("", crate::Predicate::range("/n", Some(2.0), None));
+        let doc =
+            parse_ndjson_with::<_, BitpackingUsageBuilder>(json.as_bytes(), &options).unwrap();
+        let mut output = Vec::new();
+        doc.serialize(&mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), r#"[{"n":2},{"n":3}]"#);
+    }
+
+    #[test]
+    fn test_parse_ndjson_syntax_error() {
+        assert!(BitpackingUsageBuilder::parse_ndjson("{\"a\": 1}\n{".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_parse_ndjson_lenient() {
+        let json = "{\"a\": 1}\n{\"a\": \n\n[2, 3] 4\r\n{\"a\": 5} x\n\"end\"";
+        let (doc, report) = parse_ndjson_lenient::<_, BitpackingUsageBuilder>(
+            json.as_bytes(),
+            &ParseOptions::default(),
+        )
+        .unwrap();
+        let mut output = Vec::new();
+        doc.serialize(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"[{"a":1},[2,3],4,"end"]"#
+        );
+        assert_eq!(report.lines, 6);
+        let skipped = report
+            .skipped
+            .iter()
+            .map(|skipped| (skipped.line, skipped.error.byte_offset()))
+            .collect::<Vec<_>>();
+        assert_eq!(skipped, [(2, Some(7)), (5, Some(9))]);
+        assert!(!report.is_complete());
+
+        // filters and limits still apply
+        let options = ParseOptions::new()
+            .filter_records("", crate::Predicate::range("/a", Some(2.0), None))
+            .max_nodes(Some(20));
+        let (doc, report) = parse_ndjson_lenient::<_, BitpackingUsageBuilder>(
+            "{\"a\": 1}\n{\"a\"\n{\"a\": 3}\n".as_bytes(),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(doc.child_count(doc.root()), 1);
+        assert_eq!(report.skipped.len(), 1);
+        let err = parse_ndjson_lenient::<_, BitpackingUsageBuilder>(
+            "[1, 2]\n{\n[3, 4]".as_bytes(),
+            &ParseOptions::new().max_nodes(Some(4)),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            crate::ParseErrorKind::LimitExceeded { .. }
+        ));
+    }
+
+    // the source text of every node in document order
+    fn source_texts<U: crate::usage::UsageIndex>(
+        doc: &Document<U>,
+        json: &str,
+    ) -> Vec<Option<String>> {
+        (0..doc.structure.len())
+            .filter(|&position| doc.structure.is_open(position))
+            .map(|position| {
+                let node = crate::Node::new(position);
+                doc.source_span(node)
+                    .map(|span| json[span.start as usize..span.end as usize].to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_source_spans() {
+        let json = r#" {"a": [1, "x\"y"], "b" :{"c": null}} "#;
+        let options = ParseOptions::new().source_spans(true);
+        let doc = parse_with::<_, BitpackingUsageBuilder>(json.as_bytes(), &options).unwrap();
+        assert_eq!(doc.verify(), Ok(()));
+        let spans = source_texts(&doc, json);
+        let expected = [
+            r#"{"a": [1, "x\"y"], "b" :{"c": null}}"#,
+            r#""a": [1, "x\"y"]"#,
+            r#"[1, "x\"y"]"#,
+            "1",
+            r#""x\"y""#,
+            r#""b" :{"c": null}"#,
+            r#"{"c": null}"#,
+            r#""c": null"#,
+            "null",
+        ];
+        assert_eq!(spans, expected.map(|span| Some(span.to_string())));
+
+        // not recorded by default
+        let doc = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+        assert_eq!(doc.source_span(doc.root()), None);
+    }
+
+    #[test]
+    fn test_source_spans_records() {
+        let json = "\n{\"n\": 1}\n  [true]\n{\"n\": 3}\n";
+        let options = ParseOptions::new().source_spans(true);
+        let doc =
+            parse_ndjson_with::<_, BitpackingUsageBuilder>(json.as_bytes(), &options).unwrap();
+        assert_eq!(doc.verify(), Ok(()));
+        let spans = source_texts(&doc, json);
+        assert_eq!(spans[0].as_deref(), Some(json));
+        assert_eq!(spans[1].as_deref(), Some("{\"n\": 1}"));
+        assert_eq!(spans[4].as_deref(), Some("[true]"));
+        assert_eq!(spans[5].as_deref(), Some("true"));
+
+        let json = "{\"n\": 1}\n{\"n\" \n  {\"n\": 3}\n";
+        let (doc, _) =
+            parse_ndjson_lenient::<_, BitpackingUsageBuilder>(json.as_bytes(), &options).unwrap();
+        let spans = source_texts(&doc, json);
+        assert_eq!(spans[0].as_deref(), Some(json));
+        assert_eq!(spans[4].as_deref(), Some("{\"n\": 3}"));
+        assert_eq!(spans[6].as_deref(), Some("3"));
+
+        // kept records have the spans of all their parts
+        let options = options.filter_records("", crate::Predicate::range("/n", Some(2.0), None));
+        let json = "{\"n\": 1}\n{\"n\": 3}\n";
+        let doc =
+            parse_ndjson_with::<_, BitpackingUsageBuilder>(json.as_bytes(), &options).unwrap();
+        assert_eq!(doc.verify(), Ok(()));
+        let spans = source_texts(&doc, json);
+        assert_eq!(spans[1].as_deref(), Some("{\"n\": 3}"));
+        assert_eq!(spans[2].as_deref(), Some("\"n\": 3"));
+        assert_eq!(spans[3].as_deref(), Some("3"));
+
+        let json = " 1 [2]";
+        let docs = Documents::<_, BitpackingUsageBuilder>::new(json.as_bytes(), &options)
+            .map(|doc| {
+                let doc = doc.unwrap();
+                doc.source_span(doc.root()).unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(docs, [1..2, 3..6]);
+    }
+
+    #[test]
+    fn test_parse_stream() {
+        let json = r#"{"a": 1}{"a": 2} [3]
+"x" "#;
+        let options = ParseOptions::new();
+        let docs = Documents::<_, BitpackingUsageBuilder>::new(json.as_bytes(), &options)
+            .map(|doc| doc.unwrap().root_value().to_owned_value())
+            .collect::<Vec<_>>();
+        assert_eq!(docs.len(), 4);
+        assert_eq!(docs[1].pointer("/a"), Some(&OwnedValue::Number(2.0)));
+        assert_eq!(docs[3], OwnedValue::String("x".into()));
+    }
+
+    #[test]
+    fn test_parse_stream_error() {
+        let options = ParseOptions::new();
+        let mut docs = Documents::<_, BitpackingUsageBuilder>::new("[1] [".as_bytes(), &options);
+        assert!(docs.next().unwrap().is_ok());
+        assert!(docs.next().unwrap().is_err());
+        assert!(docs.next().is_none());
+        let mut empty = Documents::<_, BitpackingUsageBuilder>::new(" ".as_bytes(), &options);
+        assert!(empty.next().is_none());
+    }
+
+    #[test]
+    fn test_parse_jsonc() {
+        let json = r#"{
+            // a comment
+            "a": [1, 2, /* inline */ 3,],
+        }"#;
+        assert!(BitpackingUsageBuilder::parse(json.as_bytes()).is_err());
+        let options = ParseOptions::new().jsonc(true);
+        let doc = parse_with::<_, BitpackingUsageBuilder>(json.as_bytes(), &options).unwrap();
+        let mut output = Vec::new();
+        doc.serialize(&mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), r#"{"a":[1,2,3]}"#);
+    }
+
+    fn limit_error(json: &str, options: &ParseOptions) -> (ParseLimit, usize) {
+        let err = parse_with::<_, BitpackingUsageBuilder>(json.as_bytes(), options).unwrap_err();
+        match err.kind() {
+            crate::ParseErrorKind::LimitExceeded { limit, max } => (*limit, *max),
+            kind => panic!("unexpected error: {kind:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let json = r#"{"a": [1, {"b": "text"}]}"#;
+        let options = ParseOptions::new()
+            .max_depth(Some(3))
+            .max_nodes(Some(7))
+            .max_string_length(Some(4));
+        assert!(parse_with::<_, BitpackingUsageBuilder>(json.as_bytes(), &options).is_ok());
+
+        let depth = ParseOptions::new().max_depth(Some(2));
+        assert_eq!(limit_error(json, &depth), (ParseLimit::Depth, 2));
+        let nodes = ParseOptions::new().max_nodes(Some(6));
+        assert_eq!(limit_error(json, &nodes), (ParseLimit::Nodes, 6));
+        let strings = ParseOptions::new().max_string_length(Some(3));
+        assert_eq!(limit_error(json, &strings), (ParseLimit::StringLength, 3));
+        let keys = ParseOptions::new().max_string_length(Some(0));
+        assert_eq!(limit_error(json, &keys), (ParseLimit::StringLength, 0));
+    }
+
+    #[test]
+    fn test_parse_memory_budget() {
+        let json = format!(
+            "[{}]",
+            (0..5000)
+                .map(|i| format!(r#""some text that repeats, number {i}""#))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let parse = |options: &ParseOptions| {
+            parse_with::<_, BitpackingUsageBuilder>(json.as_bytes(), options)
+        };
+        let budget = 100_000;
+        let unbounded = parse(&ParseOptions::new()).unwrap();
+        assert_eq!(unbounded.text_usage.stats().total_blocks, 1);
+
+        // the text is compressed early to stay within the budget
+        let doc = parse(&ParseOptions::new().memory_budget(Some(budget))).unwrap();
+        assert!(doc.text_usage.stats().total_blocks > 1);
+        assert_eq!(
+            doc.root_value().to_owned_value(),
+            unbounded.root_value().to_owned_value()
+        );
+
+        let deterministic = ParseOptions::new()
+            .memory_budget(Some(budget))
+            .deterministic(true);
+        assert_eq!(
+            limit_error(&json, &deterministic),
+            (ParseLimit::Memory, budget)
+        );
+        let tiny = ParseOptions::new().memory_budget(Some(1000));
+        assert_eq!(limit_error(&json, &tiny), (ParseLimit::Memory, 1000));
+    }
+
+    #[test]
+    fn test_parse_limits_default_depth() {
+        let json = format!("{}{}", "[".repeat(200), "]".repeat(200));
+        let err = limit_error(&json, &ParseOptions::new());
+        assert_eq!(err, (ParseLimit::Depth, 128));
+        let options = ParseOptions::new().max_depth(None);
+        assert!(parse_with::<_, BitpackingUsageBuilder>(json.as_bytes(), &options).is_ok());
+    }
+
+    #[test]
+    fn test_parse_limits_filtered() {
+        let json = "{\"n\": 1, \"s\": \"long text\"}\n";
+        let options = ParseOptions::new()
+            .filter_records("", crate::Predicate::range("/n", Some(0.0), None))
+            .max_string_length(Some(4));
+        let err =
+            parse_ndjson_with::<_, BitpackingUsageBuilder>(json.as_bytes(), &options).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("string length exceeds the limit of 4")
+        );
+        assert_eq!(err.path(), Some("/s"));
+    }
+
+    #[test]
+    fn test_progress() {
+        let json = format!("[{}]", vec!["{\"a\": \"text\"}"; 100].join(","));
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let options = ParseOptions::new().progress_interval(50).on_progress({
+            let reports = reports.clone();
+            move |progress| reports.lock().unwrap().push(*progress)
+        });
+        parse_with::<_, BitpackingUsageBuilder>(json.as_bytes(), &options).unwrap();
+        let reports = reports.lock().unwrap();
+        // the root array and three nodes for every item
+        assert_eq!(reports.len(), 301 / 50);
+        assert_eq!(reports[0].nodes, 50);
+        assert!(
+            reports
+                .windows(2)
+                .all(|w| w[0].bytes_read < w[1].bytes_read)
+        );
+        assert!(reports[5].bytes_read < json.len() as u64);
+        assert!(reports[5].tree_heap_size > 0);
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let json = format!("[{}]", vec!["1"; 10_000].join(","));
+        let token = CancellationToken::new();
+        let options = ParseOptions::new().cancellation(token.clone());
+        assert!(parse_with::<_, BitpackingUsageBuilder>(json.as_bytes(), &options).is_ok());
+
+        // cancel from within the parse
+        let options = options
+            .progress_interval(100)
+            .on_progress(move |_| token.cancel());
+        let err = parse_with::<_, BitpackingUsageBuilder>(json.as_bytes(), &options).unwrap_err();
+        assert!(matches!(err.kind(), crate::ParseErrorKind::Cancelled));
+        assert!(err.byte_offset().unwrap() < json.len() as u64);
+    }
+
+    #[test]
+    fn test_struson_single_number() {
+        let json = "42";
+        let mut reader = JsonStreamReader::new(json.as_bytes());
+        let nr: f64 = reader.next_number().unwrap().unwrap();
+        assert_eq!(nr, 42f64);
+    }
+}
@@ -0,0 +1,354 @@
+//! Parsing of documents whose root is a big array, using multiple threads.
+//!
+//! A quick scan over the bytes finds the commas between the items of the
+//! root array, and the items are split into segments at those commas. The
+//! segments are parsed in parallel, each into its own builder, and the
+//! builders are then appended in order to the builder holding the root
+//! array.
+
+use std::io::Read;
+
+use rayon::prelude::*;
+use struson::reader::JsonReader;
+
+use super::{Parser, builder_for, json_reader, parse_with};
+use crate::{
+    builder::Builder,
+    document::Document,
+    info::NodeType,
+    options::ParseOptions,
+    parse_error::{JsonParseError, ParseLimit},
+    usage::UsageBuilder,
+};
+
+/// The items of the root array between two of its commas.
+#[derive(Debug, PartialEq, Eq)]
+struct Segment {
+    start: usize,
+    end: usize,
+    // the index of the first item in the root array
+    first_index: usize,
+}
+
+pub(crate) fn parse_parallel_with<B: UsageBuilder + Send>(
+    json: &[u8],
+    options: &ParseOptions,
+    segment_size: usize,
+) -> Result<Document<B::Index>, JsonParseError> {
+    // comments could hide quotes and brackets from the scan
+    let segments = if options.jsonc {
+        None
+    } else {
+        split(json, segment_size)
+    };
+    match segments {
+        Some(segments) if segments.len() > 1 => {
+            // the positions in errors from segments are not those in the
+            // input, so we parse again to report errors where they are
+            parse_segments::<B>(json, &segments, options)
+                .or_else(|_| parse_with::<_, B>(json, options))
+        }
+        _ => parse_with::<_, B>(json, options),
+    }
+}
+
+fn parse_segments<B: UsageBuilder + Send>(
+    json: &[u8],
+    segments: &[Segment],
+    options: &ParseOptions,
+) -> Result<Document<B::Index>, JsonParseError> {
+    #[cfg(feature = "tracing")]
+    let span = tracing::info_span!("parse", segments = segments.len()).entered();
+    let parsed = segments
+        .par_iter()
+        .map(|segment| parse_segment::<B>(json, segment, options))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut builder = builder_for::<B>(options)?;
+    builder.open(NodeType::Array);
+    // the first segment starts after the opening bracket and the last one
+    // ends at the closing bracket
+    builder.source_offset(segments[0].start as u64 - 1);
+    // the root array is a node too
+    let mut nodes = 1;
+    for (segment_builder, segment_nodes) in parsed {
+        builder.append_segment(segment_builder);
+        nodes += segment_nodes;
+    }
+    builder.close(NodeType::Array);
+    builder.source_offset(segments[segments.len() - 1].end as u64 + 1);
+    #[cfg(feature = "tracing")]
+    span.exit();
+    if let Some(max) = options.max_nodes
+        && nodes > max
+    {
+        return Err(JsonParseError::limit(ParseLimit::Nodes, max));
+    }
+    Ok(builder.build())
+}
+
+// parse the items of a segment, returning the builder and the number of
+// nodes read
+fn parse_segment<B: UsageBuilder>(
+    json: &[u8],
+    segment: &Segment,
+    options: &ParseOptions,
+) -> Result<(Builder<B>, usize), JsonParseError> {
+    // wrapped in brackets, the items are nested as deep as in the input
+    let items = b"["
+        .as_slice()
+        .chain(&json[segment.start..segment.end])
+        .chain(b"]".as_slice());
+    let builder = builder_for::<B>(options)?.segment();
+    let mut parser = Parser::with_reader(json_reader(items, options, false), options, builder);
+    // the opening bracket stands in for the byte before the segment
+    parser.byte_offset = segment.start as u64 - 1;
+    parser.parse_segment_items(segment.first_index)?;
+    Ok((parser.builder, parser.nodes))
+}
+
+impl<R: Read, B: UsageBuilder> Parser<'_, R, B> {
+    // the items of the array, without the array itself
+    fn parse_segment_items(&mut self, first_index: usize) -> Result<(), JsonParseError> {
+        self.reader.begin_array()?;
+        if self.is_filtering() {
+            self.parse_filtered_items(first_index)?;
+        } else {
+            while self.reader.has_next()? {
+                self.parse_item()?;
+            }
+        }
+        self.reader.end_array()?;
+        Ok(())
+    }
+}
+
+fn is_whitespace(byte: &u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\n' | b'\r')
+}
+
+/// Split the items of the root array into segments of at least
+/// `segment_size` bytes, except for the last. This returns `None` if the
+/// root is not an array, or if the scan finds the input malformed; the
+/// scan doesn't validate the items, that is left to parsing.
+fn split(json: &[u8], segment_size: usize) -> Option<Vec<Segment>> {
+    let root = json.iter().position(|byte| !is_whitespace(byte))?;
+    if json[root] != b'[' {
+        return None;
+    }
+    let mut segments = Vec::new();
+    let mut start = root + 1;
+    let mut first_index = 0;
+    let mut index = 0;
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut end = None;
+    for (i, &byte) in json.iter().enumerate().skip(root) {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if byte == b'\\' {
+                escaped = true;
+            } else if byte == b'"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'[' | b'{' => depth += 1,
+            b']' | b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    end = Some(i);
+                    break;
+                }
+            }
+            b',' if depth == 1 => {
+                index += 1;
+                if i - start >= segment_size {
+                    segments.push(Segment {
+                        start,
+                        end: i,
+                        first_index,
+                    });
+                    start = i + 1;
+                    first_index = index;
+                }
+            }
+            _ => {}
+        }
+    }
+    let end = end?;
+    if json[end] != b']' || !json[end + 1..].iter().all(is_whitespace) {
+        return None;
+    }
+    segments.push(Segment {
+        start,
+        end,
+        first_index,
+    });
+    Some(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OwnedValue, Predicate, usage::BitpackingUsageBuilder};
+
+    fn parse_parallel(json: &str, options: &ParseOptions, segment_size: usize) -> OwnedValue {
+        let doc =
+            parse_parallel_with::<BitpackingUsageBuilder>(json.as_bytes(), options, segment_size)
+                .unwrap();
+        assert_eq!(doc.verify(), Ok(()));
+        doc.root_value().to_owned_value()
+    }
+
+    fn parse(json: &str, options: &ParseOptions) -> OwnedValue {
+        parse_with::<_, BitpackingUsageBuilder>(json.as_bytes(), options)
+            .unwrap()
+            .root_value()
+            .to_owned_value()
+    }
+
+    #[test]
+    fn test_split() {
+        let json = br#" [1, "a,]\"", {"b": [2, 3]}, 4] "#;
+        let segments = split(json, 0).unwrap();
+        assert_eq!(segments.len(), 4);
+        assert_eq!(&json[segments[1].start..segments[1].end], br#" "a,]\"""#);
+        assert_eq!(segments[2].first_index, 2);
+        assert_eq!(&json[segments[3].start..segments[3].end], b" 4");
+
+        let segments = split(json, 12).unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[1].first_index, 3);
+
+        assert_eq!(split(b"{\"a\": 1}", 0), None);
+        assert_eq!(split(b"[1, 2", 0), None);
+        assert_eq!(split(b"[1, 2} ", 0), None);
+        assert_eq!(split(b"[1, 2] x", 0), None);
+    }
+
+    #[test]
+    fn test_parse_parallel() {
+        let items = (0..500)
+            .map(|i| {
+                format!(
+                    r#"{{"id": {i}, "name": "item {i}", "even": {}, "tags": [null, "f{}"]}}"#,
+                    i % 2 == 0,
+                    i % 7
+                )
+            })
+            .collect::<Vec<_>>();
+        let json = format!("[{}]", items.join(", "));
+        let expected = parse(&json, &ParseOptions::new());
+        for options in [
+            ParseOptions::new(),
+            ParseOptions::new().string_hashes(true).spill_to_disk(true),
+            ParseOptions::new().preserve_number_lexemes(true),
+        ] {
+            for segment_size in [0, 100, 10_000, json.len()] {
+                assert_eq!(parse_parallel(&json, &options, segment_size), expected);
+            }
+        }
+        let options = ParseOptions::new();
+        // scalars and an empty array
+        for json in ["42", "[]", "[1]", r#"{"a": [1, 2]}"#] {
+            assert_eq!(parse_parallel(json, &options, 0), parse(json, &options));
+        }
+    }
+
+    #[test]
+    fn test_parse_parallel_number_lexemes() {
+        let json = format!("[{}]", vec!["1.50, 2"; 100].join(", "));
+        let options = ParseOptions::new().preserve_number_lexemes(true);
+        let doc =
+            parse_parallel_with::<BitpackingUsageBuilder>(json.as_bytes(), &options, 40).unwrap();
+        assert_eq!(doc.verify(), Ok(()));
+        let mut output = Vec::new();
+        doc.serialize(&mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), json.replace(", ", ","));
+    }
+
+    #[test]
+    fn test_parse_parallel_child_skips() {
+        let json = format!("[{}]", vec!["[1, 2]"; 1000].join(","));
+        let doc = parse_parallel_with::<BitpackingUsageBuilder>(
+            json.as_bytes(),
+            &ParseOptions::new(),
+            50,
+        )
+        .unwrap();
+        let crate::Value::Array(array) = doc.root_value() else {
+            panic!("root should be an array");
+        };
+        assert_eq!(
+            array.get(777).unwrap().to_owned_value(),
+            OwnedValue::Array(vec![OwnedValue::Number(1.0), OwnedValue::Number(2.0)])
+        );
+        assert_eq!(array.iter().count(), 1000);
+    }
+
+    #[test]
+    fn test_parse_parallel_filtered() {
+        let json = format!(
+            "[{}]",
+            (0..100)
+                .map(|i| format!(r#"{{"n": {i}}}"#))
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        let options = ParseOptions::new()
+            .filter_records("", Predicate::range("/n", Some(10.0), Some(60.0)))
+            .filter_records("/*", Predicate::range("/n", None, Some(1000.0)));
+        let parsed = parse_parallel(&json, &options, 30);
+        assert_eq!(parsed, parse(&json, &options));
+        assert_eq!(parsed.pointer("/0/n"), Some(&OwnedValue::Number(10.0)));
+    }
+
+    #[test]
+    fn test_parse_parallel_source_spans() {
+        let json = r#" [{"a": 1}, "b",  [true, null] ] "#;
+        let options = ParseOptions::new().source_spans(true);
+        let doc =
+            parse_parallel_with::<BitpackingUsageBuilder>(json.as_bytes(), &options, 0).unwrap();
+        assert_eq!(doc.verify(), Ok(()));
+        let text = |node| {
+            let span = doc.source_span(node).unwrap();
+            &json[span.start as usize..span.end as usize]
+        };
+        assert_eq!(text(doc.root()), json.trim());
+        let items = doc.children(doc.root()).collect::<Vec<_>>();
+        assert_eq!(
+            items.iter().map(|&item| text(item)).collect::<Vec<_>>(),
+            [r#"{"a": 1}"#, r#""b""#, "[true, null]"]
+        );
+        let field = doc.first_child(items[0]).unwrap();
+        assert_eq!(text(field), r#""a": 1"#);
+        assert_eq!(text(doc.last_child(items[2]).unwrap()), "null");
+    }
+
+    #[test]
+    fn test_parse_parallel_errors() {
+        let json = "[1, 2, 3, {\"a\": tru}, 5]";
+        let options = ParseOptions::new();
+        let err = parse_parallel_with::<BitpackingUsageBuilder>(json.as_bytes(), &options, 0)
+            .unwrap_err();
+        // reported where it is in the input
+        assert_eq!(err.byte_offset(), Some(json.find("tru").unwrap() as u64));
+        assert_eq!(err.path(), Some("/3/a"));
+
+        let json = format!("[{}]", vec!["[1]"; 100].join(","));
+        let options = ParseOptions::new().max_nodes(Some(150));
+        let err = parse_parallel_with::<BitpackingUsageBuilder>(json.as_bytes(), &options, 10)
+            .unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            crate::ParseErrorKind::LimitExceeded {
+                limit: ParseLimit::Nodes,
+                ..
+            }
+        ));
+    }
+}
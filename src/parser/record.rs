@@ -0,0 +1,76 @@
+use std::ops::Range;
+
+use indexmap::IndexMap;
+
+use crate::{document::OwnedValue, numbers::Number};
+
+/// A value read ahead to decide whether to keep it, kept as it was in the
+/// input, so that adding it to the document gives the same nodes as
+/// parsing it would have: with every field of objects with duplicate
+/// names, number lexemes and source spans.
+pub(super) enum Buffered {
+    Array {
+        items: Vec<Buffered>,
+        span: Range<u64>,
+    },
+    Object {
+        fields: Vec<BufferedField>,
+        span: Range<u64>,
+    },
+    String(String, Range<u64>),
+    Number {
+        number: Number,
+        lexeme: String,
+        span: Range<u64>,
+    },
+    Boolean(bool, Range<u64>),
+    Null(Range<u64>),
+}
+
+pub(super) struct BufferedField {
+    pub(super) name: String,
+    // where the name starts; the field ends with its value
+    pub(super) start: u64,
+    pub(super) value: Buffered,
+}
+
+impl Buffered {
+    pub(super) fn span(&self) -> &Range<u64> {
+        match self {
+            Buffered::Array { span, .. }
+            | Buffered::Object { span, .. }
+            | Buffered::String(_, span)
+            | Buffered::Number { span, .. }
+            | Buffered::Boolean(_, span)
+            | Buffered::Null(span) => span,
+        }
+    }
+
+    pub(super) fn is_scalar(&self) -> bool {
+        !matches!(self, Buffered::Array { .. } | Buffered::Object { .. })
+    }
+
+    /// The value to match predicates against; of fields with the same name
+    /// the last one counts.
+    pub(super) fn to_owned_value(&self) -> OwnedValue {
+        match self {
+            Buffered::Array { items, .. } => {
+                OwnedValue::Array(items.iter().map(Buffered::to_owned_value).collect())
+            }
+            Buffered::Object { fields, .. } => OwnedValue::Object(
+                fields
+                    .iter()
+                    .map(|field| (field.name.as_str().into(), field.value.to_owned_value()))
+                    .collect::<IndexMap<_, _>>(),
+            ),
+            Buffered::String(s, _) => OwnedValue::String(s.as_str().into()),
+            Buffered::Number { number, .. } => match number {
+                Number::Float(n) => OwnedValue::Number(*n),
+                Number::Integer(i) => OwnedValue::Integer(*i),
+                Number::Unsigned(u) => OwnedValue::Number(*u as f64),
+            },
+            Buffered::Boolean(b, _) => OwnedValue::Boolean(*b),
+            Buffered::Null(_) => OwnedValue::Null,
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! Encoding of the parts of a document inside the sections of a
+//! [container](crate::container).
+//!
+//! Integers are little-endian; lengths and positions are stored as LEB128
+//! varints, and sorted positions as the varint deltas between them.
+
+use std::{
+    fmt,
+    io::{self, Read, Write},
+};
+
+use vers_vecs::{BitVec, SparseRSVec};
+
+use crate::IntegrityError;
+
+/// An error while loading a saved document.
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    /// The data does not start with [`MAGIC`](crate::container::MAGIC).
+    NotADocument,
+    /// The data was written in a format version this build cannot read.
+    UnsupportedVersion(u32),
+    /// The content of a section does not match its checksum.
+    ChecksumMismatch {
+        section: u32,
+    },
+    /// A section the document needs is not in the container.
+    MissingSection(u32),
+    /// The data is truncated or does not describe a valid document.
+    Corrupt(&'static str),
+    /// The parts of the document were read but do not fit together.
+    Invalid(IntegrityError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "I/O error: {err}"),
+            LoadError::NotADocument => write!(f, "the data is not a saved document"),
+            LoadError::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {version}")
+            }
+            LoadError::ChecksumMismatch { section } => {
+                write!(f, "section {section} does not match its checksum")
+            }
+            LoadError::MissingSection(section) => write!(f, "section {section} is missing"),
+            LoadError::Corrupt(reason) => write!(f, "corrupt document: {reason}"),
+            LoadError::Invalid(err) => write!(f, "invalid document: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(err) => Some(err),
+            LoadError::Invalid(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::UnexpectedEof {
+            LoadError::Corrupt("unexpected end of data")
+        } else {
+            LoadError::Io(err)
+        }
+    }
+}
+
+// don't trust lengths read from the data when reserving memory up front
+const MAX_PREALLOCATE: usize = 1 << 16;
+
+pub(crate) fn write_u8<W: Write + ?Sized>(w: &mut W, value: u8) -> io::Result<()> {
+    w.write_all(&[value])
+}
+
+pub(crate) fn read_u8<R: Read + ?Sized>(r: &mut R) -> Result<u8, LoadError> {
+    let mut buf = [0; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+pub(crate) fn write_u32<W: Write + ?Sized>(w: &mut W, value: u32) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+pub(crate) fn read_u32<R: Read + ?Sized>(r: &mut R) -> Result<u32, LoadError> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+pub(crate) fn write_u64<W: Write + ?Sized>(w: &mut W, value: u64) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+pub(crate) fn read_u64<R: Read + ?Sized>(r: &mut R) -> Result<u64, LoadError> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+pub(crate) fn write_varint<W: Write + ?Sized>(w: &mut W, mut value: u64) -> io::Result<()> {
+    while value >= 0x80 {
+        write_u8(w, (value as u8) | 0x80)?;
+        value >>= 7;
+    }
+    write_u8(w, value as u8)
+}
+
+pub(crate) fn read_varint<R: Read + ?Sized>(r: &mut R) -> Result<u64, LoadError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = read_u8(r)?;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(LoadError::Corrupt("varint too long"))
+}
+
+pub(crate) fn write_len<W: Write + ?Sized>(w: &mut W, len: usize) -> io::Result<()> {
+    write_varint(w, len as u64)
+}
+
+pub(crate) fn read_len<R: Read + ?Sized>(r: &mut R) -> Result<usize, LoadError> {
+    usize::try_from(read_varint(r)?).map_err(|_| LoadError::Corrupt("length too large"))
+}
+
+/// A vector with room for `len` items, reserving no more than the data can
+/// be trusted with.
+pub(crate) fn vec_for<T>(len: usize) -> Vec<T> {
+    Vec::with_capacity(len.min(MAX_PREALLOCATE))
+}
+
+pub(crate) fn write_bytes<W: Write + ?Sized>(w: &mut W, bytes: &[u8]) -> io::Result<()> {
+    write_len(w, bytes.len())?;
+    w.write_all(bytes)
+}
+
+pub(crate) fn read_bytes<R: Read + ?Sized>(r: &mut R) -> Result<Vec<u8>, LoadError> {
+    let len = read_len(r)?;
+    let mut bytes = vec_for(len);
+    // read through take so a corrupt length can't make us allocate more
+    // than the data holds
+    r.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(LoadError::Corrupt("unexpected end of data"));
+    }
+    Ok(bytes)
+}
+
+pub(crate) fn write_str<W: Write + ?Sized>(w: &mut W, s: &str) -> io::Result<()> {
+    write_bytes(w, s.as_bytes())
+}
+
+pub(crate) fn read_string<R: Read + ?Sized>(r: &mut R) -> Result<String, LoadError> {
+    String::from_utf8(read_bytes(r)?).map_err(|_| LoadError::Corrupt("string is not UTF-8"))
+}
+
+/// Write sorted positions as the deltas between them.
+pub(crate) fn write_positions<W: Write + ?Sized>(w: &mut W, positions: &[u64]) -> io::Result<()> {
+    write_len(w, positions.len())?;
+    let mut previous = 0;
+    for &position in positions {
+        write_varint(w, position - previous)?;
+        previous = position;
+    }
+    Ok(())
+}
+
+/// Read sorted positions, checking that they are below `len`.
+pub(crate) fn read_positions<R: Read + ?Sized>(r: &mut R, len: u64) -> Result<Vec<u64>, LoadError> {
+    let count = read_len(r)?;
+    let mut positions = vec_for(count);
+    let mut previous = 0u64;
+    for i in 0..count {
+        let delta = read_varint(r)?;
+        if i > 0 && delta == 0 {
+            return Err(LoadError::Corrupt("positions are not increasing"));
+        }
+        let position = previous
+            .checked_add(delta)
+            .filter(|&position| position < len)
+            .ok_or(LoadError::Corrupt("position out of range"))?;
+        positions.push(position);
+        previous = position;
+    }
+    Ok(positions)
+}
+
+pub(crate) fn write_sparse<W: Write + ?Sized>(w: &mut W, vec: &SparseRSVec) -> io::Result<()> {
+    write_varint(w, vec.len())?;
+    let positions = vec.iter1().collect::<Vec<_>>();
+    write_positions(w, &positions)
+}
+
+pub(crate) fn read_sparse<R: Read + ?Sized>(r: &mut R) -> Result<SparseRSVec, LoadError> {
+    let len = read_varint(r)?;
+    let positions = read_positions(r, len)?;
+    Ok(SparseRSVec::new(&positions, len))
+}
+
+pub(crate) fn write_bit_vec<W: Write + ?Sized>(w: &mut W, bits: &BitVec) -> io::Result<()> {
+    write_len(w, bits.len())?;
+    for start in (0..bits.len()).step_by(64) {
+        let len = (bits.len() - start).min(64);
+        let limb = bits
+            .get_bits(start, len)
+            .expect("Bits within the vector should exist");
+        write_u64(w, limb)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn read_bit_vec<R: Read + ?Sized>(r: &mut R) -> Result<BitVec, LoadError> {
+    let len = read_len(r)?;
+    let limbs = len.div_ceil(64);
+    let mut data = vec_for(limbs);
+    for _ in 0..limbs {
+        data.push(read_u64(r)?);
+    }
+    let mut bits = BitVec::from_limbs(&data);
+    bits.drop_last(limbs * 64 - len);
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint_round_trip() {
+        let values = [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX];
+        let mut out = Vec::new();
+        for value in values {
+            write_varint(&mut out, value).unwrap();
+        }
+        let mut r = out.as_slice();
+        for value in values {
+            assert_eq!(read_varint(&mut r).unwrap(), value);
+        }
+        assert!(r.is_empty());
+    }
+
+    #[test]
+    fn test_sparse_round_trip() {
+        let vec = SparseRSVec::new(&[0, 3, 64, 1000], 1001);
+        let mut out = Vec::new();
+        write_sparse(&mut out, &vec).unwrap();
+        let read = read_sparse(&mut out.as_slice()).unwrap();
+        assert_eq!(read.len(), 1001);
+        assert_eq!(
+            read.iter1().take(4).collect::<Vec<_>>(),
+            vec![0, 3, 64, 1000]
+        );
+    }
+
+    #[test]
+    fn test_bit_vec_round_trip() {
+        let mut bits = BitVec::new();
+        for i in 0..130 {
+            bits.append(i % 3 == 0);
+        }
+        let mut out = Vec::new();
+        write_bit_vec(&mut out, &bits).unwrap();
+        let read = read_bit_vec(&mut out.as_slice()).unwrap();
+        assert_eq!(read.len(), bits.len());
+        assert!((0..bits.len()).all(|i| read.get(i) == bits.get(i)));
+    }
+
+    #[test]
+    fn test_truncated_bytes() {
+        let mut out = Vec::new();
+        write_bytes(&mut out, b"hello").unwrap();
+        out.truncate(3);
+        assert!(matches!(
+            read_bytes(&mut out.as_slice()),
+            Err(LoadError::Corrupt(_))
+        ));
+    }
+}
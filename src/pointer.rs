@@ -0,0 +1,83 @@
+//! JSON Pointer (RFC 6901) helpers.
+
+/// Split a JSON Pointer into its unescaped reference tokens. The empty
+/// pointer refers to the whole document and has no tokens. Returns `None`
+/// if the pointer is not valid.
+pub(crate) fn parse(pointer: &str) -> Option<Vec<String>> {
+    if pointer.is_empty() {
+        return Some(Vec::new());
+    }
+    let rest = pointer.strip_prefix('/')?;
+    rest.split('/').map(unescape).collect()
+}
+
+fn unescape(token: &str) -> Option<String> {
+    let mut out = String::with_capacity(token.len());
+    let mut chars = token.chars();
+    while let Some(c) = chars.next() {
+        if c == '~' {
+            match chars.next() {
+                Some('0') => out.push('~'),
+                Some('1') => out.push('/'),
+                _ => return None,
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    Some(out)
+}
+
+/// Escape a reference token for use in a JSON Pointer.
+pub(crate) fn escape(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+/// Extend a pointer with another reference token.
+pub(crate) fn push(pointer: &str, token: &str) -> String {
+    format!("{pointer}/{}", escape(token))
+}
+
+/// Interpret a reference token as an array index. Leading zeros are not
+/// allowed.
+pub(crate) fn array_index(token: &str) -> Option<usize> {
+    if token.is_empty()
+        || (token.len() > 1 && token.starts_with('0'))
+        || !token.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    token.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(""), Some(vec![]));
+        assert_eq!(parse("/"), Some(vec!["".to_string()]));
+        assert_eq!(
+            parse("/a~1b/c~0d/0"),
+            Some(vec!["a/b".to_string(), "c~d".to_string(), "0".to_string()])
+        );
+        assert_eq!(parse("a"), None);
+        assert_eq!(parse("/a~2"), None);
+    }
+
+    #[test]
+    fn test_push() {
+        assert_eq!(push("", "a/b"), "/a~1b");
+        assert_eq!(push("/x", "~"), "/x/~0");
+    }
+
+    #[test]
+    fn test_array_index() {
+        assert_eq!(array_index("0"), Some(0));
+        assert_eq!(array_index("12"), Some(12));
+        assert_eq!(array_index("012"), None);
+        assert_eq!(array_index("-"), None);
+        assert_eq!(array_index(""), None);
+    }
+}
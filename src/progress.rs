@@ -0,0 +1,74 @@
+use std::{
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+/// How far a parse has come, passed to the callback set with
+/// [`ParseOptions::on_progress`](crate::ParseOptions::on_progress).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// The number of nodes read, counting every value and every object
+    /// field.
+    pub nodes: usize,
+    /// The number of bytes of JSON read.
+    pub bytes_read: u64,
+    /// The heap size in bytes of the parentheses and the usage being built.
+    pub tree_heap_size: usize,
+    /// The heap size in bytes of the compressed text.
+    pub text_heap_size: usize,
+    /// The size in bytes of the text before compression.
+    pub uncompressed_text_size: usize,
+    /// The heap size in bytes of the numbers.
+    pub numbers_heap_size: usize,
+    /// The heap size in bytes of the booleans.
+    pub booleans_heap_size: usize,
+}
+
+/// A token to abort a parse from another thread.
+///
+/// Clones share the same state, so cancelling one cancels them all. A
+/// parse that has the token set with
+/// [`ParseOptions::cancellation`](crate::ParseOptions::cancellation) fails
+/// with [`ParseErrorKind::Cancelled`](crate::ParseErrorKind::Cancelled)
+/// soon after it is cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// A progress callback, shared between clones of the options.
+#[derive(Clone)]
+pub(crate) struct ProgressCallback(Arc<dyn Fn(&Progress) + Send + Sync>);
+
+impl ProgressCallback {
+    pub(crate) fn new(callback: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(callback))
+    }
+
+    pub(crate) fn call(&self, progress: &Progress) {
+        (self.0)(progress)
+    }
+}
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
@@ -0,0 +1,279 @@
+//! Python bindings, using pyo3.
+//!
+//! This is only available with the `python` feature. The `colchis-py`
+//! crate builds these into the `colchis` Python extension module; the
+//! document stays compressed on the Rust side, and Python only gets
+//! values when it asks for them.
+//!
+//! ```python
+//! import colchis
+//!
+//! doc = colchis.Document.parse('{"items": [{"id": 1}, {"id": 2}]}')
+//! item = doc.pointer("/items/1")
+//! item["id"].value()  # 2
+//! ```
+
+use std::sync::Arc;
+
+use pyo3::{
+    exceptions::{PyIndexError, PyKeyError, PyTypeError, PyValueError},
+    prelude::*,
+    types::{PyBytes, PyDict, PyList, PyString},
+};
+
+use crate::{
+    document::{Document, Node, Value},
+    info::NodeType,
+    pointer,
+    usage::{BitpackingUsageBuilder, EliasFanoUsageIndex},
+};
+
+type SharedDocument = Arc<Document<EliasFanoUsageIndex>>;
+
+/// Add the classes of the bindings to a Python module.
+pub fn register(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyDocument>()?;
+    module.add_class::<PyNode>()?;
+    Ok(())
+}
+
+/// A parsed JSON document.
+#[pyclass(name = "Document", module = "colchis", frozen)]
+pub struct PyDocument {
+    document: SharedDocument,
+}
+
+#[pymethods]
+impl PyDocument {
+    /// Parse JSON from a `str` or `bytes`. The GIL is released while
+    /// parsing.
+    #[staticmethod]
+    fn parse(py: Python<'_>, json: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let bytes = if let Ok(text) = json.downcast::<PyString>() {
+            text.to_str()?.as_bytes()
+        } else if let Ok(bytes) = json.downcast::<PyBytes>() {
+            bytes.as_bytes()
+        } else {
+            return Err(PyTypeError::new_err("JSON should be str or bytes"));
+        };
+        let document = py
+            .allow_threads(|| Document::parse::<BitpackingUsageBuilder, _>(bytes))
+            .map_err(|err| PyValueError::new_err(err.to_string()))?;
+        Ok(Self {
+            document: Arc::new(document),
+        })
+    }
+
+    /// The root value.
+    #[getter]
+    fn root(&self) -> PyNode {
+        PyNode::new(&self.document, self.document.root())
+    }
+
+    /// The value at a JSON Pointer, or `None` if there is none.
+    fn pointer(&self, pointer: &str) -> PyResult<Option<PyNode>> {
+        let tokens = pointer::parse(pointer)
+            .ok_or_else(|| PyValueError::new_err(format!("Invalid JSON Pointer: {pointer}")))?;
+        let tokens = tokens.iter().map(String::as_str).collect::<Vec<_>>();
+        Ok(self
+            .document
+            .get_path(self.document.root(), &tokens)
+            .map(|node| PyNode::new(&self.document, node)))
+    }
+
+    /// The strings that contain a fragment, in document order.
+    fn strings_containing(&self, fragment: &str) -> Vec<PyNode> {
+        self.document
+            .strings_containing(fragment)
+            .into_iter()
+            .map(|node| PyNode::new(&self.document, node))
+            .collect()
+    }
+
+    /// The approximate heap used by the document, in bytes.
+    fn heap_size(&self) -> usize {
+        self.document.heap_size()
+    }
+}
+
+/// A value in a document. Fields are skipped: indexing an object gives
+/// the value of the field.
+#[pyclass(name = "Node", module = "colchis", frozen)]
+pub struct PyNode {
+    document: SharedDocument,
+    node: Node,
+}
+
+impl PyNode {
+    fn new(document: &SharedDocument, node: Node) -> Self {
+        Self {
+            document: document.clone(),
+            node,
+        }
+    }
+
+    // the name and value of every field of an object
+    fn fields(&self) -> PyResult<Vec<(String, Node)>> {
+        if self.document.node_type(self.node) != &NodeType::Object {
+            return Err(PyTypeError::new_err("Node should be an object"));
+        }
+        Ok(self
+            .document
+            .children(self.node)
+            .map(|field| {
+                let NodeType::Field(name) = self.document.node_type(field) else {
+                    unreachable!("Object children should be fields");
+                };
+                let value = self
+                    .document
+                    .first_child(field)
+                    .expect("Field should have a value");
+                (name.clone(), value)
+            })
+            .collect())
+    }
+}
+
+#[pymethods]
+impl PyNode {
+    /// The type of the value: "object", "array", "string", "number",
+    /// "boolean" or "null".
+    #[getter(r#type)]
+    fn node_type(&self) -> &'static str {
+        match self.document.node_type(self.node) {
+            NodeType::Object => "object",
+            NodeType::Array => "array",
+            NodeType::String => "string",
+            NodeType::Number => "number",
+            NodeType::Boolean => "boolean",
+            NodeType::Null => "null",
+            NodeType::Field(_) => unreachable!("Fields should be resolved to their value"),
+        }
+    }
+
+    /// The JSON Pointer from the root to the value.
+    #[getter]
+    fn path(&self) -> String {
+        self.document.path(self.node)
+    }
+
+    /// The object or array the value is in, or `None` for the root.
+    #[getter]
+    fn parent(&self) -> Option<PyNode> {
+        let mut parent = self.document.parent(self.node)?;
+        if let NodeType::Field(_) = self.document.node_type(parent) {
+            parent = self
+                .document
+                .parent(parent)
+                .expect("Field should be in an object");
+        }
+        Some(PyNode::new(&self.document, parent))
+    }
+
+    /// The names of the fields of an object.
+    fn keys(&self) -> PyResult<Vec<String>> {
+        Ok(self.fields()?.into_iter().map(|(name, _)| name).collect())
+    }
+
+    /// The names and values of the fields of an object.
+    fn items(&self) -> PyResult<Vec<(String, PyNode)>> {
+        Ok(self
+            .fields()?
+            .into_iter()
+            .map(|(name, value)| (name, PyNode::new(&self.document, value)))
+            .collect())
+    }
+
+    /// The items of an array or the values of the fields of an object.
+    fn children(&self) -> PyResult<Vec<PyNode>> {
+        match self.document.node_type(self.node) {
+            NodeType::Array => Ok(self
+                .document
+                .children(self.node)
+                .map(|item| PyNode::new(&self.document, item))
+                .collect()),
+            NodeType::Object => Ok(self
+                .fields()?
+                .into_iter()
+                .map(|(_, value)| PyNode::new(&self.document, value))
+                .collect()),
+            _ => Err(PyTypeError::new_err("Node should be an object or array")),
+        }
+    }
+
+    /// The whole value as Python objects: dicts, lists, strings, ints,
+    /// floats, booleans and `None`.
+    fn value<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        to_python(py, self.document.value(self.node))
+    }
+
+    /// The value as compact JSON.
+    fn json(&self) -> String {
+        self.document.value(self.node).to_string()
+    }
+
+    fn __len__(&self) -> PyResult<usize> {
+        match self.document.node_type(self.node) {
+            NodeType::Object | NodeType::Array => Ok(self.document.child_count(self.node)),
+            _ => Err(PyTypeError::new_err("Node should be an object or array")),
+        }
+    }
+
+    /// The value of a field of an object by name, or an item of an array
+    /// by index; negative indexes count from the end.
+    fn __getitem__(&self, key: &Bound<'_, PyAny>) -> PyResult<PyNode> {
+        let node = match self.document.node_type(self.node) {
+            NodeType::Object => {
+                let name = key.extract::<String>()?;
+                self.document
+                    .get_path(self.node, &[&name])
+                    .ok_or_else(|| PyKeyError::new_err(name))?
+            }
+            NodeType::Array => {
+                let index = key.extract::<isize>()?;
+                let index = if index < 0 {
+                    index + self.document.child_count(self.node) as isize
+                } else {
+                    index
+                };
+                usize::try_from(index)
+                    .ok()
+                    .and_then(|index| self.document.nth_child(self.node, index))
+                    .ok_or_else(|| PyIndexError::new_err("Array index out of range"))?
+            }
+            _ => return Err(PyTypeError::new_err("Node should be an object or array")),
+        };
+        Ok(PyNode::new(&self.document, node))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<Node {} at {:?}>", self.node_type(), self.path())
+    }
+}
+
+fn to_python<'py>(
+    py: Python<'py>,
+    value: Value<'_, EliasFanoUsageIndex>,
+) -> PyResult<Bound<'py, PyAny>> {
+    Ok(match value {
+        Value::Object(object) => {
+            let dict = PyDict::new(py);
+            for (key, value) in object.iter() {
+                dict.set_item(key, to_python(py, value)?)?;
+            }
+            dict.into_any()
+        }
+        Value::Array(array) => {
+            let list = PyList::empty(py);
+            for item in array.iter() {
+                list.append(to_python(py, item)?)?;
+            }
+            list.into_any()
+        }
+        Value::String(s) => PyString::new(py, &s).into_any(),
+        Value::Number(n) => n.into_pyobject(py)?.into_any(),
+        Value::Integer(i) => i.into_pyobject(py)?.into_any(),
+        Value::Boolean(b) => b.into_pyobject(py)?.to_owned().into_any(),
+        Value::Null => py.None().into_bound(py),
+    })
+}
@@ -0,0 +1,161 @@
+use crate::{
+    builder::Builder,
+    document::Document,
+    document_builder::{BuildError, DocumentBuilder},
+    info::NodeType,
+    usage::UsageBuilder,
+};
+
+/// Receives the events of a JSON-like value, in document order.
+///
+/// A tokenizer for another format (CBOR, Avro, a custom binary log, ...)
+/// can be written against this trait, so that it feeds colchis through a
+/// [`DocumentSink`] without going through JSON text. Every `start_object`
+/// is matched by an `end_object`, and every `start_array` by an
+/// `end_array`; within an object, each `key` is followed by its value.
+pub trait EventSink {
+    type Error;
+
+    fn start_object(&mut self) -> Result<(), Self::Error>;
+    fn key(&mut self, name: &str) -> Result<(), Self::Error>;
+    fn end_object(&mut self) -> Result<(), Self::Error>;
+    fn start_array(&mut self) -> Result<(), Self::Error>;
+    fn end_array(&mut self) -> Result<(), Self::Error>;
+    fn string(&mut self, s: &str) -> Result<(), Self::Error>;
+    fn number(&mut self, n: f64) -> Result<(), Self::Error>;
+    fn integer(&mut self, i: i64) -> Result<(), Self::Error>;
+    fn boolean(&mut self, b: bool) -> Result<(), Self::Error>;
+    fn null(&mut self) -> Result<(), Self::Error>;
+}
+
+/// An [`EventSink`] that builds a document from the events.
+///
+/// Events that don't fit in the document so far, such as an `end_array`
+/// that closes an object, are rejected with a [`BuildError`], as with
+/// [`DocumentBuilder`].
+pub struct DocumentSink<B: UsageBuilder> {
+    builder: DocumentBuilder<B>,
+}
+
+impl<B: UsageBuilder> Default for DocumentSink<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: UsageBuilder> DocumentSink<B> {
+    pub fn new() -> Self {
+        Self::with_builder(Builder::new())
+    }
+
+    /// Build into a [`Builder`] that was configured. Nothing should have
+    /// been added to it.
+    pub fn with_builder(builder: Builder<B>) -> Self {
+        Self {
+            builder: DocumentBuilder::with_builder(builder),
+        }
+    }
+
+    /// Turn the events received into a document, once the root value is
+    /// complete.
+    pub fn finish(self) -> Result<Document<B::Index>, BuildError> {
+        self.builder.finish()
+    }
+}
+
+impl<B: UsageBuilder> EventSink for DocumentSink<B> {
+    type Error = BuildError;
+
+    fn start_object(&mut self) -> Result<(), BuildError> {
+        self.builder.begin_object()
+    }
+
+    fn key(&mut self, name: &str) -> Result<(), BuildError> {
+        self.builder.field(name)
+    }
+
+    fn end_object(&mut self) -> Result<(), BuildError> {
+        self.builder.end_of(NodeType::Object)
+    }
+
+    fn start_array(&mut self) -> Result<(), BuildError> {
+        self.builder.begin_array()
+    }
+
+    fn end_array(&mut self) -> Result<(), BuildError> {
+        self.builder.end_of(NodeType::Array)
+    }
+
+    fn string(&mut self, s: &str) -> Result<(), BuildError> {
+        self.builder.string(s)
+    }
+
+    fn number(&mut self, n: f64) -> Result<(), BuildError> {
+        self.builder.number(n)
+    }
+
+    fn integer(&mut self, i: i64) -> Result<(), BuildError> {
+        self.builder.integer(i)
+    }
+
+    fn boolean(&mut self, b: bool) -> Result<(), BuildError> {
+        self.builder.boolean(b)
+    }
+
+    fn null(&mut self) -> Result<(), BuildError> {
+        self.builder.null()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usage::BitpackingUsageBuilder;
+
+    // a tiny tokenizer for a made-up line format, written against the trait
+    fn feed<S: EventSink>(input: &str, sink: &mut S) -> Result<(), S::Error> {
+        for token in input.split_whitespace() {
+            match token {
+                "{" => sink.start_object()?,
+                "}" => sink.end_object()?,
+                "[" => sink.start_array()?,
+                "]" => sink.end_array()?,
+                "true" | "false" => sink.boolean(token == "true")?,
+                "null" => sink.null()?,
+                _ => match (token.strip_suffix(':'), token.parse(), token.parse()) {
+                    (Some(key), _, _) => sink.key(key)?,
+                    (None, Ok(i), _) => sink.integer(i)?,
+                    (None, Err(_), Ok(n)) => sink.number(n)?,
+                    (None, Err(_), Err(_)) => sink.string(token)?,
+                },
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_document_sink() {
+        let mut sink = DocumentSink::<BitpackingUsageBuilder>::new();
+        feed("{ a: [ 1 two true 0.5 ] b: { } c: null }", &mut sink).unwrap();
+        let doc = sink.finish().unwrap();
+        assert_eq!(doc.verify(), Ok(()));
+        let expected = BitpackingUsageBuilder::parse(
+            r#"{"a": [1, "two", true, 0.5], "b": {}, "c": null}"#.as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            doc.root_value().to_owned_value(),
+            expected.root_value().to_owned_value()
+        );
+    }
+
+    #[test]
+    fn test_document_sink_mismatched_end() {
+        let mut sink = DocumentSink::<BitpackingUsageBuilder>::new();
+        assert_eq!(feed("[ 1 }", &mut sink), Err(BuildError::UnexpectedEnd));
+        assert_eq!(feed("]", &mut sink), Ok(()));
+        let mut sink = DocumentSink::<BitpackingUsageBuilder>::new();
+        assert_eq!(feed("{ a: ]", &mut sink), Err(BuildError::UnexpectedEnd));
+        assert_eq!(sink.finish().err(), Some(BuildError::Incomplete));
+    }
+}
@@ -0,0 +1,92 @@
+//! The byte ranges of the nodes of a document in the input it was parsed
+//! from.
+//!
+//! Every parenthesis of the tree has an offset in the input: an opening
+//! parenthesis the offset where its node starts, a closing one the offset
+//! where it ends. These offsets never decrease in document order, so they
+//! are stored as one Elias-Fano encoded sequence parallel to the
+//! parentheses.
+
+use std::{
+    io::{self, Write},
+    ops::Range,
+};
+
+use vers_vecs::EliasFanoVec;
+
+use crate::persist::{self, LoadError};
+
+#[derive(Debug)]
+pub(crate) struct SourceSpans {
+    offsets: EliasFanoVec,
+}
+
+impl SourceSpans {
+    pub(crate) fn new(offsets: &[u64]) -> Self {
+        Self {
+            offsets: EliasFanoVec::from_slice(offsets),
+        }
+    }
+
+    /// The number of recorded offsets, one for every parenthesis.
+    pub(crate) fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// The span of the node with the opening parenthesis at `open` and the
+    /// closing one at `close`, or `None` if it was not recorded.
+    pub(crate) fn span(&self, open: usize, close: usize) -> Option<Range<u64>> {
+        let start = self.offsets.get(open)?;
+        let end = self.offsets.get(close)?;
+        // every value takes at least one byte, so an empty span is unknown
+        (start < end).then_some(start..end)
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        self.offsets.heap_size()
+    }
+
+    pub(crate) fn write<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        persist::write_len(w, self.offsets.len())?;
+        let mut previous = 0;
+        for i in 0..self.offsets.len() {
+            let offset = self.offsets.get_unchecked(i);
+            persist::write_varint(w, offset - previous)?;
+            previous = offset;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn read(r: &mut &[u8]) -> Result<Self, LoadError> {
+        let len = persist::read_len(r)?;
+        let mut offsets = persist::vec_for(len);
+        let mut previous = 0u64;
+        for _ in 0..len {
+            let offset = previous
+                .checked_add(persist::read_varint(r)?)
+                .ok_or(LoadError::Corrupt("source offset out of range"))?;
+            offsets.push(offset);
+            previous = offset;
+        }
+        Ok(Self::new(&offsets))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_read() {
+        let spans = SourceSpans::new(&[0, 1, 1, 4, 4, 9, 12]);
+        assert_eq!(spans.span(0, 6), Some(0..12));
+        assert_eq!(spans.span(1, 2), None);
+        assert_eq!(spans.span(3, 5), Some(4..9));
+        assert_eq!(spans.span(3, 7), None);
+        let mut out = Vec::new();
+        spans.write(&mut out).unwrap();
+        let read = SourceSpans::read(&mut out.as_slice()).unwrap();
+        assert_eq!(read.len(), 7);
+        assert_eq!(read.span(3, 5), Some(4..9));
+    }
+}
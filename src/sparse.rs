@@ -0,0 +1,56 @@
+//! Queries on [`SparseRSVec`] that work around its defects.
+
+use vers_vecs::SparseRSVec;
+
+/// The number of set bits before position `i`, like
+/// [`SparseRSVec::rank1`].
+///
+/// The rank of vers-vecs 1.6.3 is off for some positions after more than a
+/// few set bits that share their upper bits, which is common in dense
+/// vectors. Its select is correct, so we check the rank against it and
+/// search with select where the rank is off.
+pub(crate) fn rank1(vec: &SparseRSVec, i: u64) -> u64 {
+    let rank = vec.rank1(i);
+    let before = rank == 0 || vec.select1(rank as usize - 1) < i;
+    if before && vec.select1(rank as usize) >= i {
+        return rank;
+    }
+    // past the largest set bit the rank is always right
+    let (mut low, mut high) = (0, vec.rank1(u64::MAX));
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if vec.select1(middle as usize) < i {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    low
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rank1() {
+        let positions = [3, 18, 36, 41, 43, 45, 49, 69];
+        let vec = SparseRSVec::new(&positions, 72);
+        for i in 0..=80 {
+            let expected = positions.iter().filter(|&&position| position < i).count();
+            assert_eq!(rank1(&vec, i), expected as u64, "{i}");
+        }
+        let empty = SparseRSVec::new(&[], 10);
+        assert_eq!(rank1(&empty, 5), 0);
+    }
+
+    #[test]
+    fn test_rank1_miscounted() {
+        // the rank of vers-vecs 1.6.3 counts 8 set bits before 46 to 49
+        // here rather than 6
+        let vec = SparseRSVec::new(&[3, 18, 36, 41, 43, 45, 49, 69], 72);
+        for i in 46..=49 {
+            assert_eq!(rank1(&vec, i), 6, "{i}");
+        }
+    }
+}
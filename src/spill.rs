@@ -0,0 +1,82 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    sync::{Arc, Mutex},
+};
+
+/// An append-only temporary file for finished, compressed data that would
+/// otherwise stay in memory while building a document.
+///
+/// Clones share the same file, which is deleted once the last clone is
+/// dropped.
+#[derive(Debug, Clone)]
+pub struct SpillFile {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    writer: BufWriter<File>,
+    len: u64,
+}
+
+impl SpillFile {
+    pub(crate) fn new() -> io::Result<Self> {
+        Ok(Self {
+            inner: Arc::new(Mutex::new(Inner {
+                writer: BufWriter::new(tempfile::tempfile()?),
+                len: 0,
+            })),
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        // the file is only appended to, so a panic while holding the lock
+        // can at worst leave unreferenced bytes behind
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Append the data, returning the offset it was written at.
+    pub(crate) fn append(&self, data: &[u8]) -> io::Result<u64> {
+        let mut inner = self.lock();
+        let offset = inner.len;
+        inner.writer.write_all(data)?;
+        inner.len += data.len() as u64;
+        Ok(offset)
+    }
+
+    /// Read back data written at the offset.
+    pub(crate) fn read(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let mut inner = self.lock();
+        let len = inner.len;
+        inner.writer.flush()?;
+        let file = inner.writer.get_mut();
+        file.seek(SeekFrom::Start(offset))?;
+        let result = file.read_exact(buf);
+        // appends continue at the end
+        file.seek(SeekFrom::Start(len))?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_read() {
+        let spill = SpillFile::new().unwrap();
+        let a = spill.append(b"hello").unwrap();
+        let b = spill.append(b"world").unwrap();
+        let mut buf = [0; 5];
+        spill.read(b, &mut buf).unwrap();
+        assert_eq!(&buf, b"world");
+        // appending after a read continues at the end
+        let c = spill.clone().append(b"!").unwrap();
+        assert_eq!(c, 10);
+        spill.read(a, &mut buf).unwrap();
+        assert_eq!(&buf, b"hello");
+    }
+}
@@ -1,8 +1,13 @@
-use vers_vecs::BpTree;
+use std::io::{self, Read, Write};
+
+use ahash::HashMap;
+use vers_vecs::{BitVec, BpTree};
 
 use crate::{
     info::{NodeInfo, NodeInfoId},
-    tree_builder::TreeBuilder,
+    memory_report::MemoryReport,
+    persist::{self, LoadError},
+    tree_builder::{self, TreeBuilder},
     usage::{UsageBuilder, UsageIndex},
 };
 
@@ -10,18 +15,95 @@ use crate::{
 pub(crate) struct Structure<T: UsageIndex> {
     usage_index: T,
     tree: BpTree,
+    len: usize,
+    child_skips: HashMap<usize, Box<[usize]>>,
+    child_skips_size: usize,
 }
 
 impl<U: UsageIndex> Structure<U> {
     pub(crate) fn new<B: UsageBuilder<Index = U>>(tree_builder: TreeBuilder<B>) -> Self {
+        let len = tree_builder.parentheses.len();
+        let child_skips_size = tree_builder.child_skips_size();
+        let child_skips = tree_builder.child_skips;
         let tree = BpTree::from_bit_vector(tree_builder.parentheses);
         let usage_index = tree_builder.usage_builder.build();
 
-        Self { usage_index, tree }
+        Self {
+            usage_index,
+            tree,
+            len,
+            child_skips,
+            child_skips_size,
+        }
     }
 
     pub(crate) fn heap_size(&self) -> usize {
-        self.tree.heap_size() + self.usage_index.heap_size()
+        self.tree.heap_size() + self.usage_index.heap_size() + self.child_skips_size
+    }
+
+    /// The heap of the structure, with the other parts left at 0.
+    pub(crate) fn memory_report(&self) -> MemoryReport {
+        MemoryReport {
+            tree: self.tree.heap_size(),
+            usage_index: self.usage_index.heap_size(),
+            child_skips: self.child_skips_size,
+            ..MemoryReport::default()
+        }
+    }
+
+    pub(crate) fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        // the tree doesn't expose its bits, but they are set exactly at the
+        // opening parentheses
+        let mut parentheses = BitVec::from_zeros(self.len);
+        for open in self.tree.dfs_iter() {
+            parentheses.flip_bit(open);
+        }
+        persist::write_bit_vec(w, &parentheses)?;
+        self.usage_index.write(w)?;
+
+        let mut child_skips = self.child_skips.iter().collect::<Vec<_>>();
+        child_skips.sort_unstable_by_key(|&(&position, _)| position);
+        persist::write_len(w, child_skips.len())?;
+        for (&position, skips) in child_skips {
+            persist::write_len(w, position)?;
+            let skips = skips.iter().map(|&skip| skip as u64).collect::<Vec<_>>();
+            persist::write_positions(w, &skips)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn read<R: Read>(r: &mut R) -> Result<Self, LoadError> {
+        let parentheses = persist::read_bit_vec(r)?;
+        let len = parentheses.len();
+        let usage_index = U::read(r)?;
+        if usage_index.len() != len {
+            return Err(LoadError::Corrupt("usage index length mismatch"));
+        }
+
+        let count = persist::read_len(r)?;
+        let mut child_skips = HashMap::default();
+        let mut child_skips_size = 0;
+        for _ in 0..count {
+            let position = persist::read_len(r)?;
+            let skips = persist::read_positions(r, len as u64)?
+                .into_iter()
+                .map(|skip| skip as usize)
+                .collect::<Box<[usize]>>();
+            child_skips_size += skips.len() * std::mem::size_of::<usize>();
+            child_skips.insert(position, skips);
+        }
+        // wrong skips would find the wrong children rather than fail
+        if child_skips != tree_builder::child_skips_of(&parentheses) {
+            return Err(LoadError::Corrupt("child skips do not match the tree"));
+        }
+
+        Ok(Self {
+            usage_index,
+            tree: BpTree::from_bit_vector(parentheses),
+            len,
+            child_skips,
+            child_skips_size,
+        })
     }
 
     pub(crate) fn lookup_node_info(&self, node_info_id: NodeInfoId) -> &NodeInfo {
@@ -39,10 +121,46 @@ impl<U: UsageIndex> Structure<U> {
             .expect("Node information does not exist")
     }
 
+    /// The node info at a position, if the position is in the structure
+    /// and its node info id is in the lookup.
+    pub(crate) fn try_node_info(&self, i: usize) -> Option<&NodeInfo> {
+        let id = self.usage_index.node_info_id(i)?;
+        self.usage_index.node_lookup().get(id)
+    }
+
     pub(crate) fn tree(&self) -> &BpTree {
         &self.tree
     }
 
+    /// The length of the parentheses sequence.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether position i holds an opening parenthesis.
+    pub(crate) fn is_open(&self, i: usize) -> bool {
+        if i >= self.len {
+            return false;
+        }
+        let before = if i == 0 { 0 } else { self.tree.excess(i - 1) };
+        self.tree.excess(i) > before
+    }
+
+    /// The positions of every `CHILD_SKIP_INTERVAL`-th child of the node at
+    /// position `i`, starting with the child at that index. Nodes with
+    /// fewer children have none.
+    pub(crate) fn child_skips(&self, i: usize) -> &[usize] {
+        self.child_skips.get(&i).map_or(&[], |skips| skips)
+    }
+
+    pub(crate) fn usage_index(&self) -> &U {
+        &self.usage_index
+    }
+
+    pub(crate) fn usage_index_mut(&mut self) -> &mut U {
+        &mut self.usage_index
+    }
+
     pub(crate) fn text_id(&self, i: usize) -> Option<usize> {
         self.usage_index.text_id(i)
     }
@@ -84,4 +202,70 @@ mod tests {
         assert_eq!(structure.node_info(0), &NodeInfo::open(NodeType::Array));
         assert_eq!(structure.node_info(1), &NodeInfo::open(NodeType::String));
     }
+
+    #[test]
+    fn test_write_read() {
+        let mut builder = TreeBuilder::<RoaringUsageBuilder>::new();
+
+        // {"a": ["b", 1, true], "c": "d"}
+        builder.open(NodeType::Object);
+        let close_a = builder.open_field("a");
+        builder.open(NodeType::Array);
+        for node_type in [NodeType::String, NodeType::Number, NodeType::Boolean] {
+            builder.open(node_type.clone());
+            builder.close(node_type);
+        }
+        builder.close(NodeType::Array);
+        builder.close_field(close_a);
+        let close_c = builder.open_field("c");
+        builder.open(NodeType::String);
+        builder.close(NodeType::String);
+        builder.close_field(close_c);
+        builder.close(NodeType::Object);
+
+        let structure = Structure::<EliasFanoUsageIndex>::new(builder);
+        let mut out = Vec::new();
+        structure.write(&mut out).unwrap();
+        let loaded = Structure::<EliasFanoUsageIndex>::read(&mut out.as_slice()).unwrap();
+
+        assert_eq!(loaded.len(), structure.len());
+        for i in 0..structure.len() {
+            assert_eq!(loaded.is_open(i), structure.is_open(i));
+            assert_eq!(loaded.node_info(i), structure.node_info(i));
+            assert_eq!(loaded.text_id(i), structure.text_id(i));
+            assert_eq!(loaded.number_id(i), structure.number_id(i));
+            assert_eq!(loaded.boolean_id(i), structure.boolean_id(i));
+        }
+        assert!(Structure::<EliasFanoUsageIndex>::read(&mut &out[..out.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn test_read_checks_child_skips() {
+        let mut builder = TreeBuilder::<RoaringUsageBuilder>::new();
+        builder.open(NodeType::Array);
+        for _ in 0..200 {
+            builder.open(NodeType::Null);
+            builder.close(NodeType::Null);
+        }
+        builder.close(NodeType::Array);
+        let mut structure = Structure::<EliasFanoUsageIndex>::new(builder);
+        let mut out = Vec::new();
+        structure.write(&mut out).unwrap();
+        let loaded = Structure::<EliasFanoUsageIndex>::read(&mut out.as_slice()).unwrap();
+        assert_eq!(loaded.child_skips(0), structure.child_skips(0));
+
+        // skips to the wrong child, and skips of a node with few children
+        for position in [0, 1] {
+            let mut skips = structure.child_skips.clone();
+            skips.insert(position, vec![3].into_boxed_slice());
+            std::mem::swap(&mut structure.child_skips, &mut skips);
+            let mut out = Vec::new();
+            structure.write(&mut out).unwrap();
+            std::mem::swap(&mut structure.child_skips, &mut skips);
+            assert!(matches!(
+                Structure::<EliasFanoUsageIndex>::read(&mut out.as_slice()),
+                Err(LoadError::Corrupt(_))
+            ));
+        }
+    }
 }
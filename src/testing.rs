@@ -0,0 +1,218 @@
+//! Generators of random JSON values and helpers for property tests.
+//!
+//! This is only available with the `testing` feature. The generators
+//! produce [`OwnedValue`]s; [`json_value`] is a proptest strategy, and
+//! [`OwnedValue`] implements [`quickcheck::Arbitrary`]. Both shrink towards
+//! smaller values.
+
+use std::sync::Arc;
+
+use proptest::prelude::*;
+use quickcheck::{Arbitrary, Gen};
+
+use crate::{
+    builder::Builder,
+    document::{Document, OwnedValue},
+    usage::{BitpackingUsageBuilder, UsageBuilder},
+};
+
+// limits on the size of generated values, to keep tests fast
+const MAX_DEPTH: u32 = 4;
+const MAX_NODES: u32 = 64;
+const MAX_ITEMS: usize = 8;
+
+/// A proptest strategy for numbers that survive a JSON round trip.
+pub fn json_number() -> impl Strategy<Value = f64> {
+    prop_oneof![
+        any::<i32>().prop_map(f64::from),
+        any::<f64>().prop_filter("JSON numbers are finite", |n| n.is_finite()),
+    ]
+}
+
+/// A proptest strategy for scalar JSON values.
+pub fn json_scalar() -> impl Strategy<Value = OwnedValue> {
+    prop_oneof![
+        Just(OwnedValue::Null),
+        any::<bool>().prop_map(OwnedValue::Boolean),
+        json_number().prop_map(OwnedValue::Number),
+        any::<i64>().prop_map(OwnedValue::Integer),
+        any::<String>().prop_map(|s| OwnedValue::String(s.into())),
+    ]
+}
+
+/// A proptest strategy for arbitrary JSON values, nested up to a few
+/// levels deep.
+pub fn json_value() -> impl Strategy<Value = OwnedValue> {
+    json_scalar().prop_recursive(MAX_DEPTH, MAX_NODES, MAX_ITEMS as u32, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..MAX_ITEMS).prop_map(OwnedValue::Array),
+            prop::collection::vec((any::<String>(), inner), 0..MAX_ITEMS).prop_map(|entries| {
+                OwnedValue::Object(
+                    entries
+                        .into_iter()
+                        .map(|(key, value)| (Arc::from(key), value))
+                        .collect(),
+                )
+            }),
+        ]
+    })
+}
+
+/// A proptest strategy for JSON texts.
+pub fn json_text() -> impl Strategy<Value = String> {
+    json_value().prop_map(|value| to_json(&value))
+}
+
+/// Build a document holding the value.
+pub fn document(value: &OwnedValue) -> Document<<BitpackingUsageBuilder as UsageBuilder>::Index> {
+    let mut builder = Builder::<BitpackingUsageBuilder>::new();
+    builder.owned_value(value);
+    builder.build()
+}
+
+/// Serialize the value as JSON text.
+pub fn to_json(value: &OwnedValue) -> String {
+    let mut out = Vec::new();
+    document(value)
+        .serialize(&mut out)
+        .expect("Writing to memory should not fail");
+    String::from_utf8(out).expect("Serialized JSON should be UTF-8")
+}
+
+/// Assert that parsing the JSON text, serializing it and parsing the result
+/// again gives an equivalent document.
+pub fn assert_parse_serialize_parse(json: &str) {
+    let doc = BitpackingUsageBuilder::parse(json.as_bytes()).expect("JSON should parse");
+    let mut out = Vec::new();
+    doc.serialize(&mut out)
+        .expect("Writing to memory should not fail");
+    let reparsed = BitpackingUsageBuilder::parse(out.as_slice()).expect("Output should parse");
+    assert_eq!(
+        doc.root_value().to_owned_value(),
+        reparsed.root_value().to_owned_value()
+    );
+}
+
+/// Assert that a document built from the value holds that value, and that
+/// it survives serializing and parsing.
+pub fn assert_round_trip(value: &OwnedValue) {
+    let doc = document(value);
+    assert_eq!(&doc.root_value().to_owned_value(), value);
+    assert_eq!(doc.verify(), Ok(()));
+    let json = to_json(value);
+    let reparsed = BitpackingUsageBuilder::parse(json.as_bytes()).expect("Output should parse");
+    assert_eq!(&reparsed.root_value().to_owned_value(), value);
+}
+
+fn arbitrary_number(g: &mut Gen) -> f64 {
+    if bool::arbitrary(g) {
+        f64::from(i32::arbitrary(g))
+    } else {
+        let n = f64::arbitrary(g);
+        if n.is_finite() { n } else { 0.0 }
+    }
+}
+
+fn arbitrary_value(g: &mut Gen, depth: u32) -> OwnedValue {
+    let choices = if depth == 0 { 5 } else { 7 };
+    match u32::arbitrary(g) % choices {
+        0 => OwnedValue::Null,
+        1 => OwnedValue::Boolean(bool::arbitrary(g)),
+        2 => OwnedValue::Number(arbitrary_number(g)),
+        3 => OwnedValue::Integer(i64::arbitrary(g)),
+        4 => OwnedValue::String(String::arbitrary(g).into()),
+        5 => {
+            let len = usize::arbitrary(g) % MAX_ITEMS;
+            OwnedValue::Array((0..len).map(|_| arbitrary_value(g, depth - 1)).collect())
+        }
+        _ => {
+            let len = usize::arbitrary(g) % MAX_ITEMS;
+            OwnedValue::Object(
+                (0..len)
+                    .map(|_| {
+                        (
+                            Arc::from(String::arbitrary(g)),
+                            arbitrary_value(g, depth - 1),
+                        )
+                    })
+                    .collect(),
+            )
+        }
+    }
+}
+
+impl Arbitrary for OwnedValue {
+    fn arbitrary(g: &mut Gen) -> Self {
+        arbitrary_value(g, MAX_DEPTH)
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        match self {
+            OwnedValue::Null => quickcheck::empty_shrinker(),
+            OwnedValue::Boolean(b) => Box::new(b.shrink().map(OwnedValue::Boolean)),
+            OwnedValue::Number(n) => {
+                Box::new(n.shrink().filter(|n| n.is_finite()).map(OwnedValue::Number))
+            }
+            OwnedValue::Integer(i) => Box::new(i.shrink().map(OwnedValue::Integer)),
+            OwnedValue::String(s) => {
+                Box::new(s.to_string().shrink().map(|s| OwnedValue::String(s.into())))
+            }
+            OwnedValue::Array(items) => {
+                // first try the items themselves, then smaller arrays
+                let children = items.clone().into_iter();
+                let smaller = items.shrink().map(OwnedValue::Array);
+                Box::new(children.chain(smaller))
+            }
+            OwnedValue::Object(map) => {
+                let children = map.values().cloned().collect::<Vec<_>>().into_iter();
+                let entries = map
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.clone()))
+                    .collect::<Vec<_>>();
+                let smaller = entries.shrink().map(|entries| {
+                    OwnedValue::Object(
+                        entries
+                            .into_iter()
+                            .map(|(key, value)| (Arc::from(key), value))
+                            .collect(),
+                    )
+                });
+                Box::new(children.chain(smaller))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn test_proptest_round_trip(value in json_value()) {
+            assert_round_trip(&value);
+        }
+
+        #[test]
+        fn test_proptest_parse_serialize_parse(json in json_text()) {
+            assert_parse_serialize_parse(&json);
+        }
+    }
+
+    #[test]
+    fn test_quickcheck_round_trip() {
+        fn round_trip(value: OwnedValue) -> bool {
+            assert_round_trip(&value);
+            true
+        }
+        quickcheck::quickcheck(round_trip as fn(OwnedValue) -> bool);
+    }
+
+    #[test]
+    fn test_shrink_array() {
+        let value = OwnedValue::Array(vec![OwnedValue::Null, OwnedValue::Boolean(true)]);
+        let shrunk = value.shrink().collect::<Vec<_>>();
+        assert_eq!(shrunk[0], OwnedValue::Null);
+        assert!(shrunk.contains(&OwnedValue::Array(vec![])));
+    }
+}
@@ -1,14 +1,27 @@
-use std::cell::RefCell;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::io::{Read, Write};
+use std::marker::PhantomData;
 use std::num::NonZeroUsize;
-use std::sync::Arc;
+use std::ops::Deref;
+use std::sync::mpsc::{Receiver, SyncSender};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread::JoinHandle;
 
-use flate2::Compression;
-use flate2::read::DeflateDecoder;
-use flate2::write::DeflateEncoder;
+use flate2::{Compress, Compression, Crc, Decompress, FlushCompress, FlushDecompress, Status};
 use lru::LruCache;
 use vers_vecs::SparseRSVec;
 
+use crate::{
+    error::Error,
+    persist::{self, LoadError},
+    sparse,
+    spill::SpillFile,
+};
+
 /// Unique identifier for stored text
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct TextId(usize);
@@ -33,73 +46,525 @@ impl BlockId {
     }
 }
 
+/// How decompressed text is checked before it is handed out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextValidation {
+    /// Trust the stored text. This is sound for text that was stored by
+    /// [`TextUsageBuilder`] in this process, as it only accepts `&str`, so
+    /// it only applies to such text: text that was read back from a save
+    /// is always checked.
+    #[default]
+    Unchecked,
+    /// Verify the checksum and the UTF-8 of every block when it is
+    /// decompressed, panicking if it is corrupt instead of handing out
+    /// invalid strings. Use this for text from untrusted sources.
+    Checked,
+}
+
+/// A text block that does not hold what it should.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextBlockError {
+    /// The compressed data could not be decompressed.
+    Decompression { block: usize },
+    /// The decompressed data does not have the recorded size.
+    SizeMismatch {
+        block: usize,
+        expected: usize,
+        actual: usize,
+    },
+    /// The decompressed data does not match the recorded checksum.
+    Checksum { block: usize },
+    /// A text in the block is not valid UTF-8.
+    InvalidUtf8 { block: usize },
+}
+
+impl fmt::Display for TextBlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextBlockError::Decompression { block } => {
+                write!(f, "text block {block} could not be decompressed")
+            }
+            TextBlockError::SizeMismatch {
+                block,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "text block {block} decompressed to {actual} bytes instead of {expected}"
+            ),
+            TextBlockError::Checksum { block } => {
+                write!(f, "text block {block} does not match its checksum")
+            }
+            TextBlockError::InvalidUtf8 { block } => {
+                write!(f, "text block {block} holds invalid UTF-8")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TextBlockError {}
+
+fn checksum(data: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(data);
+    crc.sum()
+}
+
+// deflate refers back at most 32 KiB, so a longer dictionary is of no use
+const MAX_DICTIONARY_SIZE: usize = 32 * 1024;
+
+/// Raw deflate, starting from a preset dictionary if there is one.
+fn deflate(data: &[u8], compression: Compression, dictionary: Option<&[u8]>) -> Vec<u8> {
+    let mut compress = Compress::new(compression, false);
+    if let Some(dictionary) = dictionary {
+        compress
+            .set_dictionary(dictionary)
+            .expect("Dictionary should be set before compressing");
+    }
+    let mut compressed = Vec::with_capacity(data.len() / 2 + 64);
+    loop {
+        let consumed = compress.total_in() as usize;
+        let status = compress
+            .compress_vec(&data[consumed..], &mut compressed, FlushCompress::Finish)
+            .expect("Memory compression should not fail");
+        if status == Status::StreamEnd {
+            return compressed;
+        }
+        compressed.reserve(compressed.capacity().max(64));
+    }
+}
+
+/// Inflate what [`deflate`] compressed with the same dictionary.
+fn inflate(compressed: &[u8], size: usize, dictionary: Option<&[u8]>) -> std::io::Result<Vec<u8>> {
+    let mut decompress = Decompress::new(false);
+    if let Some(dictionary) = dictionary {
+        decompress
+            .set_dictionary(dictionary)
+            .map_err(std::io::Error::other)?;
+    }
+    let mut data = Vec::with_capacity(size);
+    loop {
+        let before = (decompress.total_in(), data.len());
+        let consumed = decompress.total_in() as usize;
+        let status = decompress
+            .decompress_vec(&compressed[consumed..], &mut data, FlushDecompress::Finish)
+            .map_err(std::io::Error::other)?;
+        if status == Status::StreamEnd {
+            return Ok(data);
+        }
+        if data.len() == data.capacity() {
+            data.reserve(data.capacity().max(64));
+        } else if (decompress.total_in(), data.len()) == before {
+            // the compressed data ends before the stream does
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+    }
+}
+
+// a block that waits for the dictionary before it is compressed
+#[derive(Debug)]
+struct PendingBlock {
+    start_text_id: TextId,
+    starts: Vec<u64>,
+    data: Vec<u8>,
+}
+
+impl PendingBlock {
+    // the texts of the block, with their terminators
+    fn texts(&self) -> impl Iterator<Item = &[u8]> {
+        let ends = self.starts.iter().skip(1).map(|&start| start as usize);
+        self.starts
+            .iter()
+            .zip(ends.chain([self.data.len()]))
+            .map(|(&start, end)| &self.data[start as usize..end])
+    }
+}
+
+/// A dictionary of the texts that occur most often in the blocks. The most
+/// frequent texts go at the end, where deflate reaches them at the
+/// shortest distance.
+fn train_dictionary(blocks: &[PendingBlock]) -> Option<Arc<[u8]>> {
+    // the count and first occurrence of every text
+    let mut counts: HashMap<&[u8], (usize, usize)> = HashMap::new();
+    for text in blocks.iter().flat_map(PendingBlock::texts) {
+        let seen = counts.len();
+        counts.entry(text).or_insert((0, seen)).0 += 1;
+    }
+    let mut texts = counts.into_iter().collect::<Vec<_>>();
+    texts.sort_by_key(|&(_, (count, first))| (Reverse(count), first));
+    let mut size = 0;
+    let mut selected = Vec::new();
+    for (text, _) in texts {
+        if size + text.len() <= MAX_DICTIONARY_SIZE {
+            size += text.len();
+            selected.push(text);
+        }
+    }
+    let dictionary = selected
+        .into_iter()
+        .rev()
+        .flatten()
+        .copied()
+        .collect::<Vec<_>>();
+    (!dictionary.is_empty()).then(|| dictionary.into())
+}
+
+/// Whether and how text blocks share a compression dictionary.
+#[derive(Debug)]
+enum Dictionary {
+    None,
+    // the first blocks wait until there are enough of them to train on
+    Training {
+        blocks: usize,
+        pending: Vec<PendingBlock>,
+    },
+    Trained(Option<Arc<[u8]>>),
+}
+
+impl Dictionary {
+    fn trained(&self) -> Option<&Arc<[u8]>> {
+        match self {
+            Dictionary::Trained(dictionary) => dictionary.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// Where the compressed data of a block is kept.
+#[derive(Debug)]
+enum CompressedData {
+    Memory(Vec<u8>),
+    Spilled {
+        spill: SpillFile,
+        offset: u64,
+        len: usize,
+    },
+}
+
+impl CompressedData {
+    fn len(&self) -> usize {
+        match self {
+            CompressedData::Memory(data) => data.len(),
+            CompressedData::Spilled { len, .. } => *len,
+        }
+    }
+
+    fn heap_size(&self) -> usize {
+        match self {
+            CompressedData::Memory(data) => data.len() * std::mem::size_of::<u8>(),
+            CompressedData::Spilled { .. } => 0,
+        }
+    }
+
+    fn bytes(&self) -> std::io::Result<Cow<'_, [u8]>> {
+        match self {
+            CompressedData::Memory(data) => Ok(Cow::Borrowed(data)),
+            CompressedData::Spilled { spill, offset, len } => {
+                let mut data = vec![0; *len];
+                spill.read(*offset, &mut data)?;
+                Ok(Cow::Owned(data))
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Block {
-    compressed_data: Vec<u8>,
+    compressed_data: CompressedData,
     original_size: usize,
+    // crc32 of the uncompressed data
+    checksum: u32,
     // the start text id for this block
     start_text_id: TextId,
     // the start points of text ids in this block
     starts: SparseRSVec,
+    // the dictionary the block was compressed with, shared between blocks
+    dictionary: Option<Arc<[u8]>>,
 }
 
 impl Block {
-    fn compress(start_text_id: TextId, starts: &[u64], data: &[u8]) -> Self {
-        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
-        encoder
-            .write_all(data)
-            .expect("Memory write should not result in IO error");
-        let compressed_data = encoder
-            .finish()
-            .expect("Memory write should not result in IO error");
+    fn compress(
+        start_text_id: TextId,
+        starts: &[u64],
+        data: &[u8],
+        compression: Compression,
+        dictionary: Option<&Arc<[u8]>>,
+        spill: Option<&SpillFile>,
+    ) -> Self {
+        let compressed = deflate(data, compression, dictionary.map(AsRef::as_ref));
+        // if the spill file can't be written we keep the block in memory
+        let compressed_data = match spill.map(|spill| (spill, spill.append(&compressed))) {
+            Some((spill, Ok(offset))) => CompressedData::Spilled {
+                spill: spill.clone(),
+                offset,
+                len: compressed.len(),
+            },
+            _ => CompressedData::Memory(compressed),
+        };
 
         let starts = SparseRSVec::new(starts, data.len() as u64);
         Block {
             compressed_data,
             original_size: data.len(),
+            checksum: checksum(data),
             start_text_id,
             starts,
+            dictionary: dictionary.cloned(),
         }
     }
 
-    fn decompress(&self) -> Vec<u8> {
-        let mut decoder = DeflateDecoder::new(self.compressed_data.as_slice());
-        let mut decompressed = Vec::with_capacity(self.original_size);
-        decoder.read_to_end(&mut decompressed).unwrap();
-        decompressed
+    fn decompress(&self) -> std::io::Result<Vec<u8>> {
+        let compressed_data = self.compressed_data.bytes()?;
+        inflate(
+            &compressed_data,
+            self.original_size,
+            self.dictionary.as_deref(),
+        )
+    }
+
+    fn decompress_checked(&self, block: usize) -> Result<Vec<u8>, TextBlockError> {
+        let data = self
+            .decompress()
+            .map_err(|_| TextBlockError::Decompression { block })?;
+        if data.len() != self.original_size {
+            return Err(TextBlockError::SizeMismatch {
+                block,
+                expected: self.original_size,
+                actual: data.len(),
+            });
+        }
+        if checksum(&data) != self.checksum {
+            return Err(TextBlockError::Checksum { block });
+        }
+        Ok(data)
     }
 
     fn heap_size(&self) -> usize {
-        self.compressed_data.len() * std::mem::size_of::<u8>() + self.starts.heap_size()
+        self.compressed_data.heap_size() + self.starts.heap_size()
+    }
+
+    // the dictionary is written by the text usage; `dictionary` is its
+    // index plus one, or 0 for none
+    fn write<W: Write + ?Sized>(&self, w: &mut W, dictionary: usize) -> std::io::Result<()> {
+        persist::write_len(w, dictionary)?;
+        persist::write_bytes(w, &self.compressed_data.bytes()?)?;
+        persist::write_len(w, self.original_size)?;
+        persist::write_u32(w, self.checksum)?;
+        persist::write_len(w, self.start_text_id.0)?;
+        persist::write_positions(w, &self.starts.iter1().collect::<Vec<_>>())
+    }
+
+    fn read<R: Read + ?Sized>(
+        r: &mut R,
+        start_text_id: TextId,
+        dictionaries: &[Arc<[u8]>],
+    ) -> Result<Self, LoadError> {
+        let dictionary = match persist::read_len(r)? {
+            0 => None,
+            index => Some(
+                dictionaries
+                    .get(index - 1)
+                    .ok_or(LoadError::Corrupt("unknown text dictionary"))?
+                    .clone(),
+            ),
+        };
+        let compressed_data = persist::read_bytes(r)?;
+        let original_size = persist::read_len(r)?;
+        let checksum = persist::read_u32(r)?;
+        if persist::read_len(r)? != start_text_id.0 {
+            return Err(LoadError::Corrupt(
+                "text block does not follow the previous",
+            ));
+        }
+        let starts = persist::read_positions(r, original_size as u64)?;
+        // every text ends with a terminator, so the first starts at 0
+        if starts.first() != Some(&0) {
+            return Err(LoadError::Corrupt("text block without texts"));
+        }
+        Ok(Block {
+            compressed_data: CompressedData::Memory(compressed_data),
+            original_size,
+            checksum,
+            start_text_id,
+            starts: SparseRSVec::new(&starts, original_size as u64),
+            dictionary,
+        })
+    }
+
+    fn text_count(&self) -> usize {
+        sparse::rank1(&self.starts, self.original_size as u64) as usize
     }
 
     fn uncompressed_size(&self) -> usize {
         self.original_size + self.starts.heap_size()
     }
 
-    fn block_slices(&self) -> Arc<[Arc<str>]> {
-        let block_data = self.decompress();
-        let starts: Vec<u64> = self.starts.iter1().collect();
-        // get the ranges using the starts (and the original size for the last range)
-        let mut r = Vec::with_capacity(starts.len());
-        // TODO: if we kept starts.len on the block, we could use a peeking
-        // iterator here meaning we don't need to materialize the starts
-        for (i, start) in starts.iter().enumerate() {
-            let start = *start as usize;
-            let next_start = if i < starts.len() - 1 {
-                starts[i + 1] as usize
-            } else {
-                self.original_size
-            };
-            // we subtract 1 here because the last byte of each string is
-            // a \0 terminator
-            let next_start = next_start - 1;
-            let s = unsafe { std::str::from_utf8_unchecked(&block_data[start..next_start]) };
-            // this is not zero-copy but we'll accept that
-            r.push(Arc::from(s))
+    fn decoded(
+        &self,
+        block: usize,
+        validation: TextValidation,
+    ) -> Result<DecodedBlock, TextBlockError> {
+        let data = match validation {
+            TextValidation::Unchecked => self
+                .decompress()
+                .map_err(|_| TextBlockError::Decompression { block })?,
+            TextValidation::Checked => self.decompress_checked(block)?,
+        };
+        let starts = self
+            .starts
+            .iter1()
+            .map(|start| start as usize)
+            .collect::<Vec<_>>();
+        let decoded = DecodedBlock { data, starts };
+        if validation == TextValidation::Checked {
+            for i in 0..decoded.starts.len() {
+                std::str::from_utf8(decoded.bytes(i))
+                    .map_err(|_| TextBlockError::InvalidUtf8 { block })?;
+            }
+        }
+        Ok(decoded)
+    }
+}
+
+/// The texts of a decompressed block, which are valid UTF-8: either they
+/// were checked, or they are trusted to come from a `&str`.
+#[derive(Debug)]
+struct DecodedBlock {
+    // every text is followed by a \0 terminator
+    data: Vec<u8>,
+    starts: Vec<usize>,
+}
+
+impl DecodedBlock {
+    fn bytes(&self, offset: usize) -> &[u8] {
+        let end = self
+            .starts
+            .get(offset + 1)
+            .copied()
+            .unwrap_or(self.data.len());
+        // we subtract 1 here because the last byte of each string is
+        // a \0 terminator
+        &self.data[self.starts[offset]..end - 1]
+    }
+
+    fn text(&self, offset: usize) -> &str {
+        // SAFETY: the texts of a decoded block are valid UTF-8
+        unsafe { std::str::from_utf8_unchecked(self.bytes(offset)) }
+    }
+}
+
+/// A string borrowed from a [`TextUsage`], which keeps its decompressed
+/// block alive so the string doesn't need to be copied.
+///
+/// Created by [`TextUsage::get_str`].
+pub struct TextGuard<'a> {
+    block: Arc<DecodedBlock>,
+    offset: usize,
+    _text_usage: PhantomData<&'a TextUsage>,
+}
+
+impl Deref for TextGuard<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.block.text(self.offset)
+    }
+}
+
+impl AsRef<str> for TextGuard<'_> {
+    fn as_ref(&self) -> &str {
+        self
+    }
+}
+
+impl fmt::Debug for TextGuard<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+// the bytes before a text that are inflated at a time to skip them
+const SKIP_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Reads a string from a [`TextUsage`] without decompressing all of it at
+/// once, for strings that are too large to comfortably hold in memory.
+///
+/// Created by [`TextUsage::text_reader`].
+pub struct TextReader<'a> {
+    source: ReaderSource<'a>,
+}
+
+enum ReaderSource<'a> {
+    // the block was decompressed already
+    Decoded {
+        text: TextGuard<'a>,
+        position: usize,
+    },
+    // the block is inflated as far as the text is read
+    Streaming {
+        decompress: Decompress,
+        compressed: Cow<'a, [u8]>,
+        // the bytes of the block before the text that are still to be skipped
+        skip: usize,
+        // the bytes of the text that are still to be read
+        remaining: usize,
+    },
+}
+
+impl Read for TextReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match &mut self.source {
+            ReaderSource::Decoded { text, position } => {
+                let bytes = &text.as_bytes()[*position..];
+                let len = bytes.len().min(buf.len());
+                buf[..len].copy_from_slice(&bytes[..len]);
+                *position += len;
+                Ok(len)
+            }
+            ReaderSource::Streaming {
+                decompress,
+                compressed,
+                skip,
+                remaining,
+            } => {
+                if *skip > 0 {
+                    let mut scratch = vec![0; SKIP_BUFFER_SIZE.min(*skip)];
+                    while *skip > 0 {
+                        let len = scratch.len().min(*skip);
+                        *skip -= inflate_some(decompress, compressed, &mut scratch[..len])?;
+                    }
+                }
+                let len = buf.len().min(*remaining);
+                if len == 0 {
+                    return Ok(0);
+                }
+                let read = inflate_some(decompress, compressed, &mut buf[..len])?;
+                *remaining -= read;
+                Ok(read)
+            }
+        }
+    }
+}
+
+/// Inflate at least one byte into `out`, and return how many.
+fn inflate_some(
+    decompress: &mut Decompress,
+    compressed: &[u8],
+    out: &mut [u8],
+) -> std::io::Result<usize> {
+    loop {
+        let before = (decompress.total_in(), decompress.total_out());
+        let consumed = decompress.total_in() as usize;
+        decompress
+            .decompress(&compressed[consumed..], out, FlushDecompress::None)
+            .map_err(std::io::Error::other)?;
+        let produced = (decompress.total_out() - before.1) as usize;
+        if produced > 0 {
+            return Ok(produced);
+        }
+        if decompress.total_in() == before.0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
         }
-        let slices: Arc<[Arc<str>]> = r.into();
-        slices
     }
 }
 
@@ -107,10 +572,95 @@ impl Block {
 pub struct TextUsageBuilder {
     block_size: usize,
     cache_capacity: usize,
+    compression: Compression,
     current_block_buffer: Vec<u8>,
     current_block_starts: Vec<u64>,
     blocks: Vec<Block>,
-    texts: Vec<BlockId>,
+    // the heap and uncompressed size of `blocks`, kept up to date as blocks
+    // are added so the memory checks during parsing don't walk them all
+    blocks_heap_size: usize,
+    blocks_uncompressed_size: usize,
+    // the number of texts in finalized blocks
+    text_count: usize,
+    hashes: Option<Vec<u64>>,
+    spill: Option<SpillFile>,
+    dictionary: Dictionary,
+    background: bool,
+    // started when the first block is compressed in the background
+    compressor: Option<Compressor>,
+}
+
+// the blocks waiting for the compressor, beyond the one it is compressing
+const COMPRESSION_QUEUE_BLOCKS: usize = 2;
+
+/// A block to compress on the compressor thread.
+struct CompressionJob {
+    block: PendingBlock,
+    compression: Compression,
+    dictionary: Option<Arc<[u8]>>,
+    spill: Option<SpillFile>,
+}
+
+/// Compresses blocks on a worker thread, one at a time in the order they
+/// were sent, so the finished blocks come back in text id order.
+struct Compressor {
+    jobs: SyncSender<CompressionJob>,
+    finished: Receiver<Block>,
+    worker: JoinHandle<()>,
+}
+
+impl Compressor {
+    fn new() -> Self {
+        let (jobs, job_receiver) = mpsc::sync_channel::<CompressionJob>(COMPRESSION_QUEUE_BLOCKS);
+        let (finished_sender, finished) = mpsc::channel();
+        let worker = std::thread::spawn(move || {
+            for job in job_receiver {
+                let block = Block::compress(
+                    job.block.start_text_id,
+                    &job.block.starts,
+                    &job.block.data,
+                    job.compression,
+                    job.dictionary.as_ref(),
+                    job.spill.as_ref(),
+                );
+                if finished_sender.send(block).is_err() {
+                    return;
+                }
+            }
+        });
+        Self {
+            jobs,
+            finished,
+            worker,
+        }
+    }
+
+    /// Queue a block, waiting if the queue is full. This fails if the
+    /// worker stopped, which it only does by panicking.
+    fn send(&self, job: CompressionJob) -> Result<(), mpsc::SendError<CompressionJob>> {
+        self.jobs.send(job)
+    }
+
+    /// The blocks compressed so far.
+    fn try_finished(&self) -> impl Iterator<Item = Block> + '_ {
+        self.finished.try_iter()
+    }
+
+    /// Wait for the queued blocks, and return them.
+    fn finish(self) -> Vec<Block> {
+        drop(self.jobs);
+        let blocks = self.finished.iter().collect();
+        if let Err(panic) = self.worker.join() {
+            std::panic::resume_unwind(panic);
+        }
+        blocks
+    }
+}
+
+pub(crate) fn text_hash(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
 }
 
 impl TextUsageBuilder {
@@ -118,39 +668,126 @@ impl TextUsageBuilder {
         Self {
             block_size,
             cache_capacity,
+            compression: Compression::default(),
             blocks: Vec::new(),
-            texts: Vec::new(),
+            blocks_heap_size: 0,
+            blocks_uncompressed_size: 0,
+            text_count: 0,
+            hashes: None,
+            spill: None,
             current_block_buffer: Vec::new(),
             current_block_starts: Vec::new(),
+            dictionary: Dictionary::None,
+            background: false,
+            compressor: None,
         }
     }
 
+    /// Set the size in bytes at which blocks are compressed. This should be
+    /// set before any text is added.
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size;
+        self
+    }
+
+    /// Set the number of decompressed blocks the text usage built keeps
+    /// cached.
+    pub fn with_cache_capacity(mut self, cache_capacity: usize) -> Self {
+        self.cache_capacity = cache_capacity;
+        self
+    }
+
+    /// Set the deflate compression level, from 0 (none) to 9 (best).
+    pub fn with_compression_level(mut self, level: u32) -> Self {
+        self.compression = Compression::new(level);
+        self
+    }
+
+    /// Also store a hash of every text, so that texts can be compared
+    /// without decompressing them.
+    pub fn with_hashes(mut self) -> Self {
+        self.hashes = Some(Vec::new());
+        self
+    }
+
+    /// Compress with a dictionary of the texts that occur most often in the
+    /// first `training_blocks` blocks, shared by all blocks. This improves
+    /// compression a lot for many short, similar texts in small blocks, as
+    /// each block otherwise starts compressing from scratch. The first
+    /// blocks are kept uncompressed until the dictionary is trained.
+    pub fn with_dictionary(mut self, training_blocks: usize) -> Self {
+        self.dictionary = Dictionary::Training {
+            blocks: training_blocks.max(1),
+            pending: Vec::new(),
+        };
+        self
+    }
+
+    /// Compress finished blocks on a worker thread, so that adding texts
+    /// continues while they are compressed. [`TextUsageBuilder::build`]
+    /// waits for the blocks that are still being compressed. The blocks
+    /// are the same as without it. On wasm32-unknown-unknown, which has no
+    /// threads, blocks are compressed in place as before.
+    pub fn with_background_compression(mut self) -> Self {
+        self.background = !cfg!(all(target_arch = "wasm32", target_os = "unknown"));
+        self
+    }
+
+    /// Write finished blocks to the spill file instead of keeping them in
+    /// memory. The text usage built reads them back from there.
+    pub(crate) fn with_spill(mut self, spill: SpillFile) -> Self {
+        self.spill = Some(spill);
+        self
+    }
+
     /// Get approximate heap size used by the builder
     pub fn heap_size(&self) -> usize {
-        let blocks_size = self.blocks.iter().map(|b| b.heap_size()).sum::<usize>();
-        let texts_size = self.texts.len() * std::mem::size_of::<BlockId>();
+        let blocks_size = self.blocks_heap_size;
         let current_buffer_size = self.current_block_buffer.len();
         let current_starts_size = self.current_block_starts.len() * std::mem::size_of::<u64>();
 
-        blocks_size + texts_size + current_buffer_size + current_starts_size
+        let hashes_size = self.hashes.as_ref().map_or(0, |hashes| hashes.len() * 8);
+        let dictionary_size = match &self.dictionary {
+            Dictionary::None => 0,
+            Dictionary::Training { pending, .. } => pending
+                .iter()
+                .map(|block| block.data.len() + block.starts.len() * std::mem::size_of::<u64>())
+                .sum(),
+            Dictionary::Trained(dictionary) => dictionary.as_ref().map_or(0, |d| d.len()),
+        };
+
+        blocks_size + current_buffer_size + current_starts_size + hashes_size + dictionary_size
+    }
+
+    /// The part of [`TextUsageBuilder::heap_size`] taken by text that isn't
+    /// compressed yet: the block being filled and the blocks kept for
+    /// training the dictionary.
+    pub fn pending_heap_size(&self) -> usize {
+        let training_size = match &self.dictionary {
+            Dictionary::Training { pending, .. } => pending
+                .iter()
+                .map(|block| block.data.len() + block.starts.len() * std::mem::size_of::<u64>())
+                .sum(),
+            _ => 0,
+        };
+        self.current_block_buffer.len()
+            + self.current_block_starts.len() * std::mem::size_of::<u64>()
+            + training_size
     }
 
     pub fn uncompressed_size(&self) -> usize {
-        let uncompressed_blocks_size = self
-            .blocks
-            .iter()
-            .map(|b| b.uncompressed_size())
-            .sum::<usize>();
-        let texts_size = self.texts.len() * std::mem::size_of::<BlockId>();
-        uncompressed_blocks_size + texts_size
+        self.blocks_uncompressed_size
     }
 
     /// Add a string to the storage and return its TextId
     pub fn add_string(&mut self, text: &str) -> TextId {
+        if let Some(hashes) = &mut self.hashes {
+            hashes.push(text_hash(text));
+        }
         let text_bytes = text.as_bytes();
         // we use the length of the previously compressed texts plus the ones
         // we are currently building to determine a unique incremental text id
-        let text_id = TextId::new(self.texts.len() + self.current_block_starts.len());
+        let text_id = TextId::new(self.text_count + self.current_block_starts.len());
 
         // Check if adding this text would exceed block size
         if (self.current_block_buffer.len() + text_bytes.len()) > self.block_size
@@ -172,37 +809,146 @@ impl TextUsageBuilder {
         text_id
     }
 
+    /// Compress the texts added since the last block into a block of their
+    /// own now, rather than when the block is full, to free the memory
+    /// they take.
+    pub(crate) fn flush(&mut self) {
+        self.finalize_current_block();
+    }
+
     fn finalize_current_block(&mut self) {
         if self.current_block_starts.is_empty() {
             // nothing to finalize, just return
             return;
         }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            texts = self.current_block_starts.len(),
+            bytes = self.current_block_buffer.len(),
+            "text block finalized"
+        );
 
-        let block_id = BlockId::new(self.blocks.len());
-
-        // Now we want to keep a mapping of text id to block id
-        let start_text_id = TextId::new(self.texts.len());
-        for _ in &self.current_block_starts {
-            self.texts.push(block_id);
+        let start_text_id = TextId::new(self.text_count);
+        self.text_count += self.current_block_starts.len();
+        if let Dictionary::Training { blocks, pending } = &mut self.dictionary {
+            pending.push(PendingBlock {
+                start_text_id,
+                starts: std::mem::take(&mut self.current_block_starts),
+                data: std::mem::take(&mut self.current_block_buffer),
+            });
+            if pending.len() >= *blocks {
+                self.finish_training();
+            }
+            return;
         }
-        // Create compressed block
-        let block = Block::compress(
+        let block = PendingBlock {
             start_text_id,
-            &self.current_block_starts,
-            &self.current_block_buffer,
-        );
+            starts: std::mem::take(&mut self.current_block_starts),
+            data: std::mem::take(&mut self.current_block_buffer),
+        };
+        let dictionary = self.dictionary.trained().cloned();
+        self.compress_block(block, dictionary);
+    }
 
+    fn compress_block(&mut self, block: PendingBlock, dictionary: Option<Arc<[u8]>>) {
+        if !self.background {
+            let block = Block::compress(
+                block.start_text_id,
+                &block.starts,
+                &block.data,
+                self.compression,
+                dictionary.as_ref(),
+                self.spill.as_ref(),
+            );
+            self.push_block(block);
+            return;
+        }
+        let compressor = self.compressor.get_or_insert_with(Compressor::new);
+        let job = CompressionJob {
+            block,
+            compression: self.compression,
+            dictionary,
+            spill: self.spill.clone(),
+        };
+        if compressor.send(job).is_err() {
+            // finishing passes on the panic of the worker
+            if let Some(compressor) = self.compressor.take() {
+                compressor.finish();
+            }
+            unreachable!("The compression thread should only stop by panicking");
+        }
+        let finished = compressor.try_finished().collect::<Vec<_>>();
+        for block in finished {
+            self.push_block(block);
+        }
+    }
+
+    fn push_block(&mut self, block: Block) {
+        self.blocks_heap_size += block.heap_size();
+        self.blocks_uncompressed_size += block.uncompressed_size();
         self.blocks.push(block);
+    }
+
+    /// Wait for the blocks that are still being compressed in the
+    /// background.
+    fn join_compressor(&mut self) {
+        if let Some(compressor) = self.compressor.take() {
+            for block in compressor.finish() {
+                self.push_block(block);
+            }
+        }
+    }
 
-        // Clear current block
-        self.current_block_buffer.clear();
-        self.current_block_starts.clear();
+    /// Train the dictionary on the blocks waiting for it, and compress them.
+    fn finish_training(&mut self) {
+        let Dictionary::Training { pending, .. } = &mut self.dictionary else {
+            return;
+        };
+        // without texts there is nothing to train on yet
+        if pending.is_empty() {
+            return;
+        }
+        let pending = std::mem::take(pending);
+        let dictionary = train_dictionary(&pending);
+        for block in pending {
+            self.compress_block(block, dictionary.clone());
+        }
+        self.dictionary = Dictionary::Trained(dictionary);
+    }
+
+    /// Append the texts of another builder after the texts added so far.
+    /// Both builders finish their current block, and the blocks of the
+    /// other builder are kept as they are.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn append_segment(&mut self, mut segment: TextUsageBuilder) {
+        self.finalize_current_block();
+        self.finish_training();
+        self.join_compressor();
+        segment.finalize_current_block();
+        segment.finish_training();
+        segment.join_compressor();
+        for mut block in segment.blocks {
+            block.start_text_id = TextId::new(self.text_count + block.start_text_id.0);
+            self.push_block(block);
+        }
+        self.text_count += segment.text_count;
+        if let (Some(hashes), Some(segment_hashes)) = (&mut self.hashes, segment.hashes) {
+            hashes.extend(segment_hashes);
+        }
     }
 
     pub fn build(mut self) -> TextUsage {
         // if there is a half-finished block, finalize it
         self.finalize_current_block();
-        TextUsage::new(self.cache_capacity, self.blocks, self.texts)
+        self.finish_training();
+        self.join_compressor();
+        TextUsage::new(
+            self.cache_capacity,
+            self.blocks,
+            self.text_count,
+            self.hashes,
+            true,
+        )
     }
 }
 
@@ -210,57 +956,340 @@ impl TextUsageBuilder {
 #[derive(Debug)]
 pub struct TextUsage {
     blocks: Vec<Block>,
-    texts: Vec<BlockId>,
-    cache: RefCell<LruCache<BlockId, Arc<[Arc<str>]>>>,
+    // the start text id of every block, so the block of a text id is found
+    // by rank
+    block_starts: SparseRSVec,
+    text_count: usize,
+    hashes: Option<Vec<u64>>,
+    validation: TextValidation,
+    // whether the text was stored by a builder in this process, rather
+    // than read, so that it can be trusted to be valid UTF-8
+    trusted: bool,
+    // a mutex rather than a refcell so that documents can be read from
+    // multiple threads
+    cache: Mutex<LruCache<BlockId, Arc<DecodedBlock>>>,
     cache_capacity: usize,
 }
 
 impl TextUsage {
-    fn new(cache_capacity: usize, blocks: Vec<Block>, text_infos: Vec<BlockId>) -> Self {
+    fn new(
+        cache_capacity: usize,
+        blocks: Vec<Block>,
+        text_count: usize,
+        hashes: Option<Vec<u64>>,
+        trusted: bool,
+    ) -> Self {
         // LruCache requires NonZeroUsize, so we use 1 as minimum capacity
         let capacity = NonZeroUsize::new(cache_capacity.max(1)).unwrap();
+        let starts = blocks
+            .iter()
+            .map(|block| block.start_text_id.0 as u64)
+            .collect::<Vec<_>>();
+        let block_starts = SparseRSVec::new(&starts, text_count as u64);
         Self {
             blocks,
-            texts: text_infos,
-            cache: RefCell::new(LruCache::new(capacity)),
+            block_starts,
+            text_count,
+            hashes,
+            validation: if trusted {
+                TextValidation::Unchecked
+            } else {
+                TextValidation::Checked
+            },
+            trusted,
+            cache: Mutex::new(LruCache::new(capacity)),
             cache_capacity,
         }
     }
 
     pub fn heap_size(&self) -> usize {
-        let blocks_size: usize = self.blocks.iter().map(|b| b.heap_size()).sum();
-        let texts_size = self.texts.len() * std::mem::size_of::<BlockId>();
+        let blocks_size: usize = self.blocks.iter().map(|b| b.heap_size()).sum::<usize>()
+            + self.dictionaries().iter().map(|d| d.len()).sum::<usize>();
         // we ignore the cache, though it will impact the heap size, it's not part of the persistent storage
-        blocks_size + texts_size
+        let hashes_size = self.hashes.as_ref().map_or(0, |hashes| hashes.len() * 8);
+        blocks_size + self.block_starts.heap_size() + hashes_size
+    }
+
+    /// The heap taken by the decompressed blocks in the cache, which
+    /// [`TextUsage::heap_size`] leaves out.
+    pub fn cache_heap_size(&self) -> usize {
+        self.lock_cache()
+            .iter()
+            .map(|(_, block)| block.data.len() + block.starts.len() * std::mem::size_of::<usize>())
+            .sum()
+    }
+
+    /// The number of texts stored
+    pub fn len(&self) -> usize {
+        self.text_count
+    }
+
+    /// Whether no texts are stored
+    pub fn is_empty(&self) -> bool {
+        self.text_count == 0
     }
 
     /// Retrieve a string by its TextId
+    ///
+    /// # Panics
+    ///
+    /// If there is no text with the id, or its block is corrupt; see
+    /// [`TextUsage::try_get_string`].
     pub fn get_string(&self, text_id: TextId) -> Arc<str> {
-        let block_id = self.texts.get(text_id.0).expect("TextId should exist");
+        Arc::from(&*self.get_str(text_id))
+    }
 
-        let block = self
-            .blocks
-            .get(block_id.as_index())
-            .expect("Block should exist");
+    /// Retrieve a string by its TextId, or the reason it can't be.
+    pub fn try_get_string(&self, text_id: TextId) -> Result<Arc<str>, Error> {
+        Ok(Arc::from(&*self.try_get_str(text_id)?))
+    }
+
+    /// Borrow a string by its TextId. Unlike [`TextUsage::get_string`]
+    /// this doesn't allocate, which makes scanning many strings cheaper;
+    /// the guard keeps the decompressed block alive while it exists.
+    ///
+    /// # Panics
+    ///
+    /// If there is no text with the id, or its block is corrupt; see
+    /// [`TextUsage::try_get_str`].
+    pub fn get_str(&self, text_id: TextId) -> TextGuard<'_> {
+        match self.try_get_str(text_id) {
+            Ok(text) => text,
+            Err(Error::TextNotFound(_)) => panic!("TextId should exist"),
+            Err(err) => panic!("Text block should not be corrupt: {err}"),
+        }
+    }
+
+    /// Borrow a string by its TextId, or the reason it can't be: there is
+    /// no text with the id, or its block doesn't decompress, or, with
+    /// [`TextValidation::Checked`], it doesn't hold what it should.
+    pub fn try_get_str(&self, text_id: TextId) -> Result<TextGuard<'_>, Error> {
+        let (block_id, block) = self.block_of(text_id).ok_or(Error::TextNotFound(text_id))?;
 
-        let block_slices = {
+        let decoded = {
             if self.cache_capacity > 0 {
-                let mut cache = self.cache.borrow_mut();
-                if let Some(cached) = cache.get(block_id) {
-                    cached.clone()
-                } else {
-                    // Decompress and cache
-                    let block_slices = block.block_slices();
-                    cache.put(*block_id, block_slices.clone());
-                    block_slices
+                let cached = self.lock_cache().get(&block_id).cloned();
+                match cached {
+                    Some(cached) => cached,
+                    None => {
+                        #[cfg(feature = "tracing")]
+                        tracing::trace!(block = block_id.as_index(), "text cache miss");
+                        // Decompress without holding the lock, so other
+                        // threads are not blocked on it
+                        let decoded = self.decode_block(block_id, block)?;
+                        self.lock_cache().put(block_id, decoded.clone());
+                        decoded
+                    }
                 }
             } else {
-                block.block_slices()
+                self.decode_block(block_id, block)?
             }
         };
 
+        Ok(TextGuard {
+            block: decoded,
+            offset: text_id.0 - block.start_text_id.0,
+            _text_usage: PhantomData,
+        })
+    }
+
+    /// Read a string by its TextId as a stream of bytes. Unless its block
+    /// is cached already, this inflates the block only as far as it is
+    /// read, and keeps nothing decompressed, so a string of many megabytes
+    /// is never held in memory in full. A string larger than the block
+    /// size is always in a block of its own.
+    ///
+    /// With [`TextValidation::Checked`] the block is decompressed and
+    /// checked as a whole first, as it can't be checked while streaming.
+    pub fn text_reader(&self, text_id: TextId) -> TextReader<'_> {
+        let (block_id, block) = self.block_of(text_id).expect("TextId should exist");
+        let cached = self.lock_cache().peek(&block_id).is_some();
+        if cached || self.validation == TextValidation::Checked {
+            return TextReader {
+                source: ReaderSource::Decoded {
+                    text: self.get_str(text_id),
+                    position: 0,
+                },
+            };
+        }
         let offset = text_id.0 - block.start_text_id.0;
-        block_slices[offset].clone()
+        let start = block.starts.select1(offset) as usize;
+        // the next text starts after the \0 terminator of this one
+        let end = block.starts.select1(offset + 1) as usize - 1;
+        let mut decompress = Decompress::new(false);
+        if let Some(dictionary) = &block.dictionary {
+            decompress
+                .set_dictionary(dictionary)
+                .expect("Dictionary should be set before decompressing");
+        }
+        TextReader {
+            source: ReaderSource::Streaming {
+                decompress,
+                compressed: block
+                    .compressed_data
+                    .bytes()
+                    .expect("Spilled text should be readable"),
+                skip: start,
+                remaining: end - start,
+            },
+        }
+    }
+
+    // the block holding a text, if the text exists
+    fn block_of(&self, text_id: TextId) -> Option<(BlockId, &Block)> {
+        if text_id.0 >= self.text_count {
+            return None;
+        }
+        // the block holding the text is the last one starting at or before it
+        let block_id =
+            BlockId::new(sparse::rank1(&self.block_starts, text_id.0 as u64 + 1) as usize - 1);
+        let block = self.blocks.get(block_id.as_index())?;
+        Some((block_id, block))
+    }
+
+    #[cfg(test)]
+    pub(crate) fn compressed_blocks(&self) -> Vec<Vec<u8>> {
+        self.blocks
+            .iter()
+            .map(|block| block.compressed_data.bytes().unwrap().into_owned())
+            .collect()
+    }
+
+    fn lock_cache(&self) -> std::sync::MutexGuard<'_, LruCache<BlockId, Arc<DecodedBlock>>> {
+        // the cache holds no invariants a panic could break, so we can
+        // recover from poisoning
+        self.cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Set how decompressed text is checked.
+    ///
+    /// Text that was read rather than built in this process is always
+    /// checked, as nothing else keeps invalid UTF-8 from being handed out
+    /// as `&str`; [`TextValidation::Unchecked`] is ignored for it.
+    pub fn set_validation(&mut self, validation: TextValidation) {
+        if self.trusted {
+            self.validation = validation;
+        }
+    }
+
+    /// Check every block: that it decompresses to the recorded size and
+    /// checksum, and that all its texts are valid UTF-8.
+    pub fn verify(&self) -> Result<(), TextBlockError> {
+        for (i, block) in self.blocks.iter().enumerate() {
+            block.decoded(i, TextValidation::Checked)?;
+        }
+        Ok(())
+    }
+
+    fn decode_block(&self, block_id: BlockId, block: &Block) -> Result<Arc<DecodedBlock>, Error> {
+        let decoded = block.decoded(block_id.as_index(), self.validation)?;
+        Ok(Arc::new(decoded))
+    }
+
+    pub(crate) fn write<W: Write + ?Sized>(&self, w: &mut W) -> std::io::Result<()> {
+        persist::write_len(w, self.cache_capacity)?;
+        persist::write_len(w, self.text_count)?;
+        let dictionaries = self.dictionaries();
+        persist::write_len(w, dictionaries.len())?;
+        for dictionary in &dictionaries {
+            persist::write_bytes(w, dictionary)?;
+        }
+        persist::write_len(w, self.blocks.len())?;
+        for block in &self.blocks {
+            let index = block.dictionary.as_ref().map_or(0, |dictionary| {
+                dictionaries
+                    .iter()
+                    .position(|d| Arc::ptr_eq(d, dictionary))
+                    .expect("Dictionary should be collected")
+                    + 1
+            });
+            block.write(w, index)?;
+        }
+        match &self.hashes {
+            Some(hashes) => {
+                persist::write_u8(w, 1)?;
+                for &hash in hashes {
+                    persist::write_u64(w, hash)?;
+                }
+            }
+            None => persist::write_u8(w, 0)?,
+        }
+        Ok(())
+    }
+
+    // the distinct dictionaries the blocks were compressed with; segments
+    // built in parallel each have their own
+    fn dictionaries(&self) -> Vec<&Arc<[u8]>> {
+        let mut dictionaries: Vec<&Arc<[u8]>> = Vec::new();
+        for dictionary in self
+            .blocks
+            .iter()
+            .filter_map(|block| block.dictionary.as_ref())
+        {
+            if !dictionaries.iter().any(|d| Arc::ptr_eq(d, dictionary)) {
+                dictionaries.push(dictionary);
+            }
+        }
+        dictionaries
+    }
+
+    /// Read text written by [`TextUsage::write`]. The blocks are not
+    /// decompressed, so their content is only checked as they are used.
+    pub(crate) fn read<R: Read + ?Sized>(r: &mut R) -> Result<Self, LoadError> {
+        let cache_capacity = persist::read_len(r)?;
+        let text_count = persist::read_len(r)?;
+        let dictionary_count = persist::read_len(r)?;
+        let mut dictionaries = persist::vec_for(dictionary_count);
+        for _ in 0..dictionary_count {
+            dictionaries.push(Arc::from(persist::read_bytes(r)?));
+        }
+        let block_count = persist::read_len(r)?;
+        let mut blocks = persist::vec_for(block_count);
+        let mut texts = 0;
+        for _ in 0..block_count {
+            let block = Block::read(r, TextId::new(texts), &dictionaries)?;
+            texts += block.text_count();
+            blocks.push(block);
+        }
+        if texts != text_count {
+            return Err(LoadError::Corrupt("text count mismatch"));
+        }
+        let hashes = match persist::read_u8(r)? {
+            0 => None,
+            1 => {
+                let mut hashes = persist::vec_for(text_count);
+                for _ in 0..text_count {
+                    hashes.push(persist::read_u64(r)?);
+                }
+                Some(hashes)
+            }
+            _ => return Err(LoadError::Corrupt("invalid hashes flag")),
+        };
+        Ok(Self::new(cache_capacity, blocks, text_count, hashes, false))
+    }
+
+    /// Whether a hash is stored for every text.
+    pub fn has_hashes(&self) -> bool {
+        self.hashes.is_some()
+    }
+
+    /// Whether two texts are equal.
+    ///
+    /// With stored hashes, texts with different hashes are known to differ
+    /// without decompressing either of them; only texts with equal hashes are
+    /// compared in full.
+    pub fn texts_equal(&self, a: TextId, b: TextId) -> bool {
+        if a == b {
+            return true;
+        }
+        if let Some(hashes) = &self.hashes
+            && hashes[a.0] != hashes[b.0]
+        {
+            return false;
+        }
+        *self.get_str(a) == *self.get_str(b)
     }
 
     /// Get storage statistics
@@ -269,6 +1298,11 @@ impl TextUsage {
             .blocks
             .iter()
             .map(|block| block.compressed_data.len())
+            .chain(
+                self.dictionaries()
+                    .iter()
+                    .map(|dictionary| dictionary.len()),
+            )
             .sum();
 
         let total_original_size: usize = self
@@ -278,7 +1312,7 @@ impl TextUsage {
             .sum::<usize>();
 
         StorageStats {
-            total_texts: self.texts.len(),
+            total_texts: self.text_count,
             total_blocks: self.blocks.len(),
             compressed_size: total_compressed_size,
             original_size: total_original_size,
@@ -290,7 +1324,7 @@ impl TextUsage {
             cache_size: if self.cache_capacity == 0 {
                 0
             } else {
-                self.cache.borrow().len()
+                self.lock_cache().len()
             },
         }
     }
@@ -363,6 +1397,142 @@ mod tests {
         assert_eq!(usage.stats().total_blocks, 2);
     }
 
+    #[test]
+    fn test_builder_block_sizes() {
+        for background in [false, true] {
+            let mut builder = TextUsageBuilder::new(8, 2);
+            if background {
+                builder = builder.with_background_compression();
+            }
+            for i in 0..50 {
+                builder.add_string(&"x".repeat(i % 7));
+            }
+            builder.flush();
+            builder.join_compressor();
+            let blocks = &builder.blocks;
+            assert_eq!(
+                builder.blocks_heap_size,
+                blocks.iter().map(Block::heap_size).sum::<usize>()
+            );
+            assert_eq!(
+                builder.uncompressed_size(),
+                blocks.iter().map(Block::uncompressed_size).sum::<usize>()
+            );
+        }
+    }
+
+    #[test]
+    fn test_many_blocks_lookup() {
+        let mut builder = TextUsageBuilder::new(8, 2);
+        let texts = (0..50).map(|i| "x".repeat(i % 7)).collect::<Vec<_>>();
+        let ids = texts
+            .iter()
+            .map(|text| builder.add_string(text))
+            .collect::<Vec<_>>();
+        let usage = builder.build();
+        assert!(usage.stats().total_blocks > 10);
+        assert_eq!(usage.len(), 50);
+        for (id, text) in ids.iter().zip(&texts) {
+            assert_eq!(usage.get_string(*id), text.as_str().into());
+        }
+    }
+
+    #[test]
+    fn test_texts_equal() {
+        for builder in [
+            TextUsageBuilder::new(8, 1),
+            TextUsageBuilder::new(8, 1).with_hashes(),
+        ] {
+            let mut builder = builder;
+            let a = builder.add_string("same");
+            let b = builder.add_string("other text");
+            let c = builder.add_string("same");
+            let usage = builder.build();
+            assert!(usage.texts_equal(a, a));
+            assert!(usage.texts_equal(a, c));
+            assert!(!usage.texts_equal(a, b));
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut builder = TextUsageBuilder::new(8, 0);
+        builder.add_string("hello");
+        builder.add_string("wörld");
+        let mut usage = builder.build();
+        assert_eq!(usage.verify(), Ok(()));
+
+        // replace the second block with one holding invalid UTF-8
+        usage.blocks[1] = Block::compress(
+            TextId::new(1),
+            &[0],
+            b"w\xff\xffrld\0",
+            Compression::default(),
+            None,
+            None,
+        );
+        assert_eq!(
+            usage.verify(),
+            Err(TextBlockError::InvalidUtf8 { block: 1 })
+        );
+
+        usage.blocks[1].checksum += 1;
+        assert_eq!(usage.verify(), Err(TextBlockError::Checksum { block: 1 }));
+
+        usage.blocks[1].compressed_data = CompressedData::Memory(vec![0xff; 4]);
+        assert!(matches!(
+            usage.verify(),
+            Err(TextBlockError::Decompression { block: 1 })
+                | Err(TextBlockError::SizeMismatch { block: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_try_get_str() {
+        let mut builder = TextUsageBuilder::new(8, 0);
+        builder.add_string("hello");
+        builder.add_string("world");
+        let mut usage = builder.build();
+        assert_eq!(&*usage.try_get_str(TextId::new(0)).unwrap(), "hello");
+        assert!(matches!(
+            usage.try_get_string(TextId::new(2)),
+            Err(Error::TextNotFound(TextId(2)))
+        ));
+
+        usage.blocks[1].compressed_data = CompressedData::Memory(vec![0xff; 4]);
+        assert!(matches!(
+            usage.try_get_str(TextId::new(1)),
+            Err(Error::CorruptText(_))
+        ));
+        assert_eq!(&*usage.try_get_str(TextId::new(0)).unwrap(), "hello");
+    }
+
+    #[test]
+    #[should_panic(expected = "Text block should not be corrupt")]
+    fn test_checked_validation_panics() {
+        let mut builder = TextUsageBuilder::new(8, 0);
+        let id = builder.add_string("hello");
+        let mut usage = builder.build();
+        usage.blocks[0].checksum += 1;
+        // unchecked does not look at the checksum
+        assert_eq!(usage.get_string(id), "hello".into());
+        usage.set_validation(TextValidation::Checked);
+        usage.get_string(id);
+    }
+
+    #[test]
+    fn test_compression_level() {
+        let text = "abcabcabcabcabcabcabcabc";
+        let mut none = TextUsageBuilder::new(1024, 0).with_compression_level(0);
+        let mut best = TextUsageBuilder::new(1024, 0).with_compression_level(9);
+        let none_id = none.add_string(text);
+        let best_id = best.add_string(text);
+        let (none, best) = (none.build(), best.build());
+        assert!(none.stats().compressed_size > best.stats().compressed_size);
+        assert_eq!(none.get_string(none_id), text.into());
+        assert_eq!(best.get_string(best_id), text.into());
+    }
+
     #[test]
     fn test_cache_functionality() {
         // short block size of only 10b bytes to have multiple blocks, with a
@@ -398,6 +1568,51 @@ mod tests {
         assert_eq!(usage.stats().total_texts, 1);
     }
 
+    #[test]
+    fn test_get_str_outlives_cache() {
+        let mut builder = TextUsageBuilder::new(8, 1);
+        let first = builder.add_string("first text");
+        let second = builder.add_string("second text");
+        let empty = builder.add_string("");
+        let usage = builder.build();
+        assert_eq!(usage.stats().total_blocks, 3);
+
+        let guard = usage.get_str(first);
+        // this evicts the block of the first text from the cache
+        assert_eq!(&*usage.get_str(second), "second text");
+        assert_eq!(&*guard, "first text");
+        assert_eq!(&*usage.get_str(empty), "");
+        assert_eq!(format!("{guard:?}"), r#""first text""#);
+    }
+
+    #[test]
+    fn test_text_reader() {
+        let large = (0..20_000)
+            .map(|i| format!("{:x}", i * 7919))
+            .collect::<String>();
+        for dictionary in [false, true] {
+            let mut builder = TextUsageBuilder::new(64, 1);
+            if dictionary {
+                builder = builder.with_dictionary(1);
+            }
+            let ids =
+                ["small", "", &large, "after", "and more"].map(|text| builder.add_string(text));
+            let usage = builder.build();
+            let read = |id| {
+                let mut text = String::new();
+                usage.text_reader(id).read_to_string(&mut text).unwrap();
+                text
+            };
+            assert_eq!(read(ids[2]), large);
+            // texts after others in their block
+            assert_eq!(read(ids[1]), "");
+            assert_eq!(read(ids[4]), "and more");
+            // the block is cached now
+            usage.get_str(ids[3]);
+            assert_eq!(read(ids[3]), "after");
+        }
+    }
+
     #[test]
     fn test_string_exactly_at_block_size() {
         let block_size = 20;
@@ -862,6 +2077,105 @@ mod tests {
         assert_eq!(usage.stats().cache_size, 3); // No eviction
     }
 
+    #[test]
+    fn test_spill() {
+        let spill = SpillFile::new().unwrap();
+        let mut builder = TextUsageBuilder::new(8, 1).with_spill(spill);
+        let ids = ["hello", "wörld", "a longer text"].map(|text| (builder.add_string(text), text));
+        let usage = builder.build();
+
+        assert_eq!(usage.verify(), Ok(()));
+        for (id, text) in ids {
+            assert_eq!(usage.get_string(id), text.into());
+        }
+        assert!(
+            usage
+                .blocks
+                .iter()
+                .all(|block| matches!(block.compressed_data, CompressedData::Spilled { .. }))
+        );
+    }
+
+    #[test]
+    fn test_write_read() {
+        let mut builder = TextUsageBuilder::new(8, 2).with_hashes();
+        let ids =
+            ["hello", "", "wörld", "a longer text"].map(|text| (builder.add_string(text), text));
+        let usage = builder.build();
+
+        let mut out = Vec::new();
+        usage.write(&mut out).unwrap();
+        let read = TextUsage::read(&mut out.as_slice()).unwrap();
+
+        assert_eq!(read.len(), usage.len());
+        assert!(read.has_hashes());
+        assert_eq!(read.verify(), Ok(()));
+        for (id, text) in ids {
+            assert_eq!(read.get_string(id), text.into());
+        }
+    }
+
+    #[test]
+    fn test_read_text_is_checked() {
+        let mut builder = TextUsageBuilder::new(8, 0).with_compression_level(0);
+        let id = builder.add_string("hello");
+        let mut out = Vec::new();
+        builder.build().write(&mut out).unwrap();
+        // stored uncompressed, so the text can be made invalid UTF-8
+        let start = out.windows(5).position(|w| w == b"hello").unwrap();
+        out[start] = 0xff;
+        let mut read = TextUsage::read(&mut out.as_slice()).unwrap();
+        read.blocks[0].checksum = checksum(&read.blocks[0].decompress().unwrap());
+
+        read.set_validation(TextValidation::Unchecked);
+        assert!(matches!(
+            read.try_get_str(id),
+            Err(Error::CorruptText(TextBlockError::InvalidUtf8 { block: 0 }))
+        ));
+    }
+
+    #[test]
+    fn test_dictionary() {
+        let texts = (0..2000)
+            .map(|i| {
+                let status = ["pending", "shipped", "delivered"][i % 3];
+                format!("https://example.com/orders/{:08x}/{status}", i * 7919)
+            })
+            .collect::<Vec<_>>();
+        let build = |builder: TextUsageBuilder| {
+            let mut builder = builder;
+            let ids = texts
+                .iter()
+                .map(|text| builder.add_string(text))
+                .collect::<Vec<_>>();
+            (builder.build(), ids)
+        };
+        let (plain, _) = build(TextUsageBuilder::new(256, 2));
+        let (usage, ids) = build(TextUsageBuilder::new(256, 2).with_dictionary(4));
+        assert_eq!(usage.verify(), Ok(()));
+        for (id, text) in ids.iter().zip(&texts) {
+            assert_eq!(&*usage.get_string(*id), text);
+        }
+        // the dictionary itself is counted too
+        let plain_size = plain.stats().compressed_size;
+        let size = usage.stats().compressed_size;
+        assert!(size * 3 < plain_size * 2, "{size} vs {plain_size}");
+
+        let mut out = Vec::new();
+        usage.write(&mut out).unwrap();
+        let read = TextUsage::read(&mut out.as_slice()).unwrap();
+        assert_eq!(read.verify(), Ok(()));
+        assert_eq!(read.dictionaries().len(), 1);
+        assert_eq!(&*read.get_string(ids[1999]), texts[1999]);
+
+        // fewer blocks than the training blocks still get a dictionary
+        let mut builder = TextUsageBuilder::new(1024, 2).with_dictionary(100);
+        let id = builder.add_string("only");
+        let usage = builder.build();
+        assert_eq!(&*usage.get_string(id), "only");
+        assert_eq!(usage.dictionaries().len(), 1);
+    }
+
     #[test]
     fn test_cache_with_empty_strings() {
         let block_size = 10;
@@ -0,0 +1,77 @@
+//! The texts by their hash, for finding the texts equal to a given one.
+
+use ahash::{HashMap, HashSet};
+
+use super::{
+    Normalization,
+    compressed_storage::{TextId, TextUsage, text_hash},
+};
+
+fn normalized(text_usage: &TextUsage, normalization: Normalization, id: usize) -> String {
+    normalization
+        .apply(&text_usage.get_str(TextId::new(id)))
+        .into_owned()
+}
+
+#[derive(Debug)]
+pub(crate) struct EqualityIndex {
+    // the ids of the texts with each hash, in order
+    texts: HashMap<u64, Vec<usize>>,
+    // the hashes shared by texts that differ, which are rare
+    collisions: HashSet<u64>,
+    normalization: Normalization,
+}
+
+impl EqualityIndex {
+    /// Index the texts as they are after normalizing them with
+    /// `normalization`.
+    pub(crate) fn new(text_usage: &TextUsage, normalization: Normalization) -> Self {
+        let mut texts = HashMap::<u64, Vec<usize>>::default();
+        let mut collisions = HashSet::default();
+        for id in 0..text_usage.len() {
+            let text = normalized(text_usage, normalization, id);
+            let hash = text_hash(&text);
+            let ids = texts.entry(hash).or_default();
+            if let Some(&first) = ids.first()
+                && normalized(text_usage, normalization, first) != text
+            {
+                collisions.insert(hash);
+            }
+            ids.push(id);
+        }
+        Self {
+            texts,
+            collisions,
+            normalization,
+        }
+    }
+
+    /// The ids of the texts equal to `text` once both are normalized, in
+    /// order. Only one of the texts with the same hash is compared, unless
+    /// the hash is shared by texts that differ.
+    pub(crate) fn texts_equal_to(&self, text_usage: &TextUsage, text: &str) -> Vec<usize> {
+        let text = self.normalization.apply(text);
+        let hash = text_hash(&text);
+        let Some(ids) = self.texts.get(&hash) else {
+            return Vec::new();
+        };
+        let equal = |id: usize| normalized(text_usage, self.normalization, id) == text;
+        if self.collisions.contains(&hash) {
+            ids.iter().copied().filter(|&id| equal(id)).collect()
+        } else if equal(ids[0]) {
+            ids.clone()
+        } else {
+            Vec::new()
+        }
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        self.texts.capacity() * std::mem::size_of::<(u64, Vec<usize>)>()
+            + self
+                .texts
+                .values()
+                .map(|ids| ids.capacity() * std::mem::size_of::<usize>())
+                .sum::<usize>()
+            + self.collisions.capacity() * std::mem::size_of::<u64>()
+    }
+}
@@ -0,0 +1,266 @@
+//! An FM-index over a sequence of texts, for finding the texts that contain
+//! a fragment without decompressing them.
+//!
+//! The texts are concatenated with a separator after each of them and a
+//! unique terminator at the end. The Burrows-Wheeler transform of that is
+//! kept in a wavelet matrix, so the rows of the suffixes that start with a
+//! fragment are found by backward search, one rank per byte of the
+//! fragment. The suffix array is only sampled, at every
+//! [`SAMPLE_RATE`]-th text position, and the position of other rows is
+//! found by walking back to a sampled one.
+
+use vers_vecs::{BitVec, RsVec, WaveletMatrix};
+
+use super::Normalization;
+
+// the distance between sampled text positions
+const SAMPLE_RATE: usize = 32;
+// the terminator and separator come before all bytes
+const TERMINATOR: u16 = 0;
+const SEPARATOR: u16 = 1;
+const FIRST_BYTE: u16 = 2;
+const SYMBOLS: usize = 256 + FIRST_BYTE as usize;
+const SYMBOL_BITS: u16 = 9;
+
+#[derive(Debug)]
+pub(crate) struct FmIndex {
+    // the Burrows-Wheeler transform
+    bwt: WaveletMatrix,
+    // for every symbol, the number of symbols in the text that are smaller
+    smaller: Vec<usize>,
+    // the rows whose text position is sampled
+    sampled: RsVec,
+    // the text position of every sampled row, in row order
+    samples: Vec<usize>,
+    // the positions of the separators, to find the text of a position
+    separators: RsVec,
+    text_count: usize,
+    // how the texts were normalized, for normalizing fragments the same way
+    normalization: Normalization,
+}
+
+impl FmIndex {
+    /// The texts in order of their ids, normalized with `normalization`.
+    pub(crate) fn new<T: AsRef<str>>(
+        texts: impl IntoIterator<Item = T>,
+        normalization: Normalization,
+    ) -> Self {
+        let mut text = Vec::new();
+        let mut text_count = 0;
+        for t in texts {
+            text.extend(t.as_ref().bytes().map(|byte| u16::from(byte) + FIRST_BYTE));
+            text.push(SEPARATOR);
+            text_count += 1;
+        }
+        text.push(TERMINATOR);
+        let len = text.len();
+
+        let mut smaller = vec![0; SYMBOLS + 1];
+        for &symbol in &text {
+            smaller[symbol as usize + 1] += 1;
+        }
+        for symbol in 1..smaller.len() {
+            smaller[symbol] += smaller[symbol - 1];
+        }
+
+        // the suffix array takes most of the memory while building, so it
+        // is only as wide as the text needs
+        let (bwt, sampled, samples) = if u32::try_from(len).is_ok() {
+            burrows_wheeler(&text, &suffix_array::<u32>(&text))
+        } else {
+            burrows_wheeler(&text, &suffix_array::<usize>(&text))
+        };
+        let mut separators = BitVec::from_zeros(len);
+        for (position, &symbol) in text.iter().enumerate() {
+            if symbol == SEPARATOR {
+                separators.flip_bit(position);
+            }
+        }
+        Self {
+            bwt: WaveletMatrix::from_bit_vec(&bwt, SYMBOL_BITS),
+            smaller,
+            sampled: RsVec::from_bit_vec(sampled),
+            samples,
+            separators: RsVec::from_bit_vec(separators),
+            text_count,
+            normalization,
+        }
+    }
+
+    pub(crate) fn normalization(&self) -> Normalization {
+        self.normalization
+    }
+
+    /// The ids of the texts that contain `fragment`, in order.
+    pub(crate) fn texts_containing(&self, fragment: &[u8]) -> Vec<usize> {
+        if fragment.is_empty() {
+            return (0..self.text_count).collect();
+        }
+        let (mut low, mut high) = (0, self.bwt.len());
+        for &byte in fragment.iter().rev() {
+            let symbol = u16::from(byte) + FIRST_BYTE;
+            low = self.last_to_first(low, symbol);
+            high = self.last_to_first(high, symbol);
+            if low >= high {
+                return Vec::new();
+            }
+        }
+        let mut ids = (low..high)
+            .map(|row| self.separators.rank1(self.locate(row)))
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        self.bwt.heap_size()
+            + self.smaller.capacity() * std::mem::size_of::<usize>()
+            + self.sampled.heap_size()
+            + self.samples.capacity() * std::mem::size_of::<usize>()
+            + self.separators.heap_size()
+    }
+
+    // the row of the suffix that starts with `symbol` followed by the
+    // suffix of `row`
+    fn last_to_first(&self, row: usize, symbol: u16) -> usize {
+        self.smaller[usize::from(symbol)] + self.bwt.rank_u64_unchecked(row, u64::from(symbol))
+    }
+
+    // the text position of a row
+    fn locate(&self, mut row: usize) -> usize {
+        let mut steps = 0;
+        while self.sampled.get_unchecked(row) == 0 {
+            row = self.last_to_first(row, self.bwt.get_u64_unchecked(row) as u16);
+            steps += 1;
+        }
+        self.samples[self.sampled.rank1(row)] + steps
+    }
+}
+
+// the Burrows-Wheeler transform packed in `SYMBOL_BITS` per symbol, the
+// sampled rows and their text positions
+fn burrows_wheeler<P: Position>(text: &[u16], suffixes: &[P]) -> (BitVec, BitVec, Vec<usize>) {
+    let len = text.len();
+    let mut bwt = BitVec::with_capacity(len * SYMBOL_BITS as usize);
+    let mut sampled = BitVec::from_zeros(len);
+    let mut samples = Vec::with_capacity(len / SAMPLE_RATE + 1);
+    for (row, position) in suffixes.iter().map(|position| position.get()).enumerate() {
+        bwt.append_bits(
+            u64::from(text[(position + len - 1) % len]),
+            SYMBOL_BITS as usize,
+        );
+        if position.is_multiple_of(SAMPLE_RATE) {
+            sampled.flip_bit(row);
+            samples.push(position);
+        }
+    }
+    (bwt, sampled, samples)
+}
+
+/// A text position or rank while sorting suffixes: `u32` for texts short
+/// enough, which halves the memory the sort takes.
+trait Position: Copy + Ord {
+    fn new(position: usize) -> Self;
+    fn get(self) -> usize;
+}
+
+impl Position for u32 {
+    fn new(position: usize) -> Self {
+        position as u32
+    }
+
+    fn get(self) -> usize {
+        self as usize
+    }
+}
+
+impl Position for usize {
+    fn new(position: usize) -> Self {
+        position
+    }
+
+    fn get(self) -> usize {
+        self
+    }
+}
+
+/// The start positions of the suffixes of `text` in sorted order, by prefix
+/// doubling. The text ends with a unique smallest symbol.
+///
+/// Each round sorts the suffixes by twice as many symbols as the one
+/// before, so this takes O(n log² n) time, and three positions of memory
+/// for every symbol.
+fn suffix_array<P: Position>(text: &[u16]) -> Vec<P> {
+    let len = text.len();
+    let mut suffixes = (0..len).map(P::new).collect::<Vec<_>>();
+    let mut rank = text
+        .iter()
+        .map(|&symbol| P::new(usize::from(symbol)))
+        .collect::<Vec<_>>();
+    let mut next = vec![P::new(0); len];
+    let mut sorted = 1;
+    loop {
+        // the rank of a suffix by its first `2 * sorted` symbols
+        let key = |i: P| {
+            let i = i.get();
+            let second = rank.get(i + sorted).map_or(0, |rank| rank.get() + 1);
+            (rank[i], P::new(second))
+        };
+        suffixes.sort_unstable_by_key(|&i| key(i));
+        next[suffixes[0].get()] = P::new(0);
+        for pair in suffixes.windows(2) {
+            let previous = next[pair[0].get()].get();
+            next[pair[1].get()] = P::new(previous + usize::from(key(pair[0]) != key(pair[1])));
+        }
+        std::mem::swap(&mut rank, &mut next);
+        if rank[suffixes[len - 1].get()].get() == len - 1 {
+            return suffixes;
+        }
+        sorted *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suffix_array() {
+        let text = b"banana"
+            .iter()
+            .map(|&b| u16::from(b) + 1)
+            .chain([0])
+            .collect::<Vec<_>>();
+        assert_eq!(suffix_array::<u32>(&text), vec![6, 5, 3, 1, 0, 4, 2]);
+        assert_eq!(suffix_array::<usize>(&text), vec![6, 5, 3, 1, 0, 4, 2]);
+    }
+
+    #[test]
+    fn test_texts_containing() {
+        let texts = ["banana", "bandana", "", "ananas", "x", "nab"];
+        let index = FmIndex::new(texts, Normalization::default());
+        assert_eq!(index.texts_containing(b"ana"), vec![0, 1, 3]);
+        assert_eq!(index.texts_containing(b"ban"), vec![0, 1]);
+        assert_eq!(index.texts_containing(b"x"), vec![4]);
+        assert_eq!(index.texts_containing(b"nab"), vec![5]);
+        // fragments don't match across texts
+        assert_eq!(index.texts_containing(b"ax"), Vec::<usize>::new());
+        assert_eq!(index.texts_containing(b"zzz"), Vec::<usize>::new());
+        assert_eq!(index.texts_containing(b""), vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_texts_containing_long() {
+        // long enough that most rows are not sampled
+        let texts = (0..300)
+            .map(|i| format!("item {i} of many"))
+            .collect::<Vec<_>>();
+        let index = FmIndex::new(&texts, Normalization::default());
+        assert_eq!(
+            index.texts_containing(b"item 29"),
+            vec![29, 290, 291, 292, 293, 294, 295, 296, 297, 298, 299]
+        );
+        assert_eq!(index.texts_containing(b"of many").len(), 300);
+    }
+}
@@ -1,3 +1,13 @@
 pub mod compressed_storage;
+pub(crate) mod equality_index;
+pub(crate) mod fm_index;
+mod normalization;
+#[cfg(feature = "regex")]
+pub(crate) mod regex;
+pub(crate) mod text_fst;
 
-pub use compressed_storage::{StorageStats, TextId, TextUsage, TextUsageBuilder};
+pub use compressed_storage::{
+    StorageStats, TextBlockError, TextGuard, TextId, TextReader, TextUsage, TextUsageBuilder,
+    TextValidation,
+};
+pub use normalization::Normalization;
@@ -0,0 +1,78 @@
+//! Normalization of text before it is indexed and searched.
+
+use std::borrow::Cow;
+
+use unicode_normalization::{UnicodeNormalization, is_nfc_quick};
+
+/// How strings and queries are normalized before they are compared by
+/// the search methods of a [`Document`](crate::Document), so that for
+/// instance `"berlin"` finds `"Berlin"`.
+///
+/// Set it with
+/// [`Document::with_search_normalization`](crate::Document::with_search_normalization).
+/// By default nothing is normalized.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Normalization {
+    case_insensitive: bool,
+    nfc: bool,
+}
+
+impl Normalization {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compare strings in lowercase, so that case doesn't matter.
+    pub fn case_insensitive(mut self, enabled: bool) -> Self {
+        self.case_insensitive = enabled;
+        self
+    }
+
+    /// Compare strings in Unicode normalization form C, so that a
+    /// precomposed `é` matches an `e` followed by a combining accent.
+    pub fn nfc(mut self, enabled: bool) -> Self {
+        self.nfc = enabled;
+        self
+    }
+
+    pub fn is_case_insensitive(&self) -> bool {
+        self.case_insensitive
+    }
+
+    pub fn is_nfc(&self) -> bool {
+        self.nfc
+    }
+
+    /// The text as it is compared; borrowed if normalizing doesn't change
+    /// it.
+    pub(crate) fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        if self.case_insensitive && text.chars().any(char::is_uppercase) {
+            text = Cow::Owned(text.to_lowercase());
+        }
+        // lowercasing can decompose characters, so this comes after it
+        if self.nfc && is_nfc_quick(text.chars()) != unicode_normalization::IsNormalized::Yes {
+            text = Cow::Owned(text.nfc().collect());
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let none = Normalization::new();
+        assert!(matches!(none.apply("Berlin"), Cow::Borrowed("Berlin")));
+        let case = Normalization::new().case_insensitive(true);
+        assert_eq!(case.apply("BerLİN"), "berli\u{307}n");
+        assert!(matches!(case.apply("berlin"), Cow::Borrowed(_)));
+        let nfc = Normalization::new().nfc(true);
+        assert_eq!(nfc.apply("Cafe\u{301}"), "Caf\u{e9}");
+        let both = nfc.case_insensitive(true);
+        assert_eq!(both.apply("CAFE\u{301}"), "caf\u{e9}");
+        assert_eq!(both.apply("\u{130}"), "i\u{307}");
+    }
+}
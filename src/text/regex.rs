@@ -0,0 +1,115 @@
+//! Regexes compiled to a DFA, which walks an FST of texts along with it, so
+//! a regex is matched against all texts at once. Every shared prefix is
+//! visited once, and a whole branch of the FST is dropped as soon as the
+//! DFA can't match anymore.
+
+use std::fmt;
+
+use fst::Automaton;
+use regex_automata::{
+    Anchored,
+    dfa::{
+        Automaton as _, StartKind,
+        dense::{self, DFA},
+    },
+    util::{primitives::StateID, start},
+};
+
+/// A regex that could not be compiled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegexError {
+    message: String,
+}
+
+impl RegexError {
+    /// Why the regex could not be compiled.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid regex: {}", self.message)
+    }
+}
+
+impl std::error::Error for RegexError {}
+
+/// A regex that matches whole texts.
+pub(crate) struct Regex {
+    dfa: DFA<Vec<u32>>,
+}
+
+impl Regex {
+    pub(crate) fn new(pattern: &str) -> Result<Self, RegexError> {
+        let dfa = dense::Builder::new()
+            .configure(DFA::config().start_kind(StartKind::Anchored))
+            .build(pattern)
+            .map_err(|err| RegexError {
+                message: err.to_string(),
+            })?;
+        Ok(Self { dfa })
+    }
+
+    pub(crate) fn is_match(&self, text: &str) -> bool {
+        let state = text
+            .bytes()
+            .fold(self.start(), |state, byte| self.accept(&state, byte));
+        Automaton::is_match(&self, &state)
+    }
+}
+
+// the state is `None` once the DFA can't match anymore
+impl Automaton for &Regex {
+    type State = Option<StateID>;
+
+    fn start(&self) -> Self::State {
+        let config = start::Config::new().anchored(Anchored::Yes);
+        self.dfa.start_state(&config).ok()
+    }
+
+    fn is_match(&self, state: &Self::State) -> bool {
+        // matches are reported one byte late, so the end of the text counts
+        // as another byte
+        state.is_some_and(|state| self.dfa.is_match_state(self.dfa.next_eoi_state(state)))
+    }
+
+    fn can_match(&self, state: &Self::State) -> bool {
+        state.is_some()
+    }
+
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        let next = self.dfa.next_state((*state)?, byte);
+        (!self.dfa.is_dead_state(next) && !self.dfa.is_quit_state(next)).then_some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::text_fst::TextFst;
+
+    #[test]
+    fn test_regex() {
+        let regex = Regex::new("a[0-9]+").unwrap();
+        assert!(regex.is_match("a12"));
+        assert!(!regex.is_match("a12b"));
+        assert!(!regex.is_match("ba12"));
+        assert!(!regex.is_match(""));
+        assert!(Regex::new("(").is_err());
+    }
+
+    #[test]
+    fn test_texts_matching() {
+        let texts = TextFst::new(
+            ["b7", "a12", "a", "a12", "", "c"],
+            crate::text::Normalization::default(),
+        );
+        let matching = |pattern| texts.texts_matching(&Regex::new(pattern).unwrap());
+        assert_eq!(matching("a[0-9]+"), vec![1, 3]);
+        assert_eq!(matching("[ab].*"), vec![0, 1, 2, 3]);
+        assert_eq!(matching(""), vec![4]);
+        assert_eq!(matching("z"), Vec::<usize>::new());
+    }
+}
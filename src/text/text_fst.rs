@@ -0,0 +1,127 @@
+//! The distinct texts in an FST, which shares their prefixes and suffixes,
+//! for finding texts by prefix, or with any other automaton.
+
+use fst::{Automaton, IntoStreamer, Map, Streamer};
+
+use super::Normalization;
+
+#[derive(Debug)]
+pub(crate) struct TextFst {
+    // maps every distinct text to its group
+    fst: Map<Vec<u8>>,
+    // the start of every group in `text_ids`
+    group_starts: Vec<usize>,
+    // the text ids, grouped by text
+    text_ids: Vec<usize>,
+    // how the texts were normalized, for normalizing queries the same way
+    normalization: Normalization,
+}
+
+impl TextFst {
+    /// The texts in order of their ids, normalized with `normalization`.
+    /// They don't need to be UTF-8, so this can hold texts with their bytes
+    /// reversed.
+    pub(crate) fn new<T: AsRef<[u8]>>(
+        texts: impl IntoIterator<Item = T>,
+        normalization: Normalization,
+    ) -> Self {
+        let mut texts = texts.into_iter().zip(0..).collect::<Vec<_>>();
+        texts.sort_by(|(a, a_id), (b, b_id)| a.as_ref().cmp(b.as_ref()).then(a_id.cmp(b_id)));
+        let mut group_starts = Vec::new();
+        let mut keys = Vec::new();
+        for (i, (text, _)) in texts.iter().enumerate() {
+            if i == 0 || texts[i - 1].0.as_ref() != text.as_ref() {
+                keys.push((text.as_ref(), group_starts.len() as u64));
+                group_starts.push(i);
+            }
+        }
+        let fst = Map::from_iter(keys).expect("Sorted distinct keys should build an FST");
+        Self {
+            fst,
+            group_starts,
+            text_ids: texts.into_iter().map(|(_, id)| id).collect(),
+            normalization,
+        }
+    }
+
+    pub(crate) fn normalization(&self) -> Normalization {
+        self.normalization
+    }
+
+    /// The ids of the texts that the automaton matches, in order.
+    pub(crate) fn texts_matching<A: Automaton>(&self, automaton: A) -> Vec<usize> {
+        let mut ids = Vec::new();
+        let mut stream = self.fst.search(automaton).into_stream();
+        while let Some((_, group)) = stream.next() {
+            let start = self.group_starts[group as usize];
+            let end = self
+                .group_starts
+                .get(group as usize + 1)
+                .copied()
+                .unwrap_or(self.text_ids.len());
+            ids.extend_from_slice(&self.text_ids[start..end]);
+        }
+        ids.sort_unstable();
+        ids
+    }
+
+    /// The ids of the texts that start with `prefix`, in order.
+    pub(crate) fn texts_with_prefix(&self, prefix: &[u8]) -> Vec<usize> {
+        self.texts_matching(Prefix(prefix))
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        self.fst.as_fst().as_bytes().len()
+            + (self.group_starts.capacity() + self.text_ids.capacity())
+                * std::mem::size_of::<usize>()
+    }
+}
+
+// matches the texts that start with the bytes; the state is the number of
+// bytes matched so far, or `None` after a mismatch
+struct Prefix<'a>(&'a [u8]);
+
+impl Automaton for Prefix<'_> {
+    type State = Option<usize>;
+
+    fn start(&self) -> Self::State {
+        Some(0)
+    }
+
+    fn is_match(&self, state: &Self::State) -> bool {
+        *state == Some(self.0.len())
+    }
+
+    fn can_match(&self, state: &Self::State) -> bool {
+        state.is_some()
+    }
+
+    fn will_always_match(&self, state: &Self::State) -> bool {
+        self.is_match(state)
+    }
+
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        match *state {
+            Some(matched) if matched == self.0.len() => Some(matched),
+            Some(matched) if self.0[matched] == byte => Some(matched + 1),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_texts_with_prefix() {
+        let texts = TextFst::new(
+            ["http://a", "https://b", "ftp://c", "http://a", "http", ""],
+            Normalization::default(),
+        );
+        assert_eq!(texts.texts_with_prefix(b"http://"), vec![0, 3]);
+        assert_eq!(texts.texts_with_prefix(b"http"), vec![0, 1, 3, 4]);
+        assert_eq!(texts.texts_with_prefix(b""), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(texts.texts_with_prefix(b"gopher"), Vec::<usize>::new());
+    }
+}
@@ -1,13 +1,66 @@
+use ahash::HashMap;
 use vers_vecs::BitVec;
 
 use crate::{
     info::{NodeInfoId, NodeType},
+    memory_report::MemoryReport,
     usage::UsageBuilder,
 };
 
+/// Every this many children of a node, the position of the child is
+/// recorded, so the n-th child can be found without visiting all children
+/// before it.
+pub(crate) const CHILD_SKIP_INTERVAL: usize = 64;
+
+struct OpenNode {
+    position: usize,
+    children: usize,
+    skips: Vec<usize>,
+}
+
+impl OpenNode {
+    fn add_child(&mut self, position: usize) {
+        if self.children > 0 && self.children.is_multiple_of(CHILD_SKIP_INTERVAL) {
+            self.skips.push(position);
+        }
+        self.children += 1;
+    }
+}
+
+/// The child skips of the tree with these parentheses, as the builder
+/// records them, to check the ones that are read.
+pub(crate) fn child_skips_of(parentheses: &BitVec) -> HashMap<usize, Box<[usize]>> {
+    let mut open_nodes: Vec<OpenNode> = Vec::new();
+    let mut child_skips = HashMap::default();
+    for (position, bit) in parentheses.iter().enumerate() {
+        if bit == 1 {
+            if let Some(parent) = open_nodes.last_mut() {
+                parent.add_child(position);
+            }
+            open_nodes.push(OpenNode {
+                position,
+                children: 0,
+                skips: Vec::new(),
+            });
+        } else if let Some(node) = open_nodes.pop()
+            && !node.skips.is_empty()
+        {
+            child_skips.insert(node.position, node.skips.into_boxed_slice());
+        }
+    }
+    child_skips
+}
+
 pub(crate) struct TreeBuilder<T: UsageBuilder> {
     pub(crate) usage_builder: T,
     pub(crate) parentheses: BitVec,
+    open_nodes: Vec<OpenNode>,
+    // for nodes with more than CHILD_SKIP_INTERVAL children, the positions
+    // of every CHILD_SKIP_INTERVAL-th child
+    pub(crate) child_skips: HashMap<usize, Box<[usize]>>,
+    // when building a segment of the children of a node, the positions of
+    // those children
+    segment_children: Option<Vec<usize>>,
 }
 
 impl<T: UsageBuilder> TreeBuilder<T> {
@@ -15,45 +68,115 @@ impl<T: UsageBuilder> TreeBuilder<T> {
         Self {
             usage_builder: T::new(),
             parentheses: BitVec::new(),
+            open_nodes: Vec::new(),
+            child_skips: HashMap::default(),
+            segment_children: None,
+        }
+    }
+
+    /// Build a segment of the children of a node, to be appended to the
+    /// builder of that node with [`TreeBuilder::append_segment`].
+    #[cfg(feature = "parallel")]
+    pub(crate) fn segment(mut self) -> Self {
+        self.segment_children = Some(Vec::new());
+        self
+    }
+
+    fn open_position(&mut self) {
+        let position = self.parentheses.len();
+        match self.open_nodes.last_mut() {
+            Some(parent) => parent.add_child(position),
+            None => {
+                if let Some(children) = &mut self.segment_children {
+                    children.push(position);
+                }
+            }
         }
+        self.open_nodes.push(OpenNode {
+            position,
+            children: 0,
+            skips: Vec::new(),
+        });
+        self.parentheses.append(true);
+    }
+
+    fn close_position(&mut self) {
+        if let Some(node) = self.open_nodes.pop()
+            && !node.skips.is_empty()
+        {
+            self.child_skips
+                .insert(node.position, node.skips.into_boxed_slice());
+        }
+        self.parentheses.append(false);
+    }
+
+    /// Append a segment built with [`TreeBuilder::segment`], whose
+    /// top-level nodes become children of the node that is open now.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn append_segment(&mut self, segment: TreeBuilder<T>) {
+        debug_assert!(segment.open_nodes.is_empty());
+        let offset = self.parentheses.len();
+        let parent = self
+            .open_nodes
+            .last_mut()
+            .expect("A node should be open to append a segment to");
+        for position in segment.segment_children.unwrap_or_default() {
+            parent.add_child(offset + position);
+        }
+        for (position, skips) in segment.child_skips {
+            let skips = skips.iter().map(|skip| offset + skip).collect();
+            self.child_skips.insert(offset + position, skips);
+        }
+        let bits = &segment.parentheses;
+        for start in (0..bits.len()).step_by(64) {
+            let len = (bits.len() - start).min(64);
+            let limb = bits
+                .get_bits(start, len)
+                .expect("Bits within the vector should exist");
+            self.parentheses.append_bits(limb, len);
+        }
+        self.usage_builder.append_segment(segment.usage_builder);
     }
 
     pub(crate) fn heap_size(&self) -> usize {
-        self.usage_builder.heap_size() + self.parentheses.heap_size()
+        self.usage_builder.heap_size() + self.parentheses.heap_size() + self.child_skips_size()
     }
 
-    #[allow(dead_code)]
-    pub(crate) fn display_heap_sizes(&self) {
-        let usage_builder_heap_size = self.usage_builder.heap_size();
-        let parentheses_heap_size = self.parentheses.heap_size();
+    /// The heap of the tree so far, with the other parts left at 0.
+    pub(crate) fn memory_report(&self) -> MemoryReport {
+        MemoryReport {
+            tree: self.parentheses.heap_size(),
+            usage_index: self.usage_builder.heap_size(),
+            child_skips: self.child_skips_size(),
+            ..MemoryReport::default()
+        }
+    }
 
-        println!(
-            "Parentheses: {:>15} ({:>6} Mb), Usage: {:>15} ({:>6} Mb)",
-            parentheses_heap_size,
-            parentheses_heap_size / (1024 * 1024),
-            usage_builder_heap_size,
-            usage_builder_heap_size / (1024 * 1024)
-        );
+    pub(crate) fn child_skips_size(&self) -> usize {
+        self.child_skips
+            .values()
+            .map(|skips| skips.len() * std::mem::size_of::<usize>())
+            .sum::<usize>()
     }
 
     pub(crate) fn open(&mut self, node_type: NodeType) {
         self.usage_builder.open(node_type);
-        self.parentheses.append(true);
+        self.open_position();
     }
 
     pub(crate) fn close(&mut self, node_type: NodeType) {
         self.usage_builder.close(node_type);
-        self.parentheses.append(false);
+        self.close_position();
     }
 
     pub(crate) fn open_field(&mut self, name: &str) -> NodeInfoId {
         let close_field_id = self.usage_builder.open_field(name);
-        self.parentheses.append(true);
+        self.open_position();
         close_field_id
     }
 
     pub(crate) fn close_field(&mut self, close_field_id: NodeInfoId) {
         self.usage_builder.close_field(close_field_id);
-        self.parentheses.append(false);
+        self.close_position();
     }
 }
@@ -1,22 +1,31 @@
 use bitpacking::{BitPacker, BitPacker4x};
 use vers_vecs::SparseRSVec;
 
-use crate::{info::NodeInfoId, lookup::NodeLookup};
+use crate::{info::NodeInfoId, lookup::NodeLookup, spill::SpillFile};
 
 use super::{EliasFanoUsageIndex, UsageBuilder};
 
+#[derive(Clone, Copy)]
+enum BlockLocation {
+    // the start in `Packed::compressed`
+    Memory(usize),
+    // the offset in the spill file
+    Spilled(u64),
+}
+
 #[derive(Clone)]
 struct BlockInfo {
     // the initial value for this block
     initial_value: Option<u32>,
     num_bits: u8,
-    compressed_start: usize,
+    location: BlockLocation,
     compressed_len: usize,
 }
 
 #[derive(Clone)]
-struct Packed {
+pub(super) struct Packed {
     compressed: Vec<u8>,
+    spill: Option<SpillFile>,
     remainder: Vec<u32>,
     block_infos: Vec<BlockInfo>,
     used: usize,
@@ -25,9 +34,10 @@ struct Packed {
 }
 
 impl Packed {
-    fn new() -> Self {
+    pub(super) fn new(spill: Option<SpillFile>) -> Self {
         Self {
             compressed: Vec::new(),
+            spill,
             remainder: Vec::new(),
             block_infos: Vec::new(),
             used: 0,
@@ -35,13 +45,13 @@ impl Packed {
         }
     }
 
-    fn heap_size(&self) -> usize {
+    pub(super) fn heap_size(&self) -> usize {
         self.compressed.len() * std::mem::size_of::<u8>()
             + self.remainder.len() * std::mem::size_of::<u32>()
             + self.block_infos.len() * std::mem::size_of::<BlockInfo>()
     }
 
-    fn append(&mut self, value: u32) {
+    pub(super) fn append(&mut self, value: u32) {
         // add stuff to remainder until it reaches the block size
         self.remainder.push(value);
         if self.remainder.len() < BitPacker4x::BLOCK_LEN {
@@ -63,43 +73,70 @@ impl Packed {
             &mut self.compressed[compressed_start..],
             num_bits,
         );
+        let packed = &self.compressed[compressed_start..compressed_start + compressed_len];
+        // if the spill file can't be written we keep the block in memory
+        let location = match self.spill.as_ref().map(|spill| spill.append(packed)) {
+            Some(Ok(offset)) => {
+                self.compressed.truncate(compressed_start);
+                BlockLocation::Spilled(offset)
+            }
+            _ => {
+                // we now determine how much packed space we actually used
+                self.used += compressed_len;
+                BlockLocation::Memory(compressed_start)
+            }
+        };
         self.block_infos.push(BlockInfo {
             initial_value: self.initial_value,
-            compressed_start,
+            location,
             compressed_len,
             num_bits,
         });
-        // we now determine how much packed space we actually used
-        self.used += compressed_len;
         // update the initial value to the last bit of the remainer
         self.initial_value = self.remainder.last().cloned();
         // now we can clear the remainder
         self.remainder.clear();
     }
 
-    fn decompressed(&self) -> Vec<u32> {
-        let mut decompressed_data = Vec::new();
-        for block_info in &self.block_infos {
-            // add enough space for another block
-            let decompressed_start = decompressed_data.len();
-            decompressed_data.resize(decompressed_start + BitPacker4x::BLOCK_LEN, 0);
-            let bitpacker = BitPacker4x::new();
-            bitpacker.decompress_strictly_sorted(
-                block_info.initial_value,
-                &self.compressed[block_info.compressed_start
-                    ..block_info.compressed_start + block_info.compressed_len],
-                &mut decompressed_data[decompressed_start..],
-                block_info.num_bits,
-            );
-        }
-        // now we add the remainder
-        decompressed_data.extend(&self.remainder);
-        decompressed_data
+    /// The positions, decompressed one block at a time, so that spilled
+    /// blocks are streamed back from the spill file.
+    pub(super) fn positions(&self) -> impl Iterator<Item = u32> + '_ {
+        self.block_infos
+            .iter()
+            .flat_map(|block_info| self.decompress_block(block_info))
+            .chain(self.remainder.iter().copied())
+    }
+
+    fn decompress_block(&self, block_info: &BlockInfo) -> [u32; BitPacker4x::BLOCK_LEN] {
+        let mut spilled = [0u8; 4 * BitPacker4x::BLOCK_LEN];
+        let compressed = match block_info.location {
+            BlockLocation::Memory(start) => {
+                &self.compressed[start..start + block_info.compressed_len]
+            }
+            BlockLocation::Spilled(offset) => {
+                let spilled = &mut spilled[..block_info.compressed_len];
+                self.spill
+                    .as_ref()
+                    .expect("Spilled blocks should have a spill file")
+                    .read(offset, spilled)
+                    .expect("Spilled usage should be readable");
+                spilled
+            }
+        };
+        let mut decompressed = [0u32; BitPacker4x::BLOCK_LEN];
+        BitPacker4x::new().decompress_strictly_sorted(
+            block_info.initial_value,
+            compressed,
+            &mut decompressed,
+            block_info.num_bits,
+        );
+        decompressed
     }
 }
 
 pub struct BitpackingUsageBuilder {
     usage: Vec<Packed>,
+    spill: Option<SpillFile>,
     node_lookup: NodeLookup,
     len: usize,
 }
@@ -110,6 +147,7 @@ impl UsageBuilder for BitpackingUsageBuilder {
     fn new() -> Self {
         Self {
             usage: Vec::new(),
+            spill: None,
             node_lookup: NodeLookup::new(),
             len: 0,
         }
@@ -124,29 +162,52 @@ impl UsageBuilder for BitpackingUsageBuilder {
         &mut self.node_lookup
     }
 
+    fn set_spill(&mut self, spill: SpillFile) {
+        self.spill = Some(spill);
+    }
+
     fn append(&mut self, node_info_id: NodeInfoId) {
         // get the positions for this node_info_id; make it an empty vec if it doesn't exist yet
         let i = node_info_id.id() as usize;
         if self.usage.len() <= i {
-            self.usage.resize(i + 1, Packed::new());
+            self.usage.resize(i + 1, Packed::new(self.spill.clone()));
         }
         let positions = self.usage.get_mut(i).expect("Entry should be present");
         positions.append(self.len as u32);
         self.len += 1;
     }
 
-    fn build(mut self) -> Self::Index {
-        let mut sparse_rs_vecs = Vec::with_capacity(self.node_lookup.len());
-        // drain usage so we can throw away memory early
-        for packed in self.usage.drain(..) {
-            let positions = packed
-                .decompressed()
-                .into_iter()
-                .map(|i| i as u64)
-                .collect::<Vec<_>>();
-            let sparse_rs_vec = SparseRSVec::new(&positions, self.len as u64);
-            sparse_rs_vecs.push(sparse_rs_vec);
+    fn append_segment(&mut self, segment: Self) {
+        let node_info_ids = self.node_lookup.register_all(&segment.node_lookup);
+        let offset = self.len as u32;
+        for (packed, node_info_id) in segment.usage.iter().zip(node_info_ids) {
+            let i = node_info_id.index();
+            if self.usage.len() <= i {
+                self.usage.resize(i + 1, Packed::new(self.spill.clone()));
+            }
+            let positions = self.usage.get_mut(i).expect("Entry should be present");
+            for position in packed.positions() {
+                positions.append(offset + position);
+            }
         }
+        self.len += segment.len;
+    }
+
+    fn build(self) -> Self::Index {
+        let len = self.len as u64;
+        let sparse_rs_vec = |packed: Packed| {
+            let positions = packed.positions().map(|i| i as u64).collect::<Vec<_>>();
+            SparseRSVec::new(&positions, len)
+        };
+        // the vector of every node info is independent of the others
+        #[cfg(feature = "parallel")]
+        let sparse_rs_vecs = {
+            use rayon::prelude::*;
+            self.usage.into_par_iter().map(sparse_rs_vec).collect()
+        };
+        // consume usage so we can throw away memory early
+        #[cfg(not(feature = "parallel"))]
+        let sparse_rs_vecs = self.usage.into_iter().map(sparse_rs_vec).collect();
         Self::Index::new(sparse_rs_vecs, self.node_lookup, self.len)
     }
 }
@@ -195,7 +256,7 @@ mod tests {
         for i in 0..size {
             initial_data.push((i * 2) as u32);
         }
-        let mut packed = Packed::new();
+        let mut packed = Packed::new(None);
         for value in &initial_data {
             packed.append(*value);
         }
@@ -206,7 +267,24 @@ mod tests {
             "Compressed {} values, original size: {}, compressed size: {}, difference: {}",
             size, original_size, compressed_size, difference
         );
-        let decompressed_data = packed.decompressed();
+        let decompressed_data = packed.positions().collect::<Vec<_>>();
         assert_eq!(initial_data, decompressed_data)
     }
+
+    #[test]
+    fn test_packed_spill() {
+        let initial_data = (0..1000).map(|i| i * 3).collect::<Vec<u32>>();
+        let mut packed = Packed::new(Some(SpillFile::new().unwrap()));
+        for value in &initial_data {
+            packed.append(*value);
+        }
+        assert_eq!(packed.used, 0);
+        assert!(
+            packed
+                .block_infos
+                .iter()
+                .all(|info| matches!(info.location, BlockLocation::Spilled(_)))
+        );
+        assert_eq!(initial_data, packed.positions().collect::<Vec<_>>())
+    }
 }
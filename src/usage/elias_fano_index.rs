@@ -1,16 +1,58 @@
-use vers_vecs::SparseRSVec;
+use std::{
+    io::{self, Read, Write},
+    sync::Arc,
+};
+
+use vers_vecs::{BitVec, SparseRSVec};
 
 use super::traits::UsageIndex;
 use crate::{
     info::{self, NodeInfoId},
     lookup::NodeLookup,
+    persist::{self, LoadError},
+    sparse,
 };
 
+/// The usage index that every usage builder builds, and that
+/// [`Document::load`](crate::Document::load) reads.
+///
+/// Name it for the documents you load or keep, as in
+/// `Document<EliasFanoUsageIndex>`.
 #[derive(Debug)]
 pub struct EliasFanoUsageIndex {
     sparse_rs_vecs: Vec<SparseRSVec>,
-    node_lookup: NodeLookup,
+    node_lookup: Arc<NodeLookup>,
     len: usize,
+    // only when asked for, as they take far more memory than the vectors
+    tags: Option<Tags>,
+}
+
+/// The node info id of every position, packed in as few bits as the ids
+/// need. This is derived from the usage vectors, so it isn't saved.
+#[derive(Debug)]
+struct Tags {
+    bits: BitVec,
+    width: usize,
+}
+
+impl Tags {
+    fn new(sparse_rs_vecs: &[SparseRSVec], len: usize) -> Self {
+        let max_id = sparse_rs_vecs.len().saturating_sub(1);
+        let width = ((usize::BITS - max_id.leading_zeros()) as usize).max(1);
+        let mut bits = BitVec::from_zeros(len * width);
+        for (id, sparse_rs_vec) in sparse_rs_vecs.iter().enumerate() {
+            for position in sparse_rs_vec.iter1() {
+                for bit in (0..width).filter(|bit| id >> bit & 1 == 1) {
+                    bits.flip_bit(position as usize * width + bit);
+                }
+            }
+        }
+        Self { bits, width }
+    }
+
+    fn get(&self, i: usize) -> NodeInfoId {
+        NodeInfoId::new(self.bits.get_bits_unchecked(i * self.width, self.width))
+    }
 }
 
 impl EliasFanoUsageIndex {
@@ -21,50 +63,121 @@ impl EliasFanoUsageIndex {
     ) -> Self {
         Self {
             sparse_rs_vecs,
-            node_lookup,
+            node_lookup: Arc::new(node_lookup),
             len,
+            tags: None,
         }
     }
 }
 
+impl EliasFanoUsageIndex {
+    // node infos that never occur in the document may not have a vector
+    fn rank1(&self, id: usize, i: usize) -> usize {
+        self.sparse_rs_vecs.get(id).map_or(0, |sparse_rs_vec| {
+            sparse::rank1(sparse_rs_vec, i as u64) as usize
+        })
+    }
+
+    // find the vector with the position set; this is fast enough for the
+    // few vectors of most documents
+    fn probe(&self, i: usize) -> Option<NodeInfoId> {
+        for (id, sparse_rs_vec) in self.sparse_rs_vecs.iter().enumerate() {
+            if let Some(true) = sparse_rs_vec.is_set(i as u64) {
+                return Some(NodeInfoId::new(id as u64));
+            }
+        }
+        None
+    }
+}
+
 impl UsageIndex for EliasFanoUsageIndex {
     fn heap_size(&self) -> usize {
-        self.sparse_rs_vecs.iter().map(|v| v.heap_size()).sum()
+        self.sparse_rs_vecs
+            .iter()
+            .map(|v| v.heap_size())
+            .sum::<usize>()
+            + self.tags.as_ref().map_or(0, |tags| tags.bits.heap_size())
     }
 
     fn node_lookup(&self) -> &NodeLookup {
         &self.node_lookup
     }
 
+    fn shared_node_lookup(&self) -> &Arc<NodeLookup> {
+        &self.node_lookup
+    }
+
+    fn set_node_lookup(&mut self, node_lookup: Arc<NodeLookup>) {
+        debug_assert!(node_lookup.len() >= self.node_lookup.len());
+        self.node_lookup = node_lookup;
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn build_node_info_tags(&mut self) {
+        if self.tags.is_none() {
+            self.tags = Some(Tags::new(&self.sparse_rs_vecs, self.len));
+        }
+    }
+
     fn node_info_id(&self, i: usize) -> Option<NodeInfoId> {
-        // we want to avoid having to store an array of node info ids and the information is already in the sparse rs vecs
-        // but is this fast enough?
-        for (id, sparse_rs_vec) in self.sparse_rs_vecs.iter().enumerate() {
-            if let Some(b) = sparse_rs_vec.is_set(i as u64) {
-                if b {
-                    return Some(NodeInfoId::new(id as u64));
-                }
-            }
+        match &self.tags {
+            Some(tags) if i < self.len => Some(tags.get(i)),
+            Some(_) => None,
+            None => self.probe(i),
         }
-        None
     }
 
     fn rank(&self, i: usize, node_info_id: NodeInfoId) -> Option<usize> {
         if i <= self.len {
-            Some(self.sparse_rs_vecs[node_info_id.id() as usize].rank1(i as u64) as usize)
+            Some(self.rank1(node_info_id.id() as usize, i))
         } else {
             None
         }
     }
 
     fn select(&self, rank: usize, node_info_id: NodeInfoId) -> Option<usize> {
-        let s = self.sparse_rs_vecs[node_info_id.id() as usize].select1(rank) as usize;
+        let s = self
+            .sparse_rs_vecs
+            .get(node_info_id.id() as usize)?
+            .select1(rank) as usize;
         if self.len != s { Some(s) } else { None }
     }
 
+    // look up the vector once for the whole batch
+    fn rank_many(&self, positions: &[usize], node_info_id: NodeInfoId) -> Option<Vec<usize>> {
+        let Some(sparse_rs_vec) = self.sparse_rs_vecs.get(node_info_id.id() as usize) else {
+            return positions
+                .iter()
+                .map(|&i| (i <= self.len).then_some(0))
+                .collect();
+        };
+        positions
+            .iter()
+            .map(|&i| (i <= self.len).then(|| sparse::rank1(sparse_rs_vec, i as u64) as usize))
+            .collect()
+    }
+
+    fn select_range(
+        &self,
+        start_rank: usize,
+        count: usize,
+        node_info_id: NodeInfoId,
+    ) -> Vec<usize> {
+        let Some(sparse_rs_vec) = self.sparse_rs_vecs.get(node_info_id.id() as usize) else {
+            return Vec::new();
+        };
+        (start_rank..start_rank + count)
+            .map(|rank| sparse_rs_vec.select1(rank) as usize)
+            .take_while(|&position| position != self.len)
+            .collect()
+    }
+
     fn text_id(&self, i: usize) -> Option<usize> {
         if i <= self.len {
-            Some(self.sparse_rs_vecs[info::STRING_OPEN_ID.index()].rank1(i as u64) as usize)
+            Some(self.rank1(info::STRING_OPEN_ID.index(), i))
         } else {
             None
         }
@@ -74,7 +187,7 @@ impl UsageIndex for EliasFanoUsageIndex {
     // the number id
     fn number_id(&self, i: usize) -> Option<usize> {
         if i <= self.len {
-            Some(self.sparse_rs_vecs[info::NUMBER_OPEN_ID.index()].rank1(i as u64) as usize)
+            Some(self.rank1(info::NUMBER_OPEN_ID.index(), i))
         } else {
             None
         }
@@ -82,9 +195,85 @@ impl UsageIndex for EliasFanoUsageIndex {
 
     fn boolean_id(&self, i: usize) -> Option<usize> {
         if i <= self.len {
-            Some(self.sparse_rs_vecs[info::BOOLEAN_OPEN_ID.index()].rank1(i as u64) as usize)
+            Some(self.rank1(info::BOOLEAN_OPEN_ID.index(), i))
         } else {
             None
         }
     }
+
+    fn write(&self, w: &mut dyn Write) -> io::Result<()> {
+        persist::write_len(w, self.len)?;
+        self.node_lookup.write(w)?;
+        persist::write_len(w, self.sparse_rs_vecs.len())?;
+        for sparse_rs_vec in &self.sparse_rs_vecs {
+            persist::write_sparse(w, sparse_rs_vec)?;
+        }
+        Ok(())
+    }
+
+    fn read(r: &mut dyn Read) -> Result<Self, LoadError> {
+        let len = persist::read_len(r)?;
+        let node_lookup = NodeLookup::read(r)?;
+        let count = persist::read_len(r)?;
+        if count > node_lookup.len() {
+            return Err(LoadError::Corrupt("more usage vectors than node infos"));
+        }
+        let mut sparse_rs_vecs = persist::vec_for(count);
+        for _ in 0..count {
+            let sparse_rs_vec = persist::read_sparse(r)?;
+            if sparse_rs_vec.len() != len as u64 {
+                return Err(LoadError::Corrupt("usage vector length mismatch"));
+            }
+            sparse_rs_vecs.push(sparse_rs_vec);
+        }
+        Ok(Self::new(sparse_rs_vecs, node_lookup, len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::{BitpackingUsageBuilder, UsageBuilder};
+
+    use super::*;
+
+    #[test]
+    fn test_node_info_id_tags() {
+        let fields = (0..100)
+            .map(|i| format!(r#""field{i}": [{i}, "x", null]"#))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let doc = BitpackingUsageBuilder::parse(format!("{{{fields}}}").as_bytes()).unwrap();
+        assert!(doc.structure.usage_index().tags.is_none());
+        let doc = doc.with_node_info_tags();
+        let index = doc.structure.usage_index();
+        assert!(index.tags.is_some());
+        for i in 0..index.len() {
+            assert_eq!(index.node_info_id(i), index.probe(i));
+        }
+        assert_eq!(index.node_info_id(index.len()), None);
+    }
+
+    #[test]
+    fn test_batches() {
+        let doc = BitpackingUsageBuilder::parse(r#"["a", 1, "b", ["c"], 2]"#.as_bytes()).unwrap();
+        let index = doc.structure.usage_index();
+        let id = info::STRING_OPEN_ID;
+        let positions = (0..=index.len()).collect::<Vec<_>>();
+        assert_eq!(
+            index.rank_many(&positions, id),
+            positions.iter().map(|&i| index.rank(i, id)).collect()
+        );
+        assert_eq!(index.rank_many(&[0, index.len() + 1], id), None);
+        assert_eq!(
+            index.select_range(1, 5, id),
+            (1..3)
+                .map(|rank| index.select(rank, id).unwrap())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(index.select_range(3, 2, id), Vec::<usize>::new());
+        // a node info that doesn't occur
+        let unused = NodeInfoId::new(1000);
+        assert_eq!(index.select_range(0, 2, unused), Vec::<usize>::new());
+        assert_eq!(index.rank_many(&[0, 3], unused), Some(vec![0, 0]));
+    }
 }
@@ -0,0 +1,226 @@
+use roaring::RoaringBitmap;
+use vers_vecs::SparseRSVec;
+
+use crate::{info::NodeInfoId, lookup::NodeLookup, spill::SpillFile};
+
+use super::{EliasFanoUsageIndex, UsageBuilder, bitpacking_builder::Packed};
+
+// how often, in positions, the representation of every node info is
+// reviewed; this is the range of positions of one roaring container
+const REVIEW_INTERVAL: usize = 1 << 16;
+// node infos used at fewer than one in this many positions are kept in a
+// plain vector, which beats the overhead of a container per range
+const RARE: usize = 8192;
+// node infos used at more than one in this many positions are bitpacked,
+// as their small gaps pack in fewer bits than roaring's 16 bit entries
+const HOT: usize = 256;
+
+/// The positions of one node info.
+#[derive(Clone)]
+enum Usage {
+    Plain(Vec<u32>),
+    Roaring(RoaringBitmap),
+    Packed(Packed),
+}
+
+impl Usage {
+    fn heap_size(&self) -> usize {
+        match self {
+            Usage::Plain(positions) => positions.capacity() * std::mem::size_of::<u32>(),
+            Usage::Roaring(bitmap) => {
+                let stats = bitmap.statistics();
+                (stats.n_bytes_array_containers
+                    + stats.n_bytes_run_containers
+                    + stats.n_bytes_bitset_containers) as usize
+            }
+            Usage::Packed(packed) => packed.heap_size(),
+        }
+    }
+
+    fn append(&mut self, position: u32) {
+        match self {
+            Usage::Plain(positions) => positions.push(position),
+            Usage::Roaring(bitmap) => {
+                bitmap.push(position);
+            }
+            Usage::Packed(packed) => packed.append(position),
+        }
+    }
+
+    fn positions(&self) -> Box<dyn Iterator<Item = u32> + '_> {
+        match self {
+            Usage::Plain(positions) => Box::new(positions.iter().copied()),
+            Usage::Roaring(bitmap) => Box::new(bitmap.iter()),
+            Usage::Packed(packed) => Box::new(packed.positions()),
+        }
+    }
+
+    // switch to the representation that suits how often the node info is
+    // used in `len` positions; bitpacked positions stay bitpacked, as hot
+    // node infos rarely cool down
+    fn review(&mut self, len: usize, spill: &Option<SpillFile>) {
+        let count = match self {
+            Usage::Plain(positions) => positions.len(),
+            Usage::Roaring(bitmap) => bitmap.len() as usize,
+            Usage::Packed(_) => return,
+        };
+        let reviewed = match self {
+            _ if count * HOT > len => {
+                let mut packed = Packed::new(spill.clone());
+                for position in self.positions() {
+                    packed.append(position);
+                }
+                Usage::Packed(packed)
+            }
+            Usage::Roaring(bitmap) if count * RARE < len => Usage::Plain(bitmap.iter().collect()),
+            Usage::Plain(positions) if count * RARE >= len => Usage::Roaring(
+                RoaringBitmap::from_sorted_iter(positions.iter().copied())
+                    .expect("Positions should be sorted"),
+            ),
+            _ => return,
+        };
+        *self = reviewed;
+    }
+}
+
+/// A usage builder that picks how to keep the positions of every node info
+/// by how often it is used: rare ones in plain vectors, common ones in
+/// roaring bitmaps and hot ones bitpacked.
+///
+/// Every node info starts in a roaring bitmap, and is reviewed as the
+/// document grows, so this doesn't need to know the shape of the data up
+/// front.
+pub struct HybridUsageBuilder {
+    usage: Vec<Usage>,
+    spill: Option<SpillFile>,
+    node_lookup: NodeLookup,
+    len: usize,
+}
+
+impl HybridUsageBuilder {
+    fn usage_mut(&mut self, node_info_id: NodeInfoId) -> &mut Usage {
+        let i = node_info_id.index();
+        if self.usage.len() <= i {
+            self.usage
+                .resize(i + 1, Usage::Roaring(RoaringBitmap::new()));
+        }
+        self.usage.get_mut(i).expect("Entry should be present")
+    }
+
+    fn review(&mut self) {
+        for usage in &mut self.usage {
+            usage.review(self.len, &self.spill);
+        }
+    }
+}
+
+impl UsageBuilder for HybridUsageBuilder {
+    type Index = EliasFanoUsageIndex;
+
+    fn new() -> Self {
+        Self {
+            usage: Vec::new(),
+            spill: None,
+            node_lookup: NodeLookup::new(),
+            len: 0,
+        }
+    }
+
+    fn heap_size(&self) -> usize {
+        let usage_heap_size: usize = self.usage.iter().map(|usage| usage.heap_size()).sum();
+        self.node_lookup.heap_size() + usage_heap_size
+    }
+
+    fn node_lookup_mut(&mut self) -> &mut NodeLookup {
+        &mut self.node_lookup
+    }
+
+    fn set_spill(&mut self, spill: SpillFile) {
+        self.spill = Some(spill);
+    }
+
+    fn append(&mut self, node_info_id: NodeInfoId) {
+        // TODO: fail if we go over u32
+        let position = self.len as u32;
+        self.usage_mut(node_info_id).append(position);
+        self.len += 1;
+        if self.len.is_multiple_of(REVIEW_INTERVAL) {
+            self.review();
+        }
+    }
+
+    fn append_segment(&mut self, segment: Self) {
+        let node_info_ids = self.node_lookup.register_all(&segment.node_lookup);
+        let offset = self.len as u32;
+        for (usage, node_info_id) in segment.usage.iter().zip(node_info_ids) {
+            let positions = self.usage_mut(node_info_id);
+            for position in usage.positions() {
+                positions.append(offset + position);
+            }
+        }
+        self.len += segment.len;
+        self.review();
+    }
+
+    fn build(self) -> Self::Index {
+        let len = self.len as u64;
+        let sparse_rs_vec = |usage: Usage| {
+            let positions = usage.positions().map(|i| i as u64).collect::<Vec<_>>();
+            SparseRSVec::new(&positions, len)
+        };
+        #[cfg(feature = "parallel")]
+        let sparse_rs_vecs = {
+            use rayon::prelude::*;
+            self.usage.into_par_iter().map(sparse_rs_vec).collect()
+        };
+        #[cfg(not(feature = "parallel"))]
+        let sparse_rs_vecs = self.usage.into_iter().map(sparse_rs_vec).collect();
+        Self::Index::new(sparse_rs_vecs, self.node_lookup, self.len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::{BitpackingUsageBuilder, UsageIndex};
+
+    use super::*;
+
+    #[test]
+    fn test_hybrid_builder() {
+        // one rare field among many records with hot fields
+        let records = (0..20_000)
+            .map(|i| match i {
+                5000 => r#"{"id": 1, "rare": true}"#.to_string(),
+                _ => format!(r#"{{"id": {i}, "name": "n"}}"#),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let json = format!("[{records}]");
+        let mut builder = HybridUsageBuilder::new();
+        for position in 0..REVIEW_INTERVAL * 2 {
+            let id = match position {
+                100 => 2,
+                _ if position % 1000 == 0 => 3,
+                _ => u64::from(position % 3 == 0),
+            };
+            builder.append(NodeInfoId::new(id));
+        }
+        assert!(matches!(builder.usage[0], Usage::Packed(_)));
+        assert!(matches!(builder.usage[2], Usage::Plain(_)));
+        assert!(matches!(builder.usage[3], Usage::Roaring(_)));
+        let index = builder.build();
+        assert_eq!(index.select(0, NodeInfoId::new(2)), Some(100));
+        assert_eq!(index.select(1, NodeInfoId::new(3)), Some(1000));
+
+        let hybrid = HybridUsageBuilder::parse(json.as_bytes()).unwrap();
+        let bitpacking = BitpackingUsageBuilder::parse(json.as_bytes()).unwrap();
+        let (hybrid, bitpacking) = (
+            hybrid.structure.usage_index(),
+            bitpacking.structure.usage_index(),
+        );
+        assert_eq!(hybrid.len(), bitpacking.len());
+        for i in 0..hybrid.len() {
+            assert_eq!(hybrid.node_info_id(i), bitpacking.node_info_id(i));
+        }
+    }
+}
@@ -1,9 +1,11 @@
 mod bitpacking_builder;
 mod elias_fano_index;
+mod hybrid_builder;
 mod roaring_builder;
 mod traits;
 
 pub use bitpacking_builder::BitpackingUsageBuilder;
-pub(crate) use elias_fano_index::EliasFanoUsageIndex;
+pub use elias_fano_index::EliasFanoUsageIndex;
+pub use hybrid_builder::HybridUsageBuilder;
 pub use roaring_builder::RoaringUsageBuilder;
 pub(crate) use traits::{UsageBuilder, UsageIndex};
@@ -56,6 +56,20 @@ impl UsageBuilder for RoaringUsageBuilder {
         self.len += 1;
     }
 
+    fn append_segment(&mut self, segment: Self) {
+        let node_info_ids = self.node_lookup.register_all(&segment.node_lookup);
+        let offset = self.len as u32;
+        for (bitmap, node_info_id) in segment.usage.iter().zip(node_info_ids) {
+            let i = node_info_id.index();
+            if self.usage.len() <= i {
+                self.usage.resize(i + 1, RoaringBitmap::new());
+            }
+            let positions = self.usage.get_mut(i).expect("Entry should be present");
+            positions.extend(bitmap.iter().map(|position| offset + position));
+        }
+        self.len += segment.len;
+    }
+
     fn build(self) -> Self::Index {
         // TODO: drain the usage so we can throw away memory early?
         let sparse_rs_vecs = self
@@ -1,10 +1,15 @@
-use std::io::Read;
+use std::{
+    io::{self, Read, Write},
+    sync::Arc,
+};
 
 use crate::{
     Document,
     info::{NodeInfo, NodeInfoId, NodeType},
     lookup::NodeLookup,
-    parser::JsonParseError,
+    parse_error::JsonParseError,
+    persist::LoadError,
+    spill::SpillFile,
 };
 
 // TODO: these traits should be sealed somehow
@@ -18,6 +23,10 @@ pub trait UsageBuilder {
 
     fn node_lookup_mut(&mut self) -> &mut NodeLookup;
 
+    /// Write finished usage data to the spill file instead of keeping it in
+    /// memory. Builders that can't do this ignore it.
+    fn set_spill(&mut self, _spill: SpillFile) {}
+
     fn open(&mut self, node_type: NodeType) {
         let node_info = NodeInfo::open(node_type);
         let node_info_id = self.node_lookup_mut().register(node_info);
@@ -45,6 +54,12 @@ pub trait UsageBuilder {
 
     fn append(&mut self, node_info_id: NodeInfoId);
 
+    /// Append the usage of a builder that was fed separately, as if its
+    /// node infos had been appended to this one.
+    fn append_segment(&mut self, segment: Self)
+    where
+        Self: Sized;
+
     fn build(self) -> Self::Index;
 
     fn parse<R: Read>(json: R) -> Result<Document<Self::Index>, JsonParseError>
@@ -53,19 +68,69 @@ pub trait UsageBuilder {
     {
         crate::parser::parse::<R, Self>(json)
     }
+
+    /// Parse newline-delimited JSON (JSON Lines) into a document whose root
+    /// is an array of the records.
+    fn parse_ndjson<R: Read>(json: R) -> Result<Document<Self::Index>, JsonParseError>
+    where
+        Self: Sized,
+    {
+        crate::parser::parse_ndjson::<R, Self>(json)
+    }
 }
 
 pub trait UsageIndex {
     fn heap_size(&self) -> usize;
 
     fn node_lookup(&self) -> &NodeLookup;
+    /// The node lookup, which documents can share.
+    fn shared_node_lookup(&self) -> &Arc<NodeLookup>;
+    /// Use another node lookup, which has to have every node info of the
+    /// current one under the same id, such as one it was cloned from and
+    /// that has grown since.
+    fn set_node_lookup(&mut self, node_lookup: Arc<NodeLookup>);
+    /// The amount of positions covered by this index.
+    fn len(&self) -> usize;
     /// The node info id at a position i in the structure.
     fn node_info_id(&self, i: usize) -> Option<NodeInfoId>;
+    /// Keep the node info id of every position, to find it without going
+    /// through the usage of every node info. Indexes that can't do this
+    /// ignore it.
+    fn build_node_info_tags(&mut self) {}
 
     fn rank(&self, i: usize, node_info_id: NodeInfoId) -> Option<usize>;
     fn select(&self, i: usize, node_info_id: NodeInfoId) -> Option<usize>;
 
+    /// The rank at every position in `positions`, or `None` if any of them
+    /// is out of range.
+    fn rank_many(&self, positions: &[usize], node_info_id: NodeInfoId) -> Option<Vec<usize>> {
+        positions
+            .iter()
+            .map(|&i| self.rank(i, node_info_id))
+            .collect()
+    }
+
+    /// The positions with the ranks from `start_rank` on, up to `count` of
+    /// them; fewer if the node info doesn't occur that often.
+    fn select_range(
+        &self,
+        start_rank: usize,
+        count: usize,
+        node_info_id: NodeInfoId,
+    ) -> Vec<usize> {
+        (start_rank..start_rank + count)
+            .map_while(|rank| self.select(rank, node_info_id))
+            .collect()
+    }
+
     fn text_id(&self, i: usize) -> Option<usize>;
     fn number_id(&self, i: usize) -> Option<usize>;
     fn boolean_id(&self, i: usize) -> Option<usize>;
+
+    /// Write the index in the saved document format.
+    fn write(&self, w: &mut dyn Write) -> io::Result<()>;
+    /// Read an index written by [`UsageIndex::write`].
+    fn read(r: &mut dyn Read) -> Result<Self, LoadError>
+    where
+        Self: Sized;
 }
@@ -0,0 +1,101 @@
+//! A JavaScript API for parsing and querying, using wasm-bindgen.
+//!
+//! This is only available with the `wasm` feature. Build for
+//! `wasm32-unknown-unknown` and run `wasm-bindgen` on the result to get a
+//! `Document` class; values are handed to JavaScript as JSON text, to be
+//! turned into objects with `JSON.parse` where needed.
+//!
+//! ```js
+//! const doc = new Document('{"items": [{"id": 1}, {"id": 2}]}');
+//! JSON.parse(doc.pointer("/items/1/id")); // 2
+//! doc.stringsContaining("a"); // JSON Pointers to the strings
+//! ```
+
+use struson::writer::{JsonStreamWriter, JsonWriter};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    document::{Document, Node},
+    pointer,
+    usage::{BitpackingUsageBuilder, EliasFanoUsageIndex},
+};
+
+/// A parsed JSON document.
+#[wasm_bindgen(js_name = Document)]
+pub struct WasmDocument {
+    document: Document<EliasFanoUsageIndex>,
+}
+
+#[wasm_bindgen(js_class = Document)]
+impl WasmDocument {
+    /// Parse a JSON text; throws an error if it isn't valid JSON.
+    #[wasm_bindgen(constructor)]
+    pub fn parse(json: &str) -> Result<WasmDocument, JsError> {
+        let document = Document::parse::<BitpackingUsageBuilder, _>(json.as_bytes())
+            .map_err(|err| JsError::new(&err.to_string()))?;
+        Ok(Self { document })
+    }
+
+    /// The value at a JSON Pointer as JSON text, or `undefined` if there
+    /// is none; throws an error if the pointer isn't valid.
+    pub fn pointer(&self, pointer: &str) -> Result<Option<String>, JsError> {
+        let tokens = pointer::parse(pointer)
+            .ok_or_else(|| JsError::new(&format!("Invalid JSON Pointer: {pointer}")))?;
+        let tokens = tokens.iter().map(String::as_str).collect::<Vec<_>>();
+        Ok(self
+            .document
+            .get_path(self.document.root(), &tokens)
+            .map(|node| self.json(node)))
+    }
+
+    /// The JSON Pointers to the strings that contain a fragment, in
+    /// document order.
+    #[wasm_bindgen(js_name = stringsContaining)]
+    pub fn strings_containing(&self, fragment: &str) -> Vec<String> {
+        self.document
+            .strings_containing(fragment)
+            .into_iter()
+            .map(|node| self.document.path(node))
+            .collect()
+    }
+
+    /// The whole document as JSON text.
+    #[wasm_bindgen(js_name = toJSON)]
+    pub fn to_json(&self) -> String {
+        self.json(self.document.root())
+    }
+
+    /// The approximate memory used by the document, in bytes.
+    #[wasm_bindgen(js_name = heapSize)]
+    pub fn heap_size(&self) -> usize {
+        self.document.heap_size()
+    }
+
+    fn json(&self, node: Node) -> String {
+        let mut out = Vec::new();
+        let mut writer = JsonStreamWriter::new(&mut out);
+        self.document
+            .serialize_node(node, &mut writer)
+            .and_then(|()| writer.finish_document())
+            .expect("Writing to a vector should not fail");
+        String::from_utf8(out).expect("JSON should be UTF-8")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wasm_document() {
+        let doc = WasmDocument::parse(r#"{"items": [{"id": 1}, {"name": "abc"}]}"#).unwrap();
+        assert_eq!(
+            doc.pointer("/items/0").unwrap().as_deref(),
+            Some(r#"{"id":1}"#)
+        );
+        assert_eq!(doc.pointer("/items/2").unwrap(), None);
+        assert_eq!(doc.strings_containing("b"), ["/items/1/name"]);
+        assert_eq!(doc.to_json(), r#"{"items":[{"id":1},{"name":"abc"}]}"#);
+        assert!(doc.heap_size() > 0);
+    }
+}